@@ -0,0 +1,203 @@
+//! Benchmarks of the transfer paths, run them with `cargo bench`.
+//!
+//! The `memory` group runs the PTP session against the in-memory device of `ptp::memory`, so
+//! it measures the protocol layer everywhere (CI included). The other groups run against the
+//! first detected device while it's connected and unlocked, without a device they're skipped.
+//!
+//! A test file of `FILE_SIZE` bytes is uploaded to the root of the first storage and deleted
+//! at the end (even if a benchmark panics), the listings are done on the root so keep it
+//! small for stable numbers.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libmtp_rs::device::raw::detect_raw_devices;
+use libmtp_rs::device::MtpDevice;
+use libmtp_rs::object::filetypes::Filetype;
+use libmtp_rs::object::properties::Property;
+use libmtp_rs::object::Object;
+use libmtp_rs::ptp::memory::{MemoryTransport, STORAGE_ID};
+use libmtp_rs::ptp::{ObjectInfo, PtpSession, FORMAT_UNDEFINED};
+use libmtp_rs::storage::files::File;
+use libmtp_rs::storage::Parent;
+use libmtp_rs::util::HandlerReturn;
+
+/// Size of the test file.
+const FILE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Files in the root folder of the in-memory device, for the listing benchmark.
+const MEMORY_FILES: usize = 256;
+
+/// Buffer sizes of the chunked reads.
+const CHUNK_SIZES: [usize; 4] = [16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// Deletes the file it holds once dropped, so a panicking benchmark doesn't leave it on the
+/// device.
+struct Uploaded<'a>(File<'a>);
+
+impl Drop for Uploaded<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.delete() {
+            eprintln!("Couldn't delete {}: {}", self.0.name(), err);
+        }
+    }
+}
+
+fn test_data() -> Vec<u8> {
+    (0..FILE_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+fn open_device() -> Option<MtpDevice> {
+    let raw = detect_raw_devices().ok()?.into_iter().next()?;
+    raw.open_uncached()
+}
+
+fn memory(c: &mut Criterion) {
+    let data = test_data();
+    let info = ObjectInfo::file("libmtp-rs-bench.bin", FILE_SIZE as u32, FORMAT_UNDEFINED);
+
+    let mut session = PtpSession::open(MemoryTransport::new()).unwrap();
+    let file = session
+        .send_object(STORAGE_ID, Parent::Root, &info, &data)
+        .unwrap();
+    for i in 1..MEMORY_FILES {
+        let info = ObjectInfo::file(format!("file-{}.txt", i), 0, FORMAT_UNDEFINED);
+        session
+            .send_object(STORAGE_ID, Parent::Root, &info, &[])
+            .unwrap();
+    }
+
+    let mut memory = c.benchmark_group("memory");
+    memory.bench_function("object_handles", |b| {
+        b.iter(|| {
+            session
+                .object_handles(STORAGE_ID, Some(Parent::Root))
+                .unwrap()
+        })
+    });
+    memory.bench_function("object_info", |b| {
+        b.iter(|| {
+            for handle in session
+                .object_handles(STORAGE_ID, Some(Parent::Root))
+                .unwrap()
+            {
+                session.object_info(handle).unwrap();
+            }
+        })
+    });
+
+    memory.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    memory.bench_function("get_object", |b| {
+        b.iter(|| session.get_object(file).unwrap())
+    });
+    memory.bench_function("send_object", |b| {
+        b.iter(|| {
+            let handle = session
+                .send_object(STORAGE_ID, Parent::Root, &info, &data)
+                .unwrap();
+            session.delete_object(handle).unwrap();
+        })
+    });
+    memory.finish();
+}
+
+fn transfers(c: &mut Criterion) {
+    let mtp_device = match open_device() {
+        Some(mtp_device) => mtp_device,
+        None => {
+            eprintln!("No device, only the in-memory device is benchmarked");
+            return;
+        }
+    };
+
+    let storage_pool = mtp_device.storage_pool();
+    let (_, storage) = storage_pool.iter().next().expect("No storage");
+
+    let data = test_data();
+    let uploaded = Uploaded(
+        storage
+            .send_bytes(
+                &data,
+                "libmtp-rs-bench.bin",
+                Parent::Root,
+                Filetype::Unknown,
+            )
+            .expect("Couldn't upload the test file"),
+    );
+    let file = &uploaded.0;
+
+    let mut listing = c.benchmark_group("listing");
+    listing.bench_function("files_and_folders", |b| {
+        b.iter(|| storage.files_and_folders(Parent::Root).unwrap())
+    });
+    listing.bench_function("list_pages", |b| {
+        b.iter(|| {
+            storage
+                .list_pages(Parent::Root, 64)
+                .map(|page| page.unwrap().entries.len())
+                .sum::<usize>()
+        })
+    });
+    listing.finish();
+
+    let mut transfer = c.benchmark_group("transfer");
+    transfer.sample_size(10);
+    transfer.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    transfer.bench_function("download_handler", |b| {
+        b.iter(|| {
+            storage
+                .get_file_to_handler(file, |data| HandlerReturn::Ok(data.len() as u32))
+                .unwrap()
+        })
+    });
+
+    for chunk_size in CHUNK_SIZES {
+        transfer.bench_with_input(
+            BenchmarkId::new("read_at", chunk_size),
+            &chunk_size,
+            |b, &chunk_size| {
+                let mut buf = vec![0; chunk_size];
+                b.iter(|| {
+                    let mut offset = 0;
+                    while offset < FILE_SIZE as u64 {
+                        offset += file.read_at(offset, &mut buf).unwrap() as u64;
+                    }
+                })
+            },
+        );
+    }
+
+    transfer.bench_function("upload_bytes", |b| {
+        // Deleted when dropped, at the end of each iteration
+        b.iter(|| {
+            Uploaded(
+                storage
+                    .send_bytes(
+                        &data,
+                        "libmtp-rs-bench-upload.bin",
+                        Parent::Root,
+                        Filetype::Unknown,
+                    )
+                    .unwrap(),
+            )
+        })
+    });
+    transfer.finish();
+
+    let mut properties = c.benchmark_group("properties");
+    properties.bench_function("search_file", |b| {
+        b.iter(|| mtp_device.search_file(file).unwrap())
+    });
+    properties.bench_function("per_property", |b| {
+        b.iter(|| {
+            (
+                file.get_string(Property::ObjectFileName).unwrap(),
+                file.get_u64(Property::ObjectSize).unwrap(),
+                file.get_u32(Property::ParentObject).unwrap(),
+                file.get_u32(Property::StorageId).unwrap(),
+            )
+        })
+    });
+    properties.finish();
+}
+
+criterion_group!(benches, memory, transfers);
+criterion_main!(benches);
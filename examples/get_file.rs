@@ -24,7 +24,7 @@ fn main() -> Result<(), Error> {
         let (_, storage) = storage_pool.iter().next().expect("No storage");
 
         let root_contents: Vec<_> = storage
-            .files_and_folders(Parent::Root)
+            .files_and_folders(Parent::Root)?
             .into_iter()
             .filter(|file| !matches!(file.ftype(), Filetype::Folder))
             .collect();
@@ -17,18 +17,24 @@ fn print_folder_tree_wfolder(folder: Option<Folder>, level: usize) {
     }
 }
 
-fn print_folder_tree_wfiles(storage: &Storage, files: Vec<File>, level: usize) {
+fn print_folder_tree_wfiles(
+    storage: &Storage,
+    files: Vec<File>,
+    level: usize,
+) -> Result<(), Error> {
     for file in files {
         match file.ftype() {
             Filetype::Folder => {
                 println!("{:>level$}{}", "", file.name(), level = level);
-                let this_contents = storage.files_and_folders(Parent::Folder(file.id()));
-                print_folder_tree_wfiles(storage, this_contents, level + 1);
+                let this_contents = storage.files_and_folders(Parent::Folder(file.id()))?;
+                print_folder_tree_wfiles(storage, this_contents, level + 1)?;
             }
 
             _ => continue,
         }
     }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Error> {
@@ -47,9 +53,9 @@ fn main() -> Result<(), Error> {
             if let Some(root) = root {
                 print_folder_tree_wfolder(Some(root), 0);
             } else {
-                let root_contents = storage.files_and_folders(Parent::Root);
+                let root_contents = storage.files_and_folders(Parent::Root)?;
                 println!("/");
-                print_folder_tree_wfiles(storage, root_contents, 1);
+                print_folder_tree_wfiles(storage, root_contents, 1)?;
             }
         } else {
             println!("Couldn't open device {}", idx + 1);
@@ -15,7 +15,7 @@ fn main() -> Result<(), Error> {
                 println!("Storage {}:", i + 1);
                 println!(
                     "  Description: {}",
-                    storage.description().unwrap_or_else(|| "Unknown")
+                    storage.description().as_deref().unwrap_or("Unknown")
                 );
                 println!(
                     "  Max. capacity: {}",
@@ -0,0 +1,250 @@
+//! Small command line tool to manage the first connected device, paths on the device are
+//! separated by `/` and start at the root of the storage pool, prefix them with the description
+//! of a storage to use only that one (like `SD card:/Music`).
+//!
+//! Usage: `mtp-cli <devices|info|ls|get|put|rm|mkdir> [args]`
+
+use anyhow::{anyhow, bail, Error};
+use libmtp_rs::device::raw::detect_raw_devices;
+use libmtp_rs::device::{MtpDevice, StorageSort};
+use libmtp_rs::object::filetypes::Filetype;
+use libmtp_rs::object::Object;
+use libmtp_rs::storage::files::FileMetadata;
+use libmtp_rs::storage::{Parent, StoragePool};
+use libmtp_rs::util::{CallbackReturn, TransferStats};
+use std::io::Write;
+use std::path::Path;
+
+const USAGE: &str = "Usage: mtp-cli <command> [args]
+
+Commands:
+  devices                 List the connected devices
+  info                    Show information about the device and its storage
+  ls [path]               List the contents of a folder
+  get <path> [local]      Download a file
+  put <local> [folder]    Upload a file into a folder
+  rm <path>               Delete a file or an empty folder
+  mkdir <path>            Create a folder";
+
+fn progress(stats: &mut TransferStats, sent: u64, total: u64) -> CallbackReturn {
+    stats.update(sent, total);
+    print!(
+        "\r{:>5.1}% {}/s",
+        stats.fraction() * 100.0,
+        bytefmt::format(stats.throughput() as u64)
+    );
+    std::io::stdout().lock().flush().expect("Failed to flush");
+    CallbackReturn::Continue
+}
+
+fn open_device() -> Result<MtpDevice, Error> {
+    let raw = detect_raw_devices()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No devices"))?;
+
+    let mut mtp_device = raw
+        .open_uncached()
+        .ok_or_else(|| anyhow!("Couldn't open device"))?;
+    mtp_device.update_storage(StorageSort::NotSorted)?;
+
+    Ok(mtp_device)
+}
+
+/// Splits a path into its parent folder and its name.
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(idx) => (&path[..idx], &path[idx + 1..]),
+        None => ("", path),
+    }
+}
+
+fn devices() -> Result<(), Error> {
+    for (i, raw) in detect_raw_devices()?.iter().enumerate() {
+        let entry = raw.device_entry();
+        println!(
+            "{}) {} {} ({:04x}:{:04x}) at bus {}, device {}",
+            i + 1,
+            entry.vendor,
+            entry.product,
+            entry.vendor_id,
+            entry.product_id,
+            raw.bus_number(),
+            raw.dev_number()
+        );
+    }
+
+    Ok(())
+}
+
+fn info(mtp_device: &MtpDevice) -> Result<(), Error> {
+    println!("Manufacturer: {}", mtp_device.manufacturer_name_lossy()?);
+    println!("Model: {}", mtp_device.model_name_lossy()?);
+    println!("Serial number: {}", mtp_device.serial_number_lossy()?);
+    println!(
+        "Friendly name: {}",
+        mtp_device
+            .friendly_name_lossy()
+            .unwrap_or_else(|_| "Unknown".to_string())
+    );
+
+    if let Ok((source, level)) = mtp_device.battery_level() {
+        println!("Battery: {}% ({:?})", level, source);
+    }
+
+    let storage_pool = mtp_device.storage_pool();
+    for (id, storage) in storage_pool.iter() {
+        println!("Storage {:#x}:", id);
+        println!(
+            "  Description: {}",
+            storage.description().as_deref().unwrap_or("Unknown")
+        );
+        println!(
+            "  Free space: {} of {}",
+            storage.free_space_human(),
+            storage.capacity_human()
+        );
+    }
+
+    Ok(())
+}
+
+fn ls(storage_pool: &StoragePool<'_>, path: &str) -> Result<(), Error> {
+    let (storage_id, parent) = storage_pool.find_folder(path)?;
+
+    let files = match storage_pool.by_id(storage_id) {
+        Some(storage) => storage.files_and_folders(parent)?,
+        None => storage_pool.files_and_folders(parent)?,
+    };
+
+    for file in files {
+        if matches!(file.ftype(), Filetype::Folder) {
+            println!("{:>12}  {}/", "-", file.name());
+        } else {
+            println!("{:>12}  {}", bytefmt::format(file.size()), file.name());
+        }
+    }
+
+    Ok(())
+}
+
+fn get(storage_pool: &StoragePool<'_>, path: &str, local: Option<&str>) -> Result<(), Error> {
+    let file = storage_pool.find_path(path)?;
+    if matches!(file.ftype(), Filetype::Folder) {
+        bail!("{} is a folder", path);
+    }
+
+    let name = file.name();
+    let local = local.unwrap_or(&name);
+    let mut stats = TransferStats::new();
+    storage_pool.get_file_to_path_with_callback(&file, local, |sent, total| {
+        progress(&mut stats, sent, total)
+    })?;
+
+    println!();
+    Ok(())
+}
+
+fn put(storage_pool: &StoragePool<'_>, local: &str, folder: &str) -> Result<(), Error> {
+    let (storage_id, parent) = storage_pool.find_folder(folder)?;
+    let path = Path::new(local);
+    let metadata = std::fs::metadata(path)?;
+
+    let file_metadata = FileMetadata {
+        file_size: metadata.len(),
+        file_name: path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Invalid file name"))?,
+        file_type: Filetype::guess(path),
+        modification_date: metadata.modified()?.into(),
+    };
+
+    // The root of every storage goes to the first one
+    let storage_id = match (storage_id, storage_pool.iter().next()) {
+        (0, Some((id, _))) => id,
+        (0, None) => bail!("The device has no storage"),
+        (id, _) => id,
+    };
+
+    let mut stats = TransferStats::new();
+    let file = storage_pool.send_file_from_path_with_callback(
+        storage_id,
+        path,
+        parent,
+        file_metadata,
+        |sent, total| progress(&mut stats, sent, total),
+    )?;
+
+    println!();
+    println!("Uploaded {} with id {}", file.name(), file.id());
+    Ok(())
+}
+
+fn rm(storage_pool: &StoragePool<'_>, path: &str) -> Result<(), Error> {
+    let file = storage_pool.find_path(path)?;
+
+    if matches!(file.ftype(), Filetype::Folder)
+        && !storage_pool
+            .files_and_folders(Parent::Folder(file.id()))?
+            .is_empty()
+    {
+        bail!("{} is not empty", path);
+    }
+
+    file.delete()?;
+    Ok(())
+}
+
+fn mkdir(storage_pool: &StoragePool<'_>, path: &str) -> Result<(), Error> {
+    let (folder, name) = split_path(path);
+    if name.is_empty() {
+        bail!("Missing folder name");
+    }
+
+    let (storage_id, parent) = storage_pool.find_folder(folder)?;
+    let (id, name) = match storage_pool.by_id(storage_id) {
+        Some(storage) => storage.create_folder(name, parent)?,
+        None => storage_pool.create_folder(name, parent)?,
+    };
+    println!("Created {} with id {}", name, id);
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let args: Vec<_> = args.iter().map(|arg| arg.as_str()).collect();
+
+    if let ["devices"] = args.as_slice() {
+        return devices();
+    }
+
+    let mtp_device = match args.as_slice() {
+        ["info"] | ["ls", ..] | ["get", _, ..] | ["put", _, ..] | ["rm", _] | ["mkdir", _] => {
+            open_device()?
+        }
+        _ => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    let storage_pool = mtp_device.storage_pool();
+    match args.as_slice() {
+        ["info"] => info(&mtp_device),
+        ["ls"] => ls(&storage_pool, ""),
+        ["ls", path] => ls(&storage_pool, path),
+        ["get", path] => get(&storage_pool, path, None),
+        ["get", path, local] => get(&storage_pool, path, Some(local)),
+        ["put", local] => put(&storage_pool, local, ""),
+        ["put", local, folder] => put(&storage_pool, local, folder),
+        ["rm", path] => rm(&storage_pool, path),
+        ["mkdir", path] => mkdir(&storage_pool, path),
+        _ => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
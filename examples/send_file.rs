@@ -3,7 +3,7 @@ use libmtp_rs::object::filetypes::Filetype;
 use libmtp_rs::storage::{files::FileMetadata, Parent};
 use libmtp_rs::{
     device::{raw::detect_raw_devices, StorageSort},
-    util::CallbackReturn,
+    util::{CallbackReturn, TransferStats},
 };
 use std::{fs::File, io::Write, path::Path};
 
@@ -39,12 +39,20 @@ fn main() -> Result<(), Error> {
             modification_date: metadata.modified()?.into(),
         };
 
+        let mut stats = TransferStats::new();
         storage.send_file_from_path_with_callback(
             path,
             Parent::Root,
             metadata,
             |sent, total| {
-                print!("\rProgress {}/{}", sent, total);
+                stats.update(sent, total);
+                print!(
+                    "\rProgress {}/{} ({:.0} KiB/s, ETA {}s)",
+                    sent,
+                    total,
+                    stats.throughput() / 1024.0,
+                    stats.eta().map_or(0, |eta| eta.as_secs())
+                );
                 std::io::stdout().lock().flush().expect("Failed to flush");
                 CallbackReturn::Continue
             },
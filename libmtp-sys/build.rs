@@ -7,6 +7,18 @@ fn main() {
     }
 
     println!("cargo:rerun-if-changed=libmtp.h");
+    println!("cargo:rerun-if-env-changed=LIBMTP_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=LIBMTP_LIB_NAME");
+
+    // pkg-config is usually missing on Windows (outside of MSYS2), let the user point
+    // to a prebuilt libmtp instead
+    if let Ok(lib_dir) = std::env::var("LIBMTP_LIB_DIR") {
+        let lib_name = std::env::var("LIBMTP_LIB_NAME").unwrap_or_else(|_| "mtp".to_string());
+        println!("cargo:rustc-link-search=native={}", lib_dir);
+        println!("cargo:rustc-link-lib={}", lib_name);
+        return;
+    }
+
     if let Err(err) = pkg_config::Config::new()
         .atleast_version(MIN_LIBMTP_VERSION)
         .cargo_metadata(true)
@@ -15,6 +27,8 @@ fn main() {
         eprintln!("Couldn't find libmtp on your system!  (minimum version: 1.1.15)");
         eprintln!("This crates requires that it's installed and its pkg-config is");
         eprintln!("working correctly!");
+        eprintln!("Alternatively set LIBMTP_LIB_DIR to the folder with a prebuilt libmtp,");
+        eprintln!("and LIBMTP_LIB_NAME if the library isn't called `mtp`.");
         panic!(
             "Couldn't find libmtp via `pkg-config`: {:?}\nPKG_CONFIG_SYSROOT_DIR={}",
             err,
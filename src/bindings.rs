@@ -0,0 +1,323 @@
+//! Contains bindings of the high-level API for [uniffi](https://mozilla.github.io/uniffi-rs/)
+//! (requires the `uniffi` feature), so Kotlin and Swift apps can use this crate instead of
+//! binding `libmtp` again.
+//!
+//! Devices can't be shared between threads, while uniffi objects are shared by the foreign
+//! side, so every [`Device`](struct.Device.html) keeps its `MtpDevice` behind a mutex and calls
+//! on the same device are serialized, except `abort` which cancels the transfer in flight.
+//!
+//! The scaffolding is set up in this crate, the bindings are generated from the `cdylib` of
+//! your app crate (depending on this one) with `uniffi-bindgen generate --library`.
+//!
+//! ## Example (Kotlin)
+//! ```kotlin
+//! val info = detectDevices().first()
+//! val device = Device.open(info.index)
+//!
+//! for (file in device.list(0u, null)) {
+//!     println("${file.name} (${file.size} bytes)")
+//! }
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::Utc;
+
+use crate::device::raw::detect_raw_devices;
+use crate::device::transfer::SendDevice;
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::object::Object;
+use crate::storage::files::{self, File, FileMetadata};
+use crate::storage::{files_and_folders, Parent};
+use crate::util::{AbortHandle, CallbackReturn};
+
+/// Errors seen by the foreign side, the message is the `Display` of the crate error.
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum BindingsError {
+    /// Any error of the device or `libmtp`.
+    #[error("{message}")]
+    Device { message: String },
+    /// The device is gone, it has to be detected and opened again.
+    #[error("The device is disconnected or not responding")]
+    Disconnected,
+    /// The requested device or object doesn't exist.
+    #[error("{message}")]
+    NotFound { message: String },
+}
+
+impl From<crate::error::Error> for BindingsError {
+    fn from(err: crate::error::Error) -> Self {
+        if err.is_disconnected() {
+            BindingsError::Disconnected
+        } else {
+            BindingsError::Device {
+                message: err.to_string(),
+            }
+        }
+    }
+}
+
+type BindingsResult<T> = std::result::Result<T, BindingsError>;
+
+/// A detected device, open it with `Device::open(index)`.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct DeviceInfo {
+    /// Position of this device in the detection.
+    pub index: u32,
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub bus_number: u32,
+    pub dev_number: u8,
+}
+
+/// A storage of a device.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct StorageInfo {
+    pub id: u32,
+    pub description: Option<String>,
+    pub capacity: u64,
+    pub free_space: u64,
+}
+
+/// A file or folder of a device.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct FileInfo {
+    pub id: u32,
+    /// Id of the parent folder, `None` for the root.
+    pub parent_id: Option<u32>,
+    pub storage_id: u32,
+    pub name: String,
+    pub size: u64,
+    /// Modification date as seconds since the Unix epoch.
+    pub modified: i64,
+    pub is_folder: bool,
+}
+
+impl From<&File<'_>> for FileInfo {
+    fn from(file: &File<'_>) -> Self {
+        FileInfo {
+            id: file.id(),
+            parent_id: match file.parent_id() {
+                Parent::Root => None,
+                Parent::Folder(id) => Some(id),
+            },
+            storage_id: file.storage_id(),
+            name: file.name().to_string(),
+            size: file.size(),
+            modified: file.modification_date().timestamp(),
+            is_folder: matches!(file.ftype(), Filetype::Folder),
+        }
+    }
+}
+
+/// Receives the progress of transfers, return `false` to cancel the transfer.
+#[uniffi::export(callback_interface)]
+pub trait ProgressListener: Send + Sync {
+    fn on_progress(&self, sent: u64, total: u64) -> bool;
+}
+
+fn progress_callback(
+    listener: Box<dyn ProgressListener>,
+) -> impl FnMut(u64, u64) -> CallbackReturn {
+    move |sent, total| {
+        if listener.on_progress(sent, total) {
+            CallbackReturn::Continue
+        } else {
+            CallbackReturn::Cancel
+        }
+    }
+}
+
+/// Detects the connected devices.
+#[uniffi::export]
+pub fn detect_devices() -> BindingsResult<Vec<DeviceInfo>> {
+    let devices = detect_raw_devices()?
+        .iter()
+        .enumerate()
+        .map(|(index, raw)| {
+            let identity = raw.identity();
+
+            DeviceInfo {
+                index: index as u32,
+                name: identity.name(),
+                vendor_id: identity.vendor_id,
+                product_id: identity.product_id,
+                bus_number: identity.bus_number,
+                dev_number: identity.dev_number,
+            }
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// An opened device, opened uncached so folders are listed one at a time.
+#[derive(uniffi::Object)]
+pub struct Device {
+    inner: Mutex<SendDevice>,
+    abort: AbortHandle,
+}
+
+impl Device {
+    fn lock(&self) -> BindingsResult<MutexGuard<'_, SendDevice>> {
+        self.inner.lock().map_err(|_| BindingsError::Device {
+            message: "A previous call on this device panicked".to_string(),
+        })
+    }
+}
+
+#[uniffi::export]
+impl Device {
+    /// Opens the device with the given index of `detect_devices`.
+    #[uniffi::constructor]
+    pub fn open(index: u32) -> BindingsResult<Arc<Self>> {
+        let raw = detect_raw_devices()?
+            .into_iter()
+            .nth(index as usize)
+            .ok_or_else(|| BindingsError::NotFound {
+                message: format!("There's no device {}", index),
+            })?;
+
+        let device: MtpDevice = raw.open_uncached().ok_or_else(|| BindingsError::Device {
+            message: format!("Couldn't open {}", raw.identity()),
+        })?;
+
+        let abort = device.abort_handle();
+        let (device, _) = SendDevice::new(device);
+
+        Ok(Arc::new(Device {
+            inner: Mutex::new(device),
+            abort,
+        }))
+    }
+
+    /// Returns the readable name of the device, like `Google Pixel 7`.
+    pub fn name(&self) -> BindingsResult<String> {
+        Ok(self.lock()?.get().identity().name())
+    }
+
+    /// Returns the serial number of the device.
+    pub fn serial_number(&self) -> BindingsResult<String> {
+        let device = self.lock()?;
+        match device.get().serial() {
+            Some(serial) => Ok(serial.to_string()),
+            None => Ok(device.get().serial_number_lossy()?),
+        }
+    }
+
+    /// Returns the battery level of the device, from 0 to 100.
+    pub fn battery_level(&self) -> BindingsResult<u8> {
+        let (_, level) = self.lock()?.get().battery_level()?;
+        Ok(level)
+    }
+
+    /// Returns every storage of the device.
+    pub fn storages(&self) -> BindingsResult<Vec<StorageInfo>> {
+        let device = self.lock()?;
+        let storage_pool = device.get().storage_pool();
+
+        let storages = storage_pool
+            .iter()
+            .map(|(id, storage)| StorageInfo {
+                id,
+                description: storage
+                    .description()
+                    .map(|description| description.into_owned()),
+                capacity: storage.maximum_capacity(),
+                free_space: storage.free_space_in_bytes(),
+            })
+            .collect();
+
+        Ok(storages)
+    }
+
+    /// Lists the contents of a folder (`None` for the root) of a storage, `0` for every
+    /// storage.
+    pub fn list(&self, storage_id: u32, parent_id: Option<u32>) -> BindingsResult<Vec<FileInfo>> {
+        let device = self.lock()?;
+        let parent = parent_id.map_or(Parent::Root, Parent::Folder);
+        let files = files_and_folders(device.get(), storage_id, parent)?;
+
+        Ok(files.iter().map(FileInfo::from).collect())
+    }
+
+    /// Downloads a file to a local path.
+    pub fn download(
+        &self,
+        object_id: u32,
+        path: String,
+        listener: Box<dyn ProgressListener>,
+    ) -> BindingsResult<()> {
+        let device = self.lock()?;
+        files::get_file_to_path_with_callback(
+            device.get(),
+            object_id,
+            path,
+            progress_callback(listener),
+        )?;
+
+        Ok(())
+    }
+
+    /// Uploads a local file into a folder (`None` for the root) of a storage, `0` to let the
+    /// device choose.
+    pub fn upload(
+        &self,
+        path: String,
+        storage_id: u32,
+        parent_id: Option<u32>,
+        listener: Box<dyn ProgressListener>,
+    ) -> BindingsResult<FileInfo> {
+        let local = Path::new(&path);
+        let name = local
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| BindingsError::NotFound {
+                message: format!("{} isn't a file", path),
+            })?;
+        let file_size = std::fs::metadata(local)
+            .map_err(crate::error::Error::from)?
+            .len();
+
+        let metadata = FileMetadata {
+            file_size,
+            file_name: name,
+            file_type: Filetype::guess(local),
+            modification_date: Utc::now(),
+        };
+
+        let device = self.lock()?;
+        let parent = parent_id.map_or(Parent::Root, Parent::Folder);
+        let file = files::send_file_from_path_with_callback(
+            device.get(),
+            storage_id,
+            local,
+            parent,
+            metadata,
+            progress_callback(listener),
+        )?;
+
+        Ok(FileInfo::from(&file))
+    }
+
+    /// Deletes a file or folder.
+    pub fn delete(&self, object_id: u32) -> BindingsResult<()> {
+        let device = self.lock()?;
+        device.get().dummy_object(object_id).delete()?;
+        Ok(())
+    }
+
+    /// Aborts the transfer in flight (and later ones until `resume`), callable while another
+    /// call is using the device.
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// Allows transfers again after `abort`.
+    pub fn resume(&self) {
+        self.abort.reset();
+    }
+}
@@ -3,22 +3,54 @@
 //! to be able to send or get files, folders, tracks, etc.
 
 pub mod capabilities;
+pub mod events;
+pub mod identity;
+pub mod options;
 pub mod raw;
+pub mod report;
+pub mod shared;
+pub mod transfer;
+pub mod worker;
 
-use capabilities::DeviceCapability;
+use capabilities::{Capabilities, DeviceCapability};
+use chrono::Utc;
+use events::Event;
+use identity::DeviceIdentity;
 use libmtp_sys as ffi;
 use num_derive::ToPrimitive;
 use num_traits::{FromPrimitive, ToPrimitive};
+use raw::{copy_raw_device, RawDevice};
+use report::{DeviceExtension, DeviceReport};
+use std::cell::{Cell, RefCell};
 use std::ffi::CString;
 use std::fmt::{self, Debug};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use transfer::{ProgressReporter, TransferHandle};
+use worker::DeviceWorker;
 
-use crate::error::Error;
+use crate::error::{Error, ErrorContext, MtpErrorKind};
+use crate::journal::{Journal, JournalEntry};
+use crate::metrics::{Metrics, SessionStats};
 use crate::object::filetypes::Filetype;
 use crate::object::properties::Property;
-use crate::object::{AsObjectId, DummyObject};
-use crate::storage::files::File;
-use crate::storage::StoragePool;
+use crate::object::{AsObjectId, DummyObject, ObjectId};
+use crate::storage::albums::{get_album, Album};
+use crate::storage::batch::{self, UploadJob};
+use crate::storage::conflict::OnConflict;
+use crate::storage::files::{self, File};
+use crate::storage::media::{self, MediaFolder};
+use crate::storage::paging::RemainingPages;
+use crate::storage::paths::PathCache;
+use crate::storage::playlists::{get_playlist, Playlist};
+use crate::storage::policy::{StoragePolicy, StorageSpace};
+use crate::storage::tracks::{get_track, Track, TrackMetadata};
+use crate::storage::verify::Verification;
+use crate::storage::{Parent, StorageId, StoragePool};
+use crate::util::{AbortHandle, CallbackReturn, RetryPolicy};
 use crate::values::AllowedValues;
+use crate::watcher::FolderWatch;
 use crate::Result;
 
 /// Sorting logic to apply after the update of storages.
@@ -32,6 +64,22 @@ pub enum StorageSort {
     ByMaximumSpace,
 }
 
+/// Caching mode of an opened `MtpDevice`, this depends on whether the device was opened with
+/// [`RawDevice::open`](raw/struct.RawDevice.html#method.open) or
+/// [`RawDevice::open_uncached`](raw/struct.RawDevice.html#method.open_uncached).
+///
+/// Cached devices read all the object metadata upfront and `libmtp` serves listings (folder
+/// lists, file lists, etc.) from there, uncached devices instead perform I/O for every listing,
+/// one folder at a time. Some APIs only work on one of these modes, calling them on the other
+/// one returns [`Error::WrongCacheMode`](../error/enum.Error.html#variant.WrongCacheMode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// The device keeps a cache of all the objects.
+    Cached,
+    /// The device doesn't keep any cache, every listing performs I/O.
+    Uncached,
+}
+
 /// Result given when updating the inner storage list of an MTP device with
 /// [`MtpDevice::update_storage`](struct.MtpDevice.html#method.battery_level).
 ///
@@ -80,6 +128,78 @@ pub enum BatteryLevel {
 /// ```
 pub struct MtpDevice {
     pub(crate) inner: *mut ffi::LIBMTP_mtpdevice_t,
+    pub(crate) raw: ffi::LIBMTP_raw_device_t,
+    pub(crate) storage_sort: StorageSort,
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) model: Option<String>,
+    pub(crate) serial: Option<String>,
+    pub(crate) identity: DeviceIdentity,
+    pub(crate) poisoned: Cell<bool>,
+    pub(crate) retry_policy: Cell<RetryPolicy>,
+    pub(crate) metrics: RefCell<Option<Rc<dyn Metrics>>>,
+    pub(crate) max_throughput: Cell<Option<u64>>,
+    pub(crate) abort: AbortHandle,
+    pub(crate) stats: Cell<SessionStats>,
+    pub(crate) journal: RefCell<Option<Journal>>,
+    pub(crate) path_cache: RefCell<Option<PathCache>>,
+    pub(crate) on_conflict: Cell<Option<OnConflict>>,
+    pub(crate) temp_uploads: Cell<bool>,
+    pub(crate) verify_uploads: Cell<Option<Verification>>,
+    pub(crate) remaining_pages: RefCell<Option<RemainingPages>>,
+}
+
+fn references_unsupported(id: u32, ftype: Filetype) -> Error {
+    Error::InvalidArgument {
+        reason: format!(
+            "libmtp can't access the references of object {} ({}), only of albums and playlists",
+            id, ftype
+        ),
+    }
+}
+
+/// Settings and state of a device that outlive its session, carried over to the device opened
+/// again by `refresh` and `reconnect`.
+struct Settings {
+    retry_policy: RetryPolicy,
+    metrics: Option<Rc<dyn Metrics>>,
+    max_throughput: Option<u64>,
+    on_conflict: Option<OnConflict>,
+    temp_uploads: bool,
+    verify_uploads: Option<Verification>,
+    abort: AbortHandle,
+    stats: SessionStats,
+    journal: Option<Journal>,
+    path_cache: Option<PathCache>,
+}
+
+impl Settings {
+    fn take(device: &MtpDevice) -> Self {
+        Settings {
+            retry_policy: device.retry_policy(),
+            metrics: device.metrics(),
+            max_throughput: device.max_throughput(),
+            on_conflict: device.on_conflict(),
+            temp_uploads: device.temp_uploads(),
+            verify_uploads: device.verify_uploads(),
+            abort: device.abort_handle(),
+            stats: device.stats(),
+            journal: device.journal.take(),
+            path_cache: device.path_cache.take(),
+        }
+    }
+
+    fn carry_settings_into(self, device: &mut MtpDevice) {
+        device.retry_policy.set(self.retry_policy);
+        device.metrics.replace(self.metrics);
+        device.max_throughput.set(self.max_throughput);
+        device.on_conflict.set(self.on_conflict);
+        device.temp_uploads.set(self.temp_uploads);
+        device.verify_uploads.set(self.verify_uploads);
+        device.abort = self.abort;
+        device.stats.set(self.stats);
+        device.journal.replace(self.journal);
+        device.path_cache.replace(self.path_cache);
+    }
 }
 
 impl Drop for MtpDevice {
@@ -109,17 +229,497 @@ impl Debug for MtpDevice {
 }
 
 impl MtpDevice {
+    /// Build an `MtpDevice` from an opened device and the raw descriptor used to open it.
+    pub(crate) fn from_raw(
+        inner: *mut ffi::LIBMTP_mtpdevice_t,
+        raw: ffi::LIBMTP_raw_device_t,
+    ) -> Self {
+        let identity = DeviceIdentity::from_raw_device(&RawDevice {
+            inner: copy_raw_device(&raw),
+        });
+
+        let mut device = MtpDevice {
+            inner,
+            raw,
+            storage_sort: StorageSort::NotSorted,
+            manufacturer: None,
+            model: None,
+            serial: None,
+            identity,
+            poisoned: Cell::new(false),
+            retry_policy: Cell::new(RetryPolicy::none()),
+            metrics: RefCell::new(None),
+            max_throughput: Cell::new(None),
+            abort: AbortHandle::default(),
+            stats: Cell::new(SessionStats::default()),
+            journal: RefCell::new(None),
+            path_cache: RefCell::new(None),
+            on_conflict: Cell::new(None),
+            temp_uploads: Cell::new(false),
+            verify_uploads: Cell::new(None),
+            remaining_pages: RefCell::new(None),
+        };
+
+        // Remembered to find the device again in `reconnect` and to give context to errors,
+        // when it's already gone, they never change so the accessors don't ask again
+        device.manufacturer = device.manufacturer_name_lossy().ok();
+        device.model = device.model_name_lossy().ok();
+        device.serial = device.serial_number().ok();
+        device.identity = DeviceIdentity::from_device(&device);
+
+        device
+    }
+
+    /// Returns the identity of this device (names, serial number and USB location), its
+    /// `Display` implementation is handy for logs, e.g.
+    /// `Google Pixel 7 (serial 1A2B3C, bus 3 dev 7)`.
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.identity
+    }
+
+    /// Returns the manufacturer name of this device as read when it was opened, without asking
+    /// the device again like `manufacturer_name` does. `None` if the device didn't give it.
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.manufacturer.as_deref()
+    }
+
+    /// Returns the model name of this device as read when it was opened, without asking the
+    /// device again like `model_name` does. `None` if the device didn't give it.
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// Returns the serial number of this device as read when it was opened, without asking the
+    /// device again like `serial_number` does. `None` if the device didn't give it (or it
+    /// isn't valid UTF-8).
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Returns (and clears) the latest error of the error stack, if the error means the device
+    /// is gone this device gets poisoned.
     pub(crate) fn latest_error(&self) -> Option<Error> {
-        unsafe {
+        let err = unsafe {
             let list = ffi::LIBMTP_Get_Errorstack(self.inner);
             let err = Error::from_latest_error(list)?;
             ffi::LIBMTP_Clear_Errorstack(self.inner);
-            Some(err)
+            err
+        };
+
+        if err.is_disconnected() {
+            self.poisoned.set(true);
+        }
+
+        Some(err)
+    }
+
+    /// Attaches the identity of this device, the operation and object to the given error.
+    pub(crate) fn with_context(
+        &self,
+        err: Error,
+        operation: &'static str,
+        object_id: Option<u32>,
+    ) -> Error {
+        let err = Error::Context {
+            context: ErrorContext {
+                device: self.identity.to_string(),
+                operation,
+                object_id,
+                path: object_id.and_then(|id| self.cached_path(id)),
+            },
+            source: Box::new(err),
+        };
+
+        self.record(|stats| stats.errors += 1);
+        self.log(JournalEntry {
+            operation,
+            object_id,
+            path: object_id.and_then(|id| self.cached_path(id)),
+            storage_id: None,
+            started: Utc::now(),
+            duration: Duration::from_secs(0),
+            error: Some(err.to_string()),
+        });
+
+        if let Some(metrics) = self.metrics() {
+            metrics.error(operation, &err);
+        }
+
+        err
+    }
+
+    /// Like `latest_error` but the error has context.
+    pub(crate) fn latest_error_in(
+        &self,
+        operation: &'static str,
+        object_id: Option<u32>,
+    ) -> Option<Error> {
+        let err = self.latest_error()?;
+        Some(self.with_context(err, operation, object_id))
+    }
+
+    /// Returns the latest error (or `Error::Unknown` if the error stack is empty) with context.
+    pub(crate) fn error_in(&self, operation: &'static str, object_id: Option<u32>) -> Error {
+        let err = self.latest_error().unwrap_or_default();
+        self.with_context(err, operation, object_id)
+    }
+
+    /// Returns the metrics installed on this device, if any.
+    pub(crate) fn metrics(&self) -> Option<Rc<dyn Metrics>> {
+        self.metrics.borrow().clone()
+    }
+
+    /// Updates the session stats of this device.
+    pub(crate) fn record(&self, update: impl FnOnce(&mut SessionStats)) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Records an operation in the journal of this device, if it's enabled.
+    pub(crate) fn log(&self, entry: JournalEntry) {
+        if let Some(journal) = self.journal.borrow_mut().as_mut() {
+            journal.push(entry);
+        }
+    }
+
+    /// Updates the path cache of this device, if it's enabled.
+    pub(crate) fn update_paths(&self, update: impl FnOnce(&mut PathCache)) {
+        if let Some(cache) = self.path_cache.borrow_mut().as_mut() {
+            update(cache);
+        }
+    }
+
+    /// Returns the cached path of the given object, if any.
+    pub(crate) fn cached_path(&self, id: u32) -> Option<String> {
+        let cache = self.path_cache.borrow();
+        cache.as_ref()?.path(id).map(str::to_string)
+    }
+
+    /// Runs `operation` honoring the retry policy of this device, the error stack is cleared
+    /// before every attempt.
+    pub(crate) fn retry<T>(&self, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        self.retry_policy.get().run(|| {
+            unsafe { ffi::LIBMTP_Clear_Errorstack(self.inner) };
+            operation()
+        })
+    }
+
+    /// Returns the session pointer to be used with `libmtp`, fails with `Error::Disconnected`
+    /// if this device was poisoned, so we never pass a dead session to `libmtp`.
+    pub(crate) fn session(&self) -> Result<*mut ffi::LIBMTP_mtpdevice_t> {
+        if self.poisoned.get() {
+            Err(Error::Disconnected)
+        } else {
+            Ok(self.inner)
+        }
+    }
+
+    /// Fails with `Error::WrongCacheMode` if this device wasn't opened with the `required` mode.
+    pub(crate) fn require_cache_mode(&self, required: CacheMode) -> Result<()> {
+        if self.cache_mode() == required {
+            Ok(())
+        } else {
+            Err(Error::WrongCacheMode { required })
         }
     }
 }
 
+/// Escape hatches to call the functions of `libmtp` this crate doesn't wrap yet, through the
+/// re-exported [`ffi`](../ffi/index.html) bindings.
 impl MtpDevice {
+    /// Returns the `libmtp` device behind this `MtpDevice`.
+    ///
+    /// ## Safety
+    /// The pointer is only valid while this `MtpDevice` lives, it must not be released (with
+    /// `LIBMTP_Release_Device`) nor used once the device is poisoned. Calls that change the
+    /// device bypass this crate, so the storage pool, the path cache and the like may be stale
+    /// afterwards, and their errors stay on the error stack of `libmtp` until `latest_error`
+    /// (or another failing call) reads them.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::ffi;
+    ///
+    /// let exists = unsafe { ffi::LIBMTP_Track_Exists(mtp_device.as_raw(), track_id) != 0 };
+    /// ```
+    pub unsafe fn as_raw(&self) -> *mut ffi::LIBMTP_mtpdevice_t {
+        self.inner
+    }
+
+    /// Returns the raw descriptor this device was opened from, with its USB location and its
+    /// entry of the `libmtp` database.
+    pub fn as_raw_descriptor(&self) -> &ffi::LIBMTP_raw_device_t {
+        &self.raw
+    }
+
+    /// Returns the `PTP_USB` struct `libmtp` keeps for the USB connection (with the `libusb`
+    /// handle and endpoints), its layout is private to `libmtp` and depends on its version and
+    /// USB backend.
+    ///
+    /// ## Safety
+    /// Same as `as_raw`, and the pointer must be cast to the struct of the linked `libmtp`.
+    pub unsafe fn usb_info(&self) -> *mut std::os::raw::c_void {
+        (*self.inner).usbinfo
+    }
+
+    /// Returns the `PTPParams` of the PTP session `libmtp` runs on the device, for the `ptp_*`
+    /// functions of its private `ptp.h`.
+    ///
+    /// ## Safety
+    /// Same as `usb_info`.
+    pub unsafe fn ptp_params(&self) -> *mut std::os::raw::c_void {
+        (*self.inner).params
+    }
+}
+
+impl MtpDevice {
+    /// Check whether this device was poisoned, i.e. some operation failed because the device is
+    /// gone, after this every operation that talks to the device fails with
+    /// `Error::Disconnected` and you should
+    /// [`reconnect`](struct.MtpDevice.html#method.reconnect) the device.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.get()
+    }
+
+    /// Returns the policy used to retry operations that fail with transient errors.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.get()
+    }
+
+    /// Sets the policy used to retry operations that fail with transient errors, check
+    /// [`RetryPolicy`](../util/struct.RetryPolicy.html) to know which operations honor it.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        self.retry_policy.set(policy);
+    }
+
+    /// Installs the hooks that will receive the metrics of this device (bytes transferred,
+    /// operation latency and errors), replacing the previous ones, check
+    /// [`Metrics`](../metrics/trait.Metrics.html) to know what's reported. The metrics are kept
+    /// when refreshing or reconnecting the device.
+    pub fn set_metrics(&self, metrics: impl Metrics + 'static) {
+        self.metrics.replace(Some(Rc::new(metrics)));
+    }
+
+    /// Removes the metrics hooks of this device.
+    pub fn clear_metrics(&self) {
+        self.metrics.replace(None);
+    }
+
+    /// Returns the bytes transferred, operations and errors of this device since it was opened,
+    /// check [`SessionStats`](../metrics/struct.SessionStats.html).
+    pub fn stats(&self) -> SessionStats {
+        self.stats.get()
+    }
+
+    /// Sets the session stats of this device back to zero.
+    pub fn reset_stats(&self) {
+        self.stats.set(SessionStats::default());
+    }
+
+    /// Starts recording the latest `capacity` operations of this device, check
+    /// [`Journal`](../journal/struct.Journal.html). Enabling it again starts a new journal. The
+    /// journal is kept when refreshing or reconnecting the device.
+    ///
+    /// ## Panics
+    /// Panics if `capacity` is zero.
+    pub fn enable_journal(&self, capacity: usize) {
+        self.journal.replace(Some(Journal::new(capacity)));
+    }
+
+    /// Stops recording operations, the journal is dropped.
+    pub fn disable_journal(&self) {
+        self.journal.replace(None);
+    }
+
+    /// Returns a copy of the journal of this device, `None` if it isn't enabled.
+    pub fn journal(&self) -> Option<Journal> {
+        self.journal.borrow().clone()
+    }
+
+    /// Starts remembering the paths of the objects of this device, check
+    /// [`PathCache`](../storage/paths/struct.PathCache.html), once enabled errors and journal
+    /// entries tell the path of their object when it's known. Enabling it again starts with an
+    /// empty cache. The cache is kept when refreshing or reconnecting the device.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// mtp_device.enable_path_cache();
+    /// let file = storage_pool.find_path("/Music/song.mp3")?;
+    ///
+    /// assert_eq!(mtp_device.object_path(file.id()).as_deref(), Some("/Music/song.mp3"));
+    /// ```
+    pub fn enable_path_cache(&self) {
+        self.path_cache.replace(Some(PathCache::new()));
+    }
+
+    /// Stops remembering paths, the cache is dropped.
+    pub fn disable_path_cache(&self) {
+        self.path_cache.replace(None);
+    }
+
+    /// Returns a copy of the path cache of this device, `None` if it isn't enabled.
+    pub fn path_cache(&self) -> Option<PathCache> {
+        self.path_cache.borrow().clone()
+    }
+
+    /// Returns the path of the given object if the path cache knows it, like
+    /// `/DCIM/Camera/IMG_0001.jpg`.
+    pub fn object_path(&self, object: impl AsObjectId) -> Option<String> {
+        self.cached_path(object.as_id())
+    }
+
+    /// Returns the id of the object at `path` of the given storage (`0` for any storage) if
+    /// the path cache knows it.
+    pub fn object_at(&self, storage_id: u32, path: &str) -> Option<u32> {
+        let cache = self.path_cache.borrow();
+        cache.as_ref()?.id(storage_id, path)
+    }
+
+    /// Returns what uploads do when the target folder has an object with the same name, `None`
+    /// (the default) uploads anyway, creating duplicates.
+    pub fn on_conflict(&self) -> Option<OnConflict> {
+        self.on_conflict.get()
+    }
+
+    /// Sets what uploads do when the target folder has an object with the same name, check
+    /// [`OnConflict`](../storage/conflict/enum.OnConflict.html). Every upload lists the target
+    /// folder first (cached devices only check files), `None` skips the check and uploads
+    /// anyway, creating duplicates. The policy is kept when refreshing or reconnecting the
+    /// device.
+    pub fn set_on_conflict(&self, policy: Option<OnConflict>) {
+        self.on_conflict.set(policy);
+    }
+
+    /// Check whether uploads go through a temporary name, see `set_temp_uploads`.
+    pub fn temp_uploads(&self) -> bool {
+        self.temp_uploads.get()
+    }
+
+    /// Makes uploads send the file under a temporary name (`song.mp3` is sent as
+    /// `.song.mp3.part`) and rename it only once the transfer is complete, so an interrupted
+    /// upload doesn't leave a truncated file that the media scanner of the device takes for a
+    /// valid one. What's left of an interrupted upload keeps the temporary name, the
+    /// `OnConflict` policy is checked against the final name. Disabled by default, the setting
+    /// is kept when refreshing or reconnecting the device.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// mtp_device.set_temp_uploads(true);
+    /// storage.send_file_from_path("video.mp4", Parent::Root, metadata)?;
+    /// ```
+    pub fn set_temp_uploads(&self, enabled: bool) {
+        self.temp_uploads.set(enabled);
+    }
+
+    /// Returns how uploads are verified, `None` (the default) doesn't verify them.
+    pub fn verify_uploads(&self) -> Option<Verification> {
+        self.verify_uploads.get()
+    }
+
+    /// Makes uploads from a local path or bytes read the new object back and compare it with
+    /// its source, check [`Verification`](../storage/verify/enum.Verification.html). A mismatch
+    /// fails the upload with `Error::VerificationFailed`, with temporary uploads the object
+    /// then keeps its temporary name. The setting is kept when refreshing or reconnecting the
+    /// device.
+    pub fn set_verify_uploads(&self, verification: Option<Verification>) {
+        self.verify_uploads.set(verification);
+    }
+
+    /// Returns the maximum throughput (in bytes per second) of transfers, if any.
+    pub fn max_throughput(&self) -> Option<u64> {
+        self.max_throughput.get()
+    }
+
+    /// Limits the throughput (in bytes per second) of every transfer made with a handler
+    /// (e.g. `get_file_to_handler`), `None` removes the limit. Transfers from and to paths or
+    /// descriptors aren't limited since `libmtp` does the I/O there, for a limit on a single
+    /// transfer use a [`RateLimiter`](../util/struct.RateLimiter.html) in your handler.
+    ///
+    /// The limit is kept when refreshing or reconnecting the device.
+    ///
+    /// ## Panics
+    /// If the limit is zero.
+    pub fn set_max_throughput(&self, bytes_per_second: Option<u64>) {
+        assert!(
+            bytes_per_second != Some(0),
+            "the throughput must be greater than zero"
+        );
+        self.max_throughput.set(bytes_per_second);
+    }
+
+    /// Returns the handle to abort the transfers of this device, e.g. from a Ctrl-C handler,
+    /// check [`AbortHandle`](../util/struct.AbortHandle.html). The handle is kept when
+    /// refreshing or reconnecting the device.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// Runs `job` on a dedicated thread, the device is moved there (devices can't be shared
+    /// between threads) and it's given back by
+    /// [`TransferHandle::join`](transfer/struct.TransferHandle.html#method.join). The job gets
+    /// the device and a [`ProgressReporter`](transfer/struct.ProgressReporter.html) whose
+    /// reports arrive to the `progress` receiver of the handle, so GUIs stay responsive.
+    ///
+    /// The metrics of the device aren't thread-safe, so they stay behind with the handle: the
+    /// job runs without them and `join` restores them. Metrics set by the job are dropped, as
+    /// are the metrics of a handle dropped without joining.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let transfer = mtp_device.spawn_transfer(move |device, progress| {
+    ///     let storage_pool = device.storage_pool();
+    ///     storage_pool.get_file_to_path_with_callback(file_id, "video.mp4", |sent, total| {
+    ///         progress.report(sent, total)
+    ///     })
+    /// });
+    ///
+    /// for progress in transfer.progress().iter() {
+    ///     println!("{}/{}", progress.sent, progress.total);
+    /// }
+    ///
+    /// let (mtp_device, result) = transfer.join();
+    /// result?;
+    /// ```
+    pub fn spawn_transfer<F, T>(self, job: F) -> TransferHandle<T>
+    where
+        F: FnOnce(&MtpDevice, ProgressReporter) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        transfer::spawn_transfer(self, job)
+    }
+
+    /// Moves the device to a worker thread that runs the commands sent to the returned
+    /// [`DeviceWorker`](worker/struct.DeviceWorker.html), which can be used from any thread.
+    /// The device is given back by
+    /// [`DeviceWorker::shutdown`](worker/struct.DeviceWorker.html#method.shutdown).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let worker = mtp_device.spawn_worker();
+    /// let deleted = worker.delete(file_id);
+    /// deleted.wait()?;
+    /// ```
+    pub fn spawn_worker(self) -> DeviceWorker {
+        DeviceWorker::new(self)
+    }
+
+    /// Returns the caching mode this device was opened with.
+    pub fn cache_mode(&self) -> CacheMode {
+        let cached = unsafe { (*self.inner).cached };
+
+        if cached != 0 {
+            CacheMode::Cached
+        } else {
+            CacheMode::Uncached
+        }
+    }
+
+    /// Check whether this device was opened in cached mode.
+    pub fn is_cached(&self) -> bool {
+        self.cache_mode() == CacheMode::Cached
+    }
+
     /// Retrieves the default music folder, if there isn't one this value may be garbage.
     /// Therefore, it's not recommended to depend on this value, unless you know exactly
     /// how the device you are interacting with handles this setting.
@@ -178,28 +778,42 @@ impl MtpDevice {
 
     /// Gets the friendly name of this device, e.g. "Kevin's Android"
     pub fn get_friendly_name(&self) -> Result<String> {
+        let u8vec = self.raw_friendly_name()?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Returns the friendly name of this device as raw bytes, useful if the device doesn't
+    /// use valid UTF-8.
+    pub fn raw_friendly_name(&self) -> Result<Vec<u8>> {
         unsafe {
-            let friendly_name = ffi::LIBMTP_Get_Friendlyname(self.inner);
+            let friendly_name = ffi::LIBMTP_Get_Friendlyname(self.session()?);
 
             if friendly_name.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Friendlyname", None))
             } else {
                 let u8vec = cstr_to_u8vec!(friendly_name);
                 libc::free(friendly_name as *mut _);
-                Ok(String::from_utf8(u8vec)?)
+                Ok(u8vec)
             }
         }
     }
 
+    /// Returns the friendly name of this device, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`.
+    pub fn friendly_name_lossy(&self) -> Result<String> {
+        let u8vec = self.raw_friendly_name()?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
+    }
+
     /// Sets the friendly name of this device
     pub fn set_friendly_name(&self, name: &str) -> Result<()> {
-        let name = CString::new(name).expect("Nul byte");
+        let name = CString::new(name)?;
 
         unsafe {
-            let res = ffi::LIBMTP_Set_Friendlyname(self.inner, name.as_ptr());
+            let res = ffi::LIBMTP_Set_Friendlyname(self.session()?, name.as_ptr());
 
             if res != 0 {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Set_Friendlyname", None))
             } else {
                 Ok(())
             }
@@ -208,82 +822,185 @@ impl MtpDevice {
 
     /// Retrieves the synchronization partner of this device.
     pub fn get_sync_partner(&self) -> Result<String> {
+        let u8vec = self.raw_sync_partner()?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Returns the synchronization partner of this device as raw bytes, useful if the device
+    /// doesn't use valid UTF-8.
+    pub fn raw_sync_partner(&self) -> Result<Vec<u8>> {
         unsafe {
-            let partner = ffi::LIBMTP_Get_Syncpartner(self.inner);
-            let u8vec = cstr_to_u8vec!(partner);
-            libc::free(partner as *mut _);
-            Ok(String::from_utf8(u8vec)?)
+            let partner = ffi::LIBMTP_Get_Syncpartner(self.session()?);
+
+            if partner.is_null() {
+                Err(self.error_in("LIBMTP_Get_Syncpartner", None))
+            } else {
+                let u8vec = cstr_to_u8vec!(partner);
+                libc::free(partner as *mut _);
+                Ok(u8vec)
+            }
         }
     }
 
+    /// Returns the synchronization partner of this device, replacing invalid UTF-8 sequences
+    /// with `U+FFFD`.
+    pub fn sync_partner_lossy(&self) -> Result<String> {
+        let u8vec = self.raw_sync_partner()?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
+    }
+
     /// Sets the synchronization partner of this device.
     pub fn set_sync_partner(&self, partner: &str) -> Result<()> {
-        let partner = CString::new(partner).expect("Nul byte");
+        let partner = CString::new(partner)?;
 
         unsafe {
-            let res = ffi::LIBMTP_Set_Syncpartner(self.inner, partner.as_ptr());
+            let res = ffi::LIBMTP_Set_Syncpartner(self.session()?, partner.as_ptr());
 
             if res != 0 {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Set_Syncpartner", None))
             } else {
                 Ok(())
             }
         }
     }
 
-    /// Returns the manufacturer name of this device.
+    /// Returns the manufacturer name of this device, asking the device every time, see
+    /// `manufacturer` for the one read when the device was opened.
     pub fn manufacturer_name(&self) -> Result<String> {
+        let u8vec = self.raw_manufacturer_name()?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Returns the manufacturer name of this device as raw bytes, useful if the device doesn't
+    /// use valid UTF-8.
+    pub fn raw_manufacturer_name(&self) -> Result<Vec<u8>> {
         unsafe {
-            let manufacturer = ffi::LIBMTP_Get_Manufacturername(self.inner);
+            let manufacturer = ffi::LIBMTP_Get_Manufacturername(self.session()?);
 
             if manufacturer.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Manufacturername", None))
             } else {
                 let u8vec = cstr_to_u8vec!(manufacturer);
                 libc::free(manufacturer as *mut _);
-                Ok(String::from_utf8(u8vec)?)
+                Ok(u8vec)
             }
         }
     }
 
-    /// Returns the model name of this device.
+    /// Returns the manufacturer name of this device, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`.
+    pub fn manufacturer_name_lossy(&self) -> Result<String> {
+        let u8vec = self.raw_manufacturer_name()?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
+    }
+
+    /// Returns the model name of this device, asking the device every time, see `model` for the
+    /// one read when the device was opened.
     pub fn model_name(&self) -> Result<String> {
+        let u8vec = self.raw_model_name()?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Returns the model name of this device as raw bytes, useful if the device doesn't
+    /// use valid UTF-8.
+    pub fn raw_model_name(&self) -> Result<Vec<u8>> {
         unsafe {
-            let model = ffi::LIBMTP_Get_Modelname(self.inner);
+            let model = ffi::LIBMTP_Get_Modelname(self.session()?);
 
             if model.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Modelname", None))
             } else {
                 let u8vec = cstr_to_u8vec!(model);
                 libc::free(model as *mut _);
-                Ok(String::from_utf8(u8vec)?)
+                Ok(u8vec)
             }
         }
     }
 
-    /// Returns the serial number of this device.
+    /// Returns the model name of this device, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`.
+    pub fn model_name_lossy(&self) -> Result<String> {
+        let u8vec = self.raw_model_name()?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
+    }
+
+    /// Returns the serial number of this device, asking the device every time, see `serial` for
+    /// the one read when the device was opened.
     pub fn serial_number(&self) -> Result<String> {
+        let u8vec = self.raw_serial_number()?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Returns the serial number of this device as raw bytes, useful if the device doesn't
+    /// use valid UTF-8.
+    pub fn raw_serial_number(&self) -> Result<Vec<u8>> {
         unsafe {
-            let serial = ffi::LIBMTP_Get_Serialnumber(self.inner);
+            let serial = ffi::LIBMTP_Get_Serialnumber(self.session()?);
 
             if serial.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Serialnumber", None))
             } else {
                 let u8vec = cstr_to_u8vec!(serial);
                 libc::free(serial as *mut _);
-                Ok(String::from_utf8(u8vec)?)
+                Ok(u8vec)
             }
         }
     }
 
+    /// Returns the serial number of this device, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`.
+    pub fn serial_number_lossy(&self) -> Result<String> {
+        let u8vec = self.raw_serial_number()?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
+    }
+
+    /// Checks that the session with the device is still alive by issuing a cheap read-only
+    /// operation (reading the battery level or the friendly name), returns
+    /// `Error::Disconnected` (and poisons the device) if the device doesn't respond. Devices
+    /// that support neither property can't be checked and are assumed to be alive, errors that
+    /// were already in the error stack are discarded.
+    pub fn ping(&self) -> Result<()> {
+        unsafe {
+            // Only the errors of the probe itself tell whether the device is gone
+            ffi::LIBMTP_Clear_Errorstack(self.inner);
+
+            let mut max_level = 0;
+            let mut current_level = 0;
+
+            let res =
+                ffi::LIBMTP_Get_Batterylevel(self.session()?, &mut max_level, &mut current_level);
+            if res == 0 {
+                return Ok(());
+            } else if self.probe_disconnected() {
+                return Err(Error::Disconnected);
+            }
+
+            let name = ffi::LIBMTP_Get_Friendlyname(self.session()?);
+            if !name.is_null() {
+                libc::free(name as *mut _);
+                return Ok(());
+            } else if self.probe_disconnected() {
+                return Err(Error::Disconnected);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the failed probe of `ping` means the device is gone, other errors (like
+    /// an unsupported property) are discarded. `latest_error` poisons the device if it's gone.
+    fn probe_disconnected(&self) -> bool {
+        self.latest_error().is_some_and(|err| err.is_disconnected())
+    }
+
     /// Returns the device (public key) certificate as an XML document string.
     pub fn device_certificate(&self) -> Result<String> {
         unsafe {
             let mut devcert = std::ptr::null_mut();
-            let res = ffi::LIBMTP_Get_Device_Certificate(self.inner, &mut devcert);
+            let res = ffi::LIBMTP_Get_Device_Certificate(self.session()?, &mut devcert);
 
             if res != 0 || devcert.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Device_Certificate", None))
             } else {
                 let u8vec = cstr_to_u8vec!(devcert);
                 libc::free(devcert as *mut _);
@@ -298,10 +1015,10 @@ impl MtpDevice {
             let mut max_level = 0;
             let mut cur_level = 0;
 
-            let res = ffi::LIBMTP_Get_Batterylevel(self.inner, &mut max_level, &mut cur_level);
+            let res = ffi::LIBMTP_Get_Batterylevel(self.session()?, &mut max_level, &mut cur_level);
 
             if res != 0 {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Batterylevel", None))
             } else {
                 let cur_level = if cur_level == 0 {
                     BatteryLevel::OnExternalPower
@@ -318,10 +1035,10 @@ impl MtpDevice {
     pub fn secure_time(&self) -> Result<String> {
         unsafe {
             let mut secure_time = std::ptr::null_mut();
-            let res = ffi::LIBMTP_Get_Secure_Time(self.inner, &mut secure_time);
+            let res = ffi::LIBMTP_Get_Secure_Time(self.session()?, &mut secure_time);
 
             if res != 0 || secure_time.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Secure_Time", None))
             } else {
                 let u8vec = cstr_to_u8vec!(secure_time);
                 libc::free(secure_time as *mut _);
@@ -337,10 +1054,11 @@ impl MtpDevice {
             let mut filetypes = std::ptr::null_mut();
             let mut len = 0;
 
-            let res = ffi::LIBMTP_Get_Supported_Filetypes(self.inner, &mut filetypes, &mut len);
+            let res =
+                ffi::LIBMTP_Get_Supported_Filetypes(self.session()?, &mut filetypes, &mut len);
 
             if res != 0 || filetypes.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Supported_Filetypes", None))
             } else {
                 let mut filetypes_vec = Vec::with_capacity(len as usize);
                 for i in 0..(len as isize) {
@@ -363,14 +1081,25 @@ impl MtpDevice {
         }
     }
 
+    /// Queries every capability once and returns a snapshot of the supported ones, prefer this
+    /// over many `check_capability` calls if you check capabilities often.
+    pub fn capabilities(&self) -> Capabilities {
+        DeviceCapability::all()
+            .iter()
+            .copied()
+            .filter(|&capability| self.check_capability(capability))
+            .map(Capabilities::from)
+            .collect()
+    }
+
     /// Reset the device only if this one supports the `PTP_OC_ResetDevice` operation code
     /// (`0x1010`)
     pub fn reset_device(&self) -> Result<()> {
         unsafe {
-            let res = ffi::LIBMTP_Reset_Device(self.inner);
+            let res = ffi::LIBMTP_Reset_Device(self.session()?);
 
             if res != 0 {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Reset_Device", None))
             } else {
                 Ok(())
             }
@@ -381,14 +1110,156 @@ impl MtpDevice {
     /// optionally sort the list. This operation may success, partially success
     /// (only ids were retrieved) or fail.
     pub fn update_storage(&mut self, sort_by: StorageSort) -> Result<UpdateResult> {
+        self.storage_sort = sort_by;
+
         unsafe {
-            let res = ffi::LIBMTP_Get_Storage(self.inner, sort_by.to_i32().unwrap());
+            let res = ffi::LIBMTP_Get_Storage(self.session()?, sort_by.to_i32().unwrap());
             match res {
                 0 => Ok(UpdateResult::Success),
                 1 => Ok(UpdateResult::OnlyIds),
-                _ => Err(self.latest_error().unwrap_or_default()),
+                _ => Err(self.error_in("LIBMTP_Get_Storage", None)),
+            }
+        }
+    }
+
+    /// Re-reads the storages (with the latest sorting used in `update_storage`) and rebuilds
+    /// the object listings of this device, useful to see changes made by someone else (or by
+    /// ourselves) since the device was opened.
+    ///
+    /// Uncached devices always perform I/O on listings, so only the storage is updated. Cached
+    /// devices have no way to invalidate the `libmtp` cache, therefore the device is released
+    /// and opened again with the same raw descriptor, this is why this method consumes the
+    /// device, if opening it again fails the device is gone and you'll get the error.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let mtp_device = mtp_device.refresh().expect("Couldn't refresh device");
+    /// let storage_pool = mtp_device.storage_pool();
+    /// ```
+    pub fn refresh(self) -> Result<MtpDevice> {
+        let sort_by = self.storage_sort;
+        let settings = Settings::take(&self);
+
+        let mut device = match self.cache_mode() {
+            CacheMode::Uncached => self,
+            CacheMode::Cached => {
+                let raw = RawDevice {
+                    inner: copy_raw_device(&self.raw),
+                };
+
+                drop(self);
+                raw.open().ok_or_else(|| Error::MtpError {
+                    kind: MtpErrorKind::Connecting,
+                    text: "Failed to open the device again".to_string(),
+                    details: Vec::new(),
+                })?
+            }
+        };
+
+        settings.carry_settings_into(&mut device);
+        device.update_storage(sort_by)?;
+        Ok(device)
+    }
+
+    /// Finds this device again after it was disconnected (e.g. a brief USB dropout), the
+    /// device is released and the connected devices are detected again looking for one with
+    /// the same vendor, product and serial number, which is opened with the same caching mode.
+    /// Devices without a serial number are only found again at the same bus and device number.
+    /// The storage is updated with the latest sorting used in `update_storage`, so storage ids
+    /// are restored (object ids are usually kept by the device).
+    ///
+    /// Use [`Error::is_disconnected`](../error/enum.Error.html#method.is_disconnected) to know
+    /// when reconnecting makes sense.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// match mtp_device.battery_level() {
+    ///     Err(err) if err.is_disconnected() => {
+    ///         mtp_device = mtp_device.reconnect_with_retries(5, Duration::from_secs(1))?;
+    ///     }
+    ///     // ...
+    /// }
+    /// ```
+    pub fn reconnect(self) -> Result<MtpDevice> {
+        self.reconnect_with_retries(0, Duration::from_secs(0))
+    }
+
+    /// Like `reconnect` but retries the detection `retries` times, sleeping `interval` between
+    /// attempts, since devices take a while to show up again after a dropout. Failing to update
+    /// the storage of the device found counts as a failed attempt too.
+    pub fn reconnect_with_retries(self, retries: u32, interval: Duration) -> Result<MtpDevice> {
+        let vendor_id = self.raw.device_entry.vendor_id;
+        let product_id = self.raw.device_entry.product_id;
+        let bus_number = self.raw.bus_location;
+        let dev_number = self.raw.devnum;
+        let serial = self.serial.clone();
+        let cache_mode = self.cache_mode();
+        let sort_by = self.storage_sort;
+        let settings = Settings::take(&self);
+
+        drop(self);
+
+        let mut last_error = None;
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                std::thread::sleep(interval);
+            }
+
+            // Without a serial number nothing tells apart two devices of the same model
+            let candidates = raw::detect_raw_devices()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|raw| {
+                    let entry = raw.device_entry();
+                    entry.vendor_id == vendor_id
+                        && entry.product_id == product_id
+                        && (serial.is_some()
+                            || (raw.bus_number() == bus_number && raw.dev_number() == dev_number))
+                });
+
+            for raw in candidates {
+                let device = match cache_mode {
+                    CacheMode::Cached => raw.open(),
+                    CacheMode::Uncached => raw.open_uncached(),
+                };
+
+                if let Some(mut device) = device {
+                    if serial.is_none() || device.serial == serial {
+                        if let Err(err) = device.update_storage(sort_by) {
+                            last_error = Some(err);
+                            continue;
+                        }
+
+                        settings.carry_settings_into(&mut device);
+                        return Ok(device);
+                    }
+                }
             }
         }
+
+        Err(last_error.unwrap_or_else(|| Error::MtpError {
+            kind: MtpErrorKind::NoDeviceAttached,
+            text: "Couldn't find the device again".to_string(),
+            details: Vec::new(),
+        }))
+    }
+
+    /// Closes the session and releases this device, unlike dropping it this method reports
+    /// failures. `libmtp` doesn't report errors when releasing a device, so the device is
+    /// pinged first (closing the session of a device that doesn't respond fails) and the errors
+    /// that were still pending in the error stack are reported too, note that the device is
+    /// always released, even when an error is returned.
+    pub fn close(self) -> Result<()> {
+        let pending = self.latest_error();
+        let alive = self.ping();
+
+        drop(self);
+
+        alive?;
+        match pending {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 
     /// Returns the inner storage pool, you need to call this if you updated
@@ -401,28 +1272,86 @@ impl MtpDevice {
     }
 
     /// Dumps out a large chunk of textual information provided from the PTP protocol and
-    /// additionally some extra MTP specific information where applicable.
+    /// additionally some extra MTP specific information where applicable, use `report` to get
+    /// this information as a struct.
     pub fn dump_device_info(&self) {
         unsafe {
             ffi::LIBMTP_Dump_Device_Info(self.inner);
         }
     }
 
+    /// Returns the MTP extensions implemented by this device.
+    pub fn extensions(&self) -> Vec<DeviceExtension> {
+        report::extensions(self)
+    }
+
+    /// Gathers the names, version, extensions, filetypes and capabilities of this device into a
+    /// single [`DeviceReport`](report/struct.DeviceReport.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let report = mtp_device.report()?;
+    /// println!("{:?} {:?} ({} filetypes)", report.manufacturer, report.model, report.filetypes.len());
+    /// ```
+    pub fn report(&self) -> Result<DeviceReport> {
+        report::report(self)
+    }
+
     /// Determines wheter a property is supported for a given file type.
     pub fn is_property_supported(&self, property: Property, filetype: Filetype) -> Result<bool> {
         let property = property.to_u32().unwrap();
         let filetype = filetype.to_u32().unwrap();
 
         unsafe {
-            let res = ffi::LIBMTP_Is_Property_Supported(self.inner, property, filetype);
+            let res = ffi::LIBMTP_Is_Property_Supported(self.session()?, property, filetype);
             match res {
                 0 => Ok(false),
                 r if r > 0 => Ok(true),
-                _ => Err(self.latest_error().unwrap_or_default()),
+                _ => Err(self.error_in("LIBMTP_Is_Property_Supported", None)),
             }
         }
     }
 
+    /// Returns the properties the device supports for objects of the given file type, so
+    /// metadata writes can be limited to what the device actually stores.
+    ///
+    /// Note that `libmtp` only answers for one property at a time, so this asks the device
+    /// once per known property. A property the device fails to answer for is left out, this
+    /// only fails if every query failed.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let supported = mtp_device.supported_properties(Filetype::Mp3)?;
+    /// if supported.iter().any(|property| matches!(property, Property::Rating)) {
+    ///     track.set_rating(80)?;
+    /// }
+    /// ```
+    pub fn supported_properties(&self, filetype: Filetype) -> Result<Vec<Property>> {
+        let mut supported = Vec::new();
+        let mut answered = false;
+        let mut last_error = None;
+
+        for property in (0..).map_while(Property::from_u32) {
+            if matches!(property, Property::Unknown) {
+                break;
+            }
+
+            match self.is_property_supported(property, filetype.clone()) {
+                Ok(true) => {
+                    answered = true;
+                    supported.push(property);
+                }
+                Ok(false) => answered = true,
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        match last_error {
+            Some(err) if !answered => Err(err),
+            _ => Ok(supported),
+        }
+    }
+
     /// Retrieves the allowes values (range or enumeration) for an specific property.
     pub fn allowed_property_values(
         &self,
@@ -436,14 +1365,14 @@ impl MtpDevice {
             let allowed_values_ptr = std::ptr::null_mut();
 
             let res = ffi::LIBMTP_Get_Allowed_Property_Values(
-                self.inner,
+                self.session()?,
                 property,
                 filetype,
                 allowed_values_ptr,
             );
 
             if res != 0 || allowed_values_ptr.is_null() {
-                Err(self.latest_error().unwrap_or_default())
+                Err(self.error_in("LIBMTP_Get_Allowed_Property_Values", None))
             } else {
                 let allowed_values =
                     AllowedValues::from_raw(allowed_values_ptr).ok_or(Error::Unknown)?;
@@ -474,10 +1403,10 @@ impl MtpDevice {
     /// repeatedly, the search is `O(n)`and the call may involve slow USB traffic. Instead use
     /// `Storage::files_and_folders` to cache files.
     pub fn search_file(&self, id: impl AsObjectId) -> Result<File<'_>> {
-        let file = unsafe { ffi::LIBMTP_Get_Filemetadata(self.inner, id.as_id()) };
+        let file = unsafe { ffi::LIBMTP_Get_Filemetadata(self.session()?, id.as_id()) };
 
         if file.is_null() {
-            Err(self.latest_error().unwrap_or_default())
+            Err(self.error_in("LIBMTP_Get_Filemetadata", None))
         } else {
             Ok(File {
                 inner: file,
@@ -486,6 +1415,221 @@ impl MtpDevice {
         }
     }
 
+    /// Returns the `count` largest files of the device (across every storage) that pass the
+    /// `filter`, largest first, the `filter` has the signature `(file: &File) -> bool`. Note
+    /// that this lists every file of the device, on uncached devices this walks every folder.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let videos = mtp_device.largest_files(10, |file| file.ftype() == Filetype::Mp4)?;
+    /// for file in videos {
+    ///     println!("{:>12}  {}", file.size(), file.name());
+    /// }
+    /// ```
+    pub fn largest_files<F>(&self, count: usize, mut filter: F) -> Result<Vec<File<'_>>>
+    where
+        F: FnMut(&File<'_>) -> bool,
+    {
+        let mut files: Vec<_> = files::list_files(self, 0, Parent::Root)?
+            .into_iter()
+            .filter(|file| filter(file))
+            .collect();
+
+        files.sort_by_key(|file| std::cmp::Reverse(file.size()));
+        files.truncate(count);
+        Ok(files)
+    }
+
+    /// Returns every file of the device (across every storage) with the given filetype, e.g.
+    /// `Filetype::Flac`, the filetype comes from the object format reported by the device so
+    /// file extensions don't matter. Use `Storage::files_of_type` to search a single storage
+    /// or folder.
+    ///
+    /// Note that this lists every file of the device, on uncached devices this walks every
+    /// folder, cached devices already have the listing in memory.
+    pub fn files_of_type(&self, ftype: Filetype) -> Result<Vec<File<'_>>> {
+        files::files_of_type(self, 0, Parent::Root, ftype)
+    }
+
+    /// Search for a track with the given id in this device, like `search_file` you don't need a
+    /// Storage for this. Don't call this function repeatedly, instead use
+    /// `StoragePool::track_list` to cache tracks.
+    pub fn search_track(&self, id: impl AsObjectId) -> Result<Track<'_>> {
+        get_track(self, id)
+    }
+
+    /// Search for a playlist with the given id in this device, like `search_file` you don't
+    /// need a Storage for this.
+    pub fn search_playlist(&self, id: impl AsObjectId) -> Result<Playlist<'_>> {
+        get_playlist(self, id)
+    }
+
+    /// Search for an album with the given id in this device, like `search_file` you don't
+    /// need a Storage for this.
+    pub fn search_album(&self, id: impl AsObjectId) -> Result<Album<'_>> {
+        get_album(self, id)
+    }
+
+    /// Returns the ids of the objects referenced by the object `id` (the tracks of an album or
+    /// playlist), like
+    /// [`PtpSession::object_references`](../ptp/struct.PtpSession.html#method.object_references).
+    /// `libmtp` only reads the references of albums and playlists, other objects fail with
+    /// `Error::InvalidArgument`.
+    pub fn object_references(&self, id: impl AsObjectId) -> Result<Vec<u32>> {
+        let id = id.as_id();
+        match self.search_file(id)?.ftype() {
+            Filetype::Playlist => Ok(get_playlist(self, id)?.tracks()),
+            Filetype::Album => Ok(get_album(self, id)?.tracks()),
+            ftype => Err(references_unsupported(id, ftype)),
+        }
+    }
+
+    /// Replaces the ids of the objects referenced by the object `id`, like
+    /// [`PtpSession::set_object_references`](../ptp/struct.PtpSession.html#method.set_object_references),
+    /// only for albums and playlists (check `object_references`).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let mut references = mtp_device.object_references(playlist_id)?;
+    /// references.retain(|&id| id != removed_id);
+    /// mtp_device.set_object_references(playlist_id, &references)?;
+    /// ```
+    pub fn set_object_references(&self, id: impl AsObjectId, references: &[u32]) -> Result<()> {
+        let id = id.as_id();
+        match self.search_file(id)?.ftype() {
+            Filetype::Playlist => get_playlist(self, id)?.set_tracks(references),
+            Filetype::Album => get_album(self, id)?.set_tracks(references),
+            ftype => Err(references_unsupported(id, ftype)),
+        }
+    }
+
+    /// Uploads a file named `name` with the contents of `data` into the `parent` folder of the
+    /// storage chosen by `storage` (its id or a `StoragePolicy`). Useful for small generated
+    /// files (like configs, playlists or `.nomedia` markers) that don't exist in the local
+    /// filesystem. Fails with `Error::StorageNotFound` if the device doesn't have that storage
+    /// (anymore).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let recordings = Parent::Folder(recordings_id);
+    /// mtp_device.send_bytes(storage.id(), b"", ".nomedia", recordings, Filetype::Unknown)?;
+    /// ```
+    pub fn send_bytes(
+        &self,
+        storage: impl Into<StoragePolicy>,
+        data: &[u8],
+        name: &str,
+        parent: Parent,
+        file_type: Filetype,
+    ) -> Result<File<'_>> {
+        let storage_id = StorageSpace::new(self).choose(storage.into(), data.len() as u64);
+        files::send_bytes(self, storage_id, data, name, parent, file_type)
+    }
+
+    /// Sends a local track into the default music folder (`Music` in the root of the primary
+    /// storage), the folder is created if the device doesn't have it.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let metadata = TrackMetadata::from_local_file("song.flac")?;
+    /// mtp_device.send_to_music("song.flac", &metadata)?;
+    /// ```
+    pub fn send_to_music(
+        &self,
+        path: impl AsRef<Path>,
+        metadata: &TrackMetadata,
+    ) -> Result<Track<'_>> {
+        media::send_to_music(self, path.as_ref(), metadata)
+    }
+
+    /// Sends a local picture into the default pictures folder (`Pictures` in the root of the
+    /// primary storage), the folder is created if the device doesn't have it. The type of the
+    /// file is guessed from its extension.
+    pub fn send_to_pictures(&self, path: impl AsRef<Path>) -> Result<File<'_>> {
+        media::send_to_media(self, MediaFolder::Pictures, path.as_ref())
+    }
+
+    /// Sends a local video into the default videos folder (`Movies` or `Video` in the root of
+    /// the primary storage), the folder is created if the device doesn't have it. The type of
+    /// the file is guessed from its extension.
+    pub fn send_to_videos(&self, path: impl AsRef<Path>) -> Result<File<'_>> {
+        media::send_to_media(self, MediaFolder::Videos, path.as_ref())
+    }
+
+    /// Returns the storage and id of a default media folder, creating the folder if the device
+    /// doesn't have it, useful to upload other kind of files there.
+    pub fn media_folder(&self, media: MediaFolder) -> Result<(StorageId, Parent)> {
+        let (storage_id, folder_id) = media::media_folder(self, media)?;
+        Ok((StorageId(storage_id), Parent::Folder(folder_id)))
+    }
+
+    /// Uploads many local files, possibly to different storages and folders, reporting the
+    /// overall progress (bytes sent of the total bytes of every job) to `progress`. A failed
+    /// upload doesn't stop the rest, the returned results are in the same order as `jobs`. If
+    /// `progress` cancels, the current upload is cancelled and the remaining jobs fail with a
+    /// `MtpErrorKind::Cancelled` error.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::storage::batch::UploadJob;
+    ///
+    /// let jobs = vec![
+    ///     UploadJob::new("notes.txt", 0, Parent::Root),
+    ///     UploadJob::new("photo.jpg", 0, Parent::Root).file_type(Filetype::Jpeg),
+    /// ];
+    ///
+    /// let results = mtp_device.send_files(jobs, |sent, total| {
+    ///     println!("{}/{}", sent, total);
+    ///     CallbackReturn::Continue
+    /// });
+    /// ```
+    pub fn send_files<C>(&self, jobs: Vec<UploadJob>, progress: C) -> Vec<Result<File<'_>>>
+    where
+        C: FnMut(u64, u64) -> CallbackReturn,
+    {
+        batch::send_files(self, jobs, progress)
+    }
+
+    /// Blocks until the device reports an event (like an object being added) and returns it,
+    /// note that `libmtp` doesn't update its cache with these events, on cached devices you may
+    /// want to `refresh` the device after objects were added or removed.
+    pub fn read_event(&self) -> Result<Event> {
+        let event = events::read_event(self)?;
+        self.update_paths_with(&event);
+        Ok(event)
+    }
+
+    /// Updates the path cache with an event, new objects are looked up to know their names.
+    fn update_paths_with(&self, event: &Event) {
+        if self.path_cache.borrow().is_none() {
+            return;
+        }
+
+        match *event {
+            Event::ObjectAdded(ObjectId(id)) => {
+                // Without the metadata its path can't be known
+                if let Ok(file) = self.search_file(id) {
+                    let (storage_id, parent) = (file.storage_id(), file.parent_id());
+                    self.update_paths(|cache| {
+                        cache.insert_child(id, storage_id, parent, &file.name())
+                    });
+                }
+            }
+            Event::ObjectRemoved(ObjectId(id)) => self.update_paths(|cache| cache.remove(id)),
+            Event::StoreRemoved(StorageId(id)) => {
+                self.update_paths(|cache| cache.remove_storage(id))
+            }
+            _ => {}
+        }
+    }
+
+    /// Watches the changes (objects created and removed) below `folder`, check
+    /// [`FolderWatch`](../watcher/struct.FolderWatch.html), the contents of the folder are
+    /// listed first to know their paths.
+    pub fn watch(&self, folder: Parent) -> Result<FolderWatch<'_>> {
+        FolderWatch::new(self, folder)
+    }
+
     // TODO: Custom operation function (c_variadic nightly feature)
     // pub fn custom_operation(&self, code: u16, params: &[u32]) -> Result<(), ErrorKind>;
 }
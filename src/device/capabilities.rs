@@ -1,11 +1,13 @@
 //! Describes the device capabilities, some devices may not necessarily implement
 //! or support certain capabilities, like copying or moving objects.
 
+use bitflags::bitflags;
 use num_derive::{FromPrimitive, ToPrimitive};
 
 /// Supported `libmtp` device capabilities, you can test if an MTP device supports
 /// one of those with [`MtpDevice::check_capability`](../struct.MtpDevice.html#method.check_capability)
-#[derive(Debug, Clone, FromPrimitive, ToPrimitive)]
+#[derive(Debug, Clone, Copy, FromPrimitive, ToPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum DeviceCapability {
     /// This capability tells whether you can get partial objects.
     GetPartialObject = 0,
@@ -18,3 +20,57 @@ pub enum DeviceCapability {
     /// This capability tells whether you can copy an object.
     CopyObject,
 }
+
+impl DeviceCapability {
+    /// Returns every capability known by `libmtp`.
+    pub fn all() -> [DeviceCapability; 5] {
+        [
+            DeviceCapability::GetPartialObject,
+            DeviceCapability::SendPartialObject,
+            DeviceCapability::EditObjects,
+            DeviceCapability::MoveObject,
+            DeviceCapability::CopyObject,
+        ]
+    }
+}
+
+bitflags! {
+    /// Snapshot of all the capabilities supported by a device, obtained with
+    /// [`MtpDevice::capabilities`](../struct.MtpDevice.html#method.capabilities), this way you can
+    /// check capabilities many times without querying the device.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let capabilities = mtp_device.capabilities();
+    ///
+    /// if capabilities.contains(Capabilities::MOVE_OBJECT | Capabilities::COPY_OBJECT) {
+    ///     // ...
+    /// }
+    /// ```
+    pub struct Capabilities: u32 {
+        const GET_PARTIAL_OBJECT = 1 << 0;
+        const SEND_PARTIAL_OBJECT = 1 << 1;
+        const EDIT_OBJECTS = 1 << 2;
+        const MOVE_OBJECT = 1 << 3;
+        const COPY_OBJECT = 1 << 4;
+    }
+}
+
+impl From<DeviceCapability> for Capabilities {
+    fn from(capability: DeviceCapability) -> Self {
+        match capability {
+            DeviceCapability::GetPartialObject => Capabilities::GET_PARTIAL_OBJECT,
+            DeviceCapability::SendPartialObject => Capabilities::SEND_PARTIAL_OBJECT,
+            DeviceCapability::EditObjects => Capabilities::EDIT_OBJECTS,
+            DeviceCapability::MoveObject => Capabilities::MOVE_OBJECT,
+            DeviceCapability::CopyObject => Capabilities::COPY_OBJECT,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Check whether this snapshot contains the given capability.
+    pub fn supports(&self, capability: DeviceCapability) -> bool {
+        self.contains(capability.into())
+    }
+}
@@ -0,0 +1,149 @@
+//! Contains the events an MTP device may report, like objects being added or removed, these
+//! can be read with [`MtpDevice::read_event`](../struct.MtpDevice.html#method.read_event).
+
+use libmtp_sys as ffi;
+
+use crate::device::MtpDevice;
+use crate::object::ObjectId;
+use crate::ptp::properties::DeviceProperty;
+use crate::storage::StorageId;
+use crate::Result;
+
+macro_rules! event_codes {
+    ($($(#[$meta:meta])* $name:ident = $code:literal,)*) => {
+        /// PTP and MTP events a device may send, as listed in its
+        /// [`DeviceInfo`](../../ptp/struct.DeviceInfo.html), other codes (like vendor-specific
+        /// ones) are kept in `Other`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+        pub enum EventCode {
+            $($(#[$meta])* $name,)*
+            Other(u16),
+        }
+
+        impl EventCode {
+            /// Returns the PTP code of this event.
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(EventCode::$name => $code,)*
+                    EventCode::Other(code) => *code,
+                }
+            }
+
+            /// Returns the event with the given PTP code.
+            pub fn from_code(code: u16) -> Self {
+                match code {
+                    $($code => EventCode::$name,)*
+                    code => EventCode::Other(code),
+                }
+            }
+        }
+    };
+}
+
+event_codes! {
+    CancelTransaction = 0x4001,
+    /// Reported as `Event::ObjectAdded`.
+    ObjectAdded = 0x4002,
+    /// Reported as `Event::ObjectRemoved`.
+    ObjectRemoved = 0x4003,
+    /// Reported as `Event::StoreAdded`.
+    StoreAdded = 0x4004,
+    /// Reported as `Event::StoreRemoved`.
+    StoreRemoved = 0x4005,
+    /// Reported as `Event::DevicePropertyChanged`.
+    DevicePropChanged = 0x4006,
+    ObjectInfoChanged = 0x4007,
+    DeviceInfoChanged = 0x4008,
+    RequestObjectTransfer = 0x4009,
+    StoreFull = 0x400A,
+    DeviceReset = 0x400B,
+    StorageInfoChanged = 0x400C,
+    CaptureComplete = 0x400D,
+    UnreportedStatus = 0x400E,
+    ObjectPropChanged = 0xC801,
+    ObjectPropDescChanged = 0xC802,
+    ObjectReferencesChanged = 0xC803,
+}
+
+/// Events reported by the device, every variant contains the id of the object, storage or
+/// property the event is about, so the affected object can be fetched right away.
+///
+/// ## Example
+/// ```no_run
+/// match mtp_device.read_event()? {
+///     Event::ObjectAdded(id) => {
+///         let file = mtp_device.search_file(id)?;
+///         println!("New file: {}", file.name());
+///     }
+///     Event::DevicePropertyChanged(DeviceProperty::BatteryLevel) => {
+///         let (_, level) = mtp_device.battery_level()?;
+///         println!("Battery: {}", level);
+///     }
+///     _ => {}
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// No event, some devices report this when nothing happened.
+    None,
+    /// A new storage is available (e.g. an SD card was inserted), contains its id.
+    StoreAdded(StorageId),
+    /// A storage was removed, contains its id.
+    StoreRemoved(StorageId),
+    /// A new object was created on the device, contains its id.
+    ObjectAdded(ObjectId),
+    /// An object was removed from the device, contains its id.
+    ObjectRemoved(ObjectId),
+    /// Some device property changed (like the battery level or the friendly name), contains
+    /// the property, unknown codes are kept in `DeviceProperty::Other`.
+    DevicePropertyChanged(DeviceProperty),
+}
+
+impl Event {
+    pub(crate) fn from_raw(event: ffi::LIBMTP_event_t, param: u32) -> Event {
+        match event {
+            ffi::LIBMTP_event_enum_LIBMTP_EVENT_STORE_ADDED => Event::StoreAdded(StorageId(param)),
+            ffi::LIBMTP_event_enum_LIBMTP_EVENT_STORE_REMOVED => {
+                Event::StoreRemoved(StorageId(param))
+            }
+            ffi::LIBMTP_event_enum_LIBMTP_EVENT_OBJECT_ADDED => Event::ObjectAdded(ObjectId(param)),
+            ffi::LIBMTP_event_enum_LIBMTP_EVENT_OBJECT_REMOVED => {
+                Event::ObjectRemoved(ObjectId(param))
+            }
+            ffi::LIBMTP_event_enum_LIBMTP_EVENT_DEVICE_PROPERTY_CHANGED => {
+                Event::DevicePropertyChanged(DeviceProperty::from_code(param as u16))
+            }
+            _ => Event::None,
+        }
+    }
+
+    /// Returns the object this event is about, for `ObjectAdded` and `ObjectRemoved`.
+    pub fn object_id(&self) -> Option<ObjectId> {
+        match *self {
+            Event::ObjectAdded(id) | Event::ObjectRemoved(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Returns the storage this event is about, for `StoreAdded` and `StoreRemoved`.
+    pub fn storage_id(&self) -> Option<StorageId> {
+        match *self {
+            Event::StoreAdded(id) | Event::StoreRemoved(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn read_event(mtpdev: &MtpDevice) -> Result<Event> {
+    let mut event = ffi::LIBMTP_event_enum_LIBMTP_EVENT_NONE;
+    let mut param = 0;
+
+    let res = unsafe { ffi::LIBMTP_Read_Event(mtpdev.session()?, &mut event, &mut param) };
+
+    if res != 0 {
+        Err(mtpdev.error_in("LIBMTP_Read_Event", None))
+    } else {
+        Ok(Event::from_raw(event, param))
+    }
+}
@@ -0,0 +1,128 @@
+//! Contains a readable description of which device is which, for logs and device pickers.
+
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+
+use crate::device::raw::{copy_raw_device, RawDevice};
+use crate::device::MtpDevice;
+
+const LIBMTP_UNKNOWN_DEVICE: &str = "UNKNOWN";
+
+/// Identity of a device: its names, serial number and USB location, the `Display`
+/// implementation gives something like `Google Pixel 7 (serial 1A2B3C, bus 3 dev 7)`, missing
+/// names fall back to the USB vendor and product ids.
+///
+/// Identities are equal (and hash alike) if they have the same USB vendor and product ids and
+/// the same serial number, without serial numbers (like identities of raw devices) the ids
+/// alone decide. The names and the USB location are left out, so the same phone plugged again
+/// into another port is still equal, while another phone of the same model isn't (as long as
+/// the serial numbers are known).
+///
+/// ## Example
+/// ```no_run
+/// for raw in detect_raw_devices()? {
+///     println!("{}", raw.identity());
+/// }
+///
+/// let mtp_device = raw.open_uncached().expect("Couldn't open device");
+/// println!("Using {}", mtp_device.identity());
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceIdentity {
+    /// Manufacturer name, from the device itself or the `libmtp` database.
+    pub manufacturer: Option<String>,
+    /// Model name, from the device itself or the `libmtp` database.
+    pub model: Option<String>,
+    /// Serial number, only known for opened devices.
+    pub serial: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub bus_number: u32,
+    pub dev_number: u8,
+}
+
+impl DeviceIdentity {
+    /// Identity of a raw device, the names come from the `libmtp` database.
+    pub(crate) fn from_raw_device(raw: &RawDevice) -> Self {
+        let entry = raw.device_entry();
+        let known =
+            |name: &str| Some(name.to_string()).filter(|name| name != LIBMTP_UNKNOWN_DEVICE);
+
+        DeviceIdentity {
+            manufacturer: known(entry.vendor),
+            model: known(entry.product),
+            serial: None,
+            vendor_id: entry.vendor_id,
+            product_id: entry.product_id,
+            bus_number: raw.bus_number(),
+            dev_number: raw.dev_number(),
+        }
+    }
+
+    /// Identity of an opened device, the names reported by the device are preferred.
+    pub(crate) fn from_device(mtpdev: &MtpDevice) -> Self {
+        let raw = RawDevice {
+            inner: copy_raw_device(&mtpdev.raw),
+        };
+        let mut identity = DeviceIdentity::from_raw_device(&raw);
+
+        let non_empty = |name: &str| Some(name.trim().to_string()).filter(|name| !name.is_empty());
+        if let Some(manufacturer) = mtpdev.manufacturer().and_then(non_empty) {
+            identity.manufacturer = Some(manufacturer);
+        }
+        if let Some(model) = mtpdev.model().and_then(non_empty) {
+            identity.model = Some(model);
+        }
+        identity.serial = mtpdev.serial.clone();
+
+        identity
+    }
+
+    /// The readable name of the device, like `Google Pixel 7`, without the serial number and
+    /// location.
+    pub fn name(&self) -> String {
+        match (&self.manufacturer, &self.model) {
+            // Some devices already include the manufacturer in the model name
+            (Some(manufacturer), Some(model))
+                if model
+                    .to_lowercase()
+                    .starts_with(&manufacturer.to_lowercase()) =>
+            {
+                model.clone()
+            }
+            (Some(manufacturer), Some(model)) => format!("{} {}", manufacturer, model),
+            (None, Some(name)) | (Some(name), None) => name.clone(),
+            (None, None) => format!("{:04x}:{:04x}", self.vendor_id, self.product_id),
+        }
+    }
+}
+
+impl PartialEq for DeviceIdentity {
+    fn eq(&self, other: &Self) -> bool {
+        self.vendor_id == other.vendor_id
+            && self.product_id == other.product_id
+            && self.serial == other.serial
+    }
+}
+
+impl Eq for DeviceIdentity {}
+
+impl Hash for DeviceIdentity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.vendor_id.hash(state);
+        self.product_id.hash(state);
+        self.serial.hash(state);
+    }
+}
+
+impl Display for DeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.name())?;
+
+        if let Some(serial) = &self.serial {
+            write!(f, "serial {}, ", serial)?;
+        }
+
+        write!(f, "bus {} dev {})", self.bus_number, self.dev_number)
+    }
+}
@@ -0,0 +1,187 @@
+//! Contains a builder to open raw devices with every knob in one place, see
+//! [`DeviceOpenOptions`](struct.DeviceOpenOptions.html).
+
+use std::time::{Duration, Instant};
+
+use crate::device::raw::{detect_raw_devices, RawDevice};
+use crate::device::{CacheMode, MtpDevice, StorageSort};
+use crate::error::{Error, MtpErrorKind};
+use crate::internals::{set_debug, DebugLevel, DeviceFlags};
+use crate::util::RetryPolicy;
+use crate::Result;
+
+/// Interval between attempts to open a device while the open timeout didn't expire.
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Interval between detections while waiting for a device.
+const DETECT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options to open a raw device, like `std::fs::OpenOptions`, by default the device is opened
+/// cached, with the quirks from the `libmtp` database and a single attempt.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::device::options::DeviceOpenOptions;
+/// use libmtp_rs::device::{CacheMode, StorageSort};
+///
+/// let mtp_device = DeviceOpenOptions::new()
+///     .cache_mode(CacheMode::Uncached)
+///     .open_timeout(Duration::from_secs(10))
+///     .fetch_storage(StorageSort::ByFreeSpace)
+///     .open(&raw_device)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceOpenOptions {
+    cache_mode: CacheMode,
+    flags: Option<DeviceFlags>,
+    storage_sort: Option<StorageSort>,
+    debug: Option<DebugLevel>,
+    open_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl Default for DeviceOpenOptions {
+    fn default() -> Self {
+        DeviceOpenOptions {
+            cache_mode: CacheMode::Cached,
+            flags: None,
+            storage_sort: None,
+            debug: None,
+            open_timeout: None,
+            retry_policy: None,
+        }
+    }
+}
+
+impl DeviceOpenOptions {
+    /// Default options.
+    pub fn new() -> Self {
+        DeviceOpenOptions::default()
+    }
+
+    /// Whether `libmtp` caches the object metadata, check
+    /// [`CacheMode`](../enum.CacheMode.html), by default `CacheMode::Cached`.
+    pub fn cache_mode(mut self, mode: CacheMode) -> Self {
+        self.cache_mode = mode;
+        self
+    }
+
+    /// Overrides the quirks `libmtp` applies to the device, by default the ones from its
+    /// database (see [`RawDevice::device_flags`](../raw/struct.RawDevice.html#method.device_flags)).
+    pub fn flags(mut self, flags: DeviceFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Updates the storage with the given sorting right after opening, like calling
+    /// [`MtpDevice::update_storage`](../struct.MtpDevice.html#method.update_storage).
+    pub fn fetch_storage(mut self, sort_by: StorageSort) -> Self {
+        self.storage_sort = Some(sort_by);
+        self
+    }
+
+    /// Sets the debug level of `libmtp` before opening, note that it's a global setting, check
+    /// [`set_debug`](../../internals/fn.set_debug.html).
+    pub fn debug(mut self, level: DebugLevel) -> Self {
+        self.debug = Some(level);
+        self
+    }
+
+    /// Keeps trying to open the device until it succeeds or `timeout` expires, useful right
+    /// after the device is plugged in since many of them need a few seconds before they answer,
+    /// by default only one attempt is made.
+    ///
+    /// Note that `libmtp` doesn't let us change the timeout of single USB transfers.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = Some(timeout);
+        self
+    }
+
+    /// The retry policy of the opened device, check
+    /// [`MtpDevice::set_retry_policy`](../struct.MtpDevice.html#method.set_retry_policy).
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Opens the raw device with these options, fails with `MtpErrorKind::Connecting` if the
+    /// device couldn't be opened (in time).
+    pub fn open(&self, raw: &RawDevice) -> Result<MtpDevice> {
+        if let Some(level) = self.debug {
+            set_debug(level);
+        }
+
+        let mut raw = raw.clone();
+        if let Some(flags) = self.flags {
+            raw.inner.device_entry.device_flags = flags.bits();
+        }
+
+        let deadline = self.open_timeout.map(|timeout| Instant::now() + timeout);
+        let mut device = loop {
+            if let Some(device) = raw.open_with_mode(self.cache_mode) {
+                break device;
+            }
+
+            match deadline {
+                Some(deadline) if Instant::now() + OPEN_RETRY_INTERVAL < deadline => {
+                    std::thread::sleep(OPEN_RETRY_INTERVAL);
+                }
+                _ => {
+                    return Err(Error::MtpError {
+                        kind: MtpErrorKind::Connecting,
+                        text: format!("Couldn't open {}", raw.identity()),
+                        details: Vec::new(),
+                    })
+                }
+            }
+        };
+
+        if let Some(policy) = self.retry_policy {
+            device.set_retry_policy(policy);
+        }
+
+        if let Some(sort_by) = self.storage_sort {
+            device.update_storage(sort_by)?;
+        }
+
+        Ok(device)
+    }
+
+    /// Polls the detection until a device accepted by `filter` appears, then opens it with
+    /// these options, giving it the rest of `timeout` to answer if the open timeout is
+    /// shorter. Fails with `MtpErrorKind::NoDeviceAttached` if no device appeared in time, check
+    /// [`wait_for_device`](../raw/fn.wait_for_device.html).
+    pub fn wait_for_device<F>(&self, timeout: Duration, mut filter: F) -> Result<MtpDevice>
+    where
+        F: FnMut(&RawDevice) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let found = match detect_raw_devices() {
+                Ok(devices) => devices.into_iter().find(|raw| filter(raw)),
+                Err(Error::MtpError {
+                    kind: MtpErrorKind::NoDeviceAttached,
+                    ..
+                }) => None,
+                Err(err) => return Err(err),
+            };
+
+            if let Some(raw) = found {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let open_timeout = self.open_timeout.map_or(remaining, |t| t.max(remaining));
+                return self.clone().open_timeout(open_timeout).open(&raw);
+            }
+
+            if Instant::now() + DETECT_INTERVAL >= deadline {
+                return Err(Error::MtpError {
+                    kind: MtpErrorKind::NoDeviceAttached,
+                    text: format!("No matching device appeared within {:?}", timeout),
+                    details: Vec::new(),
+                });
+            }
+
+            std::thread::sleep(DETECT_INTERVAL);
+        }
+    }
+}
@@ -5,58 +5,193 @@ use libmtp_sys as ffi;
 use std::ffi::CStr;
 use std::fmt::{self, Debug};
 use std::mem::MaybeUninit;
+use std::time::Duration;
 
-use crate::device::MtpDevice;
+use crate::device::identity::DeviceIdentity;
+use crate::device::options::DeviceOpenOptions;
+use crate::device::{CacheMode, MtpDevice};
 use crate::error::{Error, MtpErrorKind};
-use crate::internals::{maybe_init, DeviceEntry};
+use crate::internals::{maybe_init, DeviceEntry, DeviceFlags};
+#[cfg(feature = "rusb")]
+use crate::ptp::{
+    usb::{mtp_interface, UsbTransport},
+    PtpSession,
+};
+use crate::trace;
 use crate::Result;
 
 const LIBMTP_UNKNOWN_DEVICE: &str = "UNKNOWN";
 
 /// This struct handles a raw device, which should be opened with `open` or `open_uncached`
 /// if you want to manage the proper MTP device.
+///
+/// It's plain data, cloning it is cheap and clones open the same device. The `Debug` output
+/// shows the USB ids (as `vendor:product` in hex), the USB location, the vendor and product
+/// names of the `libmtp` entry (`None` for devices `libmtp` doesn't know) and the device flags.
 pub struct RawDevice {
     pub(crate) inner: ffi::LIBMTP_raw_device_struct,
 }
 
+impl Clone for RawDevice {
+    fn clone(&self) -> Self {
+        RawDevice {
+            inner: copy_raw_device(&self.inner),
+        }
+    }
+}
+
 impl Debug for RawDevice {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entry = self.device_entry();
+        let name = self
+            .is_known()
+            .then(|| format!("{} {}", entry.vendor, entry.product));
+
         f.debug_struct("RawDevice")
+            .field(
+                "id",
+                &format_args!("{:04x}:{:04x}", entry.vendor_id, entry.product_id),
+            )
             .field("bus_number", &self.bus_number())
             .field("dev_number", &self.dev_number())
-            .field("device_entry", &self.device_entry())
+            .field("entry", &name)
+            .field("device_flags", &self.device_flags())
             .finish()
     }
 }
 
+/// Copies a raw device descriptor, it's plain data (the device entry strings are static).
+pub(crate) fn copy_raw_device(raw: &ffi::LIBMTP_raw_device_t) -> ffi::LIBMTP_raw_device_t {
+    unsafe {
+        let mut new = MaybeUninit::zeroed().assume_init();
+        std::ptr::copy_nonoverlapping(raw, &mut new, 1);
+        new
+    }
+}
+
 impl RawDevice {
     /// Open an MTP device from this raw device descriptor, this method
     /// may cache devices, thus may be slower.
+    ///
+    /// On Windows the device is detected but fails to open while the WPD driver (the one used
+    /// by the Explorer) owns its MTP interface, `libusb` needs the WinUSB driver bound to it,
+    /// see the _Windows_ section of the README.
     pub fn open(&self) -> Option<MtpDevice> {
-        unsafe {
-            let ptr = &self.inner as *const _;
-            let device = ffi::LIBMTP_Open_Raw_Device(ptr as *mut _);
-
-            if device.is_null() {
-                None
-            } else {
-                Some(MtpDevice { inner: device })
-            }
-        }
+        self.open_with_mode(CacheMode::Cached)
     }
 
     /// Open an MTP device from this raw device descriptor, uncached version.
+    ///
+    /// Check [`open`](#method.open) for the driver requirements on Windows.
     pub fn open_uncached(&self) -> Option<MtpDevice> {
-        unsafe {
+        self.open_with_mode(CacheMode::Uncached)
+    }
+
+    /// Opens this raw device with the given options, check
+    /// [`DeviceOpenOptions`](../options/struct.DeviceOpenOptions.html).
+    pub fn open_with(&self, options: &DeviceOpenOptions) -> Result<MtpDevice> {
+        options.open(self)
+    }
+
+    pub(crate) fn open_with_mode(&self, mode: CacheMode) -> Option<MtpDevice> {
+        let cached = mode == CacheMode::Cached;
+
+        trace::open(self.bus_number(), self.dev_number(), cached, || unsafe {
             let ptr = &self.inner as *const _;
-            let device = ffi::LIBMTP_Open_Raw_Device_Uncached(ptr as *mut _);
+            let device = if cached {
+                ffi::LIBMTP_Open_Raw_Device(ptr as *mut _)
+            } else {
+                ffi::LIBMTP_Open_Raw_Device_Uncached(ptr as *mut _)
+            };
 
             if device.is_null() {
                 None
             } else {
-                Some(MtpDevice { inner: device })
+                Some(MtpDevice::from_raw(device, copy_raw_device(&self.inner)))
             }
+        })
+    }
+
+    /// Opens a low-level PTP session on this device with the pure-Rust implementation instead
+    /// of `libmtp`, useful when `libmtp` misbehaves with it. The session doesn't give an
+    /// `MtpDevice`, it has its own API, check the [`ptp`](../../ptp/index.html) module.
+    #[cfg(feature = "rusb")]
+    pub fn open_ptp(&self) -> Result<PtpSession<UsbTransport>> {
+        let transport = UsbTransport::open(self.bus_number() as u8, self.dev_number())?;
+        PtpSession::open(transport)
+    }
+
+    /// Creates a raw device descriptor for the USB device at the given location, this is useful
+    /// to open devices that aren't in the `libmtp` database (like brand-new phones), the
+    /// `flags` are the quirks `libmtp` will apply to the device, if unsure use
+    /// `DeviceFlags::NONE`.
+    ///
+    /// Note that `libmtp` only checks that there's a USB device at this location when opening
+    /// it, opening a device that doesn't speak MTP will fail.
+    pub fn from_location(
+        bus_number: u32,
+        dev_number: u8,
+        vendor_id: u16,
+        product_id: u16,
+        flags: DeviceFlags,
+    ) -> RawDevice {
+        let mut inner: ffi::LIBMTP_raw_device_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        inner.bus_location = bus_number;
+        inner.devnum = dev_number;
+        inner.device_entry.vendor_id = vendor_id;
+        inner.device_entry.product_id = product_id;
+        inner.device_entry.device_flags = flags.bits();
+
+        RawDevice { inner }
+    }
+
+    /// Creates a raw device descriptor for a USB device found with `rusb` (requires the `rusb`
+    /// feature), so apps already enumerating the bus don't have to detect devices again. The
+    /// quirks and names come from the `libmtp` database if the device is listed, fails with
+    /// `Error::InvalidArgument` if the device has no MTP (or PTP) interface.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// for device in rusb::devices()?.iter() {
+    ///     if let Ok(raw) = RawDevice::from_rusb(&device) {
+    ///         println!("{}", raw.identity());
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "rusb")]
+    pub fn from_rusb<T: rusb::UsbContext>(device: &rusb::Device<T>) -> Result<RawDevice> {
+        let bus_number = device.bus_number() as u32;
+        let dev_number = device.address();
+
+        if mtp_interface(device)?.is_none() {
+            return Err(Error::InvalidArgument {
+                reason: format!(
+                    "Device at bus {}, device {} has no MTP interface",
+                    bus_number, dev_number
+                ),
+            });
         }
+
+        let descriptor = device.device_descriptor()?;
+        let (vendor_id, product_id) = (descriptor.vendor_id(), descriptor.product_id());
+        let mut raw = RawDevice::from_location(
+            bus_number,
+            dev_number,
+            vendor_id,
+            product_id,
+            DeviceFlags::NONE,
+        );
+
+        if let Some(entry) = database_entry(vendor_id, product_id) {
+            raw.inner.device_entry = entry;
+        }
+
+        Ok(raw)
+    }
+
+    /// Whether this device is listed in the `libmtp` database.
+    pub fn is_known(&self) -> bool {
+        !self.inner.device_entry.vendor.is_null()
     }
 
     /// Returns the bus number of this raw device.
@@ -69,6 +204,16 @@ impl RawDevice {
         self.inner.devnum
     }
 
+    /// Returns the USB vendor id of this raw device.
+    pub fn vendor_id(&self) -> u16 {
+        self.inner.device_entry.vendor_id
+    }
+
+    /// Returns the USB product id of this raw device.
+    pub fn product_id(&self) -> u16 {
+        self.inner.device_entry.product_id
+    }
+
     /// Returns the device entry of this raw device.
     pub fn device_entry(&self) -> DeviceEntry {
         let vendor = unsafe {
@@ -94,6 +239,38 @@ impl RawDevice {
             device_flags: self.inner.device_entry.device_flags,
         }
     }
+
+    /// Returns the identity of this raw device, the names come from the `libmtp` database
+    /// and the serial number is unknown until the device is opened.
+    pub fn identity(&self) -> DeviceIdentity {
+        DeviceIdentity::from_raw_device(self)
+    }
+
+    /// Returns the quirks `libmtp` applies to this raw device.
+    pub fn device_flags(&self) -> DeviceFlags {
+        DeviceFlags::from_bits_truncate(self.inner.device_entry.device_flags)
+    }
+}
+
+/// Returns the entry of the `libmtp` database with the given ids, if any.
+#[cfg(feature = "rusb")]
+fn database_entry(vendor_id: u16, product_id: u16) -> Option<ffi::LIBMTP_device_entry_t> {
+    maybe_init();
+
+    unsafe {
+        let mut entries = std::ptr::null_mut();
+        let mut len = 0;
+
+        if ffi::LIBMTP_Get_Supported_Devices_List(&mut entries, &mut len) != 0 {
+            return None;
+        }
+
+        // The entries are static plain data, copying them is fine
+        (0..len as isize)
+            .map(|offset| &*entries.offset(offset))
+            .find(|entry| entry.vendor_id == vendor_id && entry.product_id == product_id)
+            .map(|entry| std::ptr::read(entry))
+    }
 }
 
 /// Detect the raw device descriptors, you will use this function whenever you want
@@ -126,13 +303,12 @@ pub fn detect_raw_devices() -> Result<Vec<RawDevice>> {
             Err(Error::MtpError {
                 kind,
                 text: "Failed to detect raw devices".to_string(),
+                details: Vec::new(),
             })
         } else {
             let mut devices_vec = Vec::with_capacity(len as usize);
             for i in 0..(len as isize) {
-                let mut new = MaybeUninit::zeroed().assume_init();
-
-                std::ptr::copy_nonoverlapping(devices.offset(i), &mut new, 1);
+                let new = copy_raw_device(&*devices.offset(i));
                 devices_vec.push(RawDevice { inner: new });
             }
 
@@ -142,6 +318,170 @@ pub fn detect_raw_devices() -> Result<Vec<RawDevice>> {
     }
 }
 
+/// Like `detect_raw_devices` but only returns the devices accepted by `filter`, so
+/// applications targeting a family of devices never open unrelated ones. Fails with
+/// `MtpErrorKind::NoDeviceAttached` if no device is accepted.
+///
+/// Note that `libmtp` still enumerates (and probes the unknown) USB devices of the bus, the
+/// filter runs on the detected descriptors.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::device::raw::detect_raw_devices_filtered;
+///
+/// // Only Sony devices
+/// let raw_devices = detect_raw_devices_filtered(|raw| raw.vendor_id() == 0x054c)?;
+/// ```
+pub fn detect_raw_devices_filtered<F>(mut filter: F) -> Result<Vec<RawDevice>>
+where
+    F: FnMut(&RawDevice) -> bool,
+{
+    let mut devices = detect_raw_devices()?;
+    devices.retain(|raw| filter(raw));
+
+    if devices.is_empty() {
+        Err(Error::MtpError {
+            kind: MtpErrorKind::NoDeviceAttached,
+            text: "No matching raw device attached".to_string(),
+            details: Vec::new(),
+        })
+    } else {
+        Ok(devices)
+    }
+}
+
+/// Waits until a device accepted by `filter` is plugged in (or unlocked) and returns it opened
+/// (cached), for "plug in your phone now" flows. The detection is polled every half second,
+/// fails with `MtpErrorKind::NoDeviceAttached` if no device appeared within `timeout`, use
+/// [`DeviceOpenOptions::wait_for_device`](../options/struct.DeviceOpenOptions.html#method.wait_for_device)
+/// to open it with other options.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::device::raw::wait_for_device;
+///
+/// println!("Plug in your phone now");
+/// let mtp_device = wait_for_device(Duration::from_secs(60), |_| true)?;
+/// println!("Found {}", mtp_device.identity());
+/// ```
+pub fn wait_for_device<F>(timeout: Duration, filter: F) -> Result<MtpDevice>
+where
+    F: FnMut(&RawDevice) -> bool,
+{
+    DeviceOpenOptions::new().wait_for_device(timeout, filter)
+}
+
+/// Like `detect_raw_devices` but also returns the USB devices that expose an MTP or PTP
+/// interface even if `libmtp` didn't recognize them (it only probes the Microsoft OS
+/// descriptor of unknown devices), so devices missing from its database can be opened anyway.
+/// Check [`RawDevice::is_known`](struct.RawDevice.html#method.is_known) to tell them apart.
+///
+/// Currently the extra probing is only implemented on Linux (through `sysfs`), on other
+/// platforms this is the same as `detect_raw_devices`.
+pub fn probe_raw_devices() -> Result<Vec<RawDevice>> {
+    let mut devices = match detect_raw_devices() {
+        Ok(devices) => devices,
+        Err(Error::MtpError {
+            kind: MtpErrorKind::NoDeviceAttached,
+            ..
+        }) => Vec::new(),
+        Err(err) => return Err(err),
+    };
+
+    for probed in probe_usb_interfaces() {
+        let already_detected = devices.iter().any(|device| {
+            device.bus_number() == probed.bus_number() && device.dev_number() == probed.dev_number()
+        });
+
+        if !already_detected {
+            devices.push(probed);
+        }
+    }
+
+    Ok(devices)
+}
+
+#[cfg(target_os = "linux")]
+fn probe_usb_interfaces() -> Vec<RawDevice> {
+    use std::fs;
+    use std::path::Path;
+
+    const USB_CLASS_PTP: u8 = 0x06;
+
+    fn read_attr(path: &Path, attr: &str) -> Option<String> {
+        fs::read_to_string(path.join(attr))
+            .ok()
+            .map(|value| value.trim().to_string())
+    }
+
+    fn read_hex<T: num_traits::Num>(path: &Path, attr: &str) -> Option<T> {
+        T::from_str_radix(&read_attr(path, attr)?, 16).ok()
+    }
+
+    let entries = match fs::read_dir("/sys/bus/usb/devices") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    let mut devices = Vec::new();
+
+    // Device entries look like `1-2` and their interfaces like `1-2:1.0`
+    for device in paths.iter().filter(|path| path.join("busnum").exists()) {
+        let name = match device.file_name().and_then(|name| name.to_str()) {
+            Some(name) => format!("{}:", name),
+            None => continue,
+        };
+
+        let has_mtp_interface = paths
+            .iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|interface| interface.to_str())
+                    .map(|interface| interface.starts_with(&name))
+                    .unwrap_or(false)
+            })
+            .any(|interface| {
+                read_hex::<u8>(interface, "bInterfaceClass") == Some(USB_CLASS_PTP)
+                    || read_attr(interface, "interface")
+                        .map(|desc| desc.contains("MTP"))
+                        .unwrap_or(false)
+            });
+
+        if !has_mtp_interface {
+            continue;
+        }
+
+        let location = (|| {
+            let bus_number = read_attr(device, "busnum")?.parse().ok()?;
+            let dev_number = read_attr(device, "devnum")?.parse().ok()?;
+            let vendor_id = read_hex(device, "idVendor")?;
+            let product_id = read_hex(device, "idProduct")?;
+            Some((bus_number, dev_number, vendor_id, product_id))
+        })();
+
+        if let Some((bus_number, dev_number, vendor_id, product_id)) = location {
+            devices.push(RawDevice::from_location(
+                bus_number,
+                dev_number,
+                vendor_id,
+                product_id,
+                DeviceFlags::NONE,
+            ));
+        }
+    }
+
+    devices
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_usb_interfaces() -> Vec<RawDevice> {
+    Vec::new()
+}
+
 /// Check if a specific device, given its bus and device number, has an
 /// MTP type device descriptor.
 pub fn check_specific_device(bus_number: u32, dev_number: u32) -> bool {
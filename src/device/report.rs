@@ -0,0 +1,115 @@
+//! Contains a structured report of everything a device claims to support, see
+//! [`MtpDevice::report`](../struct.MtpDevice.html#method.report). With the `serde` feature the
+//! report implements `Serialize`, so it can be attached to bug reports as JSON.
+
+use libmtp_sys as ffi;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::device::capabilities::DeviceCapability;
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::Result;
+
+/// An MTP extension implemented by the device, e.g. `android.com` 1.0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceExtension {
+    pub name: String,
+    pub major: i32,
+    pub minor: i32,
+}
+
+/// Everything a device claims to support, the structured counterpart of
+/// [`MtpDevice::dump_device_info`](../struct.MtpDevice.html#method.dump_device_info).
+///
+/// It's built from the public API of `libmtp` only, which doesn't expose the PTP `DeviceInfo`
+/// dataset, so the supported operations, events and device properties aren't part of it: get
+/// them with [`PtpSession::device_info`](../../ptp/struct.PtpSession.html#method.device_info).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceReport {
+    /// `None` if the device didn't give it when it was opened.
+    pub manufacturer: Option<String>,
+    /// `None` if the device didn't give it when it was opened.
+    pub model: Option<String>,
+    /// `None` if the device doesn't give it.
+    pub device_version: Option<String>,
+    /// `None` if the device didn't give it when it was opened.
+    pub serial_number: Option<String>,
+    /// `None` if the device doesn't have a friendly name.
+    pub friendly_name: Option<String>,
+    pub extensions: Vec<DeviceExtension>,
+    /// Filetypes the device supports and `libmtp` can handle.
+    pub filetypes: Vec<Filetype>,
+    pub capabilities: Vec<DeviceCapability>,
+}
+
+unsafe fn lossy_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+fn device_version(mtpdev: &MtpDevice) -> Result<Option<String>> {
+    unsafe {
+        let version = ffi::LIBMTP_Get_Deviceversion(mtpdev.session()?);
+
+        if version.is_null() {
+            // Clears the error stack, poisoning the device if it's gone
+            match mtpdev.latest_error() {
+                Some(err) if err.is_disconnected() => Err(err),
+                _ => Ok(None),
+            }
+        } else {
+            let string = lossy_string(version);
+            libc::free(version as *mut _);
+            Ok(Some(string))
+        }
+    }
+}
+
+pub(crate) fn extensions(mtpdev: &MtpDevice) -> Vec<DeviceExtension> {
+    let mut extensions = Vec::new();
+
+    unsafe {
+        let mut extension = (*mtpdev.inner).extensions;
+        while !extension.is_null() {
+            extensions.push(DeviceExtension {
+                name: lossy_string((*extension).name),
+                major: (*extension).major,
+                minor: (*extension).minor,
+            });
+
+            extension = (*extension).next;
+        }
+    }
+
+    extensions
+}
+
+pub(crate) fn report(mtpdev: &MtpDevice) -> Result<DeviceReport> {
+    let friendly_name = mtpdev
+        .friendly_name_lossy()
+        .ok()
+        .filter(|name| !name.is_empty());
+
+    let capabilities = DeviceCapability::all()
+        .iter()
+        .copied()
+        .filter(|&capability| mtpdev.check_capability(capability))
+        .collect();
+
+    Ok(DeviceReport {
+        manufacturer: mtpdev.manufacturer().map(str::to_owned),
+        model: mtpdev.model().map(str::to_owned),
+        device_version: device_version(mtpdev)?,
+        serial_number: mtpdev.serial().map(str::to_owned),
+        friendly_name,
+        extensions: extensions(mtpdev),
+        filetypes: mtpdev.supported_filetypes()?,
+        capabilities,
+    })
+}
@@ -0,0 +1,141 @@
+//! Contains `MtpDeviceSync`, a device that can be shared between threads, see
+//! [`MtpDeviceSync`](struct.MtpDeviceSync.html).
+
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use crate::device::identity::DeviceIdentity;
+use crate::device::transfer::SendDevice;
+use crate::device::MtpDevice;
+use crate::util::AbortHandle;
+
+/// A device shared between threads, clones are handles to the same device. `MtpDevice` can't
+/// be shared because `libmtp` isn't thread-safe, here the device is behind a mutex so only one
+/// operation talks to it at a time, every other caller waits for its turn.
+///
+/// Operations run in a closure given the `&MtpDevice`, objects borrowing it (like files or the
+/// storage pool) can't leave the closure. The identity and the abort handle don't need the
+/// lock, so a transfer in flight can be cancelled from another thread.
+///
+/// Note that the metrics of the device can't be used, they're dropped when the device is
+/// shared and at the end of every closure (they're not thread-safe).
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::device::shared::MtpDeviceSync;
+///
+/// let shared = MtpDeviceSync::new(mtp_device);
+/// let worker = shared.clone();
+///
+/// std::thread::spawn(move || {
+///     worker.with(|device| device.storage_pool().get_file_to_path(file_id, "video.mp4"))
+/// });
+///
+/// let battery = shared.with(|device| device.battery_level())?;
+/// ```
+#[derive(Clone)]
+pub struct MtpDeviceSync {
+    device: Arc<Mutex<SendDevice>>,
+    identity: DeviceIdentity,
+    abort: AbortHandle,
+}
+
+impl Debug for MtpDeviceSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MtpDeviceSync")
+            .field("identity", &self.identity)
+            .field("handles", &Arc::strong_count(&self.device))
+            .finish()
+    }
+}
+
+impl From<MtpDevice> for MtpDeviceSync {
+    fn from(device: MtpDevice) -> Self {
+        MtpDeviceSync::new(device)
+    }
+}
+
+impl MtpDeviceSync {
+    /// Shares `device`, its metrics are dropped.
+    pub fn new(device: MtpDevice) -> Self {
+        let identity = device.identity().clone();
+        let abort = device.abort_handle();
+        let (device, _) = SendDevice::new(device);
+
+        MtpDeviceSync {
+            device: Arc::new(Mutex::new(device)),
+            identity,
+            abort,
+        }
+    }
+
+    fn run<R>(guard: MutexGuard<'_, SendDevice>, f: impl FnOnce(&MtpDevice) -> R) -> R {
+        // Metrics set by the closure must not reach another thread, even if it panics
+        struct DropMetrics<'g>(MutexGuard<'g, SendDevice>);
+
+        impl Drop for DropMetrics<'_> {
+            fn drop(&mut self) {
+                self.0.get().metrics.take();
+            }
+        }
+
+        let guard = DropMetrics(guard);
+        f(guard.0.get())
+    }
+
+    /// Runs `f` with the device once no other thread is using it.
+    pub fn with<R>(&self, f: impl FnOnce(&MtpDevice) -> R) -> R {
+        // A panic in another closure doesn't leave the device in a broken state
+        let guard = self
+            .device
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        Self::run(guard, f)
+    }
+
+    /// Like `with` but returns `None` right away if another thread is using the device.
+    pub fn try_with<R>(&self, f: impl FnOnce(&MtpDevice) -> R) -> Option<R> {
+        let guard = match self.device.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+
+        Some(Self::run(guard, f))
+    }
+
+    /// Returns the identity of the device, without waiting for the lock.
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.identity
+    }
+
+    /// Returns the abort handle of the device, without waiting for the lock, check
+    /// [`MtpDevice::abort_handle`](../struct.MtpDevice.html#method.abort_handle).
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// Returns the device back if this is the last handle, otherwise this handle.
+    pub fn into_inner(self) -> Result<MtpDevice, Self> {
+        let MtpDeviceSync {
+            device,
+            identity,
+            abort,
+        } = self;
+
+        match Arc::try_unwrap(device) {
+            Ok(device) => {
+                let device = device
+                    .into_inner()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                Ok(device.into_inner())
+            }
+            Err(device) => Err(MtpDeviceSync {
+                device,
+                identity,
+                abort,
+            }),
+        }
+    }
+}
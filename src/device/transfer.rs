@@ -0,0 +1,147 @@
+//! Contains a worker to run transfers in the background, devices can't be shared between
+//! threads so the device is moved to a dedicated thread and given back once the transfer ends,
+//! see [`MtpDevice::spawn_transfer`](../struct.MtpDevice.html#method.spawn_transfer).
+
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::device::MtpDevice;
+use crate::metrics::Metrics;
+use crate::util::{AbortHandle, CallbackReturn};
+use crate::Result;
+
+/// Progress of a background transfer, as reported to the progress callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// Bytes transferred so far.
+    pub sent: u64,
+    /// Total bytes of the transfer.
+    pub total: u64,
+}
+
+/// Forwards the progress of a background transfer to its
+/// [`TransferHandle`](struct.TransferHandle.html), given to the job run by the worker.
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    sender: Sender<TransferProgress>,
+}
+
+impl ProgressReporter {
+    /// Reports the progress, it has the signature of progress callbacks so it can be used
+    /// right away as `|sent, total| progress.report(sent, total)`, cancellations are handled
+    /// with [`TransferHandle::cancel`](struct.TransferHandle.html#method.cancel).
+    pub fn report(&self, sent: u64, total: u64) -> CallbackReturn {
+        // Nobody listening isn't a reason to stop the transfer
+        let _ = self.sender.send(TransferProgress { sent, total });
+        CallbackReturn::Continue
+    }
+}
+
+/// A device that can be moved to other threads, used by the worker thread, `MtpDeviceSync`
+/// (and the uniffi bindings).
+pub(crate) struct SendDevice(MtpDevice);
+
+// SAFETY: the device is owned (so nothing borrows it) and its metrics, the only state shared
+// with other handles (an `Rc`), are taken out by `SendDevice::new`, the `libmtp` device itself
+// isn't tied to the thread that opened it.
+unsafe impl Send for SendDevice {}
+
+impl SendDevice {
+    /// Wraps the device, its metrics are taken out and returned to be restored later.
+    pub(crate) fn new(device: MtpDevice) -> (Self, Option<Rc<dyn Metrics>>) {
+        let metrics = device.metrics.take();
+        (SendDevice(device), metrics)
+    }
+
+    pub(crate) fn get(&self) -> &MtpDevice {
+        &self.0
+    }
+
+    pub(crate) fn into_inner(self) -> MtpDevice {
+        self.0
+    }
+}
+
+/// Handle of a transfer running in the background, created with
+/// [`MtpDevice::spawn_transfer`](../struct.MtpDevice.html#method.spawn_transfer).
+pub struct TransferHandle<T> {
+    thread: JoinHandle<(SendDevice, Result<T>)>,
+    progress: Receiver<TransferProgress>,
+    abort: AbortHandle,
+    metrics: Option<Rc<dyn Metrics>>,
+}
+
+impl<T> Debug for TransferHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransferHandle")
+            .field("finished", &self.is_finished())
+            .field("aborted", &self.abort.is_aborted())
+            .finish()
+    }
+}
+
+impl<T> TransferHandle<T> {
+    /// Returns the receiver of the progress reported by the job, use `try_recv` or
+    /// `try_iter` to poll it from an event loop.
+    pub fn progress(&self) -> &Receiver<TransferProgress> {
+        &self.progress
+    }
+
+    /// Cancels the transfer, it's aborted through the
+    /// [`AbortHandle`](../../util/struct.AbortHandle.html) of the device, which is reset again
+    /// when joining.
+    pub fn cancel(&self) {
+        self.abort.abort();
+    }
+
+    /// Check whether the job is over, i.e. `join` won't block.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the job to finish, returns the device back together with the result of the
+    /// job. The metrics the device had before spawning the transfer are restored.
+    ///
+    /// ## Panics
+    /// If the job panicked, panics of progress callbacks are already turned into errors.
+    pub fn join(self) -> (MtpDevice, Result<T>) {
+        let (SendDevice(device), result) = self
+            .thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+
+        device.metrics.replace(self.metrics);
+        device.abort.reset();
+        (device, result)
+    }
+}
+
+/// Moves `device` to a new thread running `job`, its metrics are kept by the handle until
+/// `join`.
+pub(crate) fn spawn_transfer<F, T>(device: MtpDevice, job: F) -> TransferHandle<T>
+where
+    F: FnOnce(&MtpDevice, ProgressReporter) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let abort = device.abort_handle();
+    let (sender, progress) = mpsc::channel();
+
+    let (device, metrics) = SendDevice::new(device);
+    let thread = thread::spawn(move || {
+        let device = device;
+        let result = job(device.get(), ProgressReporter { sender });
+
+        // Metrics set by the job must not reach another thread
+        device.get().metrics.take();
+        (device, result)
+    });
+
+    TransferHandle {
+        thread,
+        progress,
+        abort,
+        metrics,
+    }
+}
@@ -0,0 +1,270 @@
+//! Contains `DeviceWorker`, a thread that owns a device and runs the commands sent to it, see
+//! [`MtpDevice::spawn_worker`](../struct.MtpDevice.html#method.spawn_worker).
+
+use std::fmt::{self, Debug};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::device::identity::DeviceIdentity;
+use crate::device::transfer::SendDevice;
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::Object;
+use crate::storage::batch::{self, UploadJob};
+use crate::storage::files::{self, FileInfo};
+use crate::storage::{self, Parent};
+use crate::util::{AbortHandle, CallbackReturn};
+use crate::Result;
+
+type Job = Box<dyn FnOnce(&MtpDevice) + Send>;
+
+/// Commands run by the worker thread, each one with the sender of its reply.
+enum Command {
+    List {
+        storage_id: u32,
+        parent: Parent,
+        reply: Sender<Result<Vec<FileInfo>>>,
+    },
+    Get {
+        id: u32,
+        path: PathBuf,
+        reply: Sender<Result<()>>,
+    },
+    Send {
+        job: UploadJob,
+        reply: Sender<Result<FileInfo>>,
+    },
+    Delete {
+        id: u32,
+        reply: Sender<Result<()>>,
+    },
+    Run(Job),
+}
+
+/// Reply of a command sent to a [`DeviceWorker`](struct.DeviceWorker.html), it arrives once
+/// the worker ran the command (commands run in the order they were sent).
+#[derive(Debug)]
+pub struct Pending<T> {
+    receiver: Receiver<Result<T>>,
+}
+
+impl<T> Pending<T> {
+    fn new() -> (Sender<Result<T>>, Self) {
+        let (sender, receiver) = mpsc::channel();
+        (sender, Pending { receiver })
+    }
+
+    /// Blocks until the command is done and returns its result, fails with
+    /// `Error::WorkerStopped` if the worker stopped before running it.
+    pub fn wait(self) -> Result<T> {
+        self.receiver.recv().unwrap_or(Err(Error::WorkerStopped))
+    }
+
+    /// Returns the result if the command is done, or `None` right away, so it can be polled
+    /// from an event loop. Fails with `Error::WorkerStopped` if the worker stopped before
+    /// running it, once a result is returned the following calls return that error too.
+    pub fn try_wait(&self) -> Option<Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(Error::WorkerStopped)),
+        }
+    }
+}
+
+/// A thread that owns a device and runs the commands sent to it one after another, given the
+/// device can't leave the thread it's used on this is a ready-made way to use it from GUIs or
+/// async applications. The worker can be moved and shared between threads, every command
+/// returns a [`Pending`](struct.Pending.html) reply right away.
+///
+/// The worker stops once it's dropped or `shutdown`, after the commands already sent. Note
+/// that the metrics of the device can't be used, they're dropped when the worker is spawned
+/// (they're not thread-safe).
+///
+/// ## Example
+/// ```no_run
+/// let worker = mtp_device.spawn_worker();
+///
+/// let listing = worker.list(storage_id, Parent::Root);
+/// let download = worker.get(file_id, "video.mp4");
+///
+/// for file in listing.wait()? {
+///     println!("{} ({} bytes)", file.name, file.size);
+/// }
+/// download.wait()?;
+///
+/// let mtp_device = worker.shutdown();
+/// ```
+pub struct DeviceWorker {
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<SendDevice>>,
+    identity: DeviceIdentity,
+    abort: AbortHandle,
+}
+
+impl Debug for DeviceWorker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceWorker")
+            .field("identity", &self.identity)
+            .field("stopped", &self.is_stopped())
+            .finish()
+    }
+}
+
+impl Drop for DeviceWorker {
+    fn drop(&mut self) {
+        // A panic of a command can't be resumed while dropping, it's ignored
+        self.commands.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(device: &MtpDevice, command: Command) {
+    // A reply nobody waits for anymore is dropped
+    match command {
+        Command::List {
+            storage_id,
+            parent,
+            reply,
+        } => {
+            let result = storage::files_and_folders(device, storage_id, parent)
+                .map(|files| files.iter().map(|file| file.info()).collect());
+            let _ = reply.send(result);
+        }
+        Command::Get { id, path, reply } => {
+            let _ = reply.send(files::get_file_to_path(device, id, path));
+        }
+        Command::Send { job, reply } => {
+            let result = batch::send_files(device, vec![job], |_, _| CallbackReturn::Continue)
+                .pop()
+                .expect("One result per job")
+                .map(|file| file.info());
+            let _ = reply.send(result);
+        }
+        Command::Delete { id, reply } => {
+            let _ = reply.send(device.dummy_object(id).delete());
+        }
+        Command::Run(job) => job(device),
+    }
+}
+
+impl DeviceWorker {
+    /// Moves `device` to a new worker thread, its metrics are dropped.
+    pub fn new(device: MtpDevice) -> Self {
+        let identity = device.identity().clone();
+        let abort = device.abort_handle();
+        let (device, _) = SendDevice::new(device);
+        let (commands, receiver) = mpsc::channel::<Command>();
+
+        let thread = thread::spawn(move || {
+            let device = device;
+            for command in receiver {
+                run(device.get(), command);
+            }
+
+            // Metrics set by `run` closures must not reach another thread
+            device.get().metrics.take();
+            device
+        });
+
+        DeviceWorker {
+            commands: Some(commands),
+            thread: Some(thread),
+            identity,
+            abort,
+        }
+    }
+
+    fn send<T>(&self, command: impl FnOnce(Sender<Result<T>>) -> Command) -> Pending<T> {
+        let (reply, pending) = Pending::new();
+        if let Some(commands) = &self.commands {
+            // If the worker stopped the reply is dropped, `Pending` reports it
+            let _ = commands.send(command(reply));
+        }
+
+        pending
+    }
+
+    /// Lists the files and folders of the `parent` folder in a storage, like
+    /// [`Storage::files_and_folders`](../../storage/struct.Storage.html#method.files_and_folders).
+    pub fn list(&self, storage_id: u32, parent: Parent) -> Pending<Vec<FileInfo>> {
+        self.send(|reply| Command::List {
+            storage_id,
+            parent,
+            reply,
+        })
+    }
+
+    /// Downloads the file `id` to a local `path`.
+    pub fn get(&self, id: u32, path: impl Into<PathBuf>) -> Pending<()> {
+        let path = path.into();
+        self.send(|reply| Command::Get { id, path, reply })
+    }
+
+    /// Uploads a local file as described by `job`, like
+    /// [`MtpDevice::send_files`](../struct.MtpDevice.html#method.send_files) with a single job.
+    pub fn send_file(&self, job: UploadJob) -> Pending<FileInfo> {
+        self.send(|reply| Command::Send { job, reply })
+    }
+
+    /// Deletes the object `id`.
+    pub fn delete(&self, id: u32) -> Pending<()> {
+        self.send(|reply| Command::Delete { id, reply })
+    }
+
+    /// Runs `f` with the device on the worker thread, for everything the other commands don't
+    /// cover. Objects borrowing the device can't leave the closure.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let battery = worker.run(|device| device.battery_level()).wait()?;
+    /// ```
+    pub fn run<F, T>(&self, f: F) -> Pending<T>
+    where
+        F: FnOnce(&MtpDevice) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.send(|reply| {
+            Command::Run(Box::new(move |device| {
+                let _ = reply.send(f(device));
+            }))
+        })
+    }
+
+    /// Returns the identity of the device.
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.identity
+    }
+
+    /// Returns the abort handle of the device, aborting cancels the transfer the worker is
+    /// running, check
+    /// [`MtpDevice::abort_handle`](../struct.MtpDevice.html#method.abort_handle).
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// Check whether the worker thread stopped, i.e. a command panicked.
+    pub fn is_stopped(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Waits for the commands already sent and gives the device back.
+    ///
+    /// ## Panics
+    /// If a command panicked, the panic is resumed.
+    pub fn shutdown(mut self) -> MtpDevice {
+        self.commands.take();
+        let thread = self.thread.take().expect("The worker is only joined once");
+
+        thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            .into_inner()
+    }
+}
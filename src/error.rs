@@ -1,9 +1,15 @@
 //! This module contains information about possible errors, such as internal and `libmtp` errors.
 
 use libmtp_sys as ffi;
+use std::ffi::NulError;
+use std::fmt::{self, Display};
+use std::io;
 use std::string::FromUtf8Error;
 use thiserror::Error as ErrorTrait;
 
+use crate::device::CacheMode;
+use crate::storage::verify::Mismatch;
+
 /// Enumeration of possible `libmtp` errors, check
 /// [`Error::MtpError`](enum.Error.html#variant.MtpError) for more information.
 #[derive(Debug, Clone, Copy)]
@@ -39,6 +45,107 @@ impl MtpErrorKind {
     }
 }
 
+macro_rules! ptp_responses {
+    ($($(#[$meta:meta])* $name:ident = $code:literal,)*) => {
+        /// PTP (and MTP) response codes a device may answer an operation with, check
+        /// [`Error::PtpResponse`](enum.Error.html#variant.PtpResponse).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum PtpResponse {
+            $($(#[$meta])* $name,)*
+            /// Some other (reserved or vendor-specific) response code.
+            Other(u16),
+        }
+
+        impl PtpResponse {
+            /// Returns the PTP code of this response.
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(PtpResponse::$name => $code,)*
+                    PtpResponse::Other(code) => *code,
+                }
+            }
+
+            /// Returns the response with the given PTP code.
+            pub fn from_code(code: u16) -> Self {
+                match code {
+                    $($code => PtpResponse::$name,)*
+                    code => PtpResponse::Other(code),
+                }
+            }
+        }
+    };
+}
+
+ptp_responses! {
+    GeneralError = 0x2002,
+    SessionNotOpen = 0x2003,
+    InvalidTransactionId = 0x2004,
+    OperationNotSupported = 0x2005,
+    ParameterNotSupported = 0x2006,
+    IncompleteTransfer = 0x2007,
+    InvalidStorageId = 0x2008,
+    InvalidObjectHandle = 0x2009,
+    DevicePropNotSupported = 0x200A,
+    InvalidObjectFormatCode = 0x200B,
+    /// The storage is full.
+    StoreFull = 0x200C,
+    /// The object can't be modified nor deleted, see
+    /// [`ProtectionStatus`](../object/enum.ProtectionStatus.html).
+    ObjectWriteProtected = 0x200D,
+    StoreReadOnly = 0x200E,
+    AccessDenied = 0x200F,
+    NoThumbnailPresent = 0x2010,
+    PartialDeletion = 0x2012,
+    StoreNotAvailable = 0x2013,
+    SpecificationByFormatUnsupported = 0x2014,
+    NoValidObjectInfo = 0x2015,
+    /// The device is busy, the operation may succeed later.
+    DeviceBusy = 0x2019,
+    InvalidParentObject = 0x201A,
+    InvalidDevicePropFormat = 0x201B,
+    InvalidDevicePropValue = 0x201C,
+    InvalidParameter = 0x201D,
+    SessionAlreadyOpen = 0x201E,
+    TransactionCancelled = 0x201F,
+    InvalidObjectPropCode = 0xA801,
+    InvalidObjectPropFormat = 0xA802,
+    InvalidObjectPropValue = 0xA803,
+    InvalidObjectReference = 0xA804,
+    InvalidDataset = 0xA806,
+    ObjectTooLarge = 0xA809,
+    ObjectPropNotSupported = 0xA80A,
+}
+
+/// Context attached to the errors returned by operations on a device, this way logs of
+/// applications handling many devices tell which device failed at what.
+#[derive(Debug, Clone)]
+pub struct ErrorContext {
+    /// Identity of the device, its name, serial number and location (e.g.
+    /// `Google Pixel 4a (serial 0A1B2C3D, bus 3 dev 7)`).
+    pub device: String,
+    /// The `libmtp` operation that failed (e.g. `LIBMTP_Send_File_From_File`).
+    pub operation: &'static str,
+    /// The object the operation was working with, if known.
+    pub object_id: Option<u32>,
+    /// Path of the object, if the
+    /// [path cache](../device/struct.MtpDevice.html#method.enable_path_cache) knows it.
+    pub path: Option<String>,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed on {}", self.operation, self.device)?;
+
+        match (self.object_id, &self.path) {
+            (Some(id), Some(path)) => write!(f, " (object {}, {})", id, path)?,
+            (Some(id), None) => write!(f, " (object {})", id)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
 /// Main Error type, containing a possible *unknown* error, an specific `libmtp` error
 /// and some other internal errors like invalid UTF-8 in string conversion.
 #[derive(Debug, Clone, ErrorTrait)]
@@ -48,13 +155,130 @@ pub enum Error {
     Unknown,
 
     /// Specific `libmtp` error, contains the kind of the error and extra information
-    /// about what went wrong.
-    #[error("Internal libmtp ({kind:?}): {text}")]
-    MtpError { kind: MtpErrorKind, text: String },
+    /// about what went wrong, `details` contains every entry of the `libmtp` error stack
+    /// (oldest first) when the error was raised, which usually tells the real reason.
+    #[error("Internal libmtp ({kind:?}): {text}{}", details_suffix(.details))]
+    MtpError {
+        kind: MtpErrorKind,
+        text: String,
+        details: Vec<String>,
+    },
+
+    /// The device answered an operation with a PTP response code other than OK, like
+    /// `PtpResponse::AccessDenied` or `PtpResponse::StoreFull`, this way you can branch on the
+    /// reason (e.g. skip read-only files during a cleanup). Errors of the PTP layer that don't
+    /// come from a response (like timeouts) are still an `MtpError`.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::error::{Error, PtpResponse};
+    ///
+    /// match file.delete() {
+    ///     Err(err) if err.ptp_response() == Some(PtpResponse::ObjectWriteProtected) => {
+    ///         println!("Skipping read-only {}", file.name());
+    ///     }
+    ///     result => result?,
+    /// }
+    /// ```
+    #[error("PTP response {response:?} ({code:04x}): {text}{}", details_suffix(.details), code = .response.code())]
+    PtpResponse {
+        response: PtpResponse,
+        text: String,
+        details: Vec<String>,
+    },
 
     /// Internal error when converting strings with invalid UTF-8 encoding.
     #[error("Utf8 error ({source})")]
     Utf8Error { source: FromUtf8Error },
+
+    /// A string (or path) given to an operation contains a nul byte, which can't be passed to
+    /// `libmtp`, contains the position of the nul byte.
+    #[error("Interior nul byte at position {position}")]
+    NulError { position: usize },
+
+    /// The operation is only available on devices opened with another caching mode, check
+    /// [`CacheMode`](../device/enum.CacheMode.html) for more information.
+    #[error("Operation only available on {required:?} devices")]
+    WrongCacheMode { required: CacheMode },
+
+    /// Error when dealing with local files, contains the kind of the I/O error and its
+    /// description.
+    #[error("I/O error ({kind:?}): {text}")]
+    IoError { kind: io::ErrorKind, text: String },
+
+    /// Error when reading the metadata (like audio tags) of a local file.
+    #[error("Couldn't read metadata: {text}")]
+    MetadataError { text: String },
+
+    /// The device is gone (disconnected or not responding), check
+    /// [`MtpDevice::reconnect`](../device/struct.MtpDevice.html#method.reconnect).
+    #[error("The device is disconnected or not responding")]
+    Disconnected,
+
+    /// An error returned by some operation on a device along with its context, use
+    /// [`without_context`](enum.Error.html#method.without_context) to get the underlying error.
+    #[error("{context}: {source}")]
+    Context {
+        context: ErrorContext,
+        source: Box<Error>,
+    },
+
+    /// Some argument given to an operation isn't valid, contains the reason.
+    #[error("Invalid argument: {reason}")]
+    InvalidArgument { reason: String },
+
+    /// The storage given to an upload isn't on the device, it may have been removed (like an
+    /// SD card), update the storage to know the current ones.
+    #[error("Storage {storage_id:#x} isn't on the device, it may have been removed")]
+    StorageNotFound { storage_id: u32 },
+
+    /// An upload doesn't fit in the storage, either checked before sending anything or
+    /// because the device rejected it, contains the size of the object and the free space of
+    /// the storage as last known (update the storage after freeing space), so at least
+    /// `required - available` bytes have to be deleted.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// match storage.send_file_from_path("video.mp4", Parent::Root, metadata) {
+    ///     Err(Error::StorageFull { required, available, .. }) => {
+    ///         println!("Delete {} more bytes", required - available);
+    ///     }
+    ///     result => { result?; }
+    /// }
+    /// ```
+    #[error("Storage {storage_id:#x} is full, {required} bytes needed but {available} free")]
+    StorageFull {
+        storage_id: u32,
+        required: u64,
+        available: u64,
+    },
+
+    /// There's no object at the given path of the device, see
+    /// [`storage::paths`](../storage/paths/index.html) for the syntax of paths.
+    #[error("{path} not found on the device")]
+    PathNotFound { path: String },
+
+    /// An object with the same name exists in the folder of an upload and the
+    /// [`OnConflict`](../storage/conflict/enum.OnConflict.html) policy is `Error`, contains
+    /// the name and the id of the existing object.
+    #[error("{name} already exists on the device (object {id})")]
+    ObjectExists { name: String, id: u32 },
+
+    /// An upload read back from the device doesn't match its source, see
+    /// [`Verification`](../storage/verify/enum.Verification.html), contains the id of the
+    /// uploaded object (which is kept) and how it differs.
+    #[error("Object {id} doesn't match its source after the upload, {mismatch}")]
+    VerificationFailed { id: u32, mismatch: Mismatch },
+
+    /// A callback or handler given to an operation panicked, the panic can't unwind through
+    /// `libmtp` so it was caught, the operation cancelled and the panic message kept here.
+    #[error("A callback panicked: {message}")]
+    CallbackPanicked { message: String },
+
+    /// The [`DeviceWorker`](../device/worker/struct.DeviceWorker.html) stopped before running
+    /// a command, because a previous command panicked.
+    #[error("The device worker stopped before running the command")]
+    WorkerStopped,
 }
 
 impl Default for Error {
@@ -64,22 +288,157 @@ impl Default for Error {
 }
 
 impl Error {
+    /// Returns the context of this error (device, operation and object), if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error without its context.
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.without_context(),
+            err => err,
+        }
+    }
+
+    /// Check whether this error means the device is gone (disconnected or not responding), in
+    /// this case you may want to
+    /// [`reconnect`](../device/struct.MtpDevice.html#method.reconnect) the device.
+    pub fn is_disconnected(&self) -> bool {
+        match self.without_context() {
+            Error::Disconnected
+            | Error::MtpError {
+                kind:
+                    MtpErrorKind::Connecting | MtpErrorKind::NoDeviceAttached | MtpErrorKind::UsbLayer,
+                ..
+            } => true,
+            // The PTP layer reports I/O failures and a missing device with its own codes
+            Error::MtpError {
+                kind: MtpErrorKind::PtpLayer,
+                text,
+                ..
+            } => matches!(
+                parse_ptp_code(text),
+                Some(PTP_ERROR_IO | PTP_ERROR_NODEVICE)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Returns the PTP error code of this error if it comes from the PTP layer (e.g. `0x2019`
+    /// when the device is busy).
+    pub fn ptp_code(&self) -> Option<u16> {
+        match self.without_context() {
+            Error::PtpResponse { response, .. } => Some(response.code()),
+            Error::MtpError {
+                kind: MtpErrorKind::PtpLayer,
+                text,
+                ..
+            } => parse_ptp_code(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the response code the device answered with, if this error is an
+    /// `Error::PtpResponse`.
+    pub fn ptp_response(&self) -> Option<PtpResponse> {
+        match self.without_context() {
+            Error::PtpResponse { response, .. } => Some(*response),
+            _ => None,
+        }
+    }
+
+    /// Builds the error for a PTP layer failure, an `Error::PtpResponse` if the text holds a
+    /// response code, an `Error::MtpError` otherwise.
+    pub(crate) fn ptp_layer(text: String, details: Vec<String>) -> Self {
+        match parse_ptp_code(&text) {
+            Some(code) if is_response_code(code) => Error::PtpResponse {
+                response: PtpResponse::from_code(code),
+                text,
+                details,
+            },
+            _ => Error::MtpError {
+                kind: MtpErrorKind::PtpLayer,
+                text,
+                details,
+            },
+        }
+    }
+
+    /// Check whether this error is transient, i.e. the operation may succeed if you try again,
+    /// like PTP timeouts and busy devices.
+    pub fn is_transient(&self) -> bool {
+        const PTP_RC_DEVICE_BUSY: u16 = 0x2019;
+        const PTP_ERROR_TIMEOUT: u16 = 0x02FA;
+
+        matches!(
+            self.ptp_code(),
+            Some(PTP_RC_DEVICE_BUSY | PTP_ERROR_TIMEOUT)
+        )
+    }
+
+    /// Builds an error from the whole error stack, the kind and text come from the latest
+    /// entry and every entry is kept in `details`.
     pub(crate) unsafe fn from_latest_error(mut list: *const ffi::LIBMTP_error_t) -> Option<Self> {
-        if list.is_null() {
-            None
-        } else {
-            while !(*list).next.is_null() {
-                list = (*list).next;
-            }
+        let mut latest = None;
+        let mut details = Vec::new();
 
+        while !list.is_null() {
             let error_t = &*list;
 
-            let kind = MtpErrorKind::from_error_number(error_t.errornumber)?;
-            let u8vec = cstr_to_u8vec!(error_t.error_text);
-            let text = String::from_utf8_lossy(&u8vec).into_owned();
+            let text = if error_t.error_text.is_null() {
+                String::new()
+            } else {
+                let u8vec = cstr_to_u8vec!(error_t.error_text);
+                String::from_utf8_lossy(&u8vec).into_owned()
+            };
 
-            Some(Error::MtpError { kind, text })
+            latest = Some((error_t.errornumber, text.clone()));
+            details.push(text);
+            list = error_t.next;
         }
+
+        let (errornumber, text) = latest?;
+        let kind = MtpErrorKind::from_error_number(errornumber)?;
+
+        if let MtpErrorKind::PtpLayer = kind {
+            return Some(Error::ptp_layer(text, details));
+        }
+
+        Some(Error::MtpError {
+            kind,
+            text,
+            details,
+        })
+    }
+}
+
+/// Code of the PTP layer errors on I/O failures (`PTP_ERROR_IO`).
+const PTP_ERROR_IO: u16 = 0x02FF;
+
+/// Code of the PTP layer errors when the device is gone (`PTP_ERROR_NODEVICE`).
+const PTP_ERROR_NODEVICE: u16 = 0x02F9;
+
+/// Parses the code of PTP layer errors, their text looks like `Error 2009: ...`.
+fn parse_ptp_code(text: &str) -> Option<u16> {
+    let code = text.strip_prefix("Error ")?.get(..4)?;
+    u16::from_str_radix(code, 16).ok()
+}
+
+/// Check whether the code is a PTP or MTP response code, other codes of the PTP layer are
+/// internal to `libmtp` (like `0x02FA` for timeouts).
+fn is_response_code(code: u16) -> bool {
+    matches!(code, 0x2000..=0x2FFF | 0xA800..=0xAFFF)
+}
+
+/// Formats the error stack entries that aren't the main text of an `Error::MtpError`.
+fn details_suffix(details: &[String]) -> String {
+    match details.split_last() {
+        Some((_, previous)) if !previous.is_empty() => format!(" ({})", previous.join("; ")),
+        _ => String::new(),
     }
 }
 
@@ -88,3 +447,42 @@ impl From<FromUtf8Error> for Error {
         Error::Utf8Error { source }
     }
 }
+
+impl From<NulError> for Error {
+    fn from(err: NulError) -> Self {
+        Error::NulError {
+            position: err.nul_position(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::IoError {
+            kind: err.kind(),
+            text: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ptp_layer(text: &str) -> Error {
+        Error::MtpError {
+            kind: MtpErrorKind::PtpLayer,
+            text: text.to_string(),
+            details: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn ptp_layer_disconnections() {
+        assert!(ptp_layer("Error 02ff: PTP I/O Error").is_disconnected());
+        assert!(ptp_layer("Error 02f9: PTP No Device").is_disconnected());
+        assert!(!ptp_layer("Error 02fa: PTP Timeout").is_disconnected());
+        assert!(!ptp_layer("Error 2019: Device Busy").is_disconnected());
+        assert!(!ptp_layer("Unknown error").is_disconnected());
+    }
+}
@@ -68,6 +68,62 @@ pub struct DeviceEntry {
     pub device_flags: u32,
 }
 
+bitflags! {
+    /// Bitflags of the quirks (workarounds for bugs) `libmtp` applies to a device, as defined
+    /// in [`device-flags.h`](https://github.com/libmtp/libmtp/blob/master/src/device-flags.h). The
+    /// `Debug` output lists the name of every flag set, which is handy for bug reports.
+    ///
+    /// ## Example
+    /// ```
+    /// use libmtp_rs::internals::DeviceFlags;
+    ///
+    /// let flags = DeviceFlags::UNLOAD_DRIVER | DeviceFlags::LONG_TIMEOUT;
+    /// assert!(flags.contains(DeviceFlags::LONG_TIMEOUT));
+    /// ```
+    pub struct DeviceFlags: u32 {
+        const NONE = 0x00000000;
+        const BROKEN_MTPGETOBJPROPLIST_ALL = 0x00000001;
+        const UNLOAD_DRIVER = 0x00000002;
+        const BROKEN_MTPGETOBJPROPLIST = 0x00000004;
+        const NO_ZERO_READS = 0x00000008;
+        const IRIVER_OGG_ALZHEIMER = 0x00000010;
+        const ONLY_7BIT_FILENAMES = 0x00000020;
+        const NO_RELEASE_INTERFACE = 0x00000040;
+        const IGNORE_HEADER_ERRORS = 0x00000080;
+        const BROKEN_SET_OBJECT_PROPLIST = 0x00000100;
+        const OGG_IS_UNKNOWN = 0x00000200;
+        const BROKEN_SET_SAMPLE_DIMENSIONS = 0x00000400;
+        const ALWAYS_PROBE_DESCRIPTOR = 0x00000800;
+        const PLAYLIST_SPL_V1 = 0x00001000;
+        const PLAYLIST_SPL_V2 = 0x00002000;
+        const CANNOT_HANDLE_DATEMODIFIED = 0x00004000;
+        const BROKEN_SEND_OBJECT_PROPLIST = 0x00008000;
+        const BROKEN_BATTERY_LEVEL = 0x00010000;
+        const DELETE_SENDS_EVENT = 0x00020000;
+        const CAPTURE = 0x00040000;
+        const CAPTURE_PREVIEW = 0x00080000;
+        const NIKON_BROKEN_CAPTURE = 0x00100000;
+        const NIKON_1 = 0x00200000;
+        const NO_CAPTURE_COMPLETE = 0x00400000;
+        const OLYMPUS_XML_WRAPPED = 0x00800000;
+        const FLAC_IS_UNKNOWN = 0x01000000;
+        const UNIQUE_FILENAMES = 0x02000000;
+        const SWITCH_MODE_BLACKBERRY = 0x04000000;
+        const LONG_TIMEOUT = 0x08000000;
+        const FORCE_RESET_ON_CLOSE = 0x10000000;
+        const DONT_CLOSE_SESSION = 0x20000000;
+        const PROPLIST_OVERRIDES_OI = 0x40000000;
+        const SAMSUNG_OFFSET_BUG = 0x80000000;
+    }
+}
+
+impl DeviceEntry {
+    /// Returns the device flags of this entry as bitflags.
+    pub fn flags(&self) -> DeviceFlags {
+        DeviceFlags::from_bits_truncate(self.device_flags)
+    }
+}
+
 /// Retrieves the devices `libmtp` claims to support as stated in
 /// [`music-players.h`](https://github.com/libmtp/libmtp/blob/master/src/music-players.h).
 pub fn get_supported_devices() -> Result<Vec<DeviceEntry>> {
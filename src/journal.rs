@@ -0,0 +1,136 @@
+//! Contains an in-memory journal of the latest operations made on a device, meant to be dumped
+//! when something fails so bug reports tell what happened before, enable it with
+//! [`MtpDevice::enable_journal`](../device/struct.MtpDevice.html#method.enable_journal).
+//!
+//! Listings and transfers are recorded (the same operations reported to the
+//! [`Metrics`](../metrics/trait.Metrics.html) of the device), together with every failed
+//! `libmtp` call.
+
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// An operation recorded in the journal.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    /// Name of the operation, e.g. `"download"` or `"LIBMTP_Get_File_To_File"` for failed
+    /// `libmtp` calls.
+    pub operation: &'static str,
+    pub object_id: Option<u32>,
+    /// Path of the object, if the path cache of the device knows it.
+    pub path: Option<String>,
+    pub storage_id: Option<u32>,
+    /// When the operation started.
+    pub started: DateTime<Utc>,
+    /// How long the operation took, zero for failed `libmtp` calls.
+    pub duration: Duration,
+    /// The error of the operation, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({} ms)",
+            self.started.format("%Y-%m-%d %H:%M:%S%.3f"),
+            self.operation,
+            self.duration.as_millis()
+        )?;
+
+        if let Some(storage_id) = self.storage_id {
+            write!(f, " storage {}", storage_id)?;
+        }
+
+        if let Some(object_id) = self.object_id {
+            write!(f, " object {}", object_id)?;
+        }
+
+        if let Some(path) = &self.path {
+            write!(f, " {}", path)?;
+        }
+
+        match &self.error {
+            Some(error) => write!(f, ": {}", error),
+            None => write!(f, ": ok"),
+        }
+    }
+}
+
+/// Ring buffer with the latest operations of a device, once it's full the oldest ones are
+/// dropped. Its `Display` implementation prints an operation per line, oldest first.
+///
+/// ## Example
+/// ```no_run
+/// mtp_device.enable_journal(200);
+///
+/// if let Err(err) = sync.run() {
+///     eprintln!("Sync failed: {}", err);
+///     if let Some(journal) = mtp_device.journal() {
+///         eprintln!("{}", journal);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Journal {
+    capacity: usize,
+    entries: VecDeque<JournalEntry>,
+}
+
+impl Journal {
+    /// Creates an empty journal keeping the latest `capacity` operations.
+    ///
+    /// ## Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "The capacity must be greater than zero");
+
+        Journal {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Records an operation, dropping the oldest one if the journal is full.
+    pub fn push(&mut self, entry: JournalEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the recorded operations, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the maximum number of operations kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forgets every recorded operation.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Display for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
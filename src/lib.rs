@@ -13,6 +13,26 @@
 //! - [`device`](device/index.html): Gather/set properties and obtain storage.
 //! - [`storage`](storage/index.html): Send/get objects (files, tracks, etc) and manage storage.
 //! - [`object`](object/index.html): Copying, moving and deleting objects.
+//! - [`watcher`](watcher/index.html): Download new files as soon as they're added.
+//! - [`ptp`](ptp/index.html): Low-level pure-Rust PTP sessions, a fallback for devices where
+//!   `libmtp` misbehaves.
+//!
+//! Every object handle ([`File`](storage/files/struct.File.html),
+//! [`Folder`](storage/folders/struct.Folder.html), [`Track`](storage/tracks/struct.Track.html),
+//! etc) borrows the `MtpDevice` it came from, so the compiler rejects any use of a handle after
+//! its device is dropped (and thus closed):
+//!
+//! ```compile_fail
+//! use libmtp_rs::device::raw::detect_raw_devices;
+//!
+//! let raw_devices = detect_raw_devices().unwrap();
+//! let file = {
+//!     let mtp_device = raw_devices[0].open_uncached().unwrap();
+//!     mtp_device.search_file(42).unwrap()
+//! }; // `mtp_device` dropped here while still borrowed
+//!
+//! println!("{}", file.name());
+//! ```
 //!
 //! Aditionally if you want a more low-level control on the attributes of certain objects you may
 //! want to check the methods to get and set properties in the [`Object`](object/trait.Object.html)
@@ -23,8 +43,12 @@ use error::Error;
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "uniffi")]
+pub mod bindings;
 pub mod error;
 pub mod internals;
+pub mod journal;
+pub mod metrics;
 
 pub mod util;
 
@@ -32,10 +56,20 @@ pub mod values;
 
 pub mod device;
 pub mod object;
+pub mod ptp;
 pub mod storage;
+mod trace;
+pub mod watcher;
 
 /// Re-export for support convenience.
 pub use chrono;
 
+/// Re-export of the raw `libmtp` bindings, for the escape hatches like
+/// [`MtpDevice::as_raw`](device/struct.MtpDevice.html#method.as_raw).
+pub use libmtp_sys as ffi;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
 /// Custom Result type, this is the most used Result in this crate.
 pub type Result<T> = std::result::Result<T, Error>;
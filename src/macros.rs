@@ -27,26 +27,40 @@ macro_rules! prim_array_ptr_to_vec {
     }};
 }
 
+/// Converts a path into a nul-terminated buffer, returns `Error::NulError` from the
+/// enclosing function if the path contains a nul byte.
 macro_rules! path_to_cvec {
     ($path:expr) => {{
         let mut buf = Vec::new();
 
         cfg_if::cfg_if! {
             if #[cfg(windows)] {
-                use std::iter::once;
-                use std::os::windows::ffi::OsStrExt;
-
-                buf.extend($path.as_os_str()
-                    .encode_wide()
-                    .chain(once(0))
-                    .flat_map(|b| {
-                        let b = b.to_ne_bytes();
-                        once(b[0]).chain(once(b[1]))
-                    }));
+                // libmtp opens local files with the narrow `open`, the path must be passed as
+                // bytes and not as UTF-16, non UTF-8 paths can't be represented.
+                let path = match $path.to_str() {
+                    Some(path) => path,
+                    None => {
+                        return Err(crate::error::Error::InvalidArgument {
+                            reason: format!("{} is not a valid UTF-8 path", $path.display()),
+                        });
+                    }
+                };
+
+                if let Some(position) = path.bytes().position(|b| b == 0) {
+                    return Err(crate::error::Error::NulError { position });
+                }
+
+                buf.extend(path.bytes());
+                buf.push(0);
             } else {
                 use std::os::unix::ffi::OsStrExt;
 
-                buf.extend($path.as_os_str().as_bytes());
+                let bytes = $path.as_os_str().as_bytes();
+                if let Some(position) = bytes.iter().position(|&b| b == 0) {
+                    return Err(crate::error::Error::NulError { position });
+                }
+
+                buf.extend(bytes);
                 buf.push(0);
             }
         }
@@ -55,6 +69,8 @@ macro_rules! path_to_cvec {
     }};
 }
 
+/// Fills a `libmtp` file struct, if the file name contains a nul byte the struct is destroyed
+/// and `Error::NulError` is returned from the enclosing function.
 macro_rules! fill_file_t {
     ($filemetadata:expr, $parent:expr, $storage:expr, $file:ident) => {{
         use num_traits::ToPrimitive;
@@ -63,6 +79,14 @@ macro_rules! fill_file_t {
         let file_t = $file;
         let metadata = $filemetadata;
 
+        let filename = match CString::new(metadata.file_name) {
+            Ok(filename) => filename,
+            Err(err) => {
+                ffi::LIBMTP_destroy_file_t(file_t);
+                return Err(err.into());
+            }
+        };
+
         (*file_t).parent_id = $parent;
         (*file_t).storage_id = $storage;
         (*file_t).filesize = metadata.file_size;
@@ -71,8 +95,6 @@ macro_rules! fill_file_t {
             .to_u32()
             .expect("Unexpected variant in Filetype");
         (*file_t).modificationdate = metadata.modification_date.timestamp() as libc::time_t;
-
-        let filename = CString::new(metadata.file_name).unwrap();
         (*file_t).filename = libc::strdup(filename.as_c_str().as_ptr());
     }};
 }
@@ -0,0 +1,121 @@
+//! Hooks to collect metrics of the operations made on a device, e.g. to export them as
+//! Prometheus counters, install them with
+//! [`MtpDevice::set_metrics`](../device/struct.MtpDevice.html#method.set_metrics), every device
+//! also keeps its own [`SessionStats`](struct.SessionStats.html).
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Receives the metrics of a device, every method does nothing by default so you only have to
+/// implement the ones you care about.
+///
+/// Latency is reported for transfers (`"download"` and `"upload"`) and listings
+/// (`"files_and_folders"`, `"tracks"`, `"albums"` and `"playlists"`), errors are reported for
+/// every failed `libmtp` call with the name of the function (e.g. `"LIBMTP_Get_File_To_File"`),
+/// note that with a [`RetryPolicy`](../util/struct.RetryPolicy.html) every failed attempt is
+/// reported.
+///
+/// ## Example
+/// ```no_run
+/// struct Counters {
+///     sent: AtomicU64,
+///     received: AtomicU64,
+/// }
+///
+/// impl Metrics for Counters {
+///     fn bytes_sent(&self, bytes: u64) {
+///         self.sent.fetch_add(bytes, Ordering::Relaxed);
+///     }
+///
+///     fn bytes_received(&self, bytes: u64) {
+///         self.received.fetch_add(bytes, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counters = Arc::new(Counters { sent: AtomicU64::new(0), received: AtomicU64::new(0) });
+/// mtp_device.set_metrics(counters.clone());
+/// ```
+pub trait Metrics {
+    /// Called after a file or track was sent to the device.
+    fn bytes_sent(&self, _bytes: u64) {}
+
+    /// Called after a file was received from the device, this is the size of the object as
+    /// reported by the device.
+    fn bytes_received(&self, _bytes: u64) {}
+
+    /// Called when an operation finished, successfully or not, with the time it took.
+    fn operation(&self, _operation: &'static str, _latency: Duration) {}
+
+    /// Called when a `libmtp` call failed, the error already has its context.
+    fn error(&self, _operation: &'static str, _error: &Error) {}
+}
+
+/// Counters of the operations made on a device since it was opened (or since the last
+/// [`reset_stats`](../device/struct.MtpDevice.html#method.reset_stats)), retrieved with
+/// [`MtpDevice::stats`](../device/struct.MtpDevice.html#method.stats). They're kept when
+/// refreshing or reconnecting the device.
+///
+/// ## Example
+/// ```no_run
+/// let stats = mtp_device.stats();
+/// println!(
+///     "{} up, {} down, {} errors",
+///     format_bytes(stats.bytes_sent),
+///     format_bytes(stats.bytes_received),
+///     stats.errors,
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Bytes of the files and tracks sent to the device.
+    pub bytes_sent: u64,
+    /// Bytes of the files received from the device, as reported by the device.
+    pub bytes_received: u64,
+    /// Number of uploads, successful or not.
+    pub uploads: u64,
+    /// Number of downloads, successful or not.
+    pub downloads: u64,
+    /// Number of listings (files, tracks, albums and playlists), successful or not.
+    pub listings: u64,
+    /// Number of failed `libmtp` calls, with a `RetryPolicy` every failed attempt counts.
+    pub errors: u64,
+}
+
+impl<M: Metrics + ?Sized> Metrics for Rc<M> {
+    fn bytes_sent(&self, bytes: u64) {
+        (**self).bytes_sent(bytes)
+    }
+
+    fn bytes_received(&self, bytes: u64) {
+        (**self).bytes_received(bytes)
+    }
+
+    fn operation(&self, operation: &'static str, latency: Duration) {
+        (**self).operation(operation, latency)
+    }
+
+    fn error(&self, operation: &'static str, error: &Error) {
+        (**self).error(operation, error)
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn bytes_sent(&self, bytes: u64) {
+        (**self).bytes_sent(bytes)
+    }
+
+    fn bytes_received(&self, bytes: u64) {
+        (**self).bytes_received(bytes)
+    }
+
+    fn operation(&self, operation: &'static str, latency: Duration) {
+        (**self).operation(operation, latency)
+    }
+
+    fn error(&self, operation: &'static str, error: &Error) {
+        (**self).error(operation, error)
+    }
+}
@@ -6,8 +6,10 @@
 
 pub mod filetypes;
 pub mod properties;
+pub mod sample;
 
 use std::ffi::CString;
+use std::fmt::{self, Display};
 
 use crate::device::MtpDevice;
 use crate::storage::Parent;
@@ -16,6 +18,7 @@ use crate::Result;
 use libmtp_sys as ffi;
 use num_traits::ToPrimitive;
 use properties::Property;
+use sample::SampleData;
 
 /// Trait to allow the usage of certain structures or plain `u32` in places where an object id is
 /// required. By default every `Object` implementor automagically implements this trait.
@@ -49,6 +52,24 @@ impl AsObjectId for u32 {
     }
 }
 
+/// Id of an object as reported by the device (e.g. in
+/// [`Event::ObjectAdded`](../device/events/enum.Event.html#variant.ObjectAdded)), it can be
+/// used wherever an object id is required, like `MtpDevice::search_file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub u32);
+
+impl Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsObjectId for ObjectId {
+    fn as_id(&self) -> u32 {
+        self.0
+    }
+}
+
 /// Wrapper structure that holds an object id and a reference to an `MtpDevice`, useful if you want
 /// to work with Object methods and only have an id. (see `MtpDevice::dummy_object`).
 pub struct DummyObject<'a> {
@@ -66,6 +87,53 @@ impl Object for DummyObject<'_> {
     }
 }
 
+/// Protection status of an object, i.e. whether the device lets it be modified or deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectionStatus {
+    /// The object can be modified and deleted.
+    NoProtection,
+    /// The object (data and properties) can't be modified nor deleted.
+    ReadOnly,
+    /// The data of the object can't be modified nor deleted, its properties still can.
+    ReadOnlyData,
+    /// The object can't be transferred off the device.
+    NonTransferableData,
+    /// A vendor-specific or reserved code.
+    Other(u16),
+}
+
+impl ProtectionStatus {
+    /// Returns the MTP code of this status.
+    pub fn code(&self) -> u16 {
+        match self {
+            ProtectionStatus::NoProtection => 0x0000,
+            ProtectionStatus::ReadOnly => 0x0001,
+            ProtectionStatus::ReadOnlyData => 0x8002,
+            ProtectionStatus::NonTransferableData => 0x8003,
+            ProtectionStatus::Other(code) => *code,
+        }
+    }
+
+    /// Returns the status with the given MTP code.
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            0x0000 => ProtectionStatus::NoProtection,
+            0x0001 => ProtectionStatus::ReadOnly,
+            0x8002 => ProtectionStatus::ReadOnlyData,
+            0x8003 => ProtectionStatus::NonTransferableData,
+            code => ProtectionStatus::Other(code),
+        }
+    }
+
+    /// Whether the object can't be deleted with this status.
+    pub fn is_protected(&self) -> bool {
+        matches!(
+            self,
+            ProtectionStatus::ReadOnly | ProtectionStatus::ReadOnlyData
+        )
+    }
+}
+
 /// Common behavior of many higher abstractions is grouped in this trait, basically everything on
 /// MTP is an object with some attributes, even though this API is exposed, it's not recommended to
 /// use it to modify or get attributes that can be managed with other specefic APIs (like files,
@@ -79,21 +147,38 @@ pub trait Object {
 
     /// Retrieves a string from an object attribute.
     fn get_string(&self, property: Property) -> Result<String> {
+        let u8vec = self.get_raw_string(property)?;
+        Ok(String::from_utf8(u8vec)?)
+    }
+
+    /// Retrieves a string from an object attribute as raw bytes, useful if the device doesn't
+    /// use valid UTF-8.
+    fn get_raw_string(&self, property: Property) -> Result<Vec<u8>> {
         let property = property.to_u32().unwrap();
         let id = self.id();
         let device = self.device();
 
-        let string = unsafe { ffi::LIBMTP_Get_String_From_Object(device.inner, id, property) };
+        device.retry(|| {
+            let string =
+                unsafe { ffi::LIBMTP_Get_String_From_Object(device.session()?, id, property) };
 
-        if string.is_null() {
-            Err(device.latest_error().unwrap_or_default())
-        } else {
-            unsafe {
-                let u8vec = cstr_to_u8vec!(string);
-                libc::free(string as *mut _);
-                Ok(String::from_utf8(u8vec)?)
+            if string.is_null() {
+                Err(device.error_in("LIBMTP_Get_String_From_Object", Some(id)))
+            } else {
+                unsafe {
+                    let u8vec = cstr_to_u8vec!(string);
+                    libc::free(string as *mut _);
+                    Ok(u8vec)
+                }
             }
-        }
+        })
+    }
+
+    /// Retrieves a string from an object attribute, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`.
+    fn get_string_lossy(&self, property: Property) -> Result<String> {
+        let u8vec = self.get_raw_string(property)?;
+        Ok(String::from_utf8_lossy(&u8vec).into_owned())
     }
 
     /// Sets an object attribute from a string.
@@ -101,16 +186,19 @@ pub trait Object {
         let property = property.to_u32().unwrap();
         let id = self.id();
         let device = self.device();
-        let string = CString::new(string).expect("Nul byte");
+        let string = CString::new(string)?;
 
-        let res =
-            unsafe { ffi::LIBMTP_Set_Object_String(device.inner, id, property, string.as_ptr()) };
+        device.retry(|| {
+            let res = unsafe {
+                ffi::LIBMTP_Set_Object_String(device.session()?, id, property, string.as_ptr())
+            };
 
-        if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
-        } else {
-            Ok(())
-        }
+            if res != 0 {
+                Err(device.error_in("LIBMTP_Set_Object_String", Some(id)))
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Retrieves an `u64` from an object attribute.
@@ -119,13 +207,16 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let val = unsafe { ffi::LIBMTP_Get_u64_From_Object(device.inner, id, property, 0) };
+        device.retry(|| {
+            let val =
+                unsafe { ffi::LIBMTP_Get_u64_From_Object(device.session()?, id, property, 0) };
 
-        if let Some(err) = device.latest_error() {
-            Err(err)
-        } else {
-            Ok(val)
-        }
+            if let Some(err) = device.latest_error_in("LIBMTP_Get_u64_From_Object", Some(id)) {
+                Err(err)
+            } else {
+                Ok(val)
+            }
+        })
     }
 
     /// Retrieves an `u32` from an object attribute, returns the value of `default` on failure.
@@ -134,13 +225,16 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let val = unsafe { ffi::LIBMTP_Get_u32_From_Object(device.inner, id, property, 0) };
+        device.retry(|| {
+            let val =
+                unsafe { ffi::LIBMTP_Get_u32_From_Object(device.session()?, id, property, 0) };
 
-        if let Some(err) = device.latest_error() {
-            Err(err)
-        } else {
-            Ok(val)
-        }
+            if let Some(err) = device.latest_error_in("LIBMTP_Get_u32_From_Object", Some(id)) {
+                Err(err)
+            } else {
+                Ok(val)
+            }
+        })
     }
 
     /// Sets an object attribute from an `u32`.
@@ -149,13 +243,15 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let res = unsafe { ffi::LIBMTP_Set_Object_u32(device.inner, id, property, value) };
+        device.retry(|| {
+            let res = unsafe { ffi::LIBMTP_Set_Object_u32(device.session()?, id, property, value) };
 
-        if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
-        } else {
-            Ok(())
-        }
+            if res != 0 {
+                Err(device.error_in("LIBMTP_Set_Object_u32", Some(id)))
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Retrieves an `u16` from an object attribute, returns the value of `default` on failure.
@@ -164,13 +260,16 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let val = unsafe { ffi::LIBMTP_Get_u16_From_Object(device.inner, id, property, 0) };
+        device.retry(|| {
+            let val =
+                unsafe { ffi::LIBMTP_Get_u16_From_Object(device.session()?, id, property, 0) };
 
-        if let Some(err) = device.latest_error() {
-            Err(err)
-        } else {
-            Ok(val)
-        }
+            if let Some(err) = device.latest_error_in("LIBMTP_Get_u16_From_Object", Some(id)) {
+                Err(err)
+            } else {
+                Ok(val)
+            }
+        })
     }
 
     /// Sets an object attribute from an `u16`.
@@ -179,13 +278,15 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let res = unsafe { ffi::LIBMTP_Set_Object_u16(device.inner, id, property, value) };
+        device.retry(|| {
+            let res = unsafe { ffi::LIBMTP_Set_Object_u16(device.session()?, id, property, value) };
 
-        if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
-        } else {
-            Ok(())
-        }
+            if res != 0 {
+                Err(device.error_in("LIBMTP_Set_Object_u16", Some(id)))
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Retrieves an `u8` from an object attribute, returns the value of `default` on failure.
@@ -194,13 +295,15 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let val = unsafe { ffi::LIBMTP_Get_u8_From_Object(device.inner, id, property, 0) };
+        device.retry(|| {
+            let val = unsafe { ffi::LIBMTP_Get_u8_From_Object(device.session()?, id, property, 0) };
 
-        if let Some(err) = device.latest_error() {
-            Err(err)
-        } else {
-            Ok(val)
-        }
+            if let Some(err) = device.latest_error_in("LIBMTP_Get_u8_From_Object", Some(id)) {
+                Err(err)
+            } else {
+                Ok(val)
+            }
+        })
     }
 
     /// Sets an object attribute from an `u8`.
@@ -209,13 +312,45 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let res = unsafe { ffi::LIBMTP_Set_Object_u8(device.inner, id, property, value) };
+        device.retry(|| {
+            let res = unsafe { ffi::LIBMTP_Set_Object_u8(device.session()?, id, property, value) };
 
-        if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
-        } else {
-            Ok(())
-        }
+            if res != 0 {
+                Err(device.error_in("LIBMTP_Set_Object_u8", Some(id)))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Retrieves the protection status of this object.
+    fn protection_status(&self) -> Result<ProtectionStatus> {
+        self.get_u16(Property::ProtectionStatus)
+            .map(ProtectionStatus::from_code)
+    }
+
+    /// Sets the protection status of this object, e.g. `ProtectionStatus::ReadOnly` to lock
+    /// preloaded content against accidental deletion. Many devices report this property as
+    /// read-only, in this case an `Error::PtpResponse` is returned, usually with
+    /// `PtpResponse::ObjectWriteProtected` or `PtpResponse::AccessDenied`, check it with
+    /// [`Error::ptp_response`](../error/enum.Error.html#method.ptp_response).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::error::PtpResponse;
+    /// use libmtp_rs::object::{Object, ProtectionStatus};
+    ///
+    /// for file in storage.files_and_folders(Parent::Folder(preloaded_id))? {
+    ///     match file.set_protection_status(ProtectionStatus::ReadOnly) {
+    ///         Err(err) if err.ptp_response() == Some(PtpResponse::AccessDenied) => {
+    ///             println!("{} can't be locked", file.name());
+    ///         }
+    ///         result => result?,
+    ///     }
+    /// }
+    /// ```
+    fn set_protection_status(&self, status: ProtectionStatus) -> Result<()> {
+        self.set_u16(Property::ProtectionStatus, status.code())
     }
 
     /// Deletes a *single* file, track, playlist, folder or any other object off the MTP device.
@@ -229,11 +364,12 @@ pub trait Object {
         let id = self.id();
         let device = self.device();
 
-        let res = unsafe { ffi::LIBMTP_Delete_Object(device.inner, id) };
+        let res = unsafe { ffi::LIBMTP_Delete_Object(device.session()?, id) };
 
         if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
+            Err(device.error_in("LIBMTP_Delete_Object", Some(id)))
         } else {
+            device.update_paths(|cache| cache.remove(id));
             Ok(())
         }
     }
@@ -247,13 +383,14 @@ pub trait Object {
     fn move_to(&self, storage_id: u32, parent: Parent) -> Result<()> {
         let id = self.id();
         let device = self.device();
-        let parent = parent.to_id();
 
-        let res = unsafe { ffi::LIBMTP_Move_Object(device.inner, id, storage_id, parent) };
+        let res =
+            unsafe { ffi::LIBMTP_Move_Object(device.session()?, id, storage_id, parent.to_id()) };
 
         if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
+            Err(device.error_in("LIBMTP_Move_Object", Some(id)))
         } else {
+            device.update_paths(|cache| cache.relocate(id, storage_id, parent));
             Ok(())
         }
     }
@@ -269,15 +406,27 @@ pub trait Object {
         let device = self.device();
         let parent = parent.to_id();
 
-        let res = unsafe { ffi::LIBMTP_Copy_Object(device.inner, id, storage_id, parent) };
+        let res = unsafe { ffi::LIBMTP_Copy_Object(device.session()?, id, storage_id, parent) };
 
         if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
+            Err(device.error_in("LIBMTP_Copy_Object", Some(id)))
         } else {
             Ok(())
         }
     }
 
+    /// Retrieves the representative sample data of this object (if the device supports it for
+    /// this kind of object), e.g. the cover art of an album.
+    fn representative_sample(&self) -> Result<SampleData> {
+        sample::get_representative_sample(self.device(), self.id())
+    }
+
+    /// Retrieves the thumbnail of this object, usually available on pictures and videos, the
+    /// data is commonly a JPEG image.
+    fn thumbnail(&self) -> Result<Vec<u8>> {
+        sample::get_thumbnail(self.device(), self.id())
+    }
+
     /// Get partial data from an object, specifying an offset and the maximum bytes
     /// that should be read. Note that this may return fewer bytes than the maximum.
     fn get_partial_object(&self, offset: u64, maxbytes: u32) -> Result<Vec<u8>> {
@@ -288,7 +437,14 @@ pub trait Object {
         let mut data = std::ptr::null_mut();
 
         let res = unsafe {
-            ffi::LIBMTP_GetPartialObject(device.inner, id, offset, maxbytes, &mut data, &mut size)
+            ffi::LIBMTP_GetPartialObject(
+                device.session()?,
+                id,
+                offset,
+                maxbytes,
+                &mut data,
+                &mut size,
+            )
         };
 
         if res != 0 || data.is_null() {
@@ -298,7 +454,7 @@ pub trait Object {
                 }
             }
 
-            Err(device.latest_error().unwrap_or_default())
+            Err(device.error_in("LIBMTP_GetPartialObject", Some(id)))
         } else {
             let bytes = unsafe { prim_array_ptr_to_vec!(data, u8, size) };
             unsafe {
@@ -320,7 +476,7 @@ pub trait Object {
 
         let res = unsafe {
             ffi::LIBMTP_SendPartialObject(
-                device.inner,
+                device.session()?,
                 id,
                 offset,
                 data.as_ptr() as *mut _,
@@ -329,7 +485,7 @@ pub trait Object {
         };
 
         if res != 0 {
-            Err(device.latest_error().unwrap_or_default())
+            Err(device.error_in("LIBMTP_SendPartialObject", Some(id)))
         } else {
             Ok(())
         }
@@ -6,10 +6,12 @@ use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::ToPrimitive;
 use std::ffi::CStr;
 use std::fmt::{self, Display};
+use std::path::Path;
 
 /// Enumeration that holds the supported filetypes, this enum implements `Display`
 /// with the description of the file type.
-#[derive(Debug, Clone, FromPrimitive, ToPrimitive)]
+#[derive(Debug, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Filetype {
     Folder = 0,
     Wav,
@@ -58,6 +60,201 @@ pub enum Filetype {
     Unknown,
 }
 
+impl Filetype {
+    /// Whether this filetype is an image, mirrors `LIBMTP_FILETYPE_IS_IMAGE`.
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self,
+            Filetype::Jpeg
+                | Filetype::Jfif
+                | Filetype::Tiff
+                | Filetype::Bmp
+                | Filetype::Gif
+                | Filetype::Pict
+                | Filetype::Png
+                | Filetype::Jp2
+                | Filetype::Jpx
+                | Filetype::WindowsImageFormat
+        )
+    }
+
+    /// Whether this filetype is a video, mirrors `LIBMTP_FILETYPE_IS_VIDEO` but also considers
+    /// `Mp4` (usual container of camera videos).
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self,
+            Filetype::Wmv
+                | Filetype::Avi
+                | Filetype::Mpeg
+                | Filetype::Asf
+                | Filetype::Qt
+                | Filetype::UndefVideo
+                | Filetype::Mp4
+        )
+    }
+
+    /// Guesses the filetype from a file extension (without the dot, case insensitive),
+    /// `Filetype::Unknown` if the extension isn't known.
+    pub fn from_extension(extension: &str) -> Filetype {
+        match extension.to_ascii_lowercase().as_str() {
+            "wav" => Filetype::Wav,
+            "mp3" => Filetype::Mp3,
+            "wma" => Filetype::Wma,
+            "ogg" | "oga" | "opus" => Filetype::Ogg,
+            "aa" | "aax" => Filetype::Audible,
+            "mp4" | "m4v" => Filetype::Mp4,
+            "wmv" => Filetype::Wmv,
+            "avi" => Filetype::Avi,
+            "mpg" | "mpeg" => Filetype::Mpeg,
+            "asf" => Filetype::Asf,
+            "mov" | "qt" => Filetype::Qt,
+            "jpg" | "jpeg" => Filetype::Jpeg,
+            "jfif" => Filetype::Jfif,
+            "tif" | "tiff" => Filetype::Tiff,
+            "bmp" => Filetype::Bmp,
+            "gif" => Filetype::Gif,
+            "pic" | "pict" => Filetype::Pict,
+            "png" => Filetype::Png,
+            "vcs" => Filetype::VCalendar1,
+            "ics" => Filetype::VCalendar2,
+            "vcf" => Filetype::VCard3,
+            "exe" | "com" | "bat" => Filetype::WinExec,
+            "txt" => Filetype::Text,
+            "htm" | "html" => Filetype::Html,
+            "bin" => Filetype::Firmware,
+            "aac" => Filetype::Aac,
+            "flac" => Filetype::Flac,
+            "mp2" => Filetype::Mp2,
+            "m4a" => Filetype::M4a,
+            "doc" => Filetype::Doc,
+            "xml" => Filetype::Xml,
+            "xls" => Filetype::Xls,
+            "ppt" => Filetype::Ppt,
+            "mht" => Filetype::Mht,
+            "jp2" => Filetype::Jp2,
+            "jpx" => Filetype::Jpx,
+            _ => Filetype::Unknown,
+        }
+    }
+
+    /// Guesses the filetype from the extension of `path`, `Filetype::Unknown` if it has no
+    /// extension or it isn't known.
+    pub fn from_path(path: impl AsRef<Path>) -> Filetype {
+        path.as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(Filetype::Unknown, Filetype::from_extension)
+    }
+
+    /// Tries to detect the image filetype of some raw data by its magic bytes, only common
+    /// image formats used for cover art and thumbnails are detected.
+    pub fn detect_image(data: &[u8]) -> Option<Filetype> {
+        match data {
+            [0xFF, 0xD8, 0xFF, ..] => Some(Filetype::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Filetype::Png),
+            [b'G', b'I', b'F', b'8', ..] => Some(Filetype::Gif),
+            [b'B', b'M', ..] => Some(Filetype::Bmp),
+            [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(Filetype::Tiff),
+            [0x00, 0x00, 0x00, 0x0C, b'j', b'P', b' ', b' ', ..] => Some(Filetype::Jp2),
+            _ => None,
+        }
+    }
+
+    /// Tries to detect the filetype of some raw data (the first bytes of a file are enough) by
+    /// its magic bytes, requires the `magic` feature. Besides images, common audio, video,
+    /// document and executable formats are detected.
+    #[cfg(feature = "magic")]
+    pub fn detect(data: &[u8]) -> Option<Filetype> {
+        if let Some(ftype) = Filetype::detect_image(data) {
+            return Some(ftype);
+        }
+
+        let starts_with_ignore_case = |prefix: &[u8]| {
+            data.len() >= prefix.len() && data[..prefix.len()].eq_ignore_ascii_case(prefix)
+        };
+        let contains = |needle: &[u8]| data.windows(needle.len()).any(|window| window == needle);
+
+        let ftype = match data {
+            [b'I', b'D', b'3', ..] | [0xFF, 0xFB | 0xFA | 0xF3 | 0xF2, ..] => Filetype::Mp3,
+            [0xFF, 0xF1 | 0xF9, ..] => Filetype::Aac,
+            [b'f', b'L', b'a', b'C', ..] => Filetype::Flac,
+            [b'O', b'g', b'g', b'S', ..] => Filetype::Ogg,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Filetype::Wav,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'A', b'V', b'I', b' ', ..] => Filetype::Avi,
+            [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] => match brand {
+                [b'M', b'4', b'A' | b'B', b' ', ..] => Filetype::M4a,
+                [b'q', b't', b' ', b' ', ..] => Filetype::Qt,
+                _ => Filetype::Mp4,
+            },
+            [0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, ..] => Filetype::Asf,
+            [0x00, 0x00, 0x01, 0xBA | 0xB3, ..] => Filetype::Mpeg,
+            [b'M', b'Z', ..] => Filetype::WinExec,
+            _ if starts_with_ignore_case(b"<?xml") => Filetype::Xml,
+            _ if starts_with_ignore_case(b"<!doctype html")
+                || starts_with_ignore_case(b"<html") =>
+            {
+                Filetype::Html
+            }
+            _ if starts_with_ignore_case(b"BEGIN:VCARD") => {
+                if contains(b"VERSION:2.1") {
+                    Filetype::VCard2
+                } else {
+                    Filetype::VCard3
+                }
+            }
+            _ if starts_with_ignore_case(b"BEGIN:VCALENDAR") => {
+                if contains(b"VERSION:1.0") {
+                    Filetype::VCalendar1
+                } else {
+                    Filetype::VCalendar2
+                }
+            }
+            _ => return None,
+        };
+
+        Some(ftype)
+    }
+
+    /// Guesses the filetype of a local file, if its extension is missing or doesn't match the
+    /// contents of the file, the filetype detected from its first bytes is used. Falls back to
+    /// `from_path` if the contents aren't recognized or can't be read. Requires the `magic`
+    /// feature, without it this is the same as `from_path`.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// // A JPEG saved without extension by some app
+    /// assert_eq!(Filetype::guess("Downloads/image"), Filetype::Jpeg);
+    /// ```
+    #[cfg(feature = "magic")]
+    pub fn guess(path: impl AsRef<Path>) -> Filetype {
+        use std::io::Read;
+
+        let path = path.as_ref();
+        let by_extension = Filetype::from_path(path);
+
+        let mut head = Vec::with_capacity(512);
+        let read = std::fs::File::open(path).and_then(|file| file.take(512).read_to_end(&mut head));
+
+        match read.ok().and_then(|_| Filetype::detect(&head)) {
+            // The extension tells the variant of some containers, e.g. WMA and WMV are ASF
+            Some(Filetype::Asf) if matches!(by_extension, Filetype::Wma | Filetype::Wmv) => {
+                by_extension
+            }
+            Some(Filetype::Jpeg) if by_extension == Filetype::Jfif => by_extension,
+            Some(Filetype::Jp2) if by_extension == Filetype::Jpx => by_extension,
+            Some(detected) => detected,
+            None => by_extension,
+        }
+    }
+
+    /// Guesses the filetype of a local file, requires the `magic` feature to check the contents
+    /// of the file, without it this is the same as `from_path`.
+    #[cfg(not(feature = "magic"))]
+    pub fn guess(path: impl AsRef<Path>) -> Filetype {
+        Filetype::from_path(path)
+    }
+}
+
 impl Display for Filetype {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let ftype = self.to_u32().unwrap();
@@ -0,0 +1,72 @@
+//! Contains items to get the representative sample data and thumbnails of objects, like the
+//! cover art of albums and tracks or the preview of pictures and videos.
+
+use libmtp_sys as ffi;
+use num_traits::FromPrimitive;
+
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::Result;
+
+/// Representative sample of an object, e.g. the cover art of an album or a short preview
+/// of a song.
+#[derive(Debug, Clone)]
+pub struct SampleData {
+    /// Width of the sample if it's an image.
+    pub width: u32,
+    /// Height of the sample if it's an image.
+    pub height: u32,
+    /// Duration in milliseconds if it's audio.
+    pub duration: u32,
+    /// Filetype of the sample data.
+    pub filetype: Filetype,
+    /// Raw sample data.
+    pub data: Vec<u8>,
+}
+
+pub(crate) fn get_representative_sample(mtpdev: &MtpDevice, id: u32) -> Result<SampleData> {
+    let session = mtpdev.session()?;
+    unsafe {
+        let sample = ffi::LIBMTP_new_filesampledata_t();
+        let res = ffi::LIBMTP_Get_Representative_Sample(session, id, sample);
+
+        if res != 0 || (*sample).data.is_null() {
+            ffi::LIBMTP_destroy_filesampledata_t(sample);
+            Err(mtpdev.error_in("LIBMTP_Get_Representative_Sample", Some(id)))
+        } else {
+            let data = prim_array_ptr_to_vec!((*sample).data, u8, (*sample).size);
+            let sample_data = SampleData {
+                width: (*sample).width,
+                height: (*sample).height,
+                duration: (*sample).duration,
+                filetype: Filetype::from_u32((*sample).filetype).unwrap_or(Filetype::Unknown),
+                data,
+            };
+
+            ffi::LIBMTP_destroy_filesampledata_t(sample);
+            Ok(sample_data)
+        }
+    }
+}
+
+pub(crate) fn get_thumbnail(mtpdev: &MtpDevice, id: u32) -> Result<Vec<u8>> {
+    let session = mtpdev.session()?;
+    unsafe {
+        let mut data = std::ptr::null_mut();
+        let mut size = 0;
+
+        let res = ffi::LIBMTP_Get_Thumbnail(session, id, &mut data, &mut size);
+
+        if res != 0 || data.is_null() {
+            if !data.is_null() {
+                libc::free(data as *mut _);
+            }
+
+            Err(mtpdev.error_in("LIBMTP_Get_Thumbnail", Some(id)))
+        } else {
+            let bytes = prim_array_ptr_to_vec!(data, u8, size);
+            libc::free(data as *mut _);
+            Ok(bytes)
+        }
+    }
+}
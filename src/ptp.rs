@@ -0,0 +1,745 @@
+//! Minimal pure-Rust implementation of the PTP/MTP protocol, independent of `libmtp`, meant as
+//! a low-level fallback for devices or platforms where `libmtp` misbehaves.
+//!
+//! Note that it's a separate, lower-level API: [`MtpDevice`](../device/struct.MtpDevice.html),
+//! [`Storage`](../storage/struct.Storage.html) and the object types are bound to `libmtp` and
+//! can't run on a `PtpSession`, code switching to this fallback works with object handles and
+//! [`ObjectInfo`](struct.ObjectInfo.html) datasets instead.
+//!
+//! It implements the core operation set (opening a session, listing storage and objects,
+//! downloading and uploading objects, getting and setting object references, which `libmtp`
+//! only exposes for albums and playlists) on top of a [`Transport`](trait.Transport.html), which
+//! runs single PTP transactions, so the same [`PtpSession`](struct.PtpSession.html) drives
+//! devices over any transport:
+//! - [`ip`](ip/index.html): PTP/IP, for cameras exposing MTP over Wi-Fi.
+//! - [`memory`](memory/index.html): a device in memory, for tests and benchmarks.
+//! - [`usb`](usb/index.html): USB through `rusb` (requires the `rusb` feature), open it with
+//!   [`RawDevice::open_ptp`](../device/raw/struct.RawDevice.html#method.open_ptp).
+//!
+//! Device properties (like the clock of the device) are in [`properties`](properties/index.html).
+//!
+//! ## Example
+//! ```no_run
+//! use libmtp_rs::ptp::{ip::{IpTransport, DEFAULT_PORT}, PtpSession};
+//! use libmtp_rs::storage::Parent;
+//!
+//! let transport = IpTransport::connect(("192.168.1.1", DEFAULT_PORT), guid, "libmtp-rs")?;
+//! let mut session = PtpSession::open(transport)?;
+//! let storage_id = session.storage_ids()?[0];
+//!
+//! for handle in session.object_handles(storage_id, Some(Parent::Root))? {
+//!     let info = session.object_info(handle)?;
+//!     println!("{} ({} bytes)", info.filename, info.size);
+//! }
+//! ```
+
+pub mod ip;
+pub mod memory;
+pub mod properties;
+#[cfg(feature = "rusb")]
+pub mod usb;
+
+use std::convert::TryInto;
+
+use crate::device::events::EventCode;
+use crate::error::{Error, MtpErrorKind};
+use crate::ptp::properties::DeviceProperty;
+use crate::storage::Parent;
+use crate::Result;
+
+/// Codes of the operations used by `PtpSession`.
+pub mod operation {
+    pub const GET_DEVICE_INFO: u16 = 0x1001;
+    pub const OPEN_SESSION: u16 = 0x1002;
+    pub const CLOSE_SESSION: u16 = 0x1003;
+    pub const GET_STORAGE_IDS: u16 = 0x1004;
+    pub const GET_OBJECT_HANDLES: u16 = 0x1007;
+    pub const GET_OBJECT_INFO: u16 = 0x1008;
+    pub const GET_OBJECT: u16 = 0x1009;
+    pub const DELETE_OBJECT: u16 = 0x100B;
+    pub const SEND_OBJECT_INFO: u16 = 0x100C;
+    pub const SEND_OBJECT: u16 = 0x100D;
+    pub const GET_DEVICE_PROP_DESC: u16 = 0x1014;
+    pub const GET_DEVICE_PROP_VALUE: u16 = 0x1015;
+    pub const SET_DEVICE_PROP_VALUE: u16 = 0x1016;
+    pub const GET_OBJECT_REFERENCES: u16 = 0x9810;
+    pub const SET_OBJECT_REFERENCES: u16 = 0x9811;
+}
+
+/// Response code of a successful operation.
+pub const RESPONSE_OK: u16 = 0x2001;
+
+/// Response code when a session is already open, `PtpSession::open` accepts it.
+pub const RESPONSE_SESSION_ALREADY_OPEN: u16 = 0x201E;
+
+/// Object format of folders (PTP associations).
+pub const FORMAT_ASSOCIATION: u16 = 0x3001;
+
+/// Object format of files with an unknown format.
+pub const FORMAT_UNDEFINED: u16 = 0x3000;
+
+/// Association type of folders.
+pub const ASSOCIATION_GENERIC_FOLDER: u16 = 0x0001;
+
+/// Storage id meaning every storage in `GetObjectHandles`.
+pub const ALL_STORAGE: u32 = 0xFFFF_FFFF;
+
+macro_rules! operation_codes {
+    ($($(#[$meta:meta])* $name:ident = $code:literal,)*) => {
+        /// PTP and MTP operations a device may support, as listed in its
+        /// [`DeviceInfo`](struct.DeviceInfo.html), other codes (like vendor-specific ones) are
+        /// kept in `Other`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+        pub enum OperationCode {
+            $($(#[$meta])* $name,)*
+            Other(u16),
+        }
+
+        impl OperationCode {
+            /// Returns the PTP code of this operation.
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(OperationCode::$name => $code,)*
+                    OperationCode::Other(code) => *code,
+                }
+            }
+
+            /// Returns the operation with the given PTP code.
+            pub fn from_code(code: u16) -> Self {
+                match code {
+                    $($code => OperationCode::$name,)*
+                    code => OperationCode::Other(code),
+                }
+            }
+        }
+    };
+}
+
+operation_codes! {
+    GetDeviceInfo = 0x1001,
+    OpenSession = 0x1002,
+    CloseSession = 0x1003,
+    GetStorageIds = 0x1004,
+    GetStorageInfo = 0x1005,
+    GetNumObjects = 0x1006,
+    GetObjectHandles = 0x1007,
+    GetObjectInfo = 0x1008,
+    GetObject = 0x1009,
+    GetThumb = 0x100A,
+    DeleteObject = 0x100B,
+    SendObjectInfo = 0x100C,
+    SendObject = 0x100D,
+    InitiateCapture = 0x100E,
+    FormatStore = 0x100F,
+    ResetDevice = 0x1010,
+    SelfTest = 0x1011,
+    SetObjectProtection = 0x1012,
+    PowerDown = 0x1013,
+    GetDevicePropDesc = 0x1014,
+    GetDevicePropValue = 0x1015,
+    SetDevicePropValue = 0x1016,
+    ResetDevicePropValue = 0x1017,
+    TerminateOpenCapture = 0x1018,
+    MoveObject = 0x1019,
+    CopyObject = 0x101A,
+    /// Reads a range of an object, with 32 bits offsets.
+    GetPartialObject = 0x101B,
+    InitiateOpenCapture = 0x101C,
+    /// Reads a range of an object, with 64 bits offsets (Android extension).
+    GetPartialObject64 = 0x95C1,
+    /// Writes a range of an object (Android extension).
+    SendPartialObject = 0x95C2,
+    /// Truncates an object (Android extension).
+    TruncateObject = 0x95C3,
+    /// Starts editing an object in place (Android extension).
+    BeginEditObject = 0x95C4,
+    /// Ends editing an object in place (Android extension).
+    EndEditObject = 0x95C5,
+    GetObjectPropsSupported = 0x9801,
+    GetObjectPropDesc = 0x9802,
+    GetObjectPropValue = 0x9803,
+    SetObjectPropValue = 0x9804,
+    GetObjectPropList = 0x9805,
+    SetObjectPropList = 0x9806,
+    GetInterdependentPropDesc = 0x9807,
+    SendObjectPropList = 0x9808,
+    GetObjectReferences = 0x9810,
+    SetObjectReferences = 0x9811,
+    UpdateDeviceFirmware = 0x9812,
+    Skip = 0x9820,
+}
+
+/// An operation request, sent at the start of every transaction.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub code: u16,
+    pub transaction_id: u32,
+    pub params: Vec<u32>,
+}
+
+/// The response of the device at the end of a transaction.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub code: u16,
+    pub transaction_id: u32,
+    pub params: Vec<u32>,
+}
+
+/// A way to talk to a PTP device, like USB or TCP/IP.
+pub trait Transport {
+    /// Runs a single transaction: sends the operation and, if given, `data` in the data phase
+    /// from the initiator, then returns the response of the device along with the data it sent
+    /// in its data phase (empty if there wasn't any).
+    fn transaction(
+        &mut self,
+        operation: &Operation,
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn transaction(
+        &mut self,
+        operation: &Operation,
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        (**self).transaction(operation, data)
+    }
+}
+
+/// Builds the error for a response code other than `RESPONSE_OK`, formatted like the `libmtp`
+/// PTP errors so [`Error::ptp_code`](../error/enum.Error.html#method.ptp_code) works.
+pub(crate) fn response_error(code: u16, operation: u16) -> Error {
+    let text = format!("Error {:04x}: operation {:04x} failed", code, operation);
+    Error::ptp_layer(text, Vec::new())
+}
+
+/// Builds the error for a malformed container or dataset sent by the device.
+pub(crate) fn protocol_error(text: impl Into<String>) -> Error {
+    Error::MtpError {
+        kind: MtpErrorKind::PtpLayer,
+        text: text.into(),
+        details: Vec::new(),
+    }
+}
+
+/// Writes PTP datasets (little endian integers and UTF-16 strings).
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    pub(crate) buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u16(&mut self, value: u16) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Arrays are prefixed by their number of elements.
+    pub(crate) fn u32_array(&mut self, values: &[u32]) -> &mut Self {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.u32(*value);
+        }
+        self
+    }
+
+    /// Strings are prefixed by their number of UTF-16 units including the terminating nul,
+    /// the empty string is a single zero byte.
+    pub(crate) fn string(&mut self, value: &str) -> Result<&mut Self> {
+        let units: Vec<u16> = value.encode_utf16().collect();
+        if units.is_empty() {
+            self.buf.push(0);
+            return Ok(self);
+        }
+
+        if units.len() > 254 {
+            return Err(Error::InvalidArgument {
+                reason: format!("{} is too long for a PTP string", value),
+            });
+        }
+
+        self.buf.push(units.len() as u8 + 1);
+        for unit in units.into_iter().chain(Some(0)) {
+            self.u16(unit);
+        }
+
+        Ok(self)
+    }
+}
+
+/// Reads PTP datasets (little endian integers and UTF-16 strings).
+#[derive(Debug)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(protocol_error("Truncated PTP dataset"));
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String> {
+        let len = self.u8()? as usize;
+        let mut units = Vec::with_capacity(len);
+        for _ in 0..len {
+            units.push(self.u16()?);
+        }
+
+        // Drop the terminating nul
+        while units.last() == Some(&0) {
+            units.pop();
+        }
+
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    pub(crate) fn u16_array(&mut self) -> Result<Vec<u16>> {
+        let len = self.u32()? as usize;
+        if self.data.len() / 2 < len {
+            return Err(protocol_error("Truncated PTP array"));
+        }
+
+        (0..len).map(|_| self.u16()).collect()
+    }
+
+    pub(crate) fn u32_array(&mut self) -> Result<Vec<u32>> {
+        let len = self.u32()? as usize;
+        if self.data.len() / 4 < len {
+            return Err(protocol_error("Truncated PTP array"));
+        }
+
+        (0..len).map(|_| self.u32()).collect()
+    }
+}
+
+/// The PTP `DeviceInfo` dataset, describes the device and what it supports.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    /// PTP version times 100, e.g. `100` for PTP 1.0.
+    pub standard_version: u16,
+    /// Vendor extension, `6` for MTP.
+    pub vendor_extension_id: u32,
+    pub vendor_extension_version: u16,
+    /// Extensions like `microsoft.com: 1.0; android.com: 1.0;`.
+    pub vendor_extension_desc: String,
+    pub functional_mode: u16,
+    /// Codes of the supported operations.
+    pub operations_supported: Vec<u16>,
+    /// Codes of the events the device may send.
+    pub events_supported: Vec<u16>,
+    /// Codes of the supported device properties.
+    pub device_properties_supported: Vec<u16>,
+    /// Object formats the device can create by itself (like pictures on cameras).
+    pub capture_formats: Vec<u16>,
+    /// Object formats the device can store.
+    pub playback_formats: Vec<u16>,
+    pub manufacturer: String,
+    pub model: String,
+    pub device_version: String,
+    pub serial_number: String,
+}
+
+impl DeviceInfo {
+    /// Returns the supported operations.
+    pub fn operations(&self) -> Vec<OperationCode> {
+        self.operations_supported
+            .iter()
+            .map(|&code| OperationCode::from_code(code))
+            .collect()
+    }
+
+    /// Check whether the given operation is supported.
+    pub fn supports_operation(&self, operation: OperationCode) -> bool {
+        self.operations_supported.contains(&operation.code())
+    }
+
+    /// Returns the events the device may send.
+    pub fn events(&self) -> Vec<EventCode> {
+        self.events_supported
+            .iter()
+            .map(|&code| EventCode::from_code(code))
+            .collect()
+    }
+
+    /// Check whether the device may send the given event.
+    pub fn supports_event(&self, event: EventCode) -> bool {
+        self.events_supported.contains(&event.code())
+    }
+
+    /// Returns the supported device properties.
+    pub fn device_properties(&self) -> Vec<DeviceProperty> {
+        self.device_properties_supported
+            .iter()
+            .map(|&code| DeviceProperty::from_code(code))
+            .collect()
+    }
+
+    /// Check whether the given device property is supported.
+    pub fn supports_device_property(&self, property: DeviceProperty) -> bool {
+        self.device_properties_supported.contains(&property.code())
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        Ok(DeviceInfo {
+            standard_version: reader.u16()?,
+            vendor_extension_id: reader.u32()?,
+            vendor_extension_version: reader.u16()?,
+            vendor_extension_desc: reader.string()?,
+            functional_mode: reader.u16()?,
+            operations_supported: reader.u16_array()?,
+            events_supported: reader.u16_array()?,
+            device_properties_supported: reader.u16_array()?,
+            capture_formats: reader.u16_array()?,
+            playback_formats: reader.u16_array()?,
+            manufacturer: reader.string()?,
+            model: reader.string()?,
+            device_version: reader.string()?,
+            serial_number: reader.string()?,
+        })
+    }
+}
+
+/// The PTP `ObjectInfo` dataset, describes an object (file or folder).
+#[derive(Debug, Clone, Default)]
+pub struct ObjectInfo {
+    pub storage_id: u32,
+    /// PTP object format, e.g. `FORMAT_ASSOCIATION` for folders.
+    pub format: u16,
+    pub protection_status: u16,
+    /// Size in bytes, `0xFFFFFFFF` for objects of 4 GiB or more.
+    pub size: u32,
+    pub parent: u32,
+    pub association_type: u16,
+    pub filename: String,
+    /// Creation date in the PTP format (`YYYYMMDDThhmmss`), may be empty.
+    pub date_created: String,
+    /// Modification date in the PTP format (`YYYYMMDDThhmmss`), may be empty.
+    pub date_modified: String,
+}
+
+impl ObjectInfo {
+    /// Object info to upload a file named `filename` with the given size and PTP format.
+    pub fn file(filename: impl Into<String>, size: u32, format: u16) -> Self {
+        ObjectInfo {
+            format,
+            size,
+            filename: filename.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Object info to create a folder named `filename`.
+    pub fn folder(filename: impl Into<String>) -> Self {
+        ObjectInfo {
+            format: FORMAT_ASSOCIATION,
+            association_type: ASSOCIATION_GENERIC_FOLDER,
+            filename: filename.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Check whether this object is a folder.
+    pub fn is_folder(&self) -> bool {
+        self.format == FORMAT_ASSOCIATION
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let storage_id = reader.u32()?;
+        let format = reader.u16()?;
+        let protection_status = reader.u16()?;
+        let size = reader.u32()?;
+        // Thumbnail format and size, thumbnail and image dimensions, image bit depth
+        reader.take(2 + 4 * 6)?;
+        let parent = reader.u32()?;
+        let association_type = reader.u16()?;
+        // Association description and sequence number
+        reader.take(4 * 2)?;
+        let filename = reader.string()?;
+        let date_created = reader.string()?;
+        let date_modified = reader.string()?;
+
+        Ok(ObjectInfo {
+            storage_id,
+            format,
+            protection_status,
+            size,
+            parent,
+            association_type,
+            filename,
+            date_created,
+            date_modified,
+        })
+    }
+
+    pub(crate) fn encode(&self) -> Result<Vec<u8>> {
+        let mut writer = Writer::default();
+
+        writer
+            .u32(self.storage_id)
+            .u16(self.format)
+            .u16(self.protection_status)
+            .u32(self.size)
+            .u16(0);
+        for _ in 0..6 {
+            writer.u32(0);
+        }
+        writer
+            .u32(self.parent)
+            .u16(self.association_type)
+            .u32(0)
+            .u32(0);
+        writer
+            .string(&self.filename)?
+            .string(&self.date_created)?
+            .string(&self.date_modified)?
+            .string("")?;
+
+        Ok(writer.buf)
+    }
+}
+
+/// An open PTP session over some transport.
+#[derive(Debug)]
+pub struct PtpSession<T: Transport> {
+    transport: T,
+    transaction_id: u32,
+}
+
+impl<T: Transport> PtpSession<T> {
+    /// Opens a session on the device behind `transport`.
+    pub fn open(transport: T) -> Result<Self> {
+        let mut session = PtpSession {
+            transport,
+            transaction_id: 0,
+        };
+
+        // The session id is 1 and transactions inside it start at 1 too
+        let (response, _) = session.raw_transaction(operation::OPEN_SESSION, &[1], None)?;
+        if response.code != RESPONSE_OK && response.code != RESPONSE_SESSION_ALREADY_OPEN {
+            return Err(response_error(response.code, operation::OPEN_SESSION));
+        }
+
+        Ok(session)
+    }
+
+    /// Closes the session and returns the transport.
+    pub fn close(mut self) -> Result<T> {
+        self.transaction(operation::CLOSE_SESSION, &[], None)?;
+        Ok(self.transport)
+    }
+
+    /// Runs a transaction and returns its response even if it isn't `RESPONSE_OK`.
+    pub fn raw_transaction(
+        &mut self,
+        code: u16,
+        params: &[u32],
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        let operation = Operation {
+            code,
+            transaction_id: self.transaction_id,
+            params: params.to_vec(),
+        };
+
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        self.transport.transaction(&operation, data)
+    }
+
+    /// Runs a transaction, fails if the response isn't `RESPONSE_OK`.
+    pub fn transaction(
+        &mut self,
+        code: u16,
+        params: &[u32],
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        let (response, data) = self.raw_transaction(code, params, data)?;
+        if response.code != RESPONSE_OK {
+            return Err(response_error(response.code, code));
+        }
+
+        Ok((response, data))
+    }
+
+    /// Returns the information of the device, like its names and the operations, events and
+    /// device properties it supports.
+    pub fn device_info(&mut self) -> Result<DeviceInfo> {
+        let (_, data) = self.transaction(operation::GET_DEVICE_INFO, &[], None)?;
+        DeviceInfo::decode(&data)
+    }
+
+    /// Returns the operations the device claims to support, e.g. to check precisely whether it
+    /// can move objects or read partial objects instead of guessing from the capabilities.
+    ///
+    /// These are only available on a session, `libmtp` doesn't expose the `DeviceInfo` dataset
+    /// of an [`MtpDevice`](../device/struct.MtpDevice.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::ptp::OperationCode;
+    ///
+    /// if session.supports_operation(OperationCode::GetPartialObject64)? {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn supported_operations(&mut self) -> Result<Vec<OperationCode>> {
+        Ok(self.device_info()?.operations())
+    }
+
+    /// Check whether the device claims to support the given operation.
+    pub fn supports_operation(&mut self, operation: OperationCode) -> Result<bool> {
+        Ok(self.device_info()?.supports_operation(operation))
+    }
+
+    /// Returns the events the device may send, e.g. to know upfront whether `ObjectAdded`
+    /// events will ever arrive or the device has to be polled.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::device::events::EventCode;
+    ///
+    /// if !session.supports_event(EventCode::ObjectAdded)? {
+    ///     // poll the object handles instead
+    /// }
+    /// ```
+    pub fn supported_events(&mut self) -> Result<Vec<EventCode>> {
+        Ok(self.device_info()?.events())
+    }
+
+    /// Check whether the device claims it may send the given event.
+    pub fn supports_event(&mut self, event: EventCode) -> Result<bool> {
+        Ok(self.device_info()?.supports_event(event))
+    }
+
+    /// Returns the ids of the storage of the device.
+    pub fn storage_ids(&mut self) -> Result<Vec<u32>> {
+        let (_, data) = self.transaction(operation::GET_STORAGE_IDS, &[], None)?;
+        Reader::new(&data).u32_array()
+    }
+
+    /// Returns the handles of the objects in a storage (`ALL_STORAGE` for every storage), if
+    /// `parent` is `None` every object is returned, otherwise only the ones in that folder.
+    pub fn object_handles(&mut self, storage_id: u32, parent: Option<Parent>) -> Result<Vec<u32>> {
+        let parent = parent.map_or(0, Parent::faf_id);
+        let (_, data) = self.transaction(
+            operation::GET_OBJECT_HANDLES,
+            &[storage_id, 0, parent],
+            None,
+        )?;
+        Reader::new(&data).u32_array()
+    }
+
+    /// Returns the information of an object.
+    pub fn object_info(&mut self, handle: u32) -> Result<ObjectInfo> {
+        let (_, data) = self.transaction(operation::GET_OBJECT_INFO, &[handle], None)?;
+        ObjectInfo::decode(&data)
+    }
+
+    /// Downloads the contents of an object.
+    pub fn get_object(&mut self, handle: u32) -> Result<Vec<u8>> {
+        let (_, data) = self.transaction(operation::GET_OBJECT, &[handle], None)?;
+        Ok(data)
+    }
+
+    /// Creates an object (file or folder) described by `info` in the given storage and folder,
+    /// for files `data` are its contents. Returns the handle of the new object.
+    pub fn send_object(
+        &mut self,
+        storage_id: u32,
+        parent: Parent,
+        info: &ObjectInfo,
+        data: &[u8],
+    ) -> Result<u32> {
+        let mut info = info.clone();
+        info.storage_id = storage_id;
+        info.parent = parent.faf_id();
+
+        let dataset = info.encode()?;
+        let (response, _) = self.transaction(
+            operation::SEND_OBJECT_INFO,
+            &[storage_id, parent.faf_id()],
+            Some(&dataset),
+        )?;
+
+        let handle = *response
+            .params
+            .get(2)
+            .ok_or_else(|| protocol_error("SendObjectInfo didn't return a handle"))?;
+
+        if !info.is_folder() {
+            self.transaction(operation::SEND_OBJECT, &[], Some(data))?;
+        }
+
+        Ok(handle)
+    }
+
+    /// Deletes an object.
+    pub fn delete_object(&mut self, handle: u32) -> Result<()> {
+        self.transaction(operation::DELETE_OBJECT, &[handle, 0], None)?;
+        Ok(())
+    }
+
+    /// Returns the handles referenced by an object, like the tracks of an album or playlist,
+    /// MTP lets any object reference others so this works with custom object types too.
+    pub fn object_references(&mut self, handle: u32) -> Result<Vec<u32>> {
+        let (_, data) = self.transaction(operation::GET_OBJECT_REFERENCES, &[handle], None)?;
+        Reader::new(&data).u32_array()
+    }
+
+    /// Replaces the handles referenced by an object.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let mut references = session.object_references(collection)?;
+    /// references.retain(|&handle| handle != removed);
+    /// session.set_object_references(collection, &references)?;
+    /// ```
+    pub fn set_object_references(&mut self, handle: u32, references: &[u32]) -> Result<()> {
+        let mut writer = Writer::default();
+        writer.u32_array(references);
+
+        self.transaction(
+            operation::SET_OBJECT_REFERENCES,
+            &[handle],
+            Some(&writer.buf),
+        )?;
+        Ok(())
+    }
+
+    /// Returns a reference to the transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
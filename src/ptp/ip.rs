@@ -0,0 +1,334 @@
+//! PTP transport over TCP/IP (PTP/IP), used by cameras exposing MTP over Wi-Fi.
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::{Error, MtpErrorKind};
+use crate::ptp::{protocol_error, Operation, Reader, Response, Transport, Writer};
+use crate::Result;
+
+/// Default port of PTP/IP responders.
+pub const DEFAULT_PORT: u16 = 15740;
+
+/// Default timeout of reads and writes on both connections.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PROTOCOL_VERSION: u32 = 0x0001_0000;
+
+const INIT_COMMAND_REQUEST: u32 = 1;
+const INIT_COMMAND_ACK: u32 = 2;
+const INIT_EVENT_REQUEST: u32 = 3;
+const INIT_EVENT_ACK: u32 = 4;
+const INIT_FAIL: u32 = 5;
+const OPERATION_REQUEST: u32 = 6;
+const OPERATION_RESPONSE: u32 = 7;
+const START_DATA: u32 = 9;
+const DATA: u32 = 10;
+const END_DATA: u32 = 12;
+
+const DATA_PHASE_NONE_OR_IN: u32 = 1;
+const DATA_PHASE_OUT: u32 = 2;
+
+const HEADER_LEN: usize = 8;
+
+/// Largest payload of the data packets sent to the responder.
+const MAX_DATA_CHUNK: usize = 64 * 1024;
+
+/// Largest packet accepted from the responder, a bogus length must not allocate gigabytes.
+const MAX_PACKET_LEN: usize = 64 * 1024 * 1024;
+
+/// Largest buffer allocated up front for the data announced by the responder, it grows as
+/// the data arrives.
+const MAX_DATA_PREALLOC: u64 = 1024 * 1024;
+
+fn write_packet(stream: &mut TcpStream, kind: u32, payload: &[u8]) -> Result<()> {
+    let mut writer = Writer::default();
+    writer.u32((HEADER_LEN + payload.len()) as u32).u32(kind);
+    writer.buf.extend_from_slice(payload);
+
+    stream.write_all(&writer.buf)?;
+    Ok(())
+}
+
+fn read_packet(stream: &mut impl Read) -> Result<(u32, Vec<u8>)> {
+    let mut header = [0; HEADER_LEN];
+    stream.read_exact(&mut header)?;
+
+    let mut reader = Reader::new(&header);
+    let len = reader.u32()? as usize;
+    let kind = reader.u32()?;
+
+    if len < HEADER_LEN {
+        return Err(protocol_error("Truncated PTP/IP packet"));
+    }
+
+    if len > MAX_PACKET_LEN {
+        return Err(protocol_error(format!(
+            "PTP/IP packet of {} bytes is too large",
+            len
+        )));
+    }
+
+    // Read as it arrives, instead of allocating whatever length the responder claims
+    let payload_len = len - HEADER_LEN;
+    let mut payload = Vec::new();
+    stream.take(payload_len as u64).read_to_end(&mut payload)?;
+
+    if payload.len() < payload_len {
+        return Err(protocol_error("Truncated PTP/IP packet"));
+    }
+
+    Ok((kind, payload))
+}
+
+/// PTP/IP names are nul-terminated UTF-16 strings without a length prefix.
+fn name_to_bytes(name: &str) -> Vec<u8> {
+    name.encode_utf16()
+        .chain(Some(0))
+        .flat_map(|unit| unit.to_le_bytes().to_vec())
+        .collect()
+}
+
+fn name_from_bytes(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+fn init_failed(payload: &[u8]) -> Error {
+    let reason = Reader::new(payload).u32().unwrap_or_default();
+    Error::MtpError {
+        kind: MtpErrorKind::Connecting,
+        text: format!("PTP/IP connection refused (reason {:#x})", reason),
+        details: Vec::new(),
+    }
+}
+
+/// PTP transport over the command and event connections of a PTP/IP responder.
+#[derive(Debug)]
+pub struct IpTransport {
+    command: TcpStream,
+    // The responder drops the session if the event connection is closed
+    _event: TcpStream,
+    connection_number: u32,
+    responder_guid: [u8; 16],
+    responder_name: String,
+}
+
+impl IpTransport {
+    /// Connects to the PTP/IP responder at `addr` (usually on port `DEFAULT_PORT`), the
+    /// initiator identifies itself with `guid` and `name`, some cameras ask the user to accept
+    /// new initiators and remember them by their GUID, so keep it stable.
+    pub fn connect(addr: impl ToSocketAddrs, guid: [u8; 16], name: &str) -> Result<Self> {
+        let mut command = TcpStream::connect(&addr)?;
+        command.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        command.set_write_timeout(Some(DEFAULT_TIMEOUT))?;
+        command.set_nodelay(true)?;
+
+        let mut payload = guid.to_vec();
+        payload.extend(name_to_bytes(name));
+        payload.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        write_packet(&mut command, INIT_COMMAND_REQUEST, &payload)?;
+
+        let (kind, payload) = read_packet(&mut command)?;
+        match kind {
+            // Connection number, GUID and protocol version, besides the name
+            INIT_COMMAND_ACK if payload.len() >= 24 => {}
+            INIT_FAIL => return Err(init_failed(&payload)),
+            _ => return Err(protocol_error("Unexpected PTP/IP init command response")),
+        }
+
+        let connection_number = Reader::new(&payload).u32()?;
+        let mut responder_guid = [0; 16];
+        responder_guid.copy_from_slice(&payload[4..20]);
+        // The name is followed by the protocol version
+        let responder_name = name_from_bytes(&payload[20..payload.len() - 4]);
+
+        let mut event = TcpStream::connect(command.peer_addr()?)?;
+        event.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        write_packet(
+            &mut event,
+            INIT_EVENT_REQUEST,
+            &connection_number.to_le_bytes(),
+        )?;
+
+        let (kind, payload) = read_packet(&mut event)?;
+        match kind {
+            INIT_EVENT_ACK => {}
+            INIT_FAIL => return Err(init_failed(&payload)),
+            _ => return Err(protocol_error("Unexpected PTP/IP init event response")),
+        }
+
+        Ok(IpTransport {
+            command,
+            _event: event,
+            connection_number,
+            responder_guid,
+            responder_name,
+        })
+    }
+
+    /// Changes the timeout of reads and writes on the command connection.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.command.set_read_timeout(Some(timeout))?;
+        self.command.set_write_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    /// Returns the connection number assigned by the responder.
+    pub fn connection_number(&self) -> u32 {
+        self.connection_number
+    }
+
+    /// Returns the GUID of the responder.
+    pub fn responder_guid(&self) -> [u8; 16] {
+        self.responder_guid
+    }
+
+    /// Returns the friendly name of the responder.
+    pub fn responder_name(&self) -> &str {
+        &self.responder_name
+    }
+
+    fn send_data(&mut self, transaction_id: u32, data: &[u8]) -> Result<()> {
+        let mut start = Writer::default();
+        start.u32(transaction_id);
+        start
+            .buf
+            .extend_from_slice(&(data.len() as u64).to_le_bytes());
+        write_packet(&mut self.command, START_DATA, &start.buf)?;
+
+        let mut chunks = data.chunks(MAX_DATA_CHUNK).peekable();
+        if chunks.peek().is_none() {
+            return write_packet(&mut self.command, END_DATA, &transaction_id.to_le_bytes());
+        }
+
+        while let Some(chunk) = chunks.next() {
+            let kind = if chunks.peek().is_some() {
+                DATA
+            } else {
+                END_DATA
+            };
+
+            let mut payload = Vec::with_capacity(4 + chunk.len());
+            payload.extend_from_slice(&transaction_id.to_le_bytes());
+            payload.extend_from_slice(chunk);
+            write_packet(&mut self.command, kind, &payload)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for IpTransport {
+    fn transaction(
+        &mut self,
+        operation: &Operation,
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        let data_phase = if data.is_some() {
+            DATA_PHASE_OUT
+        } else {
+            DATA_PHASE_NONE_OR_IN
+        };
+
+        let mut request = Writer::default();
+        request
+            .u32(data_phase)
+            .u16(operation.code)
+            .u32(operation.transaction_id);
+        for &param in &operation.params {
+            request.u32(param);
+        }
+        write_packet(&mut self.command, OPERATION_REQUEST, &request.buf)?;
+
+        if let Some(data) = data {
+            self.send_data(operation.transaction_id, data)?;
+        }
+
+        let mut received = Vec::new();
+        loop {
+            let (kind, payload) = read_packet(&mut self.command)?;
+
+            match kind {
+                START_DATA => {
+                    let total = payload
+                        .get(4..12)
+                        .map(|len| u64::from_le_bytes(len.try_into().unwrap()))
+                        .unwrap_or_default();
+                    received = Vec::with_capacity(total.min(MAX_DATA_PREALLOC) as usize);
+                }
+                DATA | END_DATA if payload.len() >= 4 => received.extend_from_slice(&payload[4..]),
+                OPERATION_RESPONSE => {
+                    let mut reader = Reader::new(&payload);
+                    let code = reader.u16()?;
+                    let transaction_id = reader.u32()?;
+                    let params = (0..payload.len().saturating_sub(6) / 4)
+                        .map(|_| reader.u32())
+                        .collect::<Result<_>>()?;
+
+                    let response = Response {
+                        code,
+                        transaction_id,
+                        params,
+                    };
+
+                    return Ok((response, received));
+                }
+                kind => {
+                    return Err(protocol_error(format!(
+                        "Unexpected PTP/IP packet of type {}",
+                        kind
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(len: u32, kind: u32, payload: &[u8]) -> Vec<u8> {
+        let mut packet = len.to_le_bytes().to_vec();
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn reads_packets() {
+        let data = packet(12, DATA, &[1, 2, 3, 4]);
+        let (kind, payload) = read_packet(&mut data.as_slice()).unwrap();
+
+        assert_eq!(kind, DATA);
+        assert_eq!(payload, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_lengths_shorter_than_the_header() {
+        for len in 0..HEADER_LEN as u32 {
+            let data = packet(len, DATA, &[0; 16]);
+            assert!(read_packet(&mut data.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_packets() {
+        let data = packet(u32::MAX, DATA, &[0; 16]);
+        assert!(read_packet(&mut data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let data = packet(1024, DATA, &[0; 16]);
+        assert!(read_packet(&mut data.as_slice()).is_err());
+    }
+}
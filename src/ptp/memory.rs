@@ -0,0 +1,390 @@
+//! PTP transport to a device that only exists in memory, so code built on
+//! [`PtpSession`](../struct.PtpSession.html) can be tested and benchmarked without an attached
+//! device.
+//!
+//! It answers the core operation set used by `PtpSession` with a single storage, objects
+//! uploaded to it are kept in memory until they're deleted.
+//!
+//! ## Example
+//! ```no_run
+//! use libmtp_rs::ptp::{memory::MemoryTransport, ObjectInfo, PtpSession, FORMAT_UNDEFINED};
+//! use libmtp_rs::storage::Parent;
+//!
+//! let mut session = PtpSession::open(MemoryTransport::new())?;
+//! let storage_id = session.storage_ids()?[0];
+//!
+//! let info = ObjectInfo::file("notes.txt", 5, FORMAT_UNDEFINED);
+//! let handle = session.send_object(storage_id, Parent::Root, &info, b"hello")?;
+//! assert_eq!(session.get_object(handle)?, b"hello");
+//! ```
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::error::PtpResponse;
+use crate::ptp::{
+    operation, ObjectInfo, Operation, Reader, Response, Transport, Writer, ALL_STORAGE, RESPONSE_OK,
+};
+use crate::Result;
+
+/// Id of the storage of the device.
+pub const STORAGE_ID: u32 = 0x0001_0001;
+
+/// Parent of the objects in the root folder, as stored in their `ObjectInfo`.
+const ROOT: u32 = 0;
+
+/// Parent given to `GetObjectHandles` and `SendObjectInfo` for the root folder.
+const ROOT_PARAM: u32 = 0xFFFF_FFFF;
+
+const OPERATIONS: [u16; 13] = [
+    operation::GET_DEVICE_INFO,
+    operation::OPEN_SESSION,
+    operation::CLOSE_SESSION,
+    operation::GET_STORAGE_IDS,
+    operation::GET_OBJECT_HANDLES,
+    operation::GET_OBJECT_INFO,
+    operation::GET_OBJECT,
+    operation::DELETE_OBJECT,
+    operation::SEND_OBJECT_INFO,
+    operation::SEND_OBJECT,
+    operation::GET_OBJECT_REFERENCES,
+    operation::SET_OBJECT_REFERENCES,
+    // Unsupported device properties are answered with `DevicePropNotSupported`
+    operation::GET_DEVICE_PROP_DESC,
+];
+
+#[derive(Debug)]
+struct MemoryObject {
+    info: ObjectInfo,
+    data: Vec<u8>,
+    references: Vec<u32>,
+}
+
+/// A device in memory with a single storage, see the [module docs](index.html).
+#[derive(Debug)]
+pub struct MemoryTransport {
+    objects: BTreeMap<u32, MemoryObject>,
+    next_handle: u32,
+    /// Object created by the last `SendObjectInfo`, waiting for its data.
+    pending: Option<u32>,
+}
+
+impl Default for MemoryTransport {
+    fn default() -> Self {
+        MemoryTransport::new()
+    }
+}
+
+/// Outcome of an operation, the response code with its parameters and data.
+type Answer = (u16, Vec<u32>, Vec<u8>);
+
+fn ok(data: Vec<u8>) -> Answer {
+    (RESPONSE_OK, Vec::new(), data)
+}
+
+fn fail(response: PtpResponse) -> Answer {
+    (response.code(), Vec::new(), Vec::new())
+}
+
+fn u32_array(values: &[u32]) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.u32_array(values);
+    writer.buf
+}
+
+impl MemoryTransport {
+    /// Creates a device with an empty storage.
+    pub fn new() -> Self {
+        MemoryTransport {
+            objects: BTreeMap::new(),
+            next_handle: 1,
+            pending: None,
+        }
+    }
+
+    /// Returns the number of objects (files and folders) stored.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Check whether the storage is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    fn device_info() -> Result<Vec<u8>> {
+        let mut writer = Writer::default();
+        writer.u16(100).u32(6).u16(100);
+        writer.string("microsoft.com: 1.0;")?;
+        writer.u16(0);
+
+        writer.u32(OPERATIONS.len() as u32);
+        for code in OPERATIONS {
+            writer.u16(code);
+        }
+        // Events, device properties, capture and playback formats
+        for _ in 0..4 {
+            writer.u32(0);
+        }
+
+        for string in ["libmtp-rs", "Memory device", "1.0", "MEMORY"] {
+            writer.string(string)?;
+        }
+
+        Ok(writer.buf)
+    }
+
+    fn object_handles(&self, storage_id: u32, parent: u32) -> Answer {
+        if storage_id != STORAGE_ID && storage_id != ALL_STORAGE {
+            return fail(PtpResponse::InvalidStorageId);
+        }
+
+        let handles: Vec<u32> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| match parent {
+                0 => true,
+                ROOT_PARAM => object.info.parent == ROOT,
+                parent => object.info.parent == parent,
+            })
+            .map(|(&handle, _)| handle)
+            .collect();
+
+        ok(u32_array(&handles))
+    }
+
+    fn send_object_info(&mut self, params: &[u32], data: Option<&[u8]>) -> Result<Answer> {
+        let storage_id = params.first().copied().unwrap_or(STORAGE_ID);
+        if storage_id != STORAGE_ID {
+            return Ok(fail(PtpResponse::InvalidStorageId));
+        }
+
+        let parent = match params.get(1).copied().unwrap_or(ROOT_PARAM) {
+            ROOT | ROOT_PARAM => ROOT,
+            parent
+                if self
+                    .objects
+                    .get(&parent)
+                    .is_some_and(|o| o.info.is_folder()) =>
+            {
+                parent
+            }
+            _ => return Ok(fail(PtpResponse::InvalidParentObject)),
+        };
+
+        let mut info = match data {
+            Some(data) => ObjectInfo::decode(data)?,
+            None => return Ok(fail(PtpResponse::InvalidDataset)),
+        };
+        info.storage_id = STORAGE_ID;
+        info.parent = parent;
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        let is_folder = info.is_folder();
+        self.objects.insert(
+            handle,
+            MemoryObject {
+                info,
+                data: Vec::new(),
+                references: Vec::new(),
+            },
+        );
+
+        // Folders have no data phase
+        self.pending = if is_folder { None } else { Some(handle) };
+
+        let parent = if parent == ROOT { ROOT_PARAM } else { parent };
+        Ok((RESPONSE_OK, vec![STORAGE_ID, parent, handle], Vec::new()))
+    }
+
+    fn send_object(&mut self, data: Option<&[u8]>) -> Answer {
+        let object = match self.pending.take() {
+            Some(handle) => self.objects.get_mut(&handle),
+            None => None,
+        };
+
+        match object {
+            Some(object) => {
+                object.data = data.unwrap_or_default().to_vec();
+                object.info.size = u32::try_from(object.data.len()).unwrap_or(u32::MAX);
+                ok(Vec::new())
+            }
+            None => fail(PtpResponse::NoValidObjectInfo),
+        }
+    }
+
+    fn delete_object(&mut self, handle: u32) -> Answer {
+        if !self.objects.contains_key(&handle) {
+            return fail(PtpResponse::InvalidObjectHandle);
+        }
+
+        // Deleting a folder deletes everything below it
+        let mut deleted = vec![handle];
+        while let Some(handle) = deleted.pop() {
+            self.objects.remove(&handle);
+            deleted.extend(
+                self.objects
+                    .iter()
+                    .filter(|(_, object)| object.info.parent == handle)
+                    .map(|(&child, _)| child),
+            );
+        }
+
+        let handles: Vec<u32> = self.objects.keys().copied().collect();
+        for object in self.objects.values_mut() {
+            object
+                .references
+                .retain(|reference| handles.binary_search(reference).is_ok());
+        }
+
+        ok(Vec::new())
+    }
+
+    fn answer(&mut self, operation: &Operation, data: Option<&[u8]>) -> Result<Answer> {
+        let first = operation.params.first().copied().unwrap_or(0);
+
+        let answer = match operation.code {
+            operation::GET_DEVICE_INFO => ok(Self::device_info()?),
+            operation::OPEN_SESSION | operation::CLOSE_SESSION => ok(Vec::new()),
+            operation::GET_STORAGE_IDS => ok(u32_array(&[STORAGE_ID])),
+            operation::GET_OBJECT_HANDLES => {
+                let parent = operation.params.get(2).copied().unwrap_or(0);
+                self.object_handles(first, parent)
+            }
+            operation::GET_OBJECT_INFO => match self.objects.get(&first) {
+                Some(object) => ok(object.info.encode()?),
+                None => fail(PtpResponse::InvalidObjectHandle),
+            },
+            operation::GET_OBJECT => match self.objects.get(&first) {
+                Some(object) => ok(object.data.clone()),
+                None => fail(PtpResponse::InvalidObjectHandle),
+            },
+            operation::DELETE_OBJECT => self.delete_object(first),
+            operation::SEND_OBJECT_INFO => self.send_object_info(&operation.params, data)?,
+            operation::SEND_OBJECT => self.send_object(data),
+            operation::GET_OBJECT_REFERENCES => match self.objects.get(&first) {
+                Some(object) => ok(u32_array(&object.references)),
+                None => fail(PtpResponse::InvalidObjectHandle),
+            },
+            operation::SET_OBJECT_REFERENCES => {
+                let references = Reader::new(data.unwrap_or_default()).u32_array()?;
+                if references.iter().any(|r| !self.objects.contains_key(r)) {
+                    fail(PtpResponse::InvalidObjectReference)
+                } else if let Some(object) = self.objects.get_mut(&first) {
+                    object.references = references;
+                    ok(Vec::new())
+                } else {
+                    fail(PtpResponse::InvalidObjectHandle)
+                }
+            }
+            operation::GET_DEVICE_PROP_DESC => fail(PtpResponse::DevicePropNotSupported),
+            _ => fail(PtpResponse::OperationNotSupported),
+        };
+
+        Ok(answer)
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn transaction(
+        &mut self,
+        operation: &Operation,
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        // `SendObject` has to follow its `SendObjectInfo` right away
+        if operation.code != operation::SEND_OBJECT {
+            self.pending = None;
+        }
+
+        let (code, params, data) = self.answer(operation, data)?;
+        let response = Response {
+            code,
+            transaction_id: operation.transaction_id,
+            params,
+        };
+
+        Ok((response, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ptp::{PtpSession, FORMAT_UNDEFINED};
+    use crate::storage::Parent;
+
+    fn session() -> PtpSession<MemoryTransport> {
+        PtpSession::open(MemoryTransport::new()).unwrap()
+    }
+
+    #[test]
+    fn uploads_can_be_listed_and_downloaded() {
+        let mut session = session();
+        let folder = session
+            .send_object(STORAGE_ID, Parent::Root, &ObjectInfo::folder("Music"), &[])
+            .unwrap();
+        let info = ObjectInfo::file("song.mp3", 4, FORMAT_UNDEFINED);
+        let file = session
+            .send_object(STORAGE_ID, Parent::Folder(folder), &info, b"data")
+            .unwrap();
+
+        assert_eq!(
+            session
+                .object_handles(STORAGE_ID, Some(Parent::Root))
+                .unwrap(),
+            [folder]
+        );
+        assert_eq!(
+            session
+                .object_handles(STORAGE_ID, Some(Parent::Folder(folder)))
+                .unwrap(),
+            [file]
+        );
+        assert_eq!(session.object_handles(ALL_STORAGE, None).unwrap().len(), 2);
+
+        let info = session.object_info(file).unwrap();
+        assert_eq!(info.filename, "song.mp3");
+        assert_eq!(info.parent, folder);
+        assert_eq!(info.size, 4);
+        assert_eq!(session.get_object(file).unwrap(), b"data");
+    }
+
+    #[test]
+    fn deleting_a_folder_deletes_its_contents() {
+        let mut session = session();
+        let folder = session
+            .send_object(STORAGE_ID, Parent::Root, &ObjectInfo::folder("DCIM"), &[])
+            .unwrap();
+        let info = ObjectInfo::file("photo.jpg", 1, FORMAT_UNDEFINED);
+        let file = session
+            .send_object(STORAGE_ID, Parent::Folder(folder), &info, b"x")
+            .unwrap();
+
+        session.delete_object(folder).unwrap();
+
+        assert!(session.transport().is_empty());
+        let err = session.get_object(file).unwrap_err();
+        assert_eq!(err.ptp_response(), Some(PtpResponse::InvalidObjectHandle));
+    }
+
+    #[test]
+    fn references_must_exist() {
+        let mut session = session();
+        let info = ObjectInfo::file("a.mp3", 0, FORMAT_UNDEFINED);
+        let track = session
+            .send_object(STORAGE_ID, Parent::Root, &info, &[])
+            .unwrap();
+        let info = ObjectInfo::file("list.pla", 0, FORMAT_UNDEFINED);
+        let playlist = session
+            .send_object(STORAGE_ID, Parent::Root, &info, &[])
+            .unwrap();
+
+        session.set_object_references(playlist, &[track]).unwrap();
+        assert_eq!(session.object_references(playlist).unwrap(), [track]);
+        assert!(session.set_object_references(playlist, &[99]).is_err());
+
+        session.delete_object(track).unwrap();
+        assert!(session.object_references(playlist).unwrap().is_empty());
+    }
+}
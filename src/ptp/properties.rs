@@ -0,0 +1,430 @@
+//! Contains the device properties of PTP sessions (like the clock or the battery level of the
+//! device), `libmtp` doesn't expose device properties other than the friendly name, sync
+//! partner and battery level, so these are only available on a `PtpSession` and not on an
+//! [`MtpDevice`](../../device/struct.MtpDevice.html). Release the `MtpDevice` before opening
+//! the session, both can't claim the USB interface at the same time.
+//!
+//! This is why the clock of the device can't be set from an `MtpDevice`: the public API of
+//! `libmtp` has no way to send a device property value (`LIBMTP_Custom_Operation` has no data
+//! phase), so `set_datetime`, `device_properties`, `get_device_property_raw` and
+//! `set_device_property_raw` are only available on a `PtpSession`, opened with
+//! [`RawDevice::open_ptp`](../../device/raw/struct.RawDevice.html#method.open_ptp).
+//!
+//! ## Example
+//! ```no_run
+//! for (property, value) in session.device_properties()? {
+//!     match value {
+//!         Ok(value) => println!("{:?}: {}", property, value),
+//!         Err(err) => println!("{:?}: {}", property, err),
+//!     }
+//! }
+//! ```
+
+use chrono::{DateTime, Local};
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{self, Display};
+use std::time::SystemTime;
+
+use crate::error::Error;
+use crate::ptp::{operation, protocol_error, PtpSession, Reader, Transport, Writer};
+use crate::Result;
+
+/// PTP data type of strings, arrays are the element type with the `ARRAY` bit set.
+const TYPE_STRING: u16 = 0xFFFF;
+const TYPE_ARRAY: u16 = 0x4000;
+
+macro_rules! device_properties {
+    ($($(#[$meta:meta])* $name:ident = $code:literal,)*) => {
+        /// Known PTP and MTP device properties, other codes (like vendor-specific ones) are
+        /// kept in `Other`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+        pub enum DeviceProperty {
+            $($(#[$meta])* $name,)*
+            Other(u16),
+        }
+
+        impl DeviceProperty {
+            /// Returns the PTP code of this property.
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(DeviceProperty::$name => $code,)*
+                    DeviceProperty::Other(code) => *code,
+                }
+            }
+
+            /// Returns the property with the given PTP code.
+            pub fn from_code(code: u16) -> Self {
+                match code {
+                    $($code => DeviceProperty::$name,)*
+                    code => DeviceProperty::Other(code),
+                }
+            }
+        }
+    };
+}
+
+device_properties! {
+    /// Battery level, usually a percentage.
+    BatteryLevel = 0x5001,
+    FunctionalMode = 0x5002,
+    ImageSize = 0x5003,
+    CompressionSetting = 0x5004,
+    WhiteBalance = 0x5005,
+    RgbGain = 0x5006,
+    FNumber = 0x5007,
+    FocalLength = 0x5008,
+    FocusDistance = 0x5009,
+    FocusMode = 0x500A,
+    ExposureMeteringMode = 0x500B,
+    FlashMode = 0x500C,
+    ExposureTime = 0x500D,
+    ExposureProgramMode = 0x500E,
+    ExposureIndex = 0x500F,
+    ExposureBiasCompensation = 0x5010,
+    /// Clock of the device, as `YYYYMMDDThhmmss`.
+    DateTime = 0x5011,
+    CaptureDelay = 0x5012,
+    StillCaptureMode = 0x5013,
+    Contrast = 0x5014,
+    Sharpness = 0x5015,
+    DigitalZoom = 0x5016,
+    EffectMode = 0x5017,
+    BurstNumber = 0x5018,
+    BurstInterval = 0x5019,
+    TimelapseNumber = 0x501A,
+    TimelapseInterval = 0x501B,
+    FocusMeteringMode = 0x501C,
+    UploadUrl = 0x501D,
+    Artist = 0x501E,
+    CopyrightInfo = 0x501F,
+    SynchronizationPartner = 0xD401,
+    DeviceFriendlyName = 0xD402,
+    Volume = 0xD403,
+    SupportedFormatsOrdered = 0xD404,
+    /// Icon of the device, in the Windows `.ico` format.
+    DeviceIcon = 0xD405,
+    SessionInitiatorVersionInfo = 0xD406,
+    PerceivedDeviceType = 0xD407,
+    PlaybackRate = 0xD410,
+    PlaybackObject = 0xD411,
+    PlaybackContainerIndex = 0xD412,
+    PlaybackPosition = 0xD413,
+}
+
+/// Value of a device property, with the PTP data type the device uses for it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    Str(String),
+    /// Array of values of the same type.
+    Array(Vec<PropertyValue>),
+}
+
+impl PropertyValue {
+    pub(crate) fn decode(reader: &mut Reader<'_>, data_type: u16) -> Result<Self> {
+        Ok(match data_type {
+            0x0001 => PropertyValue::I8(reader.u8()? as i8),
+            0x0002 => PropertyValue::U8(reader.u8()?),
+            0x0003 => PropertyValue::I16(reader.u16()? as i16),
+            0x0004 => PropertyValue::U16(reader.u16()?),
+            0x0005 => PropertyValue::I32(reader.u32()? as i32),
+            0x0006 => PropertyValue::U32(reader.u32()?),
+            0x0007 => PropertyValue::I64(i64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+            0x0008 => PropertyValue::U64(u64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+            0x0009 => {
+                PropertyValue::I128(i128::from_le_bytes(reader.take(16)?.try_into().unwrap()))
+            }
+            0x000A => {
+                PropertyValue::U128(u128::from_le_bytes(reader.take(16)?.try_into().unwrap()))
+            }
+            TYPE_STRING => PropertyValue::Str(reader.string()?),
+            data_type if data_type & TYPE_ARRAY != 0 && data_type != TYPE_STRING => {
+                let len = reader.u32()?;
+                let values = (0..len)
+                    .map(|_| PropertyValue::decode(reader, data_type & !TYPE_ARRAY))
+                    .collect::<Result<_>>()?;
+                PropertyValue::Array(values)
+            }
+            data_type => {
+                return Err(protocol_error(format!(
+                    "Unknown PTP data type {:04x}",
+                    data_type
+                )))
+            }
+        })
+    }
+
+    pub(crate) fn encode(&self, writer: &mut Writer) -> Result<()> {
+        match self {
+            PropertyValue::I8(value) => writer.buf.push(*value as u8),
+            PropertyValue::U8(value) => writer.buf.push(*value),
+            PropertyValue::I16(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::U16(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::I32(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::U32(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::I64(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::U64(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::I128(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::U128(value) => writer.buf.extend_from_slice(&value.to_le_bytes()),
+            PropertyValue::Str(value) => {
+                writer.string(value)?;
+            }
+            PropertyValue::Array(values) => {
+                writer.u32(values.len() as u32);
+                for value in values {
+                    value.encode(writer)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value as an `u64` if it's an integer that fits.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            PropertyValue::I8(value) => u64::try_from(value).ok(),
+            PropertyValue::U8(value) => Some(value.into()),
+            PropertyValue::I16(value) => u64::try_from(value).ok(),
+            PropertyValue::U16(value) => Some(value.into()),
+            PropertyValue::I32(value) => u64::try_from(value).ok(),
+            PropertyValue::U32(value) => Some(value.into()),
+            PropertyValue::I64(value) => u64::try_from(value).ok(),
+            PropertyValue::U64(value) => Some(value),
+            PropertyValue::I128(value) => u64::try_from(value).ok(),
+            PropertyValue::U128(value) => u64::try_from(value).ok(),
+            PropertyValue::Str(_) | PropertyValue::Array(_) => None,
+        }
+    }
+
+    /// Returns the value as a string slice if it's a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValue::I8(value) => write!(f, "{}", value),
+            PropertyValue::U8(value) => write!(f, "{}", value),
+            PropertyValue::I16(value) => write!(f, "{}", value),
+            PropertyValue::U16(value) => write!(f, "{}", value),
+            PropertyValue::I32(value) => write!(f, "{}", value),
+            PropertyValue::U32(value) => write!(f, "{}", value),
+            PropertyValue::I64(value) => write!(f, "{}", value),
+            PropertyValue::U64(value) => write!(f, "{}", value),
+            PropertyValue::I128(value) => write!(f, "{}", value),
+            PropertyValue::U128(value) => write!(f, "{}", value),
+            PropertyValue::Str(value) => write!(f, "{:?}", value),
+            // Big arrays (like the device icon) would flood the output
+            PropertyValue::Array(values) => write!(f, "[{} values]", values.len()),
+        }
+    }
+}
+
+impl<T: Transport> PtpSession<T> {
+    /// Returns the device properties the device claims to support, e.g. to only show the
+    /// applicable settings.
+    pub fn supported_device_properties(&mut self) -> Result<Vec<DeviceProperty>> {
+        Ok(self.device_info()?.device_properties())
+    }
+
+    /// Check whether the device claims to support the given device property.
+    pub fn supports_device_property(&mut self, property: DeviceProperty) -> Result<bool> {
+        Ok(self.device_info()?.supports_device_property(property))
+    }
+
+    /// Returns the current value of a device property, asking the device for its description
+    /// to know the data type.
+    pub fn device_property(&mut self, property: DeviceProperty) -> Result<PropertyValue> {
+        let (_, data) = self.transaction(
+            operation::GET_DEVICE_PROP_DESC,
+            &[property.code() as u32],
+            None,
+        )?;
+
+        let mut reader = Reader::new(&data);
+        // Property code
+        reader.u16()?;
+        let data_type = reader.u16()?;
+        // Whether the property can be set
+        reader.u8()?;
+        // Factory default value, then the current one
+        PropertyValue::decode(&mut reader, data_type)?;
+        PropertyValue::decode(&mut reader, data_type)
+    }
+
+    /// Sets a device property, `value` must have the data type the device uses for the
+    /// property (check it with `device_property`), otherwise the device rejects it.
+    pub fn set_device_property(
+        &mut self,
+        property: DeviceProperty,
+        value: &PropertyValue,
+    ) -> Result<()> {
+        self.set_device_property_raw(property.code(), value)
+    }
+
+    /// Like `device_property` but with the raw PTP code, an escape hatch for vendor-specific
+    /// properties. There's no `MtpDevice` counterpart, see the [module docs](index.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// // Some vendor-specific knob
+    /// let value = session.get_device_property_raw(0xD2C1)?;
+    /// if let Some(level) = value.as_u64() {
+    ///     session.set_device_property_raw(0xD2C1, &PropertyValue::U8(level as u8 + 1))?;
+    /// }
+    /// ```
+    pub fn get_device_property_raw(&mut self, code: u16) -> Result<PropertyValue> {
+        self.device_property(DeviceProperty::from_code(code))
+    }
+
+    /// Like `set_device_property` but with the raw PTP code.
+    pub fn set_device_property_raw(&mut self, code: u16, value: &PropertyValue) -> Result<()> {
+        let mut writer = Writer::default();
+        value.encode(&mut writer)?;
+
+        self.transaction(
+            operation::SET_DEVICE_PROP_VALUE,
+            &[code as u32],
+            Some(&writer.buf),
+        )?;
+        Ok(())
+    }
+
+    /// Reads every device property the device reports as supported (battery level, sync
+    /// partner, device icon, playback rate, etc), properties the `DeviceProperty` enum doesn't
+    /// know are kept as `DeviceProperty::Other`. Each property comes with its own result, so a
+    /// property the device fails to read (or with a data type this crate can't decode) doesn't
+    /// hide the rest.
+    ///
+    /// There's no `MtpDevice` counterpart, see the [module docs](index.html).
+    pub fn device_properties(&mut self) -> Result<Vec<(DeviceProperty, Result<PropertyValue>)>> {
+        let info = self.device_info()?;
+
+        Ok(info
+            .device_properties_supported
+            .into_iter()
+            .map(|code| {
+                let property = DeviceProperty::from_code(code);
+                (property, self.device_property(property))
+            })
+            .collect())
+    }
+
+    /// Sets the clock of the device to the given time, as local time since PTP devices (like
+    /// cameras) don't know about time zones. Fails with `Error::InvalidArgument` if the device
+    /// doesn't report the `DateTime` property as supported.
+    ///
+    /// There's no `MtpDevice` counterpart, `libmtp` can't send device property values, see the
+    /// [module docs](index.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let mut session = raw_device.open_ptp()?;
+    /// session.set_datetime(SystemTime::now())?;
+    /// ```
+    pub fn set_datetime(&mut self, time: SystemTime) -> Result<()> {
+        let code = DeviceProperty::DateTime.code();
+        let info = self.device_info()?;
+        if !info.device_properties_supported.contains(&code) {
+            return Err(Error::InvalidArgument {
+                reason: format!("{} doesn't support setting its clock", info.model),
+            });
+        }
+
+        let time: DateTime<Local> = time.into();
+        let time = PropertyValue::Str(time.format("%Y%m%dT%H%M%S").to_string());
+        self.set_device_property_raw(code, &time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ptp::{Operation, Response, RESPONSE_OK};
+
+    /// Answers with a device supporting the battery level and the clock, but failing to
+    /// describe the clock.
+    struct FakeTransport;
+
+    fn u16_array(writer: &mut Writer, values: &[u16]) {
+        writer.u32(values.len() as u32);
+        for &value in values {
+            writer.u16(value);
+        }
+    }
+
+    fn device_info() -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.u16(100).u32(6).u16(100);
+        writer.string("microsoft.com: 1.0;").unwrap();
+        writer.u16(0);
+        u16_array(&mut writer, &[operation::GET_DEVICE_PROP_DESC]);
+        u16_array(&mut writer, &[]);
+        u16_array(&mut writer, &[0x5001, 0x5011]);
+        u16_array(&mut writer, &[]);
+        u16_array(&mut writer, &[]);
+        for string in ["Vendor", "Player", "1.0", "0A1B2C3D"] {
+            writer.string(string).unwrap();
+        }
+
+        writer.buf
+    }
+
+    impl Transport for FakeTransport {
+        fn transaction(
+            &mut self,
+            operation: &Operation,
+            _data: Option<&[u8]>,
+        ) -> Result<(Response, Vec<u8>)> {
+            let (code, data) = match (operation.code, operation.params.first()) {
+                (operation::GET_DEVICE_INFO, _) => (RESPONSE_OK, device_info()),
+                (operation::GET_DEVICE_PROP_DESC, Some(0x5001)) => {
+                    let mut writer = Writer::default();
+                    writer.u16(0x5001).u16(0x0002);
+                    // Read-only, 100 by default and 80 now
+                    writer.buf.extend_from_slice(&[0, 100, 80]);
+                    (RESPONSE_OK, writer.buf)
+                }
+                // DevicePropNotSupported
+                (operation::GET_DEVICE_PROP_DESC, _) => (0x200A, Vec::new()),
+                _ => (RESPONSE_OK, Vec::new()),
+            };
+
+            let response = Response {
+                code,
+                transaction_id: operation.transaction_id,
+                params: Vec::new(),
+            };
+            Ok((response, data))
+        }
+    }
+
+    #[test]
+    fn failing_properties_dont_hide_the_rest() {
+        let mut session = PtpSession::open(FakeTransport).unwrap();
+        let properties = session.device_properties().unwrap();
+
+        assert_eq!(properties.len(), 2);
+        assert!(matches!(
+            properties[0],
+            (DeviceProperty::BatteryLevel, Ok(PropertyValue::U8(80)))
+        ));
+        assert!(matches!(properties[1], (DeviceProperty::DateTime, Err(_))));
+    }
+}
@@ -0,0 +1,287 @@
+//! PTP transport over USB bulk endpoints, implemented with `rusb` (requires the `rusb`
+//! feature).
+
+use rusb::{Device, DeviceHandle, Direction, GlobalContext, TransferType, UsbContext};
+use std::time::Duration;
+
+use crate::error::{Error, MtpErrorKind};
+use crate::ptp::{protocol_error, Operation, Reader, Response, Transport, Writer};
+use crate::Result;
+
+const CONTAINER_COMMAND: u16 = 1;
+const CONTAINER_DATA: u16 = 2;
+const CONTAINER_RESPONSE: u16 = 3;
+
+const HEADER_LEN: usize = 12;
+
+const USB_CLASS_PTP: u8 = 0x06;
+const USB_CLASS_VENDOR: u8 = 0xFF;
+
+/// Default timeout of every bulk transfer.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl From<rusb::Error> for Error {
+    fn from(err: rusb::Error) -> Self {
+        match err {
+            rusb::Error::NoDevice => Error::Disconnected,
+            err => Error::MtpError {
+                kind: MtpErrorKind::UsbLayer,
+                text: err.to_string(),
+                details: Vec::new(),
+            },
+        }
+    }
+}
+
+/// PTP transport over the bulk endpoints of the MTP interface of a USB device.
+pub struct UsbTransport {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+    endpoint_in: u8,
+    endpoint_out: u8,
+    max_packet_size: usize,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for UsbTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsbTransport")
+            .field("interface", &self.interface)
+            .field("endpoint_in", &self.endpoint_in)
+            .field("endpoint_out", &self.endpoint_out)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Returns the interface number, the bulk in and out endpoints and the maximum packet size of
+/// the MTP (or PTP) interface of `device`, i.e. the first one with the still image or vendor
+/// specific class and a pair of bulk endpoints.
+pub(crate) fn mtp_interface<T: UsbContext>(
+    device: &Device<T>,
+) -> Result<Option<(u8, u8, u8, usize)>> {
+    let config = device.active_config_descriptor()?;
+
+    for interface in config.interfaces() {
+        for descriptor in interface.descriptors() {
+            if !matches!(descriptor.class_code(), USB_CLASS_PTP | USB_CLASS_VENDOR) {
+                continue;
+            }
+
+            let bulk = |direction| {
+                descriptor.endpoint_descriptors().find(|endpoint| {
+                    endpoint.transfer_type() == TransferType::Bulk
+                        && endpoint.direction() == direction
+                })
+            };
+
+            if let (Some(endpoint_in), Some(endpoint_out)) =
+                (bulk(Direction::In), bulk(Direction::Out))
+            {
+                return Ok(Some((
+                    descriptor.interface_number(),
+                    endpoint_in.address(),
+                    endpoint_out.address(),
+                    endpoint_in.max_packet_size() as usize,
+                )));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+impl UsbTransport {
+    /// Opens the USB device at the given bus and device number and claims its MTP (or PTP)
+    /// interface, i.e. the first one with the still image or vendor specific class and a pair
+    /// of bulk endpoints.
+    pub fn open(bus_number: u8, dev_number: u8) -> Result<Self> {
+        let device = rusb::devices()?
+            .iter()
+            .find(|device| device.bus_number() == bus_number && device.address() == dev_number)
+            .ok_or(Error::Disconnected)?;
+
+        let (interface, endpoint_in, endpoint_out, max_packet_size) = mtp_interface(&device)?
+            .ok_or_else(|| Error::InvalidArgument {
+                reason: format!(
+                    "Device at bus {}, device {} has no MTP interface",
+                    bus_number, dev_number
+                ),
+            })?;
+
+        let mut handle = device.open()?;
+        // Not supported on every platform, in that case claiming fails if the interface is busy
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle.claim_interface(interface)?;
+
+        Ok(UsbTransport {
+            handle,
+            interface,
+            endpoint_in,
+            endpoint_out,
+            max_packet_size: max_packet_size.max(1),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Changes the timeout of every bulk transfer.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn write_container(
+        &mut self,
+        kind: u16,
+        code: u16,
+        transaction_id: u32,
+        payload: &[u8],
+    ) -> Result<()> {
+        let mut writer = Writer::default();
+        writer
+            .u32((HEADER_LEN + payload.len()) as u32)
+            .u16(kind)
+            .u16(code)
+            .u32(transaction_id);
+        writer.buf.extend_from_slice(payload);
+
+        let mut written = 0;
+        while written < writer.buf.len() {
+            written +=
+                self.handle
+                    .write_bulk(self.endpoint_out, &writer.buf[written..], self.timeout)?;
+        }
+
+        // A transfer that ends at a packet boundary is terminated by a zero length packet
+        if writer.buf.len() % self.max_packet_size == 0 {
+            self.handle
+                .write_bulk(self.endpoint_out, &[], self.timeout)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a whole container, returns its type, code, transaction id and payload.
+    fn read_container(&mut self) -> Result<(u16, u16, u32, Vec<u8>)> {
+        let mut buf = vec![0; 64 * self.max_packet_size];
+
+        // Skip the zero length packets that terminate the previous container
+        let mut read = 0;
+        while read == 0 {
+            read = self
+                .handle
+                .read_bulk(self.endpoint_in, &mut buf, self.timeout)?;
+        }
+
+        if read < HEADER_LEN {
+            return Err(protocol_error("Truncated PTP container"));
+        }
+
+        let mut header = Reader::new(&buf[..HEADER_LEN]);
+        let len = header.u32()? as usize;
+        let kind = header.u16()?;
+        let code = header.u16()?;
+        let transaction_id = header.u32()?;
+
+        // Objects of 4 GiB or more have an invalid length, read until a short packet
+        let unknown_len = len == u32::MAX as usize;
+        if !unknown_len && len < HEADER_LEN {
+            return Err(protocol_error(format!(
+                "Invalid PTP container length {}",
+                len
+            )));
+        }
+
+        let mut container = buf[..read].to_vec();
+        let mut last = read;
+        loop {
+            let done = if unknown_len {
+                last < buf.len()
+            } else {
+                container.len() >= len
+            };
+
+            if done {
+                break;
+            }
+
+            last = self
+                .handle
+                .read_bulk(self.endpoint_in, &mut buf, self.timeout)?;
+            if last == 0 && !unknown_len {
+                return Err(protocol_error("Truncated PTP container"));
+            }
+
+            container.extend_from_slice(&buf[..last]);
+        }
+
+        if !unknown_len {
+            container.truncate(len);
+        }
+
+        Ok((kind, code, transaction_id, container.split_off(HEADER_LEN)))
+    }
+}
+
+impl Transport for UsbTransport {
+    fn transaction(
+        &mut self,
+        operation: &Operation,
+        data: Option<&[u8]>,
+    ) -> Result<(Response, Vec<u8>)> {
+        let mut params = Writer::default();
+        for &param in &operation.params {
+            params.u32(param);
+        }
+
+        self.write_container(
+            CONTAINER_COMMAND,
+            operation.code,
+            operation.transaction_id,
+            &params.buf,
+        )?;
+
+        if let Some(data) = data {
+            self.write_container(
+                CONTAINER_DATA,
+                operation.code,
+                operation.transaction_id,
+                data,
+            )?;
+        }
+
+        let mut received = Vec::new();
+        loop {
+            let (kind, code, transaction_id, payload) = self.read_container()?;
+
+            match kind {
+                CONTAINER_DATA => received = payload,
+                CONTAINER_RESPONSE => {
+                    let mut reader = Reader::new(&payload);
+                    let params = (0..payload.len() / 4)
+                        .map(|_| reader.u32())
+                        .collect::<Result<_>>()?;
+
+                    let response = Response {
+                        code,
+                        transaction_id,
+                        params,
+                    };
+
+                    return Ok((response, received));
+                }
+                kind => {
+                    return Err(protocol_error(format!(
+                        "Unexpected PTP container of type {}",
+                        kind
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UsbTransport {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
@@ -2,9 +2,32 @@
 //! an specific device, and perform certain operations like sending and getting
 //! files, tracks, etc.
 
+pub mod albums;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod batch;
+pub mod cache;
+pub mod conflict;
 pub mod files;
 pub mod folders;
-
+pub mod ignore;
+pub mod import;
+pub mod media;
+pub mod paging;
+pub mod paths;
+pub mod playlists;
+pub mod policy;
+pub mod query;
+#[cfg(test)]
+mod scratch;
+pub mod smart_playlists;
+pub mod sync;
+pub mod tracks;
+pub mod usage;
+pub mod verify;
+
+use cache::FolderCache;
+use conflict::Resolution;
 use derivative::Derivative;
 use files::{File, FileMetadata};
 use libmtp_sys as ffi;
@@ -13,48 +36,87 @@ use num_traits::FromPrimitive;
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::ffi::CStr;
 use std::fmt::{self, Debug};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
 
-use crate::device::MtpDevice;
-use crate::object::AsObjectId;
-use crate::storage::folders::Folder;
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::{Error, MtpErrorKind, PtpResponse};
+use crate::object::filetypes::Filetype;
+use crate::object::{AsObjectId, Object};
+use crate::storage::albums::{create_album, get_album_list, Album};
 use crate::storage::folders::{create_folder, get_folder_list, get_folder_list_storage};
-use crate::util::{CallbackReturn, HandlerReturn};
+use crate::storage::folders::{Folder, FolderIter};
+use crate::storage::import::{CameraImport, Organizer};
+use crate::storage::paging::{PageToken, Pages};
+use crate::storage::paths::Placed;
+use crate::storage::playlists::{create_playlist, get_playlist_list, Playlist};
+use crate::storage::policy::{StoragePolicy, StorageSpace};
+use crate::storage::query::{Query, Search};
+use crate::storage::sync::DirectorySync;
+use crate::storage::tracks::{get_track_list, Track, TrackMetadata};
+use crate::storage::usage::{usage_report, UsageReport};
+use crate::storage::verify::{verify_upload, UploadSource};
+use crate::trace;
+use crate::util::{format_bytes, optional_str, CallbackReturn, HandlerReturn};
 use crate::Result;
 
 /// Internal function to retrieve files and folders from a single storage or the whole storage pool.
-fn files_and_folders(mtpdev: &MtpDevice, storage_id: u32, parent: Parent) -> Vec<File> {
-    let parent_id = parent.faf_id();
-
-    let mut head =
-        unsafe { ffi::LIBMTP_Get_Files_And_Folders(mtpdev.inner, storage_id, parent_id) };
+pub(crate) fn files_and_folders(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+) -> Result<Vec<File<'_>>> {
+    trace::list(mtpdev, "files_and_folders", storage_id, || {
+        mtpdev.require_cache_mode(CacheMode::Uncached)?;
+
+        let parent_id = parent.faf_id();
+
+        let mut head =
+            unsafe { ffi::LIBMTP_Get_Files_And_Folders(mtpdev.session()?, storage_id, parent_id) };
+
+        let mut files = Vec::new();
+        while !head.is_null() {
+            files.push(File {
+                inner: head,
+                owner: mtpdev,
+            });
+
+            head = unsafe { (*head).next };
+        }
 
-    let mut files = Vec::new();
-    while !head.is_null() {
-        files.push(File {
-            inner: head,
-            owner: mtpdev,
+        mtpdev.update_paths(|cache| {
+            for file in &files {
+                cache.insert_child(file.id(), file.storage_id(), parent, &file.name());
+            }
         });
 
-        head = unsafe { (*head).next };
-    }
-
-    files
+        Ok(files)
+    })
 }
 
 /// Represents the parent folder of an object, the top-most parent is called the "root" as in
 /// *nix like systems.
-#[derive(Debug, Copy, Clone)]
+///
+/// Note that `Parent::Folder(0)` isn't equal to `Parent::Root`, even if some devices report it
+/// for objects of the root.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Parent {
     Root,
     Folder(u32),
 }
 
+impl fmt::Display for Parent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parent::Root => write!(f, "root"),
+            Parent::Folder(id) => write!(f, "folder {}", id),
+        }
+    }
+}
+
 impl Parent {
     pub(crate) fn faf_id(self) -> u32 {
         match self {
@@ -93,6 +155,158 @@ pub enum AccessCapability {
     ReadWrite = 0,
     ReadOnly,
     ReadOnlyWithObjectDeletion,
+    /// A value the PTP specification doesn't define.
+    Unknown = 0xFFFF,
+}
+
+/// Id of a storage as reported by the device (e.g. in
+/// [`Event::StoreAdded`](../device/events/enum.Event.html#variant.StoreAdded)), use it with
+/// `StoragePool::by_id` once the storage is updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StorageId(pub u32);
+
+impl fmt::Display for StorageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+impl From<u32> for StorageId {
+    fn from(id: u32) -> Self {
+        StorageId(id)
+    }
+}
+
+impl From<&Storage<'_>> for StorageId {
+    fn from(storage: &Storage<'_>) -> Self {
+        StorageId(storage.id())
+    }
+}
+
+/// Returns the name of an upload in progress with temporary uploads, hidden and with an
+/// extension media scanners don't recognize.
+pub(crate) fn temp_upload_name(name: &str) -> String {
+    format!(".{}.part", name)
+}
+
+/// Returns the free space of the given storage as last known, for `0` (the device chooses) the
+/// most free space of any storage.
+fn free_space(mtpdev: &MtpDevice, storage_id: u32) -> Option<u64> {
+    let mut free = None;
+
+    unsafe {
+        let mut storage = (*mtpdev.inner).storage;
+        while !storage.is_null() {
+            if storage_id == 0 || (*storage).id == storage_id {
+                free = free.max(Some((*storage).FreeSpaceInBytes));
+            }
+
+            storage = (*storage).next;
+        }
+    }
+
+    free
+}
+
+/// Runs an upload (instrumented by `trace::upload`) of an object named `name` to the given
+/// storage, fails with `Error::StorageNotFound` if the device doesn't know the storage or
+/// rejects it, e.g. because the SD card was removed. `0` lets the device choose the storage.
+///
+/// Fails with `Error::StorageFull` if the `required` bytes (the size of the object) don't fit
+/// in the free space of the storage, or if the upload is rejected because the storage is full.
+///
+/// The `OnConflict` policy of the device is applied first, `upload` gets the name to use, which
+/// is a temporary one (renamed once the transfer is complete) if the device has temporary
+/// uploads enabled or an existing object is replaced. The new object is compared with `source`
+/// (if any) before it's renamed, if the device verifies uploads, and only then the replaced
+/// object is deleted, so it's kept if anything before fails.
+pub(crate) fn upload_to<'a, T: Placed<'a>>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+    name: &str,
+    required: u64,
+    source: Option<UploadSource<'_>>,
+    upload: impl FnOnce(&str) -> Result<T>,
+) -> Result<T> {
+    let not_found = || Error::StorageNotFound { storage_id };
+
+    let known = unsafe {
+        let mut storage = (*mtpdev.inner).storage;
+        while !storage.is_null() && (*storage).id != storage_id {
+            storage = (*storage).next;
+        }
+
+        !storage.is_null()
+    };
+
+    if storage_id != 0 && !known {
+        return Err(not_found());
+    }
+
+    let (name, replaces) = match mtpdev.on_conflict() {
+        Some(policy) => match conflict::resolve(mtpdev, storage_id, parent, name, policy)? {
+            Resolution::Upload(name) => (name, None),
+            Resolution::Existing(id) => return T::fetch(mtpdev, id),
+            Resolution::Replace(id) => (Cow::Borrowed(name), Some(id)),
+        },
+        None => (Cow::Borrowed(name), None),
+    };
+
+    let storage_full = || Error::StorageFull {
+        storage_id,
+        required,
+        available: free_space(mtpdev, storage_id).unwrap_or(0),
+    };
+
+    if let Some(available) = free_space(mtpdev, storage_id) {
+        if required > available {
+            return Err(storage_full());
+        }
+    }
+
+    let temp_name = (mtpdev.temp_uploads() || replaces.is_some()).then(|| temp_upload_name(&name));
+    let upload_name = temp_name.as_deref().unwrap_or(&name);
+
+    let mut object = trace::upload(mtpdev, storage_id, |_| required, || upload(upload_name))
+        .map_err(|err| {
+            match (err.without_context(), err.ptp_response()) {
+                (_, Some(PtpResponse::InvalidStorageId)) => not_found(),
+                // `libmtp` updates the free space when it rejects the upload
+                (_, Some(PtpResponse::StoreFull))
+                | (
+                    Error::MtpError {
+                        kind: MtpErrorKind::StorageFull,
+                        ..
+                    },
+                    _,
+                ) => storage_full(),
+                _ => err,
+            }
+        })?;
+
+    if let (Some(verification), Some(source)) = (mtpdev.verify_uploads(), source) {
+        let (id, ..) = object.placement();
+        verify_upload(mtpdev, id, source, verification)?;
+    }
+
+    if let Some(id) = replaces {
+        if let Err(err) = mtpdev.dummy_object(id).delete() {
+            let (new, ..) = object.placement();
+            let _ = mtpdev.dummy_object(new).delete();
+            return Err(err);
+        }
+    }
+
+    if temp_name.is_some() {
+        object.rename_to(&name)?;
+    }
+
+    if let (id, storage_id, parent, Some(name)) = object.placement() {
+        mtpdev.update_paths(|cache| cache.insert_child(id, storage_id, parent, &name));
+    }
+
+    Ok(object)
 }
 
 /// Storage descriptor of some MTP device, note that updating the storage and
@@ -144,7 +358,7 @@ impl<'a> Storage<'a> {
     /// Returns the access capability
     pub fn access_capability(&self) -> AccessCapability {
         let cap = unsafe { (*self.inner).AccessCapability };
-        AccessCapability::from_u16(cap).expect("Unknown access capability")
+        AccessCapability::from_u16(cap).unwrap_or(AccessCapability::Unknown)
     }
 
     /// Returns the maximum capacity
@@ -157,33 +371,35 @@ impl<'a> Storage<'a> {
         unsafe { (*self.inner).FreeSpaceInBytes }
     }
 
+    /// Returns the maximum capacity formatted with `util::format_bytes`, like `128 GiB`.
+    pub fn capacity_human(&self) -> String {
+        format_bytes(self.maximum_capacity())
+    }
+
+    /// Returns the free space formatted with `util::format_bytes`, like `58.3 GiB`.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// // 58.3 GiB free of 128 GiB
+    /// println!("{} free of {}", storage.free_space_human(), storage.capacity_human());
+    /// ```
+    pub fn free_space_human(&self) -> String {
+        format_bytes(self.free_space_in_bytes())
+    }
+
     /// Returns the free space in objects
     pub fn free_space_in_objects(&self) -> u64 {
         unsafe { (*self.inner).FreeSpaceInObjects }
     }
 
-    /// Returns the storage description
-    pub fn description(&self) -> Option<&str> {
-        unsafe {
-            if (*self.inner).StorageDescription.is_null() {
-                None
-            } else {
-                let cstr = CStr::from_ptr((*self.inner).StorageDescription);
-                Some(cstr.to_str().expect("Invalid UTF-8"))
-            }
-        }
+    /// Returns the storage description, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn description(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).StorageDescription) }
     }
 
-    /// Returns the volume identifier
-    pub fn volume_identifier(&self) -> Option<&str> {
-        unsafe {
-            if (*self.inner).VolumeIdentifier.is_null() {
-                None
-            } else {
-                let cstr = CStr::from_ptr((*self.inner).VolumeIdentifier);
-                Some(cstr.to_str().expect("Invalid UTF-8"))
-            }
-        }
+    /// Returns the volume identifier, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn volume_identifier(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).VolumeIdentifier) }
     }
 
     /// Formats this storage (if its device supports the operation).
@@ -191,10 +407,10 @@ impl<'a> Storage<'a> {
     /// **WARNING:** This **WILL DELETE ALL DATA** from the device, make sure
     /// you've got confirmation from the user before calling this function.
     pub fn format_storage(&self) -> Result<()> {
-        let res = unsafe { ffi::LIBMTP_Format_Storage(self.owner.inner, self.inner) };
+        let res = unsafe { ffi::LIBMTP_Format_Storage(self.owner.session()?, self.inner) };
 
         if res != 0 {
-            Err(self.owner.latest_error().unwrap_or_default())
+            Err(self.owner.error_in("LIBMTP_Format_Storage", None))
         } else {
             Ok(())
         }
@@ -202,17 +418,132 @@ impl<'a> Storage<'a> {
 
     /// Retrieves the contents of a certain folder (`parent`) in this storage, the result contains
     /// both files and folders, note that this request will always perform I/O with the device.
-    pub fn files_and_folders(&self, parent: Parent) -> Vec<File<'a>> {
+    ///
+    /// This is only available on uncached devices, cached devices should use `folder_list` instead.
+    pub fn files_and_folders(&self, parent: Parent) -> Result<Vec<File<'a>>> {
         let storage_id = unsafe { (*self.inner).id };
         files_and_folders(self.owner, storage_id, parent)
     }
 
+    /// Lists the contents of `parent` in pages of `page_size` entries, useful for folders with
+    /// many thousands of children, like `files_and_folders` this is only available on uncached
+    /// devices.
+    ///
+    /// ## Panics
+    /// Panics if `page_size` is zero.
+    pub fn list_pages(&self, parent: Parent, page_size: usize) -> Pages<'a> {
+        Pages::new(self.owner, self.id(), parent, page_size)
+    }
+
+    /// Resumes a paginated listing right after the page that returned `token`, the folder is
+    /// only listed again if the device doesn't keep the rest of that listing anymore (check
+    /// [`Pages`](paging/struct.Pages.html)).
+    ///
+    /// ## Panics
+    /// Panics if `page_size` is zero.
+    pub fn resume_pages(&self, token: PageToken, page_size: usize) -> Pages<'a> {
+        Pages::resume(self.owner, token, page_size)
+    }
+
+    /// Returns an empty `FolderCache` for this storage, folders are fetched and memoized on
+    /// demand. This is only available on uncached devices.
+    pub fn folder_cache(&self) -> FolderCache<'a> {
+        FolderCache::new(self.owner, self.id())
+    }
+
+    /// Caches the contents of `parent` and every folder below it in a `FolderCache`, so you
+    /// can browse that subtree without performing I/O again. This is only available on
+    /// uncached devices.
+    pub fn cache_subtree(&self, parent: Parent) -> Result<FolderCache<'a>> {
+        let mut cache = self.folder_cache();
+        cache.cache_subtree(parent)?;
+        Ok(cache)
+    }
+
+    /// Walks the whole storage and returns the cumulative size of every folder (like `du`),
+    /// note that this performs I/O for every folder. This is only available on uncached
+    /// devices.
+    pub fn usage_report(&self) -> Result<UsageReport> {
+        usage_report(self.owner, self.id(), Parent::Root)
+    }
+
+    /// Returns every file with the given filetype below `parent` (recursively) in this
+    /// storage, e.g. every `Filetype::Flac` in the music folder.
+    pub fn files_of_type(&self, ftype: Filetype, parent: Parent) -> Result<Vec<File<'a>>> {
+        files::files_of_type(self.owner, self.id(), parent, ftype)
+    }
+
+    /// Searches the files below `parent` in this storage matching the `query`, the folders are
+    /// walked lazily while iterating.
+    pub fn search(&self, query: Query, parent: Parent) -> Search<'a> {
+        Search::new(self.owner, self.id(), parent, query)
+    }
+
     /// Optionally returns a `Folder`, with this struct you can build a tree
     /// structure (see `Folder` for more info)
+    ///
+    /// On uncached devices the tree is built walking the storage one folder at a time, which
+    /// may take a while on storages with many folders.
     pub fn folder_list(&self) -> Option<Folder<'a>> {
         unsafe { get_folder_list_storage(self.owner, (*self.inner).id) }
     }
 
+    /// Retrieves all the tracks in this storage, this is only available on cached devices.
+    pub fn track_list(&self) -> Result<Vec<Track<'a>>> {
+        get_track_list(self.owner, self.id())
+    }
+
+    /// Retrieves all the albums in this storage, this is only available on cached devices.
+    pub fn album_list(&self) -> Result<Vec<Album<'a>>> {
+        get_album_list(self.owner, self.id())
+    }
+
+    /// Returns an iterator over every file in this storage (folders aren't included), on
+    /// cached devices the device-wide listing is filtered, on uncached ones the folders of this
+    /// storage are walked.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let total: u64 = storage.files()?.map(|file| file.size()).sum();
+    /// ```
+    pub fn files(&self) -> Result<std::vec::IntoIter<File<'a>>> {
+        Ok(files::list_files(self.owner, self.id(), Parent::Root)?.into_iter())
+    }
+
+    /// Returns an iterator over every folder in this storage, depth-first.
+    pub fn folders(&self) -> FolderIter<'a> {
+        FolderIter::new(self.folder_list())
+    }
+
+    /// Returns an iterator over the tracks in this storage, this is only available on cached
+    /// devices.
+    pub fn tracks(&self) -> Result<std::vec::IntoIter<Track<'a>>> {
+        Ok(self.track_list()?.into_iter())
+    }
+
+    /// Returns an iterator over the albums in this storage, this is only available on cached
+    /// devices.
+    pub fn albums(&self) -> Result<std::vec::IntoIter<Album<'a>>> {
+        Ok(self.album_list()?.into_iter())
+    }
+
+    /// Creates a new album in this storage with the given name and tracks (ids), note that the
+    /// name may be different due to device file system restrictions.
+    pub fn create_album(&self, name: &str, tracks: &[u32], parent: Parent) -> Result<Album<'a>> {
+        create_album(self.owner, name, tracks, parent, self.id())
+    }
+
+    /// Creates a new playlist in this storage with the given name and tracks (ids), note that
+    /// the name may be different due to device file system restrictions.
+    pub fn create_playlist(
+        &self,
+        name: &str,
+        tracks: &[u32],
+        parent: Parent,
+    ) -> Result<Playlist<'a>> {
+        create_playlist(self.owner, name, tracks, parent, self.id())
+    }
+
     /// Tries to create a new folder in this storage for the relevant `MtpDevice`, returns the id
     /// of the new folder and its name, note that the name may be different due to device file
     /// system restrictions.
@@ -316,16 +647,44 @@ impl<'a> Storage<'a> {
         files::get_file_to_handler_with_callback(self.owner, file, handler, callback)
     }
 
+    /// Imports the photos among `files` into the local `destination` directory, the directory
+    /// of each photo inside `destination` is decided by the `organizer` (e.g. `DateOrganizer`
+    /// places them in `YYYY/MM/DD` directories), non-image files are skipped. Returns the
+    /// local paths of the imported photos.
+    ///
+    /// With the `exif` feature enabled the capture date of the photo is read from its EXIF
+    /// metadata, otherwise (or if it's missing) the modification date is used.
+    pub fn import_photos<'f>(
+        &self,
+        files: impl IntoIterator<Item = &'f File<'a>>,
+        destination: impl AsRef<Path>,
+        organizer: &impl Organizer,
+    ) -> Result<Vec<PathBuf>>
+    where
+        'a: 'f,
+    {
+        import::import_photos(self.owner, files, destination, organizer)
+    }
+
+    /// Returns a builder to import new photos and videos from the camera folders (`DCIM`) of
+    /// this storage.
+    pub fn camera_import(&self) -> CameraImport<'a> {
+        CameraImport::new(self.owner, self.id())
+    }
+
+    /// Mirrors the `local` directory into the `parent` folder of this storage, plan the operations and
+    /// apply them with the returned [`DirectorySync`](sync/struct.DirectorySync.html).
+    pub fn sync_directory(&self, local: impl AsRef<Path>, parent: Parent) -> DirectorySync<'a> {
+        DirectorySync::new(self.owner, self.id(), local, parent)
+    }
+
     /// Sends a local file to the MTP device who this storage belongs to.
-    pub fn send_file_from_path<C>(
+    pub fn send_file_from_path(
         &self,
         path: impl AsRef<Path>,
         parent: Parent,
         metadata: FileMetadata<'_>,
-    ) -> Result<File<'a>>
-    where
-        C: FnMut(u64, u64) -> CallbackReturn,
-    {
+    ) -> Result<File<'a>> {
         let storage_id = self.id();
         files::send_file_from_path(self.owner, storage_id, path, parent, metadata)
     }
@@ -385,6 +744,76 @@ impl<'a> Storage<'a> {
         )
     }
 
+    /// Sends a local track to the MTP device who this storage belongs to, the metadata is
+    /// attached to the new track object.
+    pub fn send_track_from_path(
+        &self,
+        path: impl AsRef<Path>,
+        parent: Parent,
+        metadata: &TrackMetadata,
+    ) -> Result<Track<'a>> {
+        let storage_id = self.id();
+        tracks::send_track_from_path(self.owner, storage_id, path, parent, metadata)
+    }
+
+    /// Sends a local track to the MTP device who this storage belongs to, the metadata is
+    /// attached to the new track object.
+    ///
+    /// The `callback` parameter is a progress function with the following signature `(sent_bytes:
+    /// u64, total_bytes: u64) -> CallbackReturn`, this way you can check the progress and if you
+    /// want to cancel operation you just return `CallbackReturn::Cancel`.
+    pub fn send_track_from_path_with_callback<C>(
+        &self,
+        path: impl AsRef<Path>,
+        parent: Parent,
+        metadata: &TrackMetadata,
+        callback: C,
+    ) -> Result<Track<'a>>
+    where
+        C: FnMut(u64, u64) -> CallbackReturn,
+    {
+        let storage_id = self.id();
+        tracks::send_track_from_path_with_callback(
+            self.owner, storage_id, path, parent, metadata, callback,
+        )
+    }
+
+    /// Sends a track with the contents of `data` to this storage, the metadata is attached to
+    /// the new track object and its `file_size` is taken from `data`, useful for tracks
+    /// transcoded in memory that never touch the local filesystem.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let opus = transcode_to_opus(&flac)?;
+    /// let metadata = TrackMetadata {
+    ///     file_name: "01 - Intro.opus".to_string(),
+    ///     file_type: Filetype::Ogg,
+    ///     ..original_metadata
+    /// };
+    ///
+    /// storage.send_track_from_bytes(&opus, Parent::Folder(music_id), &metadata)?;
+    /// ```
+    pub fn send_track_from_bytes(
+        &self,
+        data: &[u8],
+        parent: Parent,
+        metadata: &TrackMetadata,
+    ) -> Result<Track<'a>> {
+        tracks::send_track_from_bytes(self.owner, self.id(), data, parent, metadata)
+    }
+
+    /// Uploads a file named `name` with the contents of `data` into the `parent` folder of
+    /// this storage, useful for small generated files that don't exist in the local filesystem.
+    pub fn send_bytes(
+        &self,
+        data: &[u8],
+        name: &str,
+        parent: Parent,
+        file_type: Filetype,
+    ) -> Result<File<'a>> {
+        files::send_bytes(self.owner, self.id(), data, name, parent, file_type)
+    }
+
     /// Sends a bunch of data to the MTP device who this storage belongs to.
     ///
     /// The `handler` parameter is a function that gives you a chunk to write data with the
@@ -508,18 +937,141 @@ impl<'a> StoragePool<'a> {
         }
     }
 
+    /// Resolves the storage of an upload of `size` bytes.
+    fn choose(&self, storage: impl Into<StoragePolicy>, size: u64) -> u32 {
+        StorageSpace::new(self.owner).choose(storage.into(), size)
+    }
+
     /// Retrieves the contents of a certain folder (`parent`) in all storages, the result contains
     /// both files and folders, note that this request will always perform I/O with the device.
-    pub fn files_and_folders(&self, parent: Parent) -> Vec<File<'a>> {
+    ///
+    /// This is only available on uncached devices, cached devices should use `folder_list` instead.
+    pub fn files_and_folders(&self, parent: Parent) -> Result<Vec<File<'a>>> {
         files_and_folders(self.owner, 0, parent)
     }
 
+    /// Lists the contents of `parent` in every storage in pages of `page_size` entries, useful
+    /// for folders with many thousands of children, this is only available on uncached
+    /// devices.
+    ///
+    /// ## Panics
+    /// Panics if `page_size` is zero.
+    pub fn list_pages(&self, parent: Parent, page_size: usize) -> Pages<'a> {
+        Pages::new(self.owner, 0, parent, page_size)
+    }
+
+    /// Resumes a paginated listing right after the page that returned `token`, the folder is
+    /// only listed again if the device doesn't keep the rest of that listing anymore (check
+    /// [`Pages`](paging/struct.Pages.html)).
+    ///
+    /// ## Panics
+    /// Panics if `page_size` is zero.
+    pub fn resume_pages(&self, token: PageToken, page_size: usize) -> Pages<'a> {
+        Pages::resume(self.owner, token, page_size)
+    }
+
+    /// Finds the object at `path`, like `/Music/song.mp3` or `SD card:/DCIM/IMG_0001.jpg` to
+    /// look only in the storage with that description, check the
+    /// [`paths`](paths/index.html) module for the syntax. Fails with `Error::PathNotFound` if
+    /// there's no such object, the root folder isn't an object.
+    ///
+    /// The folders are listed one at a time, so this is only available on uncached devices.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let file = storage_pool.find_path("SD card:/DCIM/Camera/IMG_0001.jpg")?;
+    /// storage_pool.get_file_to_path(&file, "IMG_0001.jpg")?;
+    /// ```
+    pub fn find_path(&self, path: &str) -> Result<File<'a>> {
+        paths::find_path(self, path)
+    }
+
+    /// Like `find_path` but for folders, the root is allowed. Returns the storage of the folder
+    /// (`0` for the root of every storage) along with the folder, ready to upload into it.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let (storage_id, parent) = storage_pool.find_folder("SD card:/Music")?;
+    /// storage_pool.send_file_from_path(storage_id, "song.mp3", parent, metadata)?;
+    /// ```
+    pub fn find_folder(&self, path: &str) -> Result<(u32, Parent)> {
+        paths::find_folder(self, path)
+    }
+
+    /// Returns an empty `FolderCache` for all storages, folders are fetched and memoized on
+    /// demand. This is only available on uncached devices.
+    pub fn folder_cache(&self) -> FolderCache<'a> {
+        FolderCache::new(self.owner, 0)
+    }
+
+    /// Caches the contents of `parent` and every folder below it (in all storages) in a
+    /// `FolderCache`, so you can browse that subtree without performing I/O again. This is
+    /// only available on uncached devices.
+    pub fn cache_subtree(&self, parent: Parent) -> Result<FolderCache<'a>> {
+        let mut cache = self.folder_cache();
+        cache.cache_subtree(parent)?;
+        Ok(cache)
+    }
+
+    /// Walks every storage and returns the cumulative size of every folder (like `du`), note
+    /// that this performs I/O for every folder. This is only available on uncached devices.
+    pub fn usage_report(&self) -> Result<UsageReport> {
+        usage_report(self.owner, 0, Parent::Root)
+    }
+
+    /// Returns every file with the given filetype below `parent` (recursively) in every
+    /// storage.
+    pub fn files_of_type(&self, ftype: Filetype, parent: Parent) -> Result<Vec<File<'a>>> {
+        files::files_of_type(self.owner, 0, parent, ftype)
+    }
+
+    /// Searches the files below `parent` in every storage matching the `query`, the folders
+    /// are walked lazily while iterating.
+    pub fn search(&self, query: Query, parent: Parent) -> Search<'a> {
+        Search::new(self.owner, 0, parent, query)
+    }
+
     /// Optionally returns a `Folder`, with this struct you can build a tree
     /// structure (see `Folder` for more info)
+    ///
+    /// On uncached devices the tree is built walking all the storages one folder at a time,
+    /// which may take a while on devices with many folders.
     pub fn folder_list(&self) -> Option<Folder<'_>> {
         get_folder_list(self.owner)
     }
 
+    /// Retrieves all the tracks in all storages, this is only available on cached devices.
+    pub fn track_list(&self) -> Result<Vec<Track<'a>>> {
+        get_track_list(self.owner, 0)
+    }
+
+    /// Retrieves all the albums in all storages, this is only available on cached devices.
+    pub fn album_list(&self) -> Result<Vec<Album<'a>>> {
+        get_album_list(self.owner, 0)
+    }
+
+    /// Creates a new album in the primary storage with the given name and tracks (ids), note
+    /// that the name may be different due to device file system restrictions.
+    pub fn create_album(&self, name: &str, tracks: &[u32], parent: Parent) -> Result<Album<'a>> {
+        create_album(self.owner, name, tracks, parent, 0)
+    }
+
+    /// Retrieves all the playlists in all storages, this is only available on cached devices.
+    pub fn playlist_list(&self) -> Result<Vec<Playlist<'a>>> {
+        get_playlist_list(self.owner)
+    }
+
+    /// Creates a new playlist in the primary storage with the given name and tracks (ids), note
+    /// that the name may be different due to device file system restrictions.
+    pub fn create_playlist(
+        &self,
+        name: &str,
+        tracks: &[u32],
+        parent: Parent,
+    ) -> Result<Playlist<'a>> {
+        create_playlist(self.owner, name, tracks, parent, 0)
+    }
+
     /// Tries to create a new folder in the default storage of the relevant `MtpDevice`, returns
     /// the id of the new folder and its name, note that the name may be different due to device
     /// file system restrictions.
@@ -623,29 +1175,62 @@ impl<'a> StoragePool<'a> {
         files::get_file_to_handler_with_callback(self.owner, file, handler, callback)
     }
 
-    /// Sends a local file to the MTP device who this storage belongs to, note that this method
-    /// will send the file to the primary storage.
-    pub fn send_file_from_path<C>(
+    /// Imports the photos among `files` into the local `destination` directory, the directory
+    /// of each photo inside `destination` is decided by the `organizer` (e.g. `DateOrganizer`
+    /// places them in `YYYY/MM/DD` directories), non-image files are skipped. Returns the
+    /// local paths of the imported photos.
+    ///
+    /// With the `exif` feature enabled the capture date of the photo is read from its EXIF
+    /// metadata, otherwise (or if it's missing) the modification date is used.
+    pub fn import_photos<'f>(
+        &self,
+        files: impl IntoIterator<Item = &'f File<'a>>,
+        destination: impl AsRef<Path>,
+        organizer: &impl Organizer,
+    ) -> Result<Vec<PathBuf>>
+    where
+        'a: 'f,
+    {
+        import::import_photos(self.owner, files, destination, organizer)
+    }
+
+    /// Returns a builder to import new photos and videos from the camera folders (`DCIM`) of
+    /// all the storages.
+    pub fn camera_import(&self) -> CameraImport<'a> {
+        CameraImport::new(self.owner, 0)
+    }
+
+    /// Mirrors the `local` directory into the `parent` folder, the device chooses the storage of
+    /// new objects unless a `storage_policy` is given, plan the operations and apply them with
+    /// the returned [`DirectorySync`](sync/struct.DirectorySync.html).
+    pub fn sync_directory(&self, local: impl AsRef<Path>, parent: Parent) -> DirectorySync<'a> {
+        DirectorySync::new(self.owner, 0, local, parent)
+    }
+
+    /// Sends a local file to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
+    pub fn send_file_from_path(
         &self,
+        storage: impl Into<StoragePolicy>,
         path: impl AsRef<Path>,
         parent: Parent,
         metadata: FileMetadata<'_>,
-    ) -> Result<File<'a>>
-    where
-        C: FnMut(u64, u64) -> CallbackReturn,
-    {
-        let storage_id = 0;
+    ) -> Result<File<'a>> {
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_path(self.owner, storage_id, path, parent, metadata)
     }
 
-    /// Sends a local file to the MTP device who this storage belongs to, note that this method
-    /// will send the file to the primary storage.
+    /// Sends a local file to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
     ///
     /// The `callback` parameter is a progress function with the following signature `(sent_bytes:
     /// u64, total_bytes: u64) -> CallbackReturn`, this way you can check the progress and if you
     /// want to cancel operation you just return `CallbackReturn::Cancel`.
     pub fn send_file_from_path_with_callback<C>(
         &self,
+        storage: impl Into<StoragePolicy>,
         path: impl AsRef<Path>,
         parent: Parent,
         metadata: FileMetadata<'_>,
@@ -654,27 +1239,30 @@ impl<'a> StoragePool<'a> {
     where
         C: FnMut(u64, u64) -> CallbackReturn,
     {
-        let storage_id = 0;
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_path_with_callback(
             self.owner, storage_id, path, parent, metadata, callback,
         )
     }
 
-    /// Sends a local file via descriptor to the MTP device who this storage belongs to, note
-    /// that this method will send the file to the primary storage.
+    /// Sends a local file via descriptor to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
     #[cfg(unix)]
     pub fn send_file_from_descriptor(
         &self,
+        storage: impl Into<StoragePolicy>,
         descriptor: impl AsRawFd,
         parent: Parent,
         metadata: FileMetadata<'_>,
     ) -> Result<File<'a>> {
-        let storage_id = 0;
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_descriptor(self.owner, storage_id, descriptor, parent, metadata)
     }
 
-    /// Sends a local file via descriptor to the MTP device who this storage belongs to, note
-    /// that this method will send the file to the primary storage.
+    /// Sends a local file via descriptor to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
     ///
     /// The `callback` parameter is a progress function with the following signature `(sent_bytes:
     /// u64, total_bytes: u64) -> CallbackReturn`, this way you can check the progress and if you
@@ -682,6 +1270,7 @@ impl<'a> StoragePool<'a> {
     #[cfg(unix)]
     pub fn send_file_from_descriptor_with_callback<C>(
         &self,
+        storage: impl Into<StoragePolicy>,
         descriptor: impl AsRawFd,
         parent: Parent,
         metadata: FileMetadata<'_>,
@@ -690,14 +1279,68 @@ impl<'a> StoragePool<'a> {
     where
         C: FnMut(u64, u64) -> CallbackReturn,
     {
-        let storage_id = 0;
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_descriptor_with_callback(
             self.owner, storage_id, descriptor, parent, metadata, callback,
         )
     }
 
-    /// Sends a bunch of data to the MTP device who this storage belongs to, note that this
-    /// method will send the file to primary storage.
+    /// Sends a local track to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), the metadata is attached to
+    /// the new track object, fails with `Error::StorageNotFound` if the device doesn't have
+    /// that storage (anymore).
+    pub fn send_track_from_path(
+        &self,
+        storage: impl Into<StoragePolicy>,
+        path: impl AsRef<Path>,
+        parent: Parent,
+        metadata: &TrackMetadata,
+    ) -> Result<Track<'a>> {
+        let storage_id = self.choose(storage, metadata.file_size);
+        tracks::send_track_from_path(self.owner, storage_id, path, parent, metadata)
+    }
+
+    /// Sends a local track to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), the metadata is attached to
+    /// the new track object, fails with `Error::StorageNotFound` if the device doesn't have
+    /// that storage (anymore).
+    ///
+    /// The `callback` parameter is a progress function with the following signature `(sent_bytes:
+    /// u64, total_bytes: u64) -> CallbackReturn`, this way you can check the progress and if you
+    /// want to cancel operation you just return `CallbackReturn::Cancel`.
+    pub fn send_track_from_path_with_callback<C>(
+        &self,
+        storage: impl Into<StoragePolicy>,
+        path: impl AsRef<Path>,
+        parent: Parent,
+        metadata: &TrackMetadata,
+        callback: C,
+    ) -> Result<Track<'a>>
+    where
+        C: FnMut(u64, u64) -> CallbackReturn,
+    {
+        let storage_id = self.choose(storage, metadata.file_size);
+        tracks::send_track_from_path_with_callback(
+            self.owner, storage_id, path, parent, metadata, callback,
+        )
+    }
+
+    /// Sends a track with the contents of `data` to the storage chosen by `storage`, the metadata
+    /// is attached to the new track object and its `file_size` is taken from `data`.
+    pub fn send_track_from_bytes(
+        &self,
+        storage: impl Into<StoragePolicy>,
+        data: &[u8],
+        parent: Parent,
+        metadata: &TrackMetadata,
+    ) -> Result<Track<'a>> {
+        let storage_id = self.choose(storage, data.len() as u64);
+        tracks::send_track_from_bytes(self.owner, storage_id, data, parent, metadata)
+    }
+
+    /// Sends a bunch of data to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
     ///
     /// The `handler` parameter is a function that gives you a chunk to write data with the
     /// following signature `(data: &mut [u8]) -> HandlerReturn`, you should return
@@ -705,6 +1348,7 @@ impl<'a> StoragePool<'a> {
     /// wrote to `data`.
     pub fn send_file_from_handler<H>(
         &self,
+        storage: impl Into<StoragePolicy>,
         handler: H,
         parent: Parent,
         metadata: FileMetadata<'_>,
@@ -712,12 +1356,13 @@ impl<'a> StoragePool<'a> {
     where
         H: FnMut(&mut [u8]) -> HandlerReturn,
     {
-        let storage_id = 0;
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_handler(self.owner, storage_id, parent, metadata, handler)
     }
 
-    /// Sends a bunch of data to the MTP device who this storage belongs to, note that this
-    /// method will send the file to primary storage.
+    /// Sends a bunch of data to the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), fails with
+    /// `Error::StorageNotFound` if the device doesn't have that storage (anymore).
     ///
     /// The `handler` parameter is a function that gives you a chunk to write data with the
     /// following signature `(data: &mut [u8]) -> HandlerReturn`, you should return
@@ -729,6 +1374,7 @@ impl<'a> StoragePool<'a> {
     /// want to cancel operation you just return `CallbackReturn::Cancel`.
     pub fn send_file_from_handler_with_callback<H, C>(
         &self,
+        storage: impl Into<StoragePolicy>,
         handler: H,
         parent: Parent,
         metadata: FileMetadata<'_>,
@@ -738,7 +1384,7 @@ impl<'a> StoragePool<'a> {
         H: FnMut(&mut [u8]) -> HandlerReturn,
         C: FnMut(u64, u64) -> CallbackReturn,
     {
-        let storage_id = 0;
+        let storage_id = self.choose(storage, metadata.file_size);
         files::send_file_from_handler_with_callback(
             self.owner, storage_id, parent, metadata, handler, callback,
         )
@@ -0,0 +1,268 @@
+//! Contains relevant items to handle album objects in the device.
+
+use libmtp_sys as ffi;
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::fmt::{self, Debug};
+
+use crate::device::{CacheMode, MtpDevice};
+use crate::object::{AsObjectId, Object};
+use crate::storage::Parent;
+use crate::trace;
+use crate::util::optional_str;
+use crate::util::replace_id_array;
+use crate::Result;
+
+/// Abstraction of an album object, it implements `Object`, an album is a list of references
+/// to track objects with some metadata (artist, composer, genre) attached.
+pub struct Album<'a> {
+    pub(crate) inner: *mut ffi::LIBMTP_album_t,
+    pub(crate) owner: &'a MtpDevice,
+}
+
+impl Drop for Album<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::LIBMTP_destroy_album_t(self.inner);
+        }
+    }
+}
+
+impl Object for Album<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).album_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Object for &Album<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).album_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Debug for Album<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Album")
+            .field("id", &self.id())
+            .field("parent_id", &self.parent_id())
+            .field("storage_id", &self.storage_id())
+            .field("name", &self.name())
+            .field("artist", &self.artist())
+            .field("composer", &self.composer())
+            .field("genre", &self.genre())
+            .field("tracks", &self.tracks())
+            .finish()
+    }
+}
+
+impl Album<'_> {
+    /// Returns the id of the storage it belongs to.
+    pub fn storage_id(&self) -> u32 {
+        unsafe { (*self.inner).storage_id }
+    }
+
+    /// Returns the id of its parent.
+    pub fn parent_id(&self) -> Parent {
+        let id = unsafe { (*self.inner).parent_id };
+
+        if id == ffi::LIBMTP_FILES_AND_FOLDERS_ROOT {
+            Parent::Root
+        } else {
+            Parent::Folder(id)
+        }
+    }
+
+    /// Returns the name of this album.
+    pub fn name(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).name) }
+    }
+
+    /// Returns the name of the album artist.
+    pub fn artist(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).artist) }
+    }
+
+    /// Returns the name of the recording composer.
+    pub fn composer(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).composer) }
+    }
+
+    /// Returns the genre of this album.
+    pub fn genre(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).genre) }
+    }
+
+    /// Returns the ids of the tracks in this album.
+    pub fn tracks(&self) -> Vec<u32> {
+        unsafe {
+            let len = (*self.inner).no_tracks;
+
+            if (*self.inner).tracks.is_null() || len == 0 {
+                Vec::new()
+            } else {
+                prim_array_ptr_to_vec!((*self.inner).tracks, u32, len)
+            }
+        }
+    }
+
+    /// Returns the number of tracks in this album.
+    pub fn len(&self) -> usize {
+        unsafe { (*self.inner).no_tracks as usize }
+    }
+
+    /// Check whether this album has no tracks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces the tracks of this album and updates it on the device, the rest of the
+    /// metadata is preserved.
+    pub fn set_tracks(&mut self, tracks: &[u32]) -> Result<()> {
+        let session = self.owner.session()?;
+        let old_tracks = self.tracks();
+
+        let res = unsafe {
+            replace_id_array(
+                &mut (*self.inner).tracks,
+                &mut (*self.inner).no_tracks,
+                tracks,
+            );
+            ffi::LIBMTP_Update_Album(session, self.inner)
+        };
+
+        if res != 0 {
+            unsafe {
+                replace_id_array(
+                    &mut (*self.inner).tracks,
+                    &mut (*self.inner).no_tracks,
+                    &old_tracks,
+                )
+            };
+
+            Err(self.owner.error_in("LIBMTP_Update_Album", Some(self.id())))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the current track references of this album from the device, which may have
+    /// changed since this album was retrieved.
+    fn current_tracks(&self) -> Result<Vec<u32>> {
+        let current = get_album(self.owner, self.id())?;
+        Ok(current.tracks())
+    }
+
+    /// Adds a track to this album (if it isn't already there) and updates it on the device,
+    /// the current references are read from the device first so no changes get lost.
+    pub fn add_track(&mut self, track: impl AsObjectId) -> Result<()> {
+        let track = track.as_id();
+        let mut tracks = self.current_tracks()?;
+
+        if !tracks.contains(&track) {
+            tracks.push(track);
+        }
+
+        self.set_tracks(&tracks)
+    }
+
+    /// Removes a track from this album and updates it on the device, the current references
+    /// are read from the device first so no changes get lost. Removing the last track leaves
+    /// an empty album, it's not deleted.
+    pub fn remove_track(&mut self, track: impl AsObjectId) -> Result<()> {
+        let track = track.as_id();
+        let mut tracks = self.current_tracks()?;
+        tracks.retain(|&id| id != track);
+
+        self.set_tracks(&tracks)
+    }
+
+    /// Rename this album in-place.
+    pub fn rename(&mut self, new_name: &str) -> Result<()> {
+        let session = self.owner.session()?;
+        let new_name = CString::new(new_name)?;
+
+        let res = unsafe { ffi::LIBMTP_Set_Album_Name(session, self.inner, new_name.as_ptr()) };
+
+        if res != 0 {
+            Err(self
+                .owner
+                .error_in("LIBMTP_Set_Album_Name", Some(self.id())))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn get_album_list(mtpdev: &MtpDevice, storage_id: u32) -> Result<Vec<Album<'_>>> {
+    trace::list(mtpdev, "albums", storage_id, || {
+        let session = mtpdev.session()?;
+        mtpdev.require_cache_mode(CacheMode::Cached)?;
+
+        let mut head = unsafe { ffi::LIBMTP_Get_Album_List_For_Storage(session, storage_id) };
+
+        let mut albums = Vec::new();
+        while !head.is_null() {
+            albums.push(Album {
+                inner: head,
+                owner: mtpdev,
+            });
+
+            head = unsafe { (*head).next };
+        }
+
+        Ok(albums)
+    })
+}
+
+pub(crate) fn get_album(mtpdev: &MtpDevice, album: impl AsObjectId) -> Result<Album<'_>> {
+    let session = mtpdev.session()?;
+    let album = unsafe { ffi::LIBMTP_Get_Album(session, album.as_id()) };
+
+    if album.is_null() {
+        Err(mtpdev.error_in("LIBMTP_Get_Album", None))
+    } else {
+        Ok(Album {
+            inner: album,
+            owner: mtpdev,
+        })
+    }
+}
+
+pub(crate) fn create_album<'a>(
+    mtpdev: &'a MtpDevice,
+    name: &str,
+    tracks: &[u32],
+    parent: Parent,
+    storage_id: u32,
+) -> Result<Album<'a>> {
+    let session = mtpdev.session()?;
+    let name = CString::new(name)?;
+
+    unsafe {
+        let album = ffi::LIBMTP_new_album_t();
+        (*album).name = libc::strdup(name.as_ptr());
+        (*album).parent_id = parent.to_id();
+        (*album).storage_id = storage_id;
+        replace_id_array(&mut (*album).tracks, &mut (*album).no_tracks, tracks);
+
+        let res = ffi::LIBMTP_Create_New_Album(session, album);
+
+        if res != 0 {
+            ffi::LIBMTP_destroy_album_t(album);
+            Err(mtpdev.error_in("LIBMTP_Create_New_Album", None))
+        } else {
+            Ok(Album {
+                inner: album,
+                owner: mtpdev,
+            })
+        }
+    }
+}
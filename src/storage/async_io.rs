@@ -0,0 +1,425 @@
+//! Contains bridges between the blocking transfers of `libmtp` and `tokio` readers and writers
+//! (requires the `async` feature), so async sources (like HTTP downloads) can stream straight
+//! onto the device, and device files can stream straight into async sinks (like HTTP uploads)
+//! or be served as a [`Stream`](https://docs.rs/futures-core/0.3/futures_core/stream/trait.Stream.html)
+//! of chunks.
+//!
+//! The transfers block the current worker thread with
+//! [`block_in_place`](https://docs.rs/tokio/1/tokio/task/fn.block_in_place.html), so they need
+//! the multi-threaded runtime (they fail with `Error::InvalidArgument` on a current-thread one,
+//! like the default of `#[tokio::test]`), while the reader (or writer) is driven on a helper
+//! thread and chunks are exchanged through a bounded channel, this way the network and the
+//! device work at the same time.
+
+use bytes::Bytes;
+use futures_core::Stream;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc;
+
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::Object;
+use crate::storage::files::{self, File, FileMetadata};
+use crate::storage::policy::{StoragePolicy, StorageSpace};
+use crate::storage::{Parent, Storage, StoragePool};
+use crate::util::HandlerReturn;
+use crate::Result;
+
+/// Size of the chunks read from async readers.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of chunks buffered between the async side and the device.
+const CHANNEL_CAPACITY: usize = 8;
+
+/// Returns the handle of the current runtime, failing if `block_in_place` can't be used on it
+/// (it panics outside of the multi-threaded runtime).
+fn blocking_runtime() -> Result<Handle> {
+    let handle = Handle::try_current().map_err(|_| Error::InvalidArgument {
+        reason: "async transfers must run inside a tokio runtime".to_string(),
+    })?;
+
+    if handle.runtime_flavor() == RuntimeFlavor::CurrentThread {
+        return Err(Error::InvalidArgument {
+            reason: "async transfers block a worker thread, they need the multi-threaded runtime"
+                .to_string(),
+        });
+    }
+
+    Ok(handle)
+}
+
+/// Hands the chunks read from the reader to `libmtp`, which asks for exactly the declared size
+/// of the file and keeps asking until it gets it.
+struct ChunkFeeder<F> {
+    next_chunk: F,
+    pending: Vec<u8>,
+    offset: usize,
+    /// Bytes still owed to `libmtp`.
+    remaining: u64,
+    error: Option<io::Error>,
+}
+
+impl<F> ChunkFeeder<F>
+where
+    F: FnMut() -> Option<io::Result<Vec<u8>>>,
+{
+    fn new(file_size: u64, next_chunk: F) -> Self {
+        ChunkFeeder {
+            next_chunk,
+            pending: Vec::new(),
+            offset: 0,
+            remaining: file_size,
+            error: None,
+        }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> HandlerReturn {
+        if self.offset == self.pending.len() {
+            match (self.next_chunk)() {
+                Some(Ok(chunk)) => {
+                    self.pending = chunk;
+                    self.offset = 0;
+                }
+                Some(Err(err)) => {
+                    self.error = Some(err);
+                    return HandlerReturn::Error;
+                }
+                // A short reader would leave `libmtp` waiting for the rest
+                None if self.remaining > 0 => {
+                    self.error = Some(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("the reader ended {} bytes short", self.remaining),
+                    ));
+                    return HandlerReturn::Error;
+                }
+                None => return HandlerReturn::Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.pending.len() - self.offset);
+        buf[..len].copy_from_slice(&self.pending[self.offset..self.offset + len]);
+        self.offset += len;
+        self.remaining = self.remaining.saturating_sub(len as u64);
+        HandlerReturn::Ok(len as u32)
+    }
+}
+
+pub(crate) fn send_file_from_async_read<'a, R>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    mut reader: R,
+    parent: Parent,
+    metadata: FileMetadata<'_>,
+) -> Result<File<'a>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let handle = blocking_runtime()?;
+    let (tx, mut rx) = mpsc::channel::<io::Result<Vec<u8>>>(CHANNEL_CAPACITY);
+
+    tokio::task::block_in_place(|| {
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                handle.block_on(async move {
+                    loop {
+                        let mut chunk = vec![0; CHUNK_SIZE];
+                        let chunk = match reader.read(&mut chunk).await {
+                            Ok(0) => break,
+                            Ok(len) => {
+                                chunk.truncate(len);
+                                Ok(chunk)
+                            }
+                            Err(err) => Err(err),
+                        };
+
+                        let failed = chunk.is_err();
+                        // The upload is over (or failed) if the receiver is gone
+                        if tx.send(chunk).await.is_err() || failed {
+                            break;
+                        }
+                    }
+                })
+            });
+
+            let mut feeder = ChunkFeeder::new(metadata.file_size, || rx.blocking_recv());
+            let result = files::send_file_from_handler(
+                mtpdev,
+                storage_id,
+                parent,
+                metadata,
+                |buf: &mut [u8]| feeder.fill(buf),
+            );
+
+            // Stops the reader if the upload ended early
+            let ChunkFeeder { error, .. } = feeder;
+            drop(rx);
+
+            match error {
+                Some(err) => Err(err.into()),
+                None => result,
+            }
+        })
+    })
+}
+
+pub(crate) fn get_file_to_async_write<W>(
+    mtpdev: &MtpDevice,
+    file_id: u32,
+    mut writer: W,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    let handle = blocking_runtime()?;
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(CHANNEL_CAPACITY);
+
+    tokio::task::block_in_place(|| {
+        std::thread::scope(|scope| {
+            let writer_thread = scope.spawn(move || {
+                handle.block_on(async move {
+                    while let Some(chunk) = rx.recv().await {
+                        writer.write_all(&chunk).await?;
+                    }
+
+                    writer.flush().await
+                })
+            });
+
+            // If the writer fails it drops the receiver and the download is cancelled
+            let result = files::get_file_to_handler(mtpdev, file_id, |data: &[u8]| {
+                match tx.blocking_send(data.to_vec()) {
+                    Ok(()) => HandlerReturn::Ok(data.len() as u32),
+                    Err(_) => HandlerReturn::Cancel,
+                }
+            });
+
+            // Lets the writer finish
+            drop(tx);
+
+            let written = writer_thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+
+            written?;
+            result
+        })
+    })
+}
+
+impl File<'_> {
+    /// Downloads this file into an async `writer`, the writer is flushed at the end.
+    ///
+    /// The download blocks the current worker thread until it's done, so it needs the
+    /// multi-threaded runtime and fails with `Error::InvalidArgument` on a current-thread one,
+    /// check the [module documentation](../async_io/index.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let local = tokio::fs::File::create("video.mp4").await?;
+    /// file.download_async(local).await?;
+    /// ```
+    pub async fn download_async<W>(&self, writer: W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        get_file_to_async_write(self.owner, self.id(), writer)
+    }
+}
+
+/// Stream over the contents of a file in chunks of bytes, every chunk is fetched from the
+/// device only when polled so slow consumers don't buffer the whole file, created with
+/// [`File::chunks`](../files/struct.File.html#method.chunks).
+#[derive(Debug)]
+pub struct Chunks<'f, 'a> {
+    file: &'f File<'a>,
+    offset: u64,
+    chunk_size: usize,
+    done: bool,
+}
+
+impl Chunks<'_, '_> {
+    /// Returns the number of bytes already streamed.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Stream for Chunks<'_, '_> {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunks = self.get_mut();
+        let remaining = chunks.file.size().saturating_sub(chunks.offset);
+        if chunks.done || remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = vec![0; (chunks.chunk_size as u64).min(remaining) as usize];
+        let read = blocking_runtime().and_then(|_| {
+            tokio::task::block_in_place(|| chunks.file.read_at(chunks.offset, &mut chunk))
+        });
+
+        match read {
+            Ok(0) => {
+                chunks.done = true;
+                Poll::Ready(None)
+            }
+            Ok(len) => {
+                chunk.truncate(len);
+                chunks.offset += len as u64;
+                Poll::Ready(Some(Ok(Bytes::from(chunk))))
+            }
+            Err(err) => {
+                chunks.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+
+        let remaining = self.file.size().saturating_sub(self.offset);
+        let chunks = remaining.div_ceil(self.chunk_size as u64);
+        (0, Some(chunks as usize))
+    }
+}
+
+impl<'a> File<'a> {
+    /// Streams this file in chunks of at most `chunk_size` bytes, like `read_at` the device must
+    /// support partial reads. Every chunk blocks the current worker thread while it's fetched,
+    /// so on a current-thread runtime the stream yields an `Error::InvalidArgument` and ends,
+    /// check the [module documentation](../async_io/index.html).
+    ///
+    /// ## Panics
+    /// Panics if `chunk_size` is zero.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use futures::StreamExt;
+    ///
+    /// let mut chunks = file.chunks(64 * 1024);
+    /// while let Some(chunk) = chunks.next().await {
+    ///     socket.write_all(&chunk?).await?;
+    /// }
+    /// ```
+    pub fn chunks(&self, chunk_size: usize) -> Chunks<'_, 'a> {
+        assert!(chunk_size > 0, "The chunk size must be greater than zero");
+
+        Chunks {
+            file: self,
+            offset: 0,
+            chunk_size,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Storage<'a> {
+    /// Uploads a file reading its contents from an async `reader` into the `parent` folder of
+    /// this storage, `metadata.file_size` must be the exact number of bytes the reader yields,
+    /// if it ends earlier the upload fails with an `UnexpectedEof` I/O error.
+    ///
+    /// The upload blocks the current worker thread until it's done, so it needs the
+    /// multi-threaded runtime and fails with `Error::InvalidArgument` on a current-thread one,
+    /// check the [module documentation](async_io/index.html).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let response = reqwest::get(url).await?;
+    /// let metadata = FileMetadata {
+    ///     file_size: response.content_length().unwrap(),
+    ///     file_name: "podcast.mp3",
+    ///     file_type: Filetype::Mp3,
+    ///     modification_date: Utc::now(),
+    /// };
+    ///
+    /// let reader = StreamReader::new(response.bytes_stream().map_err(to_io_error));
+    /// storage.send_file_from_async_read(reader, Parent::Root, metadata).await?;
+    /// ```
+    pub async fn send_file_from_async_read<R>(
+        &self,
+        reader: R,
+        parent: Parent,
+        metadata: FileMetadata<'_>,
+    ) -> Result<File<'a>>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        send_file_from_async_read(self.owner, self.id(), reader, parent, metadata)
+    }
+}
+
+impl<'a> StoragePool<'a> {
+    /// Uploads a file reading its contents from an async `reader` into the `parent` folder of
+    /// the storage chosen by `storage` (its id or a
+    /// [`StoragePolicy`](policy/enum.StoragePolicy.html)), `metadata.file_size` must be the
+    /// exact number of bytes the reader yields.
+    ///
+    /// Like [`Storage::send_file_from_async_read`](struct.Storage.html#method.send_file_from_async_read),
+    /// the upload blocks the current worker thread until it's done and fails with
+    /// `Error::InvalidArgument` on a current-thread runtime.
+    pub async fn send_file_from_async_read<R>(
+        &self,
+        storage: impl Into<StoragePolicy>,
+        reader: R,
+        parent: Parent,
+        metadata: FileMetadata<'_>,
+    ) -> Result<File<'a>>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let storage_id = StorageSpace::new(self.owner).choose(storage.into(), metadata.file_size);
+        send_file_from_async_read(self.owner, storage_id, reader, parent, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feeder(
+        file_size: u64,
+        chunks: &[&[u8]],
+    ) -> ChunkFeeder<impl FnMut() -> Option<io::Result<Vec<u8>>>> {
+        let mut chunks: Vec<Vec<u8>> = chunks.iter().map(|chunk| chunk.to_vec()).collect();
+        chunks.reverse();
+        ChunkFeeder::new(file_size, move || chunks.pop().map(Ok))
+    }
+
+    #[test]
+    fn feeds_every_chunk() {
+        let mut feeder = feeder(5, &[b"abc", b"de"]);
+        let mut buf = [0; 2];
+
+        let mut received = Vec::new();
+        while let HandlerReturn::Ok(len) = feeder.fill(&mut buf) {
+            if len == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..len as usize]);
+        }
+
+        assert_eq!(received, b"abcde");
+        assert!(feeder.error.is_none());
+    }
+
+    #[test]
+    fn short_reader_fails_the_upload() {
+        let mut feeder = feeder(10, &[b"abc"]);
+        let mut buf = [0; 8];
+
+        assert!(matches!(feeder.fill(&mut buf), HandlerReturn::Ok(3)));
+        assert!(matches!(feeder.fill(&mut buf), HandlerReturn::Error));
+        assert_eq!(
+            feeder.error.map(|err| err.kind()),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Contains relevant items to upload many files at once, see
+//! [`MtpDevice::send_files`](../../device/struct.MtpDevice.html#method.send_files).
+
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::device::MtpDevice;
+use crate::error::{Error, MtpErrorKind};
+use crate::object::filetypes::Filetype;
+use crate::storage::files::{self, File, FileMetadata};
+use crate::storage::policy::{StoragePolicy, StorageSpace};
+use crate::storage::Parent;
+use crate::util::CallbackReturn;
+use crate::Result;
+
+/// A local file to be uploaded with `send_files`, by default the name of the uploaded file is
+/// the name of the local file and its type is guessed with
+/// [`Filetype::guess`](../../object/filetypes/enum.Filetype.html#method.guess).
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::batch::UploadJob;
+///
+/// let job = UploadJob::new("photos/cat.jpg", storage.id(), Parent::Root)
+///     .file_type(Filetype::Jpeg);
+///
+/// // Onto the SD card while it has room
+/// let job = UploadJob::new("videos/trip.mp4", StoragePolicy::PreferRemovable, Parent::Root);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UploadJob {
+    /// Path of the local file.
+    pub path: PathBuf,
+    /// Storage where the file is uploaded, `StoragePolicy::Specific(StorageId(0))` lets the
+    /// device choose.
+    pub storage: StoragePolicy,
+    /// Folder where the file is uploaded.
+    pub parent: Parent,
+    /// Name of the uploaded file, if `None` the name of the local file is used.
+    pub file_name: Option<String>,
+    /// Type of the uploaded file, if `None` it's guessed from the local file.
+    pub file_type: Option<Filetype>,
+}
+
+impl UploadJob {
+    /// Uploads the file at `path` into the `parent` folder of the storage chosen by `storage`
+    /// (its id or a `StoragePolicy`).
+    pub fn new(
+        path: impl Into<PathBuf>,
+        storage: impl Into<StoragePolicy>,
+        parent: Parent,
+    ) -> Self {
+        UploadJob {
+            path: path.into(),
+            storage: storage.into(),
+            parent,
+            file_name: None,
+            file_type: None,
+        }
+    }
+
+    /// Changes the name of the uploaded file.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Changes the type of the uploaded file instead of guessing it.
+    pub fn file_type(mut self, file_type: Filetype) -> Self {
+        self.file_type = Some(file_type);
+        self
+    }
+}
+
+fn cancelled() -> Error {
+    Error::MtpError {
+        kind: MtpErrorKind::Cancelled,
+        text: "Upload cancelled by the progress callback".to_string(),
+        details: Vec::new(),
+    }
+}
+
+pub(crate) fn send_files<C>(
+    mtpdev: &MtpDevice,
+    jobs: Vec<UploadJob>,
+    mut progress: C,
+) -> Vec<Result<File<'_>>>
+where
+    C: FnMut(u64, u64) -> CallbackReturn,
+{
+    let metadata: Vec<_> = jobs.iter().map(|job| fs::metadata(&job.path)).collect();
+    let total: u64 = metadata
+        .iter()
+        .filter_map(|metadata| metadata.as_ref().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    // Keeps count of the space taken by the previous uploads
+    let mut space = StorageSpace::new(mtpdev);
+    let mut results = Vec::with_capacity(jobs.len());
+    let mut done = 0;
+    let mut cancel = false;
+
+    for (job, metadata) in jobs.into_iter().zip(metadata) {
+        if cancel {
+            results.push(Err(cancelled()));
+            continue;
+        }
+
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                results.push(Err(err.into()));
+                continue;
+            }
+        };
+
+        let file_name = match &job.file_name {
+            Some(file_name) => file_name.clone(),
+            None => match job.path.file_name().and_then(|name| name.to_str()) {
+                Some(file_name) => file_name.to_string(),
+                None => {
+                    results.push(Err(Error::InvalidArgument {
+                        reason: format!("{} has no valid UTF-8 file name", job.path.display()),
+                    }));
+                    continue;
+                }
+            },
+        };
+
+        let modification_date: DateTime<Utc> = match metadata.modified() {
+            Ok(modified) => modified.into(),
+            Err(err) => {
+                results.push(Err(err.into()));
+                continue;
+            }
+        };
+
+        let file_metadata = FileMetadata {
+            file_size: metadata.len(),
+            file_name: &file_name,
+            file_type: match &job.file_type {
+                Some(file_type) => file_type.clone(),
+                None => Filetype::guess(&job.path),
+            },
+            modification_date,
+        };
+
+        let storage_id = space.choose(job.storage, metadata.len());
+        let result = files::send_file_from_path_with_callback(
+            mtpdev,
+            storage_id,
+            &job.path,
+            job.parent,
+            file_metadata,
+            |sent, _| match progress(done + sent, total) {
+                CallbackReturn::Continue => CallbackReturn::Continue,
+                CallbackReturn::Cancel => {
+                    cancel = true;
+                    CallbackReturn::Cancel
+                }
+            },
+        );
+
+        done += metadata.len();
+        results.push(result);
+    }
+
+    results
+}
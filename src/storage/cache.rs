@@ -0,0 +1,188 @@
+//! Contains a Rust-side cache of folder contents, this is useful to get cached-like ergonomics on
+//! uncached devices without waiting for the full enumeration `libmtp` does on cached devices, you
+//! can cache only the storage or folder subtree you care about (e.g. just `DCIM`).
+
+use std::collections::HashMap;
+
+use crate::device::events::Event;
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::object::{Object, ObjectId};
+use crate::storage::files::File;
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Memoizes the contents of folders fetched with `files_and_folders` and the metadata of objects
+/// fetched by id, every folder (or object) is fetched from the device only the first time you
+/// ask for it, later requests are served from memory.
+///
+/// Note that this cache doesn't know about changes made on the device after some folder was
+/// cached, feed the device events to [`handle_event`](struct.FolderCache.html#method.handle_event)
+/// to invalidate what changed, or use [`refresh`](struct.FolderCache.html#method.refresh),
+/// [`invalidate`](struct.FolderCache.html#method.invalidate) or
+/// [`clear`](struct.FolderCache.html#method.clear) if you know the contents changed.
+///
+/// ## Example
+/// ```no_run
+/// let mut cache = storage.cache_subtree(Parent::Folder(dcim_id))?;
+/// for file in cache.children(Parent::Folder(dcim_id))? {
+///     println!("{}", file.name());
+/// }
+/// ```
+pub struct FolderCache<'a> {
+    owner: &'a MtpDevice,
+    storage_id: u32,
+    children: HashMap<u32, Vec<File<'a>>>,
+    objects: HashMap<u32, File<'a>>,
+}
+
+impl<'a> FolderCache<'a> {
+    pub(crate) fn new(owner: &'a MtpDevice, storage_id: u32) -> Self {
+        FolderCache {
+            owner,
+            storage_id,
+            children: HashMap::new(),
+            objects: HashMap::new(),
+        }
+    }
+
+    /// Returns the `MtpDevice` that owns this cache.
+    pub fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+
+    /// Returns the id of the storage this cache lists, `0` means all the storages.
+    pub fn storage_id(&self) -> u32 {
+        self.storage_id
+    }
+
+    /// Returns the contents of `parent`, performing I/O with the device only if this folder
+    /// wasn't cached yet.
+    pub fn children(&mut self, parent: Parent) -> Result<&[File<'a>]> {
+        let key = parent.faf_id();
+
+        if !self.children.contains_key(&key) {
+            let files = files_and_folders(self.owner, self.storage_id, parent)?;
+            self.children.insert(key, files);
+        }
+
+        Ok(&self.children[&key])
+    }
+
+    /// Returns the contents of `parent` only if it's already cached, never performs I/O.
+    pub fn cached_children(&self, parent: Parent) -> Option<&[File<'a>]> {
+        self.children
+            .get(&parent.faf_id())
+            .map(|files| files.as_slice())
+    }
+
+    /// Check whether the contents of `parent` are cached.
+    pub fn is_cached(&self, parent: Parent) -> bool {
+        self.children.contains_key(&parent.faf_id())
+    }
+
+    /// Caches the contents of `parent` and recursively the contents of every folder below it.
+    pub fn cache_subtree(&mut self, parent: Parent) -> Result<()> {
+        let mut pending = vec![parent];
+
+        while let Some(parent) = pending.pop() {
+            let folders = self
+                .children(parent)?
+                .iter()
+                .filter(|file| matches!(file.ftype(), Filetype::Folder))
+                .map(|folder| Parent::Folder(folder.id()));
+
+            pending.extend(folders);
+        }
+
+        Ok(())
+    }
+
+    /// Searches an object by its id in the cached folders, never performs I/O.
+    pub fn find(&self, id: u32) -> Option<&File<'a>> {
+        self.children
+            .values()
+            .flat_map(|files| files.iter())
+            .find(|file| file.id() == id)
+    }
+
+    /// Returns the metadata of the object with the given id, from the cached folders or from
+    /// previous calls if possible, otherwise it's fetched from the device and memoized.
+    pub fn metadata(&mut self, id: u32) -> Result<&File<'a>> {
+        if self.find(id).is_some() {
+            return Ok(self.find(id).expect("Just found"));
+        }
+
+        if !self.objects.contains_key(&id) {
+            let file = self.owner.search_file(id)?;
+            self.objects.insert(id, file);
+        }
+
+        Ok(&self.objects[&id])
+    }
+
+    /// Forgets the contents of `parent` and fetches them again from the device.
+    pub fn refresh(&mut self, parent: Parent) -> Result<&[File<'a>]> {
+        self.invalidate(parent);
+        self.children(parent)
+    }
+
+    /// Invalidates what changed according to an event read from the device (e.g. with
+    /// [`MtpDevice::read_event`](../../device/struct.MtpDevice.html#method.read_event)), returns
+    /// whether something was invalidated.
+    ///
+    /// New objects are looked up on the device to know which folder changed, if the lookup
+    /// fails the whole cache is cleared.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::ObjectAdded(ObjectId(id)) => match self.owner.search_file(id) {
+                Ok(file) => {
+                    let parent = file.parent_id();
+                    let cached = self.is_cached(parent);
+                    self.invalidate(parent);
+                    self.objects.insert(id, file);
+                    cached
+                }
+                Err(_) => {
+                    self.clear();
+                    true
+                }
+            },
+            Event::ObjectRemoved(ObjectId(id)) => {
+                let parent = self.find(id).map(|file| file.parent_id());
+                let cached = parent.is_some()
+                    || self.objects.contains_key(&id)
+                    || self.children.contains_key(&id);
+
+                if let Some(parent) = parent {
+                    self.invalidate(parent);
+                }
+                self.invalidate(Parent::Folder(id));
+                self.objects.remove(&id);
+                cached
+            }
+            Event::StoreAdded(_) | Event::StoreRemoved(_) => {
+                self.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the contents of `parent`, the next time they're requested they'll be
+    /// fetched from the device again.
+    pub fn invalidate(&mut self, parent: Parent) {
+        self.children.remove(&parent.faf_id());
+    }
+
+    /// Forgets the metadata of the object with the given id fetched with `metadata`.
+    pub fn invalidate_object(&mut self, id: u32) {
+        self.objects.remove(&id);
+    }
+
+    /// Forgets the contents of all the cached folders and objects.
+    pub fn clear(&mut self) {
+        self.children.clear();
+        self.objects.clear();
+    }
+}
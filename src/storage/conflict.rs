@@ -0,0 +1,396 @@
+//! Contains `OnConflict`, what uploads do when an object with the same name already exists in
+//! the target folder, since MTP itself happily creates duplicates. Set it with
+//! [`MtpDevice::set_on_conflict`](../../device/struct.MtpDevice.html#method.set_on_conflict).
+//!
+//! Also contains `OnLocalConflict`, what downloads with
+//! [`File::download_to`](../files/struct.File.html#method.download_to) do when the local file
+//! already exists, they return the `DownloadAction` taken.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::device::capabilities::DeviceCapability;
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::{Error, MtpErrorKind};
+use crate::object::Object;
+use crate::storage::files::{get_file_to_path_with_callback, list_files, File};
+use crate::storage::{files_and_folders, Parent};
+use crate::trace;
+use crate::util::{aborted, CallbackReturn};
+use crate::Result;
+
+/// Size of the reads of a resumed download.
+const RESUME_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// What to do when uploading a file named like an object of the target folder.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::conflict::OnConflict;
+///
+/// mtp_device.set_on_conflict(Some(OnConflict::RenameWithSuffix));
+/// // Uploaded as `notes (1).txt` if `notes.txt` exists
+/// mtp_device.send_bytes(storage.id(), b"...", "notes.txt", Parent::Root, Filetype::Text)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Nothing is uploaded, the existing object is returned instead.
+    Skip,
+    /// The file is uploaded, then the existing object is deleted, if the upload fails the
+    /// existing object is kept.
+    Overwrite,
+    /// The file is uploaded with a suffix before its extension, like `song (1).mp3`.
+    RenameWithSuffix,
+    /// The upload fails with `Error::ObjectExists`.
+    Error,
+}
+
+/// Outcome of checking the target folder of an upload.
+pub(crate) enum Resolution<'n> {
+    /// Upload with this name.
+    Upload(Cow<'n, str>),
+    /// Don't upload, this object has the name.
+    Existing(u32),
+    /// Upload with the same name, this object is deleted once the upload succeeds.
+    Replace(u32),
+}
+
+/// Returns the objects of the `parent` folder, cached devices only know about files.
+fn siblings(mtpdev: &MtpDevice, storage_id: u32, parent: Parent) -> Result<Vec<File<'_>>> {
+    if let CacheMode::Uncached = mtpdev.cache_mode() {
+        return files_and_folders(mtpdev, storage_id, parent);
+    }
+
+    let mut files = list_files(mtpdev, storage_id, parent)?;
+    files.retain(|file| match parent {
+        Parent::Root => matches!(file.parent_id(), Parent::Root | Parent::Folder(0)),
+        Parent::Folder(id) => file.parent_id().to_id() == id,
+    });
+
+    Ok(files)
+}
+
+/// Returns `name` with ` (n)` before its extension.
+fn with_suffix(name: &str, n: usize) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{} ({}){}", &name[..dot], n, &name[dot..]),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+pub(crate) fn resolve<'n>(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+    name: &'n str,
+    policy: OnConflict,
+) -> Result<Resolution<'n>> {
+    let siblings = siblings(mtpdev, storage_id, parent)?;
+    let existing = match siblings.iter().find(|file| file.name() == name) {
+        Some(existing) => existing,
+        None => return Ok(Resolution::Upload(Cow::Borrowed(name))),
+    };
+
+    match policy {
+        OnConflict::Skip => Ok(Resolution::Existing(existing.id())),
+        OnConflict::Overwrite => Ok(Resolution::Replace(existing.id())),
+        OnConflict::RenameWithSuffix => {
+            let taken: HashSet<Cow<'_, str>> = siblings.iter().map(|file| file.name()).collect();
+            let name = (1..)
+                .map(|n| with_suffix(name, n))
+                .find(|candidate| !taken.contains(candidate.as_str()))
+                .expect("Ran out of suffixes");
+
+            Ok(Resolution::Upload(Cow::Owned(name)))
+        }
+        OnConflict::Error => Err(Error::ObjectExists {
+            name: name.to_string(),
+            id: existing.id(),
+        }),
+    }
+}
+
+/// What to do when downloading a file to a local path that already exists.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::conflict::{DownloadAction, OnLocalConflict};
+///
+/// match file.download_to("video.mp4", OnLocalConflict::ResumeIfPartial)? {
+///     DownloadAction::Skipped => println!("Already downloaded"),
+///     DownloadAction::Resumed { offset } => println!("Resumed after {} bytes", offset),
+///     _ => println!("Downloaded"),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnLocalConflict {
+    /// Nothing is downloaded, the local file is kept.
+    Skip,
+    /// The local file is replaced.
+    Overwrite,
+    /// The file is downloaded next to the local one with a suffix before its extension, like
+    /// `song (1).mp3`.
+    Rename,
+    /// If the local file is shorter than the file of the device, it's taken as an interrupted
+    /// download and only the rest is downloaded, if it has the same size nothing is downloaded,
+    /// otherwise it's replaced. Resuming needs
+    /// [`DeviceCapability::GetPartialObject`](../../device/capabilities/enum.DeviceCapability.html#variant.GetPartialObject),
+    /// without it the whole file is downloaded again.
+    ResumeIfPartial,
+}
+
+/// What a download did, returned by
+/// [`File::download_to`](../files/struct.File.html#method.download_to).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadAction {
+    /// There was no local file, the file was downloaded.
+    Downloaded,
+    /// The local file was kept.
+    Skipped,
+    /// The local file was replaced.
+    Overwritten,
+    /// The file was downloaded to this path instead.
+    Renamed(PathBuf),
+    /// The local file was completed from this offset.
+    Resumed { offset: u64 },
+}
+
+fn cancelled() -> Error {
+    Error::MtpError {
+        kind: MtpErrorKind::Cancelled,
+        text: "Download cancelled by the progress callback".to_string(),
+        details: Vec::new(),
+    }
+}
+
+/// Returns `path` with the first free suffix, see `with_suffix`.
+fn free_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::InvalidArgument {
+            reason: format!("{} has no valid UTF-8 file name", path.display()),
+        })?;
+
+    let path = (1..)
+        .map(|n| path.with_file_name(with_suffix(name, n)))
+        .find(|candidate| !candidate.exists())
+        .expect("Ran out of suffixes");
+
+    Ok(path)
+}
+
+/// Returns `name` if it can be used as the name of a local file, fails with
+/// `Error::InvalidArgument` otherwise. Names of objects come from the device, one with
+/// separators (or like `..`) would escape the folder it's downloaded to.
+pub(crate) fn local_file_name(name: &str) -> Result<&str> {
+    let mut components = Path::new(name).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+
+    if single && !name.contains(['/', '\\', '\0']) {
+        Ok(name)
+    } else {
+        Err(Error::InvalidArgument {
+            reason: format!("{:?} can't be used as a local file name", name),
+        })
+    }
+}
+
+/// Moves the downloaded `partial` file into `directory` as `name`, or with the first free
+/// suffix (like `photo (1).jpg`) if it's taken, local files are never replaced. Returns the
+/// final path.
+pub(crate) fn persist_download(partial: &Path, directory: &Path, name: &str) -> Result<PathBuf> {
+    let name = local_file_name(name)?;
+    let candidates = std::iter::once(name.to_string()).chain((1..).map(|n| with_suffix(name, n)));
+
+    for candidate in candidates {
+        let path = directory.join(candidate);
+
+        // A rename replaces existing files, so the name is reserved first
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+
+        if let Err(err) = fs::rename(partial, &path) {
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
+        }
+
+        return Ok(path);
+    }
+
+    unreachable!("Ran out of suffixes")
+}
+
+/// Appends the contents of `file` from `offset` to the local file at `path`.
+fn resume<C>(file: &File<'_>, path: &Path, offset: u64, mut callback: C) -> Result<()>
+where
+    C: FnMut(u64, u64) -> CallbackReturn,
+{
+    let mtpdev = file.device();
+    let abort = mtpdev.abort_handle();
+    let size = file.size();
+
+    trace::download(mtpdev, file.id(), || {
+        let mut local = OpenOptions::new().append(true).open(path)?;
+        let mut buf = vec![0; RESUME_CHUNK_SIZE];
+        let mut position = offset;
+
+        while position < size {
+            if abort.is_aborted() {
+                return Err(aborted());
+            }
+
+            let read = file.read_at(position, &mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            local.write_all(&buf[..read])?;
+            position += read as u64;
+
+            if let CallbackReturn::Cancel = callback(position, size) {
+                return Err(cancelled());
+            }
+        }
+
+        local.flush()?;
+        Ok(position - offset)
+    })
+}
+
+pub(crate) fn download_to_path<C>(
+    file: &File<'_>,
+    path: &Path,
+    policy: OnLocalConflict,
+    callback: C,
+) -> Result<DownloadAction>
+where
+    C: FnMut(u64, u64) -> CallbackReturn,
+{
+    let mtpdev = file.device();
+    let local_size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            get_file_to_path_with_callback(mtpdev, file.id(), path, callback)?;
+            return Ok(DownloadAction::Downloaded);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    match policy {
+        OnLocalConflict::Skip => Ok(DownloadAction::Skipped),
+        OnLocalConflict::Overwrite => {
+            get_file_to_path_with_callback(mtpdev, file.id(), path, callback)?;
+            Ok(DownloadAction::Overwritten)
+        }
+        OnLocalConflict::Rename => {
+            let renamed = free_path(path)?;
+            get_file_to_path_with_callback(mtpdev, file.id(), &renamed, callback)?;
+            Ok(DownloadAction::Renamed(renamed))
+        }
+        OnLocalConflict::ResumeIfPartial => {
+            let size = file.size();
+            if local_size == size {
+                Ok(DownloadAction::Skipped)
+            } else if local_size > 0
+                && local_size < size
+                && mtpdev.check_capability(DeviceCapability::GetPartialObject)
+            {
+                resume(file, path, local_size, callback)?;
+                Ok(DownloadAction::Resumed { offset: local_size })
+            } else {
+                get_file_to_path_with_callback(mtpdev, file.id(), path, callback)?;
+                Ok(DownloadAction::Overwritten)
+            }
+        }
+    }
+}
+
+impl File<'_> {
+    /// Downloads this file to a local `path`, `policy` tells what to do if the path already
+    /// exists, returns what was done.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::storage::conflict::{DownloadAction, OnLocalConflict};
+    ///
+    /// if let DownloadAction::Renamed(path) = file.download_to("notes.txt", OnLocalConflict::Rename)? {
+    ///     println!("Saved as {}", path.display());
+    /// }
+    /// ```
+    pub fn download_to(
+        &self,
+        path: impl AsRef<Path>,
+        policy: OnLocalConflict,
+    ) -> Result<DownloadAction> {
+        download_to_path(self, path.as_ref(), policy, |_, _| CallbackReturn::Continue)
+    }
+
+    /// Like `download_to` with a progress `callback`, the sent bytes of a resumed download
+    /// count the part that was already downloaded.
+    pub fn download_to_with_callback<C>(
+        &self,
+        path: impl AsRef<Path>,
+        policy: OnLocalConflict,
+        callback: C,
+    ) -> Result<DownloadAction>
+    where
+        C: FnMut(u64, u64) -> CallbackReturn,
+    {
+        download_to_path(self, path.as_ref(), policy, callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::scratch::ScratchDir;
+
+    #[test]
+    fn local_file_names() {
+        for name in &["IMG_0001.JPG", "notes..txt", ".hidden", "a b (1).mp3"] {
+            assert_eq!(local_file_name(name).unwrap(), *name);
+        }
+
+        for name in &["", ".", "..", "../x", "a/b", "a\\b", "/etc", "a\0b"] {
+            assert!(local_file_name(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn persist_download_keeps_existing_files() {
+        let dir = ScratchDir::new("persist");
+        dir.write("photo.jpg", "old");
+        dir.write("photo (1).jpg", "older");
+        let partial = dir.write(".photo.jpg.part", "new");
+
+        let path = persist_download(&partial, &dir, "photo.jpg").unwrap();
+        assert_eq!(path, dir.join("photo (2).jpg"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("photo.jpg")).unwrap(), "old");
+        assert_eq!(
+            fs::read_to_string(dir.join("photo (1).jpg")).unwrap(),
+            "older"
+        );
+        assert!(!partial.exists());
+    }
+
+    #[test]
+    fn persist_download_rejects_escaping_names() {
+        let dir = ScratchDir::new("escape");
+        let partial = dir.write(".part", "data");
+
+        assert!(persist_download(&partial, &dir, "../escaped").is_err());
+        assert!(!dir.parent().unwrap().join("escaped").exists());
+        assert!(partial.exists());
+    }
+}
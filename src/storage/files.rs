@@ -3,23 +3,105 @@
 use chrono::{DateTime, TimeZone, Utc};
 use libmtp_sys as ffi;
 use num_traits::FromPrimitive;
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
-use std::fmt::{self, Debug};
+use std::fmt::{self, Debug, Display};
 use std::path::Path;
 
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
 
-use crate::device::MtpDevice;
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::{Error, MtpErrorKind, PtpResponse};
 use crate::object::filetypes::Filetype;
 use crate::object::{AsObjectId, Object};
-use crate::storage::Parent;
-use crate::util::data_get_func_handler;
-use crate::util::data_put_func_handler;
-use crate::util::progress_func_handler;
-use crate::util::{CallbackReturn, HandlerReturn};
+use crate::storage::folders::folder_subtree_ids;
+use crate::storage::verify::UploadSource;
+use crate::storage::{self, files_and_folders, Parent};
+use crate::trace;
+use crate::util::guard_callbacks;
+use crate::util::{format_bytes, throttled, CallbackReturn, HandlerReturn, RateLimiter};
+use crate::util::{DataHandler, ProgressCallback};
 use crate::Result;
 
+/// Internal function to list every file (folders aren't included) below `parent`, recursively,
+/// in a single storage or the whole storage pool (`storage_id` is `0`).
+///
+/// Uncached devices walk the folders one at a time, cached devices filter the full file
+/// listing of `libmtp`.
+pub(crate) fn list_files(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+) -> Result<Vec<File<'_>>> {
+    let mut files = Vec::new();
+
+    if let CacheMode::Uncached = mtpdev.cache_mode() {
+        let mut pending = vec![parent];
+        while let Some(folder) = pending.pop() {
+            for file in files_and_folders(mtpdev, storage_id, folder)? {
+                if matches!(file.ftype(), Filetype::Folder) {
+                    pending.push(Parent::Folder(file.id()));
+                } else {
+                    files.push(file);
+                }
+            }
+        }
+
+        return Ok(files);
+    }
+
+    let folders = match parent {
+        Parent::Root => None,
+        Parent::Folder(id) => Some(folder_subtree_ids(mtpdev, id)),
+    };
+
+    let mut head = unsafe {
+        ffi::LIBMTP_Get_Filelisting_With_Callback(mtpdev.session()?, None, std::ptr::null())
+    };
+
+    if head.is_null() {
+        if let Some(err) = mtpdev.latest_error_in("LIBMTP_Get_Filelisting_With_Callback", None) {
+            return Err(err);
+        }
+    }
+
+    while !head.is_null() {
+        let file = File {
+            inner: head,
+            owner: mtpdev,
+        };
+        head = unsafe { (*head).next };
+
+        let in_storage = storage_id == 0 || file.storage_id() == storage_id;
+        let in_folder = match &folders {
+            Some(folders) => folders.contains(&unsafe { (*file.inner).parent_id }),
+            None => true,
+        };
+
+        if in_storage && in_folder && !matches!(file.ftype(), Filetype::Folder) {
+            files.push(file);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Internal function to list the files with the given filetype below `parent`.
+pub(crate) fn files_of_type(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+    ftype: Filetype,
+) -> Result<Vec<File<'_>>> {
+    let mut files = list_files(mtpdev, storage_id, parent)?;
+    files.retain(|file| file.ftype() == ftype);
+    Ok(files)
+}
+
+/// Default limit of [`File::read_to_vec`](struct.File.html#method.read_to_vec), 16 MiB.
+pub const DEFAULT_READ_LIMIT: u64 = 16 * 1024 * 1024;
+
 /// Abstraction of a file object, it implements `Object`, you may want to use
 /// this struct to create a tree representation of one storage.
 pub struct File<'a> {
@@ -69,7 +151,48 @@ impl Debug for File<'_> {
     }
 }
 
+/// Formats the file like a line of `ls -l`, size, modification date and name (folders end with
+/// `/`), e.g. `   4.2 MiB  2021-03-14 09:26  IMG_0042.JPG`.
+impl Display for File<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = self.modification_date().format("%Y-%m-%d %H:%M");
+
+        if matches!(self.ftype(), Filetype::Folder) {
+            write!(f, "{:>10}  {}  {}/", "-", date, self.name())
+        } else {
+            let size = format_bytes(self.size());
+            write!(f, "{:>10}  {}  {}", size, date, self.name())
+        }
+    }
+}
+
+/// Owned copy of the fields of a [`File`](struct.File.html), which doesn't borrow the device,
+/// e.g. to hand it to another thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub id: u32,
+    pub parent_id: Parent,
+    pub storage_id: u32,
+    pub name: String,
+    pub size: u64,
+    pub ftype: Filetype,
+    pub modification_date: DateTime<Utc>,
+}
+
 impl File<'_> {
+    /// Returns an owned copy of the fields of this file.
+    pub fn info(&self) -> FileInfo {
+        FileInfo {
+            id: self.id(),
+            parent_id: self.parent_id(),
+            storage_id: self.storage_id(),
+            name: self.name().to_string(),
+            size: self.size(),
+            ftype: self.ftype(),
+            modification_date: self.modification_date(),
+        }
+    }
+
     /// Returns the id of the storage it belongs to.
     pub fn storage_id(&self) -> u32 {
         unsafe { (*self.inner).storage_id }
@@ -91,37 +214,117 @@ impl File<'_> {
         unsafe { (*self.inner).filesize }
     }
 
-    /// Returns the name of this file.
-    pub fn name(&self) -> &str {
+    /// Returns the name of this file, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn name(&self) -> Cow<'_, str> {
         unsafe {
             let cstr = CStr::from_ptr((*self.inner).filename);
-            cstr.to_str().expect("Invalid UTF-8 on file name")
+            cstr.to_string_lossy()
         }
     }
 
+    /// Same as `name`, for code written against `std::fs::DirEntry`.
+    pub fn file_name(&self) -> Cow<'_, str> {
+        self.name()
+    }
+
+    /// Same as `size`, for code written against `std::fs::Metadata`.
+    pub fn len(&self) -> u64 {
+        self.size()
+    }
+
+    /// Check whether this file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Same as `modification_date`, for code written against `std::fs::Metadata`.
+    pub fn modified(&self) -> DateTime<Utc> {
+        self.modification_date()
+    }
+
     /// Returns the type of this file.
     pub fn ftype(&self) -> Filetype {
         let ftype = unsafe { (*self.inner).filetype };
         Filetype::from_u32(ftype).expect("Unexpected raw variant of Filetype")
     }
 
-    /// Returns the latest modification date in UTC.
+    /// Returns the latest modification date in UTC, the Unix epoch if the device reports a
+    /// date out of range.
     pub fn modification_date(&self) -> DateTime<Utc> {
         let epoch = unsafe { (*self.inner).modificationdate };
-        Utc.timestamp(epoch, 0)
+        Utc.timestamp_opt(epoch, 0).single().unwrap_or_default()
+    }
+
+    /// Reads the bytes of this file starting at `offset` into `buf`, returns how many bytes
+    /// were read, which is fewer than the length of `buf` only if the end of the file was
+    /// reached. This lets you access parts of a file (like the header and footer of a video)
+    /// without downloading the whole file, the device must support
+    /// [`DeviceCapability::GetPartialObject`](../../device/capabilities/enum.DeviceCapability.html#variant.GetPartialObject).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// // The end of central directory record of a zip file
+    /// let mut footer = [0; 22];
+    /// file.read_at(file.size() - 22, &mut footer)?;
+    /// ```
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size();
+        let mut read = 0;
+
+        while read < buf.len() {
+            let position = offset + read as u64;
+            if position >= size {
+                break;
+            }
+
+            let wanted = (buf.len() - read).min(u32::MAX as usize) as u32;
+            let data = self.get_partial_object(position, wanted)?;
+            if data.is_empty() {
+                break;
+            }
+
+            let len = data.len().min(buf.len() - read);
+            buf[read..read + len].copy_from_slice(&data[..len]);
+            read += len;
+        }
+
+        Ok(read)
+    }
+
+    /// Downloads this file into memory, useful for small files like playlists or configs,
+    /// fails with `Error::InvalidArgument` if the file is larger than `DEFAULT_READ_LIMIT`,
+    /// use `read_to_vec_with_limit` to change the limit.
+    pub fn read_to_vec(&self) -> Result<Vec<u8>> {
+        self.read_to_vec_with_limit(DEFAULT_READ_LIMIT)
+    }
+
+    /// Like `read_to_vec` but fails if the file is larger than `limit` bytes, the limit is
+    /// checked again while downloading in case the device reported a wrong size.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let playlist = file.read_to_vec_with_limit(1024 * 1024)?;
+    /// let playlist = String::from_utf8_lossy(&playlist);
+    /// ```
+    pub fn read_to_vec_with_limit(&self, limit: u64) -> Result<Vec<u8>> {
+        read_object_to_vec(self.owner, self.id(), &self.name(), self.size(), limit)
     }
 
     /// Rename this file in-place.
     pub fn rename(&mut self, new_name: &str) -> Result<()> {
-        let new_name = CString::new(new_name).expect("Nul byte");
+        let session = self.owner.session()?;
+        let new_name = CString::new(new_name)?;
 
         let res = unsafe {
-            ffi::LIBMTP_Set_File_Name(self.owner.inner, self.inner, new_name.as_ptr() as *const _)
+            ffi::LIBMTP_Set_File_Name(session, self.inner, new_name.as_ptr() as *const _)
         };
 
         if res != 0 {
-            Err(self.owner.latest_error().unwrap_or_default())
+            Err(self.owner.error_in("LIBMTP_Set_File_Name", Some(self.id())))
         } else {
+            let id = self.id();
+            self.owner
+                .update_paths(|cache| cache.rename(id, &self.name()));
             Ok(())
         }
     }
@@ -141,24 +344,8 @@ pub(crate) fn get_file_to_path(
     file: impl AsObjectId,
     path: impl AsRef<Path>,
 ) -> Result<()> {
-    let path = path.as_ref();
-    let path = path_to_cvec!(path);
-
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_File(
-            mtpdev.inner,
-            file.as_id(),
-            path.as_ptr() as *const _,
-            None,
-            std::ptr::null(),
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(())
-    }
+    // Always with a callback, so the transfer can be aborted
+    get_file_to_path_with_callback(mtpdev, file, path, |_, _| CallbackReturn::Continue)
 }
 
 pub(crate) fn get_file_to_path_with_callback<C>(
@@ -170,27 +357,40 @@ pub(crate) fn get_file_to_path_with_callback<C>(
 where
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let path = path.as_ref();
-    let path = path_to_cvec!(path);
-
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_File(
-            mtpdev.inner,
-            file.as_id(),
-            path.as_ptr() as *const _,
-            Some(progress_func_handler),
-            callback,
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(())
-    }
+    trace::download(mtpdev, file.as_id(), || {
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let path = path.as_ref();
+            let path = path_to_cvec!(path);
+
+            let mut received = 0;
+            let mut callback = |sent, total| {
+                received = sent;
+                callback(sent, total)
+            };
+            let callback = ProgressCallback::new(&mut callback);
+
+            mtpdev.retry(|| {
+                let res = unsafe {
+                    ffi::LIBMTP_Get_File_To_File(
+                        session,
+                        file.as_id(),
+                        path.as_ptr() as *const _,
+                        callback.func(),
+                        callback.data(),
+                    )
+                };
+
+                if res != 0 {
+                    Err(mtpdev.error_in("LIBMTP_Get_File_To_File", Some(file.as_id())))
+                } else {
+                    Ok(())
+                }
+            })?;
+
+            Ok(received)
+        })
+    })
 }
 
 #[cfg(unix)]
@@ -199,21 +399,7 @@ pub(crate) fn get_file_to_descriptor(
     file: impl AsObjectId,
     descriptor: impl AsRawFd,
 ) -> Result<()> {
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_File_Descriptor(
-            mtpdev.inner,
-            file.as_id(),
-            descriptor.as_raw_fd(),
-            None,
-            std::ptr::null(),
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(())
-    }
+    get_file_to_descriptor_with_callback(mtpdev, file, descriptor, |_, _| CallbackReturn::Continue)
 }
 
 #[cfg(unix)]
@@ -226,24 +412,118 @@ pub(crate) fn get_file_to_descriptor_with_callback<C>(
 where
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_File_Descriptor(
-            mtpdev.inner,
-            file.as_id(),
-            descriptor.as_raw_fd(),
-            Some(progress_func_handler),
-            callback,
-        )
+    trace::download(mtpdev, file.as_id(), || {
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let mut received = 0;
+            let mut callback = |sent, total| {
+                received = sent;
+                callback(sent, total)
+            };
+            let callback = ProgressCallback::new(&mut callback);
+
+            let res = unsafe {
+                ffi::LIBMTP_Get_File_To_File_Descriptor(
+                    session,
+                    file.as_id(),
+                    descriptor.as_raw_fd(),
+                    callback.func(),
+                    callback.data(),
+                )
+            };
+
+            if res != 0 {
+                Err(mtpdev.error_in("LIBMTP_Get_File_To_File_Descriptor", Some(file.as_id())))
+            } else {
+                Ok(received)
+            }
+        })
+    })
+}
+
+/// Internal function to download an object of `size` bytes into memory, fails if it's larger
+/// than `limit` bytes.
+pub(crate) fn read_object_to_vec(
+    mtpdev: &MtpDevice,
+    id: u32,
+    name: &str,
+    size: u64,
+    limit: u64,
+) -> Result<Vec<u8>> {
+    let too_large = || Error::InvalidArgument {
+        reason: format!("{} is larger than {} bytes", name, limit),
     };
 
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(())
+    if size > limit {
+        return Err(too_large());
     }
+
+    let mut data = Vec::with_capacity(size as usize);
+    let mut exceeded = false;
+
+    let result = get_file_to_handler(mtpdev, id, |chunk| {
+        if (data.len() + chunk.len()) as u64 > limit {
+            exceeded = true;
+            return HandlerReturn::Cancel;
+        }
+
+        data.extend_from_slice(chunk);
+        HandlerReturn::Ok(chunk.len() as u32)
+    });
+
+    if exceeded {
+        return Err(too_large());
+    }
+
+    result?;
+    if data.len() as u64 != size {
+        return Err(Error::MtpError {
+            kind: MtpErrorKind::General,
+            text: format!(
+                "Downloaded {} of the {} bytes of {}",
+                data.len(),
+                size,
+                name
+            ),
+            details: Vec::new(),
+        });
+    }
+
+    Ok(data)
+}
+
+/// Deletes the object `id` left behind by a failed upload, an object that's already gone is
+/// fine.
+fn delete_partial(mtpdev: &MtpDevice, id: u32) -> Result<()> {
+    match mtpdev.dummy_object(id).delete() {
+        Err(err) if err.ptp_response() == Some(PtpResponse::InvalidObjectHandle) => Ok(()),
+        result => result,
+    }
+}
+
+/// Error of a transfer through a data handler that failed, a handler that cancelled or failed
+/// isn't a failure of the device, so the errors `libmtp` added for it (which look like I/O
+/// errors) are discarded instead of poisoning the device.
+fn handler_error(
+    mtpdev: &MtpDevice,
+    last_return: HandlerReturn,
+    operation: &'static str,
+    object_id: Option<u32>,
+) -> Error {
+    let (kind, text) = match last_return {
+        HandlerReturn::Ok(_) => return mtpdev.error_in(operation, object_id),
+        HandlerReturn::Cancel => (MtpErrorKind::Cancelled, "Cancelled by the data handler"),
+        HandlerReturn::Error => (MtpErrorKind::General, "The data handler failed"),
+    };
+
+    unsafe { ffi::LIBMTP_Clear_Errorstack(mtpdev.inner) };
+    let err = Error::MtpError {
+        kind,
+        text: text.to_string(),
+        details: Vec::new(),
+    };
+
+    mtpdev.with_context(err, operation, object_id)
 }
 
 pub(crate) fn get_file_to_handler<H>(
@@ -254,31 +534,44 @@ pub(crate) fn get_file_to_handler<H>(
 where
     H: FnMut(&[u8]) -> HandlerReturn,
 {
-    let handler: &mut dyn FnMut(&[u8]) -> HandlerReturn = &mut handler;
-    let mut handler_return = HandlerReturn::Ok(0);
-
-    let private = &mut (&mut handler_return, handler) as *mut _ as *mut libc::c_void;
-
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_Handler(
-            mtpdev.inner,
-            file.as_id(),
-            Some(data_put_func_handler),
-            private,
-            None,
-            std::ptr::null(),
-        )
-    };
-
-    if res != 0 && handler_return.is_error() {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        if handler_return.is_cancel() {
-            let _ = mtpdev.latest_error();
-        }
-
-        Ok(())
-    }
+    trace::download(mtpdev, file.as_id(), || {
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let mut limiter = mtpdev.max_throughput().map(RateLimiter::new);
+            let mut received = 0;
+            let mut handler = |data: &[u8]| {
+                let ret = throttled(&mut limiter, handler(data));
+                if let HandlerReturn::Ok(len) = ret {
+                    received += len.min(data.len() as u32) as u64;
+                }
+
+                ret
+            };
+            let mut handler = DataHandler::new(&mut handler);
+
+            let res = unsafe {
+                ffi::LIBMTP_Get_File_To_Handler(
+                    session,
+                    file.as_id(),
+                    handler.put_func(),
+                    handler.data(),
+                    None,
+                    std::ptr::null(),
+                )
+            };
+
+            if res != 0 {
+                Err(handler_error(
+                    mtpdev,
+                    handler.last_return(),
+                    "LIBMTP_Get_File_To_Handler",
+                    Some(file.as_id()),
+                ))
+            } else {
+                Ok(received)
+            }
+        })
+    })
 }
 
 pub(crate) fn get_file_to_handler_with_callback<H, C>(
@@ -291,34 +584,46 @@ where
     H: FnMut(&[u8]) -> HandlerReturn,
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let handler: &mut dyn FnMut(&[u8]) -> HandlerReturn = &mut handler;
-    let mut handler_return = HandlerReturn::Ok(0);
-
-    let private = &mut (&mut handler_return, handler) as *mut _ as *mut libc::c_void;
-
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Get_File_To_Handler(
-            mtpdev.inner,
-            file.as_id(),
-            Some(data_put_func_handler),
-            private,
-            Some(progress_func_handler),
-            callback,
-        )
-    };
-
-    if res != 0 && handler_return.is_error() {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        if handler_return.is_cancel() {
-            let _ = mtpdev.latest_error();
-        }
-
-        Ok(())
-    }
+    trace::download(mtpdev, file.as_id(), || {
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let mut limiter = mtpdev.max_throughput().map(RateLimiter::new);
+            let mut received = 0;
+            let mut handler = |data: &[u8]| {
+                let ret = throttled(&mut limiter, handler(data));
+                if let HandlerReturn::Ok(len) = ret {
+                    received += len.min(data.len() as u32) as u64;
+                }
+
+                ret
+            };
+            let mut handler = DataHandler::new(&mut handler);
+
+            let callback = ProgressCallback::new(&mut callback);
+
+            let res = unsafe {
+                ffi::LIBMTP_Get_File_To_Handler(
+                    session,
+                    file.as_id(),
+                    handler.put_func(),
+                    handler.data(),
+                    callback.func(),
+                    callback.data(),
+                )
+            };
+
+            if res != 0 {
+                Err(handler_error(
+                    mtpdev,
+                    handler.last_return(),
+                    "LIBMTP_Get_File_To_Handler",
+                    Some(file.as_id()),
+                ))
+            } else {
+                Ok(received)
+            }
+        })
+    })
 }
 
 pub(crate) fn send_file_from_path<'a>(
@@ -328,30 +633,10 @@ pub(crate) fn send_file_from_path<'a>(
     parent: Parent,
     metadata: FileMetadata<'_>,
 ) -> Result<File<'a>> {
-    let path = path.as_ref();
-    let path = path_to_cvec!(path);
-
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_File(
-            mtpdev.inner,
-            path.as_ptr() as *const _,
-            file_t,
-            None,
-            std::ptr::null(),
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
-        })
-    }
+    // Always with a callback, so the transfer can be aborted
+    send_file_from_path_with_callback(mtpdev, storage_id, path, parent, metadata, |_, _| {
+        CallbackReturn::Continue
+    })
 }
 
 pub(crate) fn send_file_from_path_with_callback<'a, C>(
@@ -365,33 +650,75 @@ pub(crate) fn send_file_from_path_with_callback<'a, C>(
 where
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let path = path.as_ref();
-    let path = path_to_cvec!(path);
-
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_File(
-            mtpdev.inner,
-            path.as_ptr() as *const _,
-            file_t,
-            Some(progress_func_handler),
-            callback,
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
-        })
-    }
+    let name = metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(
+        mtpdev,
+        storage_id,
+        parent,
+        name,
+        size,
+        Some(UploadSource::Path(path.as_ref())),
+        |name| {
+            let metadata = FileMetadata {
+                file_name: name,
+                ..metadata
+            };
+
+            guard_callbacks(mtpdev, || {
+                let session = mtpdev.session()?;
+                let path = path.as_ref();
+                let path = path_to_cvec!(path);
+
+                // A failed attempt may leave a partial object behind, sending again would
+                // duplicate it, so it must be deleted before retrying
+                let mut partial = None;
+                let result = mtpdev.retry(|| {
+                    if let Some(id) = partial {
+                        delete_partial(mtpdev, id)?;
+                        partial = None;
+                    }
+
+                    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
+                    unsafe { fill_file_t!(&metadata, parent.to_id(), storage_id, file_t) };
+
+                    let callback = ProgressCallback::new(&mut callback);
+
+                    let res = unsafe {
+                        ffi::LIBMTP_Send_File_From_File(
+                            session,
+                            path.as_ptr() as *const _,
+                            file_t,
+                            callback.func(),
+                            callback.data(),
+                        )
+                    };
+
+                    if res != 0 {
+                        let err = mtpdev.error_in("LIBMTP_Send_File_From_File", None);
+                        let id = unsafe { (*file_t).item_id };
+                        if id != 0 {
+                            partial = Some(id);
+                        }
+
+                        unsafe { ffi::LIBMTP_destroy_file_t(file_t) };
+                        Err(err)
+                    } else {
+                        Ok(File {
+                            inner: file_t,
+                            owner: mtpdev,
+                        })
+                    }
+                });
+
+                if let (Err(_), Some(id)) = (&result, partial) {
+                    let _ = delete_partial(mtpdev, id);
+                }
+
+                result
+            })
+        },
+    )
 }
 
 #[cfg(unix)]
@@ -402,27 +729,14 @@ pub(crate) fn send_file_from_descriptor<'a>(
     parent: Parent,
     metadata: FileMetadata<'_>,
 ) -> Result<File<'a>> {
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_File_Descriptor(
-            mtpdev.inner,
-            descriptor.as_raw_fd(),
-            file_t,
-            None,
-            std::ptr::null(),
-        )
-    };
-
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
-        })
-    }
+    send_file_from_descriptor_with_callback(
+        mtpdev,
+        storage_id,
+        descriptor,
+        parent,
+        metadata,
+        |_, _| CallbackReturn::Continue,
+    )
 }
 
 #[cfg(unix)]
@@ -437,73 +751,141 @@ pub(crate) fn send_file_from_descriptor_with_callback<'a, C>(
 where
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_File_Descriptor(
-            mtpdev.inner,
-            descriptor.as_raw_fd(),
-            file_t,
-            Some(progress_func_handler),
-            callback,
-        )
-    };
+    let name = metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(mtpdev, storage_id, parent, name, size, None, |name| {
+        let metadata = FileMetadata {
+            file_name: name,
+            ..metadata
+        };
 
-    if res != 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let file_t = unsafe { ffi::LIBMTP_new_file_t() };
+            unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
+
+            let callback = ProgressCallback::new(&mut callback);
+
+            let res = unsafe {
+                ffi::LIBMTP_Send_File_From_File_Descriptor(
+                    session,
+                    descriptor.as_raw_fd(),
+                    file_t,
+                    callback.func(),
+                    callback.data(),
+                )
+            };
+
+            if res != 0 {
+                unsafe { ffi::LIBMTP_destroy_file_t(file_t) };
+                Err(mtpdev.error_in("LIBMTP_Send_File_From_File_Descriptor", None))
+            } else {
+                Ok(File {
+                    inner: file_t,
+                    owner: mtpdev,
+                })
+            }
         })
-    }
+    })
 }
 
 pub(crate) fn send_file_from_handler<'a, H>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+    metadata: FileMetadata<'_>,
+    handler: H,
+) -> Result<File<'a>>
+where
+    H: FnMut(&mut [u8]) -> HandlerReturn,
+{
+    send_from_handler(mtpdev, storage_id, parent, metadata, handler, None)
+}
+
+/// Uploads from a handler fed by `source` if it's known, so the upload can be verified.
+fn send_from_handler<'a, H>(
     mtpdev: &'a MtpDevice,
     storage_id: u32,
     parent: Parent,
     metadata: FileMetadata<'_>,
     mut handler: H,
+    source: Option<UploadSource<'_>>,
 ) -> Result<File<'a>>
 where
     H: FnMut(&mut [u8]) -> HandlerReturn,
 {
-    let handler: &mut dyn FnMut(&mut [u8]) -> HandlerReturn = &mut handler;
-    let mut handler_return = HandlerReturn::Ok(0);
-
-    let private = &mut (&mut handler_return, handler) as *mut _ as *mut libc::c_void;
-
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_Handler(
-            mtpdev.inner,
-            Some(data_get_func_handler),
-            private,
-            file_t,
-            None,
-            std::ptr::null(),
-        )
+    let name = metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(mtpdev, storage_id, parent, name, size, source, |name| {
+        let metadata = FileMetadata {
+            file_name: name,
+            ..metadata
+        };
+
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let mut limiter = mtpdev.max_throughput().map(RateLimiter::new);
+            let mut handler = |data: &mut [u8]| throttled(&mut limiter, handler(data));
+            let mut handler = DataHandler::new(&mut handler);
+
+            let file_t = unsafe { ffi::LIBMTP_new_file_t() };
+            unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
+
+            let res = unsafe {
+                ffi::LIBMTP_Send_File_From_Handler(
+                    session,
+                    handler.get_func(),
+                    handler.data(),
+                    file_t,
+                    None,
+                    std::ptr::null(),
+                )
+            };
+
+            if res != 0 {
+                unsafe { ffi::LIBMTP_destroy_file_t(file_t) };
+                Err(handler_error(
+                    mtpdev,
+                    handler.last_return(),
+                    "LIBMTP_Send_File_From_Handler",
+                    None,
+                ))
+            } else {
+                Ok(File {
+                    inner: file_t,
+                    owner: mtpdev,
+                })
+            }
+        })
+    })
+}
+
+/// Internal function to upload a file with the contents of `data`.
+pub(crate) fn send_bytes<'a>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    data: &[u8],
+    name: &str,
+    parent: Parent,
+    file_type: Filetype,
+) -> Result<File<'a>> {
+    let metadata = FileMetadata {
+        file_size: data.len() as u64,
+        file_name: name,
+        file_type,
+        modification_date: Utc::now(),
     };
 
-    if res != 0 && handler_return.is_error() {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        if handler_return.is_cancel() {
-            let _ = mtpdev.latest_error();
-        }
+    let mut remaining = data;
+    let handler = |buf: &mut [u8]| {
+        let len = buf.len().min(remaining.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        remaining = &remaining[len..];
+        HandlerReturn::Ok(len as u32)
+    };
 
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
-        })
-    }
+    let source = Some(UploadSource::Bytes(data));
+    send_from_handler(mtpdev, storage_id, parent, metadata, handler, source)
 }
 
 pub(crate) fn send_file_from_handler_with_callback<'a, H, C>(
@@ -518,38 +900,50 @@ where
     H: FnMut(&mut [u8]) -> HandlerReturn,
     C: FnMut(u64, u64) -> CallbackReturn,
 {
-    let handler: &mut dyn FnMut(&mut [u8]) -> HandlerReturn = &mut handler;
-    let mut handler_return = HandlerReturn::Ok(0);
-
-    let private = &mut (&mut handler_return, handler) as *mut _ as *mut libc::c_void;
-
-    let file_t = unsafe { ffi::LIBMTP_new_file_t() };
-    unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
-
-    let mut callback: &mut dyn FnMut(u64, u64) -> CallbackReturn = &mut callback;
-    let callback = &mut callback as *mut _ as *mut libc::c_void as *const _;
-
-    let res = unsafe {
-        ffi::LIBMTP_Send_File_From_Handler(
-            mtpdev.inner,
-            Some(data_get_func_handler),
-            private,
-            file_t,
-            Some(progress_func_handler),
-            callback,
-        )
-    };
-
-    if res != 0 && handler_return.is_error() {
-        Err(mtpdev.latest_error().unwrap_or_default())
-    } else {
-        if handler_return.is_cancel() {
-            let _ = mtpdev.latest_error();
-        }
+    let name = metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(mtpdev, storage_id, parent, name, size, None, |name| {
+        let metadata = FileMetadata {
+            file_name: name,
+            ..metadata
+        };
 
-        Ok(File {
-            inner: file_t,
-            owner: mtpdev,
+        guard_callbacks(mtpdev, || {
+            let session = mtpdev.session()?;
+            let mut limiter = mtpdev.max_throughput().map(RateLimiter::new);
+            let mut handler = |data: &mut [u8]| throttled(&mut limiter, handler(data));
+            let mut handler = DataHandler::new(&mut handler);
+
+            let file_t = unsafe { ffi::LIBMTP_new_file_t() };
+            unsafe { fill_file_t!(metadata, parent.to_id(), storage_id, file_t) };
+
+            let callback = ProgressCallback::new(&mut callback);
+
+            let res = unsafe {
+                ffi::LIBMTP_Send_File_From_Handler(
+                    session,
+                    handler.get_func(),
+                    handler.data(),
+                    file_t,
+                    callback.func(),
+                    callback.data(),
+                )
+            };
+
+            if res != 0 {
+                unsafe { ffi::LIBMTP_destroy_file_t(file_t) };
+                Err(handler_error(
+                    mtpdev,
+                    handler.last_return(),
+                    "LIBMTP_Send_File_From_Handler",
+                    None,
+                ))
+            } else {
+                Ok(File {
+                    inner: file_t,
+                    owner: mtpdev,
+                })
+            }
         })
-    }
+    })
 }
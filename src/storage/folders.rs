@@ -1,35 +1,50 @@
 //! Contains relevant items to handle folder objects in the device.
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::{CStr, CString};
-use std::fmt::{self, Debug};
+use std::fmt::{self, Debug, Write};
+use std::rc::Rc;
 
 use libmtp_sys as ffi;
 
-use crate::device::MtpDevice;
+use crate::device::{CacheMode, MtpDevice};
 use crate::object::Object;
 use crate::storage::Parent;
 use crate::Result;
 
-pub struct Folder<'a> {
-    inner: *mut ffi::LIBMTP_folder_t,
-    owner: &'a MtpDevice,
-
-    sibling_or_child: bool,
-}
+/// Owner of a `libmtp` folder tree, the whole tree is destroyed once every `Folder` pointing
+/// into it is dropped.
+struct FolderTree(*mut ffi::LIBMTP_folder_t);
 
-impl Drop for Folder<'_> {
+impl Drop for FolderTree {
     fn drop(&mut self) {
-        // (Recursively) destroy this folder only if this one was the
-        // first folder gathered
-        if !self.sibling_or_child {
-            unsafe {
-                ffi::LIBMTP_destroy_folder_t(self.inner);
-            }
+        unsafe {
+            ffi::LIBMTP_destroy_folder_t(self.0);
         }
     }
 }
 
+/// Owned copy of the fields of a [`Folder`](struct.Folder.html), which doesn't borrow the
+/// device nor keeps the folder tree alive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FolderInfo {
+    pub id: u32,
+    /// Id of the parent folder, `0` for folders in the root.
+    pub parent_id: u32,
+    pub storage_id: u32,
+    pub name: String,
+}
+
+/// Abstraction of a folder in a folder tree, a folder can't outlive the device it belongs to,
+/// and its siblings and children keep the tree alive, so they remain valid after the folder
+/// they were gathered from is dropped.
+pub struct Folder<'a> {
+    inner: *mut ffi::LIBMTP_folder_t,
+    owner: &'a MtpDevice,
+    tree: Rc<FolderTree>,
+}
+
 impl Object for Folder<'_> {
     fn id(&self) -> u32 {
         unsafe { (*self.inner).folder_id }
@@ -53,7 +68,9 @@ impl Object for &Folder<'_> {
 impl Debug for Folder<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Folder")
+            .field("id", &self.id())
             .field("parent_id", &self.parent_id())
+            .field("storage_id", &self.storage_id())
             .field("name", &self.name())
             .finish()
     }
@@ -64,13 +81,68 @@ impl<'a> Folder<'a> {
         unsafe { (*self.inner).parent_id }
     }
 
-    pub fn name(&self) -> &str {
+    pub fn storage_id(&self) -> u32 {
+        unsafe { (*self.inner).storage_id }
+    }
+
+    /// Returns the name of this folder, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn name(&self) -> Cow<'_, str> {
         unsafe {
             let cstr = CStr::from_ptr((*self.inner).name);
-            cstr.to_str().expect("Invalid UTF-8 on folder name")
+            cstr.to_string_lossy()
         }
     }
 
+    /// Returns an owned copy of the fields of this folder.
+    pub fn info(&self) -> FolderInfo {
+        FolderInfo {
+            id: self.id(),
+            parent_id: self.parent_id(),
+            storage_id: self.storage_id(),
+            name: self.name().to_string(),
+        }
+    }
+
+    /// Renders this folder and every folder below it as an indented tree, with the id of every
+    /// folder, handy for CLI tools and bug reports. The siblings of this folder aren't
+    /// included.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let dcim = storage.folder_list().and_then(|root| root.find(dcim_id)).unwrap();
+    /// print!("{}", dcim.to_tree_string());
+    /// // DCIM (12)
+    /// // ├── Camera (13)
+    /// // │   └── 2024 (20)
+    /// // └── Screenshots (14)
+    /// ```
+    pub fn to_tree_string(&self) -> String {
+        fn render(folder: &Folder<'_>, prefix: &str, out: &mut String) {
+            let mut current = folder.child();
+            while let Some(child) = current {
+                let next = child.sibling();
+                let (branch, indent) = if next.is_some() {
+                    ("├── ", "│   ")
+                } else {
+                    ("└── ", "    ")
+                };
+
+                let _ = writeln!(out, "{}{}{} ({})", prefix, branch, child.name(), child.id());
+                render(&child, &format!("{}{}", prefix, indent), out);
+                current = next;
+            }
+        }
+
+        let mut out = format!("{} ({})\n", self.name(), self.id());
+        render(self, "", &mut out);
+        out
+    }
+
+    /// Prints the tree given by `to_tree_string` to the standard output.
+    pub fn print_tree(&self) {
+        print!("{}", self.to_tree_string());
+    }
+
     pub fn sibling(&self) -> Option<Folder<'a>> {
         unsafe {
             if (*self.inner).sibling.is_null() {
@@ -79,7 +151,7 @@ impl<'a> Folder<'a> {
                 Some(Folder {
                     inner: (*self.inner).sibling,
                     owner: self.owner,
-                    sibling_or_child: true,
+                    tree: Rc::clone(&self.tree),
                 })
             }
         }
@@ -93,7 +165,7 @@ impl<'a> Folder<'a> {
                 Some(Folder {
                     inner: (*self.inner).child,
                     owner: self.owner,
-                    sibling_or_child: true,
+                    tree: Rc::clone(&self.tree),
                 })
             }
         }
@@ -108,27 +180,117 @@ impl<'a> Folder<'a> {
             Some(Folder {
                 inner: folder,
                 owner: self.owner,
-                sibling_or_child: true,
+                tree: Rc::clone(&self.tree),
             })
         }
     }
 
     pub fn rename(&mut self, new_name: &str) -> Result<()> {
-        let new_name = CString::new(new_name).expect("Nul byte");
+        let session = self.owner.session()?;
+        let new_name = CString::new(new_name)?;
 
-        let res =
-            unsafe { ffi::LIBMTP_Set_Folder_Name(self.owner.inner, self.inner, new_name.as_ptr()) };
+        let res = unsafe { ffi::LIBMTP_Set_Folder_Name(session, self.inner, new_name.as_ptr()) };
 
         if res != 0 {
-            Err(self.owner.latest_error().unwrap_or_default())
+            Err(self
+                .owner
+                .error_in("LIBMTP_Set_Folder_Name", Some(self.id())))
         } else {
+            // The device may have changed the name, `libmtp` updated it
+            let id = self.id();
+            self.owner
+                .update_paths(|cache| cache.rename(id, &self.name()));
             Ok(())
         }
     }
 }
 
+/// Iterator over every folder of a folder tree, depth-first, every folder comes before its
+/// children.
+///
+/// ## Example
+/// ```no_run
+/// for folder in storage.folders() {
+///     println!("{} (parent {})", folder.name(), folder.parent_id());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FolderIter<'a> {
+    pending: Vec<Folder<'a>>,
+}
+
+impl<'a> FolderIter<'a> {
+    /// Walks `first`, its siblings and all their descendants.
+    pub(crate) fn new(first: Option<Folder<'a>>) -> Self {
+        FolderIter {
+            pending: first.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for FolderIter<'a> {
+    type Item = Folder<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let folder = self.pending.pop()?;
+
+        // The child is pushed last so the subtree is walked before the siblings
+        self.pending.extend(folder.sibling());
+        self.pending.extend(folder.child());
+
+        Some(folder)
+    }
+}
+
+/// Builds a `libmtp` folder tree walking the contents of `parent` one level at a time, this way
+/// uncached devices can also be used with `Folder`. Returns null if there are no folders.
+unsafe fn build_folder_tree(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent_id: u32,
+) -> *mut ffi::LIBMTP_folder_t {
+    let mut head = ffi::LIBMTP_Get_Files_And_Folders(mtpdev.inner, storage_id, parent_id);
+
+    let mut first: *mut ffi::LIBMTP_folder_t = std::ptr::null_mut();
+    let mut last: *mut ffi::LIBMTP_folder_t = std::ptr::null_mut();
+
+    while !head.is_null() {
+        let next = (*head).next;
+
+        if (*head).filetype == ffi::LIBMTP_filetype_t_LIBMTP_FILETYPE_FOLDER
+            && !(*head).filename.is_null()
+        {
+            let folder = ffi::LIBMTP_new_folder_t();
+            (*folder).folder_id = (*head).item_id;
+            (*folder).parent_id = (*head).parent_id;
+            (*folder).storage_id = (*head).storage_id;
+            (*folder).name = libc::strdup((*head).filename);
+            (*folder).child = build_folder_tree(mtpdev, (*head).storage_id, (*head).item_id);
+
+            if last.is_null() {
+                first = folder;
+            } else {
+                (*last).sibling = folder;
+            }
+
+            last = folder;
+        }
+
+        ffi::LIBMTP_destroy_file_t(head);
+        head = next;
+    }
+
+    first
+}
+
 pub(crate) fn get_folder_list(mtpdev: &MtpDevice) -> Option<Folder<'_>> {
-    let folder = unsafe { ffi::LIBMTP_Get_Folder_List(mtpdev.inner) };
+    let session = mtpdev.session().ok()?;
+    let folder = match mtpdev.cache_mode() {
+        CacheMode::Cached => unsafe { ffi::LIBMTP_Get_Folder_List(session) },
+        CacheMode::Uncached => unsafe {
+            build_folder_tree(mtpdev, 0, ffi::LIBMTP_FILES_AND_FOLDERS_ROOT)
+        },
+    };
 
     if folder.is_null() {
         None
@@ -136,13 +298,21 @@ pub(crate) fn get_folder_list(mtpdev: &MtpDevice) -> Option<Folder<'_>> {
         Some(Folder {
             inner: folder,
             owner: mtpdev,
-            sibling_or_child: false,
+            tree: Rc::new(FolderTree(folder)),
         })
     }
 }
 
 pub(crate) fn get_folder_list_storage(mtpdev: &MtpDevice, storage_id: u32) -> Option<Folder<'_>> {
-    let folder = unsafe { ffi::LIBMTP_Get_Folder_List_For_Storage(mtpdev.inner, storage_id) };
+    let session = mtpdev.session().ok()?;
+    let folder = match mtpdev.cache_mode() {
+        CacheMode::Cached => unsafe {
+            ffi::LIBMTP_Get_Folder_List_For_Storage(session, storage_id)
+        },
+        CacheMode::Uncached => unsafe {
+            build_folder_tree(mtpdev, storage_id, ffi::LIBMTP_FILES_AND_FOLDERS_ROOT)
+        },
+    };
 
     if folder.is_null() {
         None
@@ -150,26 +320,48 @@ pub(crate) fn get_folder_list_storage(mtpdev: &MtpDevice, storage_id: u32) -> Op
         Some(Folder {
             inner: folder,
             owner: mtpdev,
-            sibling_or_child: false,
+            tree: Rc::new(FolderTree(folder)),
         })
     }
 }
 
+/// Returns the id of `folder_id` and of every folder below it.
+pub(crate) fn folder_subtree_ids(mtpdev: &MtpDevice, folder_id: u32) -> HashSet<u32> {
+    fn visit(folder: Option<Folder<'_>>, ids: &mut HashSet<u32>) {
+        let mut current = folder;
+        while let Some(folder) = current {
+            ids.insert(folder.id());
+            visit(folder.child(), ids);
+            current = folder.sibling();
+        }
+    }
+
+    let mut ids = HashSet::new();
+    ids.insert(folder_id);
+
+    let root = get_folder_list(mtpdev);
+    if let Some(folder) = root.as_ref().and_then(|root| root.find(folder_id)) {
+        visit(folder.child(), &mut ids);
+    }
+
+    ids
+}
+
 pub(crate) fn create_folder<'a>(
     mtpdev: &MtpDevice,
     name: &'a str,
     parent: Parent,
     storage_id: u32,
 ) -> Result<(u32, Cow<'a, str>)> {
-    let name_cstr = CString::new(name).expect("Nul byte");
-    let parent = parent.faf_id();
+    let session = mtpdev.session()?;
+    let name_cstr = CString::new(name)?;
+    let parent_id = parent.faf_id();
 
     let name_in_c = unsafe { libc::strdup(name_cstr.as_ptr()) };
-    let folder_id =
-        unsafe { ffi::LIBMTP_Create_Folder(mtpdev.inner, name_in_c, parent, storage_id) };
+    let folder_id = unsafe { ffi::LIBMTP_Create_Folder(session, name_in_c, parent_id, storage_id) };
 
     let name_from_c = unsafe { CStr::from_ptr(name_in_c) };
-    let name_from_c = name_from_c.to_str().expect("Invalid UTF-8");
+    let name_from_c = name_from_c.to_string_lossy();
 
     let name = if name_from_c == name {
         Cow::Borrowed(name)
@@ -185,8 +377,9 @@ pub(crate) fn create_folder<'a>(
     }
 
     if folder_id == 0 {
-        Err(mtpdev.latest_error().unwrap_or_default())
+        Err(mtpdev.error_in("LIBMTP_Create_Folder", None))
     } else {
+        mtpdev.update_paths(|cache| cache.insert_child(folder_id, storage_id, parent, &name));
         Ok((folder_id, name))
     }
 }
@@ -0,0 +1,335 @@
+//! Contains gitignore-style patterns to skip files (like thumbnails, `.DS_Store` or cache
+//! folders) when walking folders, used by [`DirectorySync`](../sync/struct.DirectorySync.html)
+//! and [`CameraImport`](../import/struct.CameraImport.html).
+//!
+//! The supported syntax is the one of `.gitignore` files: blank lines and lines starting with
+//! `#` are skipped, `*` matches anything but `/`, `?` matches a single character, `[a-z]`
+//! matches a class of characters, `**` matches any number of folders, a trailing `/` only
+//! matches folders, a leading `!` re-includes what a previous pattern excluded, and a pattern
+//! with a `/` (other than a trailing one) is relative to the root of the walk, otherwise it
+//! matches at any depth. The last matching pattern wins, and everything below an ignored folder
+//! is ignored too.
+
+use std::path::{Component, Path};
+
+use crate::Result;
+
+/// Name of the file with ignore patterns read from the root of a synchronized directory.
+pub const IGNORE_FILE_NAME: &str = ".mtpignore";
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Set of gitignore-style patterns, paths are checked relatively to the root of the walk
+/// (the synchronized directory, the `DCIM` folder, etc).
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::ignore::IgnorePatterns;
+///
+/// let mut patterns = IgnorePatterns::parse(".DS_Store\n.thumbnails/\n*.tmp");
+/// patterns.add("!keep.tmp");
+///
+/// assert!(patterns.is_ignored("Music/.DS_Store", false));
+/// assert!(!patterns.is_ignored("keep.tmp", false));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IgnorePatterns {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnorePatterns {
+    /// Empty set of patterns, nothing is ignored.
+    pub fn new() -> Self {
+        IgnorePatterns::default()
+    }
+
+    /// Parses the contents of an ignore file, one pattern per line.
+    pub fn parse(contents: &str) -> Self {
+        let mut patterns = IgnorePatterns::new();
+        for line in contents.lines() {
+            patterns.add(line);
+        }
+
+        patterns
+    }
+
+    /// Reads the patterns of the ignore file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(IgnorePatterns::parse(&contents))
+    }
+
+    /// Adds a single pattern, after the current ones, blank lines and comments are skipped.
+    pub fn add(&mut self, pattern: &str) {
+        let mut pattern = pattern.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return;
+        }
+
+        // `\!` and `\#` escape a leading `!` or `#`
+        let negated = pattern.starts_with('!');
+        if negated || pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+            pattern = &pattern[1..];
+        }
+
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() {
+            return;
+        }
+
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        let mut glob = Vec::new();
+        if !anchored {
+            glob.extend("**/".chars());
+        }
+        glob.extend(pattern.chars());
+
+        self.patterns.push(Pattern {
+            glob,
+            negated,
+            dir_only,
+        });
+    }
+
+    /// Adds every pattern of `other` after the current ones.
+    pub fn extend(&mut self, other: &IgnorePatterns) {
+        self.patterns.extend(other.patterns.iter().cloned());
+    }
+
+    /// Returns the number of patterns.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Check whether there are no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check whether the given path (relative to the root of the walk) is ignored, `is_dir`
+    /// tells if the path is a folder.
+    pub fn is_ignored(&self, path: impl AsRef<Path>, is_dir: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+
+        let components: Vec<_> = path
+            .as_ref()
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+
+        let mut text = Vec::new();
+        for (idx, component) in components.iter().enumerate() {
+            if idx > 0 {
+                text.push('/');
+            }
+            text.extend(component.chars());
+
+            let last = idx == components.len() - 1;
+            if self.matches(&text, !last || is_dir) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn matches(&self, text: &[char], is_dir: bool) -> bool {
+        let mut ignored = false;
+        for pattern in &self.patterns {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+
+            if glob(&pattern.glob, text) {
+                ignored = !pattern.negated;
+            }
+        }
+
+        ignored
+    }
+}
+
+/// Matches `text` against a glob, `*` and `?` never match `/`.
+pub(crate) fn glob(pattern: &[char], text: &[char]) -> bool {
+    if pattern.starts_with(&['*', '*']) {
+        let rest = &pattern[2..];
+
+        // `a/**/b` also matches `a/b`
+        if rest.first() == Some(&'/') && glob(&rest[1..], text) {
+            return true;
+        }
+
+        return (0..=text.len()).any(|idx| glob(rest, &text[idx..]));
+    }
+
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some('*'), _) => (0..=text.len())
+            .take_while(|&idx| idx == 0 || text[idx - 1] != '/')
+            .any(|idx| glob(&pattern[1..], &text[idx..])),
+        (Some('?'), Some(&ch)) if ch != '/' => glob(&pattern[1..], &text[1..]),
+        (Some('['), Some(&ch)) if ch != '/' => match class(&pattern[1..], ch) {
+            Some((matched, len)) => matched && glob(&pattern[1 + len..], &text[1..]),
+            None => ch == '[' && glob(&pattern[1..], &text[1..]),
+        },
+        (Some('\\'), Some(&ch)) if pattern.len() > 1 => {
+            ch == pattern[1] && glob(&pattern[2..], &text[1..])
+        }
+        (Some(&expected), Some(&ch)) => expected == ch && glob(&pattern[1..], &text[1..]),
+        (Some(_), None) => false,
+    }
+}
+
+/// Matches `ch` against the character class at the start of `pattern` (after the `[`),
+/// returns whether it matched and the length of the class including the `]`, or `None` if the
+/// class isn't closed.
+fn class(pattern: &[char], ch: char) -> Option<(bool, usize)> {
+    let mut idx = 0;
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    if negated {
+        idx += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while idx < pattern.len() {
+        let start = pattern[idx];
+        if start == ']' && !first {
+            return Some((matched != negated, idx + 1));
+        }
+        first = false;
+
+        if idx + 2 < pattern.len() && pattern[idx + 1] == '-' && pattern[idx + 2] != ']' {
+            matched |= start <= ch && ch <= pattern[idx + 2];
+            idx += 3;
+        } else {
+            matched |= start == ch;
+            idx += 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(patterns: &str, path: &str) -> bool {
+        IgnorePatterns::parse(patterns).is_ignored(path, false)
+    }
+
+    #[test]
+    fn star_matches_within_a_component() {
+        assert!(ignored("*.tmp", "a.tmp"));
+        assert!(ignored("*.tmp", "Music/a.tmp"));
+        assert!(ignored("*.tmp", ".tmp"));
+        assert!(!ignored("*.tmp", "a.tmp.mp3"));
+        assert!(ignored("Music*", "Music Videos/a.mp4"));
+        assert!(!ignored("a*b", "a/b"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(ignored("IMG_?.jpg", "IMG_1.jpg"));
+        assert!(!ignored("IMG_?.jpg", "IMG_12.jpg"));
+        assert!(!ignored("IMG_?.jpg", "IMG_.jpg"));
+        assert!(!ignored("a?b", "a/b"));
+    }
+
+    #[test]
+    fn classes_and_ranges() {
+        assert!(ignored("[a-c].txt", "b.txt"));
+        assert!(!ignored("[a-c].txt", "d.txt"));
+        assert!(ignored("file[0-9xz]", "filex"));
+        assert!(!ignored("file[0-9xz]", "filey"));
+        assert!(ignored("[]]", "]"));
+        // Unclosed classes match a literal `[`
+        assert!(ignored("[ab", "[ab"));
+    }
+
+    #[test]
+    fn negated_classes() {
+        assert!(ignored("[!x].txt", "a.txt"));
+        assert!(!ignored("[!x].txt", "x.txt"));
+        assert!(ignored("[^a-c]", "d"));
+        assert!(!ignored("[^a-c]", "b"));
+    }
+
+    #[test]
+    fn anchored_patterns() {
+        // Without a `/` the pattern matches at any depth
+        assert!(ignored("cache", "cache"));
+        assert!(ignored("cache", "Android/data/cache"));
+
+        assert!(ignored("/cache", "cache"));
+        assert!(!ignored("/cache", "Android/cache"));
+        assert!(ignored("Android/cache", "Android/cache"));
+        assert!(!ignored("Android/cache", "sdcard/Android/cache"));
+        assert!(ignored("Android/**/cache", "Android/cache"));
+        assert!(ignored("Android/**/cache", "Android/data/app/cache"));
+        assert!(ignored("**/cache", "a/b/cache"));
+    }
+
+    #[test]
+    fn directory_patterns() {
+        let patterns = IgnorePatterns::parse(".thumbnails/");
+        assert!(patterns.is_ignored(".thumbnails", true));
+        assert!(!patterns.is_ignored(".thumbnails", false));
+        assert!(patterns.is_ignored("DCIM/.thumbnails/1.jpg", false));
+        assert!(!patterns.is_ignored("DCIM/.thumbnails.jpg", false));
+    }
+
+    #[test]
+    fn everything_below_ignored_folders_is_ignored() {
+        let patterns = IgnorePatterns::parse("build\n!build/keep.txt");
+        assert!(patterns.is_ignored("build/out/a.o", false));
+        // The folder is excluded, so re-including a file inside it has no effect
+        assert!(patterns.is_ignored("build/keep.txt", false));
+    }
+
+    #[test]
+    fn negations_and_last_match_wins() {
+        let patterns = IgnorePatterns::parse("*.tmp\n!keep.tmp");
+        assert!(patterns.is_ignored("a.tmp", false));
+        assert!(!patterns.is_ignored("keep.tmp", false));
+
+        let patterns = IgnorePatterns::parse("!keep.tmp\n*.tmp");
+        assert!(patterns.is_ignored("keep.tmp", false));
+    }
+
+    #[test]
+    fn escaped_characters() {
+        assert!(ignored("\\*.txt", "*.txt"));
+        assert!(!ignored("\\*.txt", "a.txt"));
+        assert!(ignored("what\\?", "what?"));
+        assert!(!ignored("what\\?", "whats"));
+        assert!(ignored("\\[a]", "[a]"));
+        assert!(!ignored("\\[a]", "a"));
+        assert!(ignored("\\#notes", "#notes"));
+        assert!(ignored("\\!important", "!important"));
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let patterns = IgnorePatterns::parse("# Thumbnails\n\n   \n*.thm\n");
+        assert_eq!(patterns.len(), 1);
+        assert!(!patterns.is_ignored("# Thumbnails", false));
+        assert!(patterns.is_ignored("a.thm", false));
+        assert!(!IgnorePatterns::new().is_ignored("a.thm", false));
+    }
+}
@@ -0,0 +1,419 @@
+//! Contains relevant items to import photos from the device into a local directory, the layout
+//! of the imported files is decided by an [`Organizer`](trait.Organizer.html).
+//!
+//! For the classic "import from camera" workflow use [`CameraImport`](struct.CameraImport.html)
+//! which enumerates the camera folders under `DCIM`, skips the objects recorded in an
+//! [`ImportState`](struct.ImportState.html) and optionally deletes the imported objects.
+
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::filetypes::Filetype;
+use crate::object::Object;
+use crate::storage::conflict::{local_file_name, persist_download};
+use crate::storage::files::{self, File};
+use crate::storage::ignore::IgnorePatterns;
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Policy that decides where an imported photo is placed, the returned directory is relative
+/// to the import destination and the original file name is kept.
+///
+/// Closures with the signature `Fn(&File, NaiveDateTime) -> PathBuf` implement this trait.
+pub trait Organizer {
+    /// Returns the directory (relative to the import destination) for `file`, where
+    /// `capture_date` is the date the photo was taken if known (read from EXIF metadata when
+    /// the `exif` feature is enabled), otherwise its modification date.
+    fn directory(&self, file: &File<'_>, capture_date: NaiveDateTime) -> PathBuf;
+}
+
+impl<F> Organizer for F
+where
+    F: Fn(&File<'_>, NaiveDateTime) -> PathBuf,
+{
+    fn directory(&self, file: &File<'_>, capture_date: NaiveDateTime) -> PathBuf {
+        self(file, capture_date)
+    }
+}
+
+/// Places every photo in a `YYYY/MM/DD` directory according to its capture date.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateOrganizer;
+
+impl Organizer for DateOrganizer {
+    fn directory(&self, _file: &File<'_>, capture_date: NaiveDateTime) -> PathBuf {
+        PathBuf::from(capture_date.format("%Y/%m/%d").to_string())
+    }
+}
+
+/// Places every photo directly in the import destination.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlatOrganizer;
+
+impl Organizer for FlatOrganizer {
+    fn directory(&self, _file: &File<'_>, _capture_date: NaiveDateTime) -> PathBuf {
+        PathBuf::new()
+    }
+}
+
+/// Reads the capture date (`DateTimeOriginal`, or `DateTime` as fallback) from the EXIF
+/// metadata of a local image, returns `None` if the image doesn't have it.
+#[cfg(feature = "exif")]
+pub fn exif_capture_date(path: impl AsRef<Path>) -> Option<NaiveDateTime> {
+    use chrono::NaiveDate;
+    use std::io::BufReader;
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+    let metadata = exif::Reader::new().read_from_container(&mut reader).ok()?;
+
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .filter_map(|&tag| metadata.get_field(tag, exif::In::PRIMARY))
+        .find_map(|field| match field.value {
+            exif::Value::Ascii(ref values) => {
+                let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+                NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)?
+                    .and_hms_opt(date.hour as u32, date.minute as u32, date.second as u32)
+            }
+            _ => None,
+        })
+}
+
+#[cfg(not(feature = "exif"))]
+fn exif_capture_date(_path: impl AsRef<Path>) -> Option<NaiveDateTime> {
+    None
+}
+
+/// Downloads a single file into the directory decided by the `organizer`, the download goes
+/// to a temporary file first so that an interrupted import doesn't leave partial files behind.
+/// Existing local files are kept, the file gets a suffix instead (like `IMG_0001 (1).JPG`).
+fn import_file(
+    mtpdev: &MtpDevice,
+    file: &File<'_>,
+    destination: &Path,
+    organizer: &impl Organizer,
+) -> Result<PathBuf> {
+    let file_name = file.name();
+    let name = local_file_name(&file_name)?;
+    std::fs::create_dir_all(destination)?;
+
+    let partial = destination.join(format!(".{}.part", name));
+    if let Err(err) = files::get_file_to_path(mtpdev, file, &partial) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err);
+    }
+
+    let capture_date =
+        exif_capture_date(&partial).unwrap_or_else(|| file.modification_date().naive_utc());
+
+    let directory = destination.join(organizer.directory(file, capture_date));
+    let path = std::fs::create_dir_all(&directory)
+        .map_err(Error::from)
+        .and_then(|_| persist_download(&partial, &directory, name));
+
+    if path.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    path
+}
+
+pub(crate) fn import_photos<'f, 'a: 'f>(
+    mtpdev: &MtpDevice,
+    files: impl IntoIterator<Item = &'f File<'a>>,
+    destination: impl AsRef<Path>,
+    organizer: &impl Organizer,
+) -> Result<Vec<PathBuf>> {
+    let destination = destination.as_ref();
+
+    files
+        .into_iter()
+        .filter(|file| file.ftype().is_image())
+        .map(|file| import_file(mtpdev, file, destination, organizer))
+        .collect()
+}
+
+/// Record of the objects already imported from a device, objects are identified by their id
+/// and modification date (ids may be reused by the device after an object is deleted).
+///
+/// The state can be persisted between imports with [`load`](struct.ImportState.html#method.load)
+/// and [`save`](struct.ImportState.html#method.save), the format is a plain text file with one
+/// `<id> <timestamp>` pair per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportState {
+    imported: HashMap<u32, i64>,
+}
+
+impl ImportState {
+    /// Creates an empty state, i.e. nothing was imported yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a state previously saved in `path`, if the file doesn't exist an empty state is
+    /// returned.
+    pub fn load(path: impl AsRef<Path>) -> Result<ImportState> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut imported = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry = line
+                .split_once(' ')
+                .and_then(|(id, date)| Some((id.parse().ok()?, date.trim().parse().ok()?)));
+
+            match entry {
+                Some((id, date)) => {
+                    imported.insert(id, date);
+                }
+
+                None => {
+                    return Err(Error::InvalidArgument {
+                        reason: format!("Malformed import state at line {}", number + 1),
+                    })
+                }
+            }
+        }
+
+        Ok(ImportState { imported })
+    }
+
+    /// Saves this state into `path`, overwriting it.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut entries: Vec<_> = self.imported.iter().collect();
+        entries.sort_unstable();
+
+        let mut contents = String::new();
+        for (id, date) in entries {
+            let _ = writeln!(contents, "{} {}", id, date);
+        }
+
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Check whether the given file was already imported.
+    pub fn is_imported(&self, file: &File<'_>) -> bool {
+        self.imported.get(&file.id()) == Some(&file.modification_date().timestamp())
+    }
+
+    /// Records the given file as imported.
+    pub fn mark_imported(&mut self, file: &File<'_>) {
+        self.imported
+            .insert(file.id(), file.modification_date().timestamp());
+    }
+
+    /// Forgets the object with the given id, it will be imported again the next time.
+    pub fn forget(&mut self, id: u32) {
+        self.imported.remove(&id);
+    }
+
+    /// Returns the number of recorded objects.
+    pub fn len(&self) -> usize {
+        self.imported.len()
+    }
+
+    /// Check whether there are no recorded objects.
+    pub fn is_empty(&self) -> bool {
+        self.imported.is_empty()
+    }
+}
+
+/// Summary of a [`CameraImport`](struct.CameraImport.html) run.
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Local paths of the imported files.
+    pub imported: Vec<PathBuf>,
+    /// Number of files skipped because they were already imported.
+    pub skipped: usize,
+    /// Number of imported files deleted from the device.
+    pub deleted: usize,
+}
+
+/// Imports new photos (and optionally videos) from the camera folders under `DCIM`, note that
+/// enumerating folders requires an uncached device.
+///
+/// ## Example
+/// ```no_run
+/// let mut state = ImportState::load("import-state")?;
+/// let report = storage
+///     .camera_import()
+///     .organizer(DateOrganizer)
+///     .include_videos(true)
+///     .delete_after_import(false)
+///     .run(&mut state, "Pictures")?;
+/// state.save("import-state")?;
+/// println!("Imported {} files", report.imported.len());
+/// ```
+pub struct CameraImport<'a, O = DateOrganizer> {
+    owner: &'a MtpDevice,
+    storage_id: u32,
+    organizer: O,
+    include_videos: bool,
+    delete_after_import: bool,
+    ignore: IgnorePatterns,
+}
+
+impl<'a> CameraImport<'a> {
+    pub(crate) fn new(owner: &'a MtpDevice, storage_id: u32) -> Self {
+        CameraImport {
+            owner,
+            storage_id,
+            organizer: DateOrganizer,
+            include_videos: true,
+            delete_after_import: false,
+            ignore: IgnorePatterns::new(),
+        }
+    }
+}
+
+impl<'a, O: Organizer> CameraImport<'a, O> {
+    /// Uses another organizer to decide where imported files are placed, by default files are
+    /// placed by `DateOrganizer`.
+    pub fn organizer<P: Organizer>(self, organizer: P) -> CameraImport<'a, P> {
+        CameraImport {
+            owner: self.owner,
+            storage_id: self.storage_id,
+            organizer,
+            include_videos: self.include_videos,
+            delete_after_import: self.delete_after_import,
+            ignore: self.ignore,
+        }
+    }
+
+    /// Whether videos are imported along with photos, `true` by default.
+    pub fn include_videos(mut self, include: bool) -> Self {
+        self.include_videos = include;
+        self
+    }
+
+    /// Whether imported files are deleted from the device afterwards, `false` by default.
+    pub fn delete_after_import(mut self, delete: bool) -> Self {
+        self.delete_after_import = delete;
+        self
+    }
+
+    /// Skips the files and folders matching these patterns, paths are relative to the `DCIM`
+    /// folder (e.g. `.thumbnails/` or `100CANON/*.THM`).
+    pub fn ignore(mut self, patterns: IgnorePatterns) -> Self {
+        self.ignore = patterns;
+        self
+    }
+
+    fn is_media(&self, ftype: Filetype) -> bool {
+        ftype.is_image() || (self.include_videos && ftype.is_video())
+    }
+
+    /// Returns the `DCIM` folders found in the root of the storage (case insensitive).
+    pub fn dcim_folders(&self) -> Result<Vec<File<'a>>> {
+        let root = files_and_folders(self.owner, self.storage_id, Parent::Root)?;
+
+        Ok(root
+            .into_iter()
+            .filter(|file| {
+                matches!(file.ftype(), Filetype::Folder) && file.name().eq_ignore_ascii_case("dcim")
+            })
+            .collect())
+    }
+
+    /// Returns the camera folders, i.e. the folders inside `DCIM` (like `100CANON` or
+    /// `Camera`).
+    pub fn camera_folders(&self) -> Result<Vec<File<'a>>> {
+        let mut folders = Vec::new();
+
+        for dcim in self.dcim_folders()? {
+            let children =
+                files_and_folders(self.owner, self.storage_id, Parent::Folder(dcim.id()))?;
+            folders.extend(
+                children
+                    .into_iter()
+                    .filter(|file| matches!(file.ftype(), Filetype::Folder)),
+            );
+        }
+
+        Ok(folders)
+    }
+
+    /// Returns every photo (and video, if included) below `DCIM`.
+    pub fn media(&self) -> Result<Vec<File<'a>>> {
+        let mut pending: Vec<_> = self
+            .dcim_folders()?
+            .iter()
+            .map(|dcim| (Parent::Folder(dcim.id()), PathBuf::new()))
+            .collect();
+
+        let mut media = Vec::new();
+        while let Some((parent, relative)) = pending.pop() {
+            for file in files_and_folders(self.owner, self.storage_id, parent)? {
+                let ftype = file.ftype();
+                let is_folder = matches!(ftype, Filetype::Folder);
+                let path = relative.join(&*file.name());
+
+                if self.ignore.is_ignored(&path, is_folder) {
+                    continue;
+                }
+
+                if is_folder {
+                    pending.push((Parent::Folder(file.id()), path));
+                } else if self.is_media(ftype) {
+                    media.push(file);
+                }
+            }
+        }
+
+        Ok(media)
+    }
+
+    /// Returns the media that wasn't imported yet according to `state`.
+    pub fn pending(&self, state: &ImportState) -> Result<Vec<File<'a>>> {
+        Ok(self
+            .media()?
+            .into_iter()
+            .filter(|file| !state.is_imported(file))
+            .collect())
+    }
+
+    /// Downloads the media that wasn't imported yet into `destination`, every imported file
+    /// is recorded in `state` as soon as it's downloaded, so if some download fails you can
+    /// save the state and retry later without importing files twice.
+    pub fn run(
+        &self,
+        state: &mut ImportState,
+        destination: impl AsRef<Path>,
+    ) -> Result<ImportReport> {
+        let destination = destination.as_ref();
+        let mut report = ImportReport::default();
+
+        for file in self.media()? {
+            if state.is_imported(&file) {
+                report.skipped += 1;
+                continue;
+            }
+
+            let path = import_file(self.owner, &file, destination, &self.organizer)?;
+            state.mark_imported(&file);
+            report.imported.push(path);
+
+            if self.delete_after_import {
+                file.delete()?;
+                state.forget(file.id());
+                report.deleted += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
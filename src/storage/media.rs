@@ -0,0 +1,148 @@
+//! Contains senders into the default media folders of the device (music, pictures and videos),
+//! see [`MtpDevice::send_to_music`](../../device/struct.MtpDevice.html#method.send_to_music).
+//!
+//! The folders are looked up in the root of the primary storage, like `libmtp` does to fill
+//! the `default_*_folder` ids, and created there if the device doesn't have them.
+
+use std::fs;
+use std::path::Path;
+
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::Error;
+use crate::object::filetypes::Filetype;
+use crate::object::Object;
+use crate::storage::files::{self, File, FileMetadata};
+use crate::storage::folders::{create_folder, get_folder_list_storage};
+use crate::storage::tracks::{self, Track, TrackMetadata};
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Value of the `default_*_folder` ids when the device doesn't have that folder.
+const NO_FOLDER: u32 = 0xFFFF_FFFF;
+
+/// Default media folders of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaFolder {
+    Music,
+    Pictures,
+    Videos,
+}
+
+impl MediaFolder {
+    /// Names of the folder recognized in the root of the storage, the first one is used to
+    /// create it.
+    pub fn names(&self) -> &'static [&'static str] {
+        match self {
+            MediaFolder::Music => &["Music", "My Music", "My_Music"],
+            MediaFolder::Pictures => &["Pictures", "My Pictures", "My_Pictures"],
+            MediaFolder::Videos => &["Movies", "Video", "My Video", "My_Video"],
+        }
+    }
+
+    fn default_id(&self, mtpdev: &MtpDevice) -> u32 {
+        match self {
+            MediaFolder::Music => mtpdev.default_music_folder(),
+            MediaFolder::Pictures => mtpdev.default_picture_folder(),
+            MediaFolder::Videos => mtpdev.default_video_folder(),
+        }
+    }
+}
+
+/// Returns the storage and id of the given media folder, creating it if it's missing.
+pub(crate) fn media_folder(mtpdev: &MtpDevice, media: MediaFolder) -> Result<(u32, u32)> {
+    let storage = unsafe { (*mtpdev.inner).storage };
+    if storage.is_null() {
+        return Err(Error::InvalidArgument {
+            reason: "The device has no storage, update the storage first".to_string(),
+        });
+    }
+
+    let storage_id = unsafe { (*storage).id };
+
+    let default_id = media.default_id(mtpdev);
+    if default_id != 0 && default_id != NO_FOLDER {
+        return Ok((storage_id, default_id));
+    }
+
+    let is_media_folder = |name: &str| {
+        media
+            .names()
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name))
+    };
+
+    let existing = match mtpdev.cache_mode() {
+        CacheMode::Cached => {
+            let mut current = get_folder_list_storage(mtpdev, storage_id);
+            let mut found = None;
+            while let Some(folder) = current {
+                if is_media_folder(&folder.name()) {
+                    found = Some(folder.id());
+                    break;
+                }
+
+                current = folder.sibling();
+            }
+
+            found
+        }
+        CacheMode::Uncached => files_and_folders(mtpdev, storage_id, Parent::Root)?
+            .iter()
+            .find(|file| matches!(file.ftype(), Filetype::Folder) && is_media_folder(&file.name()))
+            .map(|file| file.id()),
+    };
+
+    let folder_id = match existing {
+        Some(folder_id) => folder_id,
+        None => create_folder(mtpdev, media.names()[0], Parent::Root, storage_id)?.0,
+    };
+
+    Ok((storage_id, folder_id))
+}
+
+/// Uploads the local file at `path` into the given media folder, the type of the file is
+/// guessed from its extension.
+pub(crate) fn send_to_media<'a>(
+    mtpdev: &'a MtpDevice,
+    media: MediaFolder,
+    path: &Path,
+) -> Result<File<'a>> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::InvalidArgument {
+            reason: format!("{} has no valid UTF-8 file name", path.display()),
+        })?;
+
+    let metadata = fs::metadata(path)?;
+    let file_metadata = FileMetadata {
+        file_size: metadata.len(),
+        file_name,
+        file_type: Filetype::guess(path),
+        modification_date: metadata.modified()?.into(),
+    };
+
+    let (storage_id, folder_id) = media_folder(mtpdev, media)?;
+    files::send_file_from_path(
+        mtpdev,
+        storage_id,
+        path,
+        Parent::Folder(folder_id),
+        file_metadata,
+    )
+}
+
+pub(crate) fn send_to_music<'a>(
+    mtpdev: &'a MtpDevice,
+    path: &Path,
+    metadata: &TrackMetadata,
+) -> Result<Track<'a>> {
+    let (storage_id, folder_id) = media_folder(mtpdev, MediaFolder::Music)?;
+    tracks::send_track_from_path(
+        mtpdev,
+        storage_id,
+        path,
+        Parent::Folder(folder_id),
+        metadata,
+    )
+}
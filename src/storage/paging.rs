@@ -0,0 +1,206 @@
+//! Contains a paginated listing of folders, useful for folders with many thousands of children,
+//! see [`Storage::list_pages`](../struct.Storage.html#method.list_pages).
+
+use libmtp_sys as ffi;
+
+use crate::device::MtpDevice;
+use crate::storage::files::File;
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Position in the listing of a folder, given with every page but the last one to resume the
+/// listing later with `resume_pages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageToken {
+    storage_id: u32,
+    parent: u32,
+    offset: usize,
+}
+
+impl PageToken {
+    /// Returns the id of the storage being listed, `0` for every storage.
+    pub fn storage_id(&self) -> u32 {
+        self.storage_id
+    }
+
+    /// Returns the folder being listed.
+    pub fn parent(&self) -> Parent {
+        if self.parent == Parent::Root.faf_id() {
+            Parent::Root
+        } else {
+            Parent::Folder(self.parent)
+        }
+    }
+
+    /// Returns the number of entries of the previous pages.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// A batch of entries (files and folders) of a folder.
+#[derive(Debug)]
+pub struct Page<'a> {
+    /// The entries of this page.
+    pub entries: Vec<File<'a>>,
+    /// Token to get the next page, `None` if this is the last one.
+    pub next: Option<PageToken>,
+}
+
+/// Iterator over the pages of the listing of a folder, every page has `page_size` entries but
+/// the last one.
+///
+/// Note that `libmtp` only lists whole folders, the folder is listed once when the first page
+/// is requested and the following pages come from memory. When the `Pages` is dropped before
+/// the last page the rest of the listing is kept by the device, so resuming with the latest
+/// token doesn't list the folder again (entries added or deleted meanwhile aren't seen), only
+/// the latest listing is kept.
+///
+/// ## Example
+/// ```no_run
+/// for page in storage.list_pages(Parent::Folder(camera_id), 100) {
+///     let page = page?;
+///     for entry in page.entries {
+///         println!("{}", entry.name());
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Pages<'a> {
+    owner: &'a MtpDevice,
+    token: PageToken,
+    page_size: usize,
+    listing: Option<std::vec::IntoIter<File<'a>>>,
+    done: bool,
+}
+
+impl<'a> Pages<'a> {
+    pub(crate) fn new(
+        owner: &'a MtpDevice,
+        storage_id: u32,
+        parent: Parent,
+        page_size: usize,
+    ) -> Self {
+        let token = PageToken {
+            storage_id,
+            parent: parent.faf_id(),
+            offset: 0,
+        };
+
+        Pages::resume(owner, token, page_size)
+    }
+
+    /// Resumes the listing right after the page that returned `token`.
+    pub(crate) fn resume(owner: &'a MtpDevice, token: PageToken, page_size: usize) -> Self {
+        assert!(page_size > 0, "The page size must be greater than zero");
+
+        let listing = {
+            let mut remaining = owner.remaining_pages.borrow_mut();
+            match remaining.take() {
+                Some(rest) if rest.token == token => Some(rest.into_files(owner).into_iter()),
+                other => {
+                    *remaining = other;
+                    None
+                }
+            }
+        };
+
+        Pages {
+            owner,
+            token,
+            page_size,
+            listing,
+            done: false,
+        }
+    }
+
+    /// Returns the number of entries of every page.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = Result<Page<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if self.listing.is_none() {
+            let files =
+                match files_and_folders(self.owner, self.token.storage_id, self.token.parent()) {
+                    Ok(files) => files,
+                    Err(err) => {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                };
+
+            let mut listing = files.into_iter();
+            if self.token.offset > 0 {
+                listing.nth(self.token.offset - 1);
+            }
+            self.listing = Some(listing);
+        }
+
+        let listing = self.listing.as_mut()?;
+        let entries: Vec<_> = listing.by_ref().take(self.page_size).collect();
+        self.token.offset += entries.len();
+
+        let next = if listing.len() > 0 {
+            Some(self.token)
+        } else {
+            self.done = true;
+            None
+        };
+
+        Some(Ok(Page { entries, next }))
+    }
+}
+
+impl Drop for Pages<'_> {
+    fn drop(&mut self) {
+        let listing = match self.listing.take() {
+            Some(listing) if listing.len() > 0 => listing,
+            _ => return,
+        };
+
+        let files = listing
+            .map(|file| {
+                let inner = file.inner;
+                std::mem::forget(file);
+                inner
+            })
+            .collect();
+
+        self.owner.remaining_pages.replace(Some(RemainingPages {
+            token: self.token,
+            files,
+        }));
+    }
+}
+
+/// The entries a dropped `Pages` didn't reach, kept by the device for `resume_pages`.
+pub(crate) struct RemainingPages {
+    token: PageToken,
+    files: Vec<*mut ffi::LIBMTP_file_t>,
+}
+
+impl RemainingPages {
+    fn into_files(mut self, owner: &MtpDevice) -> Vec<File<'_>> {
+        std::mem::take(&mut self.files)
+            .into_iter()
+            .map(|inner| File { inner, owner })
+            .collect()
+    }
+}
+
+impl Drop for RemainingPages {
+    fn drop(&mut self) {
+        for file in self.files.drain(..) {
+            unsafe { ffi::LIBMTP_destroy_file_t(file) };
+        }
+    }
+}
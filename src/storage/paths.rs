@@ -0,0 +1,409 @@
+//! Contains the resolution of paths like `/Music/song.mp3` into objects of the device, see
+//! [`StoragePool::find_path`](../struct.StoragePool.html#method.find_path).
+//!
+//! Paths are separated by `/` and start at the root of the storage pool, i.e. the first folder
+//! with that name in any storage. To address a single storage (like the SD card when the
+//! internal storage has the same folders) prefix the path with its description and a colon:
+//! `SD card:/DCIM/Camera/IMG_0001.jpg`, the volume identifier may be used instead of the
+//! description.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::filetypes::Filetype;
+use crate::object::properties::Property;
+use crate::object::Object;
+use crate::storage::files::File;
+use crate::storage::tracks::Track;
+use crate::storage::{files_and_folders, Parent, StoragePool};
+use crate::Result;
+
+/// A path of the device, optionally prefixed with the storage it belongs to.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::paths::DevicePath;
+///
+/// let path = DevicePath::parse("SD card:/DCIM/Camera");
+/// assert_eq!(path.storage(), Some("SD card"));
+/// assert_eq!(path.components(), ["DCIM", "Camera"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevicePath<'p> {
+    storage: Option<&'p str>,
+    components: Vec<&'p str>,
+}
+
+impl<'p> DevicePath<'p> {
+    /// Parses a path, a prefix before the first `:` is the storage if it's followed by `/` (or
+    /// nothing) and doesn't contain `/` itself, so names with colons in the middle of the path
+    /// are fine. Empty components (like in `//`) are ignored.
+    pub fn parse(path: &'p str) -> Self {
+        let (storage, rest) = match path.split_once(':') {
+            Some((storage, rest))
+                if !storage.contains('/') && (rest.is_empty() || rest.starts_with('/')) =>
+            {
+                (Some(storage), rest)
+            }
+            _ => (None, path),
+        };
+
+        DevicePath {
+            storage,
+            components: rest.split('/').filter(|name| !name.is_empty()).collect(),
+        }
+    }
+
+    /// Returns the description (or volume identifier) of the storage, `None` for every
+    /// storage.
+    pub fn storage(&self) -> Option<&'p str> {
+        self.storage
+    }
+
+    /// Returns the names of the folders leading to the object, and the object itself.
+    pub fn components(&self) -> &[&'p str] {
+        &self.components
+    }
+
+    /// Check whether this path is the root folder (of a storage or every storage).
+    pub fn is_root(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+impl Display for DevicePath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(storage) = self.storage {
+            write!(f, "{}:", storage)?;
+        }
+
+        write!(f, "/{}", self.components.join("/"))
+    }
+}
+
+fn not_found(path: &DevicePath<'_>) -> Error {
+    Error::PathNotFound {
+        path: path.to_string(),
+    }
+}
+
+/// Returns the id of the storage of `path`, `0` if it doesn't name one.
+fn storage_id(pool: &StoragePool<'_>, path: &DevicePath<'_>) -> Result<u32> {
+    let name = match path.storage() {
+        Some(name) => name,
+        None => return Ok(0),
+    };
+
+    pool.iter()
+        .find(|(_, storage)| storage.description().as_deref() == Some(name))
+        .or_else(|| {
+            pool.iter()
+                .find(|(_, storage)| storage.volume_identifier().as_deref() == Some(name))
+        })
+        .map(|(id, _)| id)
+        .ok_or_else(|| Error::InvalidArgument {
+            reason: format!("There's no storage named {}", name),
+        })
+}
+
+/// Walks the folders of `path`, returns `None` for the root.
+fn walk<'a>(
+    pool: &StoragePool<'a>,
+    storage_id: u32,
+    path: &DevicePath<'_>,
+) -> Result<Option<File<'a>>> {
+    let mut found: Option<File<'a>> = None;
+
+    for name in path.components() {
+        let parent = match &found {
+            None => Parent::Root,
+            Some(folder) if matches!(folder.ftype(), Filetype::Folder) => {
+                Parent::Folder(folder.id())
+            }
+            Some(_) => return Err(not_found(path)),
+        };
+
+        let file = files_and_folders(pool.owner, storage_id, parent)?
+            .into_iter()
+            .find(|file| file.name() == *name)
+            .ok_or_else(|| not_found(path))?;
+
+        found = Some(file);
+    }
+
+    Ok(found)
+}
+
+pub(crate) fn find_path<'a>(pool: &StoragePool<'a>, path: &str) -> Result<File<'a>> {
+    let path = DevicePath::parse(path);
+    let storage_id = storage_id(pool, &path)?;
+
+    walk(pool, storage_id, &path)?.ok_or_else(|| Error::InvalidArgument {
+        reason: format!("{} is the root folder", path),
+    })
+}
+
+pub(crate) fn find_folder(pool: &StoragePool<'_>, path: &str) -> Result<(u32, Parent)> {
+    let path = DevicePath::parse(path);
+    let storage_id = storage_id(pool, &path)?;
+
+    match walk(pool, storage_id, &path)? {
+        None => Ok((storage_id, Parent::Root)),
+        Some(folder) if matches!(folder.ftype(), Filetype::Folder) => {
+            Ok((folder.storage_id(), Parent::Folder(folder.id())))
+        }
+        Some(_) => Err(Error::InvalidArgument {
+            reason: format!("{} is not a folder", path),
+        }),
+    }
+}
+
+/// Path of a cached object, `path` is relative to the root of its storage.
+#[derive(Debug, Clone)]
+struct CachedPath {
+    storage_id: u32,
+    path: String,
+}
+
+/// Map between object ids and their paths (in both directions), so errors and logs can show
+/// where objects are without walking the folders again. Enable it with
+/// [`MtpDevice::enable_path_cache`](../../device/struct.MtpDevice.html#method.enable_path_cache).
+///
+/// Objects are remembered when they're found by path, listed from a folder with a known path,
+/// uploaded or created, and forgotten (or updated) when they're deleted, renamed or moved, as
+/// well as with the events read with `MtpDevice::read_event`. Paths are the ones of
+/// `find_path`, without the storage prefix, e.g. `/DCIM/Camera/IMG_0001.jpg`.
+#[derive(Debug, Clone, Default)]
+pub struct PathCache {
+    paths: HashMap<u32, CachedPath>,
+    ids: HashMap<(u32, String), u32>,
+}
+
+impl PathCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        PathCache::default()
+    }
+
+    /// Returns the path of the object with the given id, if it's known.
+    pub fn path(&self, id: u32) -> Option<&str> {
+        self.paths.get(&id).map(|cached| cached.path.as_str())
+    }
+
+    /// Returns the storage of the object with the given id, if it's known.
+    pub fn storage_id(&self, id: u32) -> Option<u32> {
+        self.paths.get(&id).map(|cached| cached.storage_id)
+    }
+
+    /// Returns the id of the object at `path` of the storage, `0` looks in every storage (the
+    /// first match wins).
+    pub fn id(&self, storage_id: u32, path: &str) -> Option<u32> {
+        let path = format!("/{}", DevicePath::parse(path).components().join("/"));
+
+        if storage_id != 0 {
+            return self.ids.get(&(storage_id, path)).copied();
+        }
+
+        self.ids
+            .iter()
+            .find(|((_, cached), _)| *cached == path)
+            .map(|(_, id)| *id)
+    }
+
+    /// Returns the number of known objects.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Check whether no object is known.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Forgets every object.
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.ids.clear();
+    }
+
+    /// Returns the path of the folder, `/` for the root.
+    fn folder_path(&self, parent: Parent) -> Option<&str> {
+        match parent {
+            Parent::Root | Parent::Folder(0) => Some(""),
+            Parent::Folder(id) => self.path(id),
+        }
+    }
+
+    fn insert(&mut self, id: u32, storage_id: u32, path: String) {
+        if let Some(old) = self.paths.get(&id) {
+            self.ids.remove(&(old.storage_id, old.path.clone()));
+        }
+
+        // An object replaced by another one with the same path is gone
+        if let Some(replaced) = self.ids.insert((storage_id, path.clone()), id) {
+            if replaced != id {
+                self.paths.remove(&replaced);
+            }
+        }
+        self.paths.insert(id, CachedPath { storage_id, path });
+    }
+
+    /// Remembers the object named `name` in the `parent` folder, if the path of the folder is
+    /// known, a `storage_id` of `0` is taken from the folder.
+    pub(crate) fn insert_child(&mut self, id: u32, storage_id: u32, parent: Parent, name: &str) {
+        let storage_id = match (storage_id, parent) {
+            (0, Parent::Folder(folder)) => match self.storage_id(folder) {
+                Some(storage_id) => storage_id,
+                None => return,
+            },
+            // The device chose the storage
+            (0, Parent::Root) => return,
+            (storage_id, _) => storage_id,
+        };
+
+        if let Some(folder) = self.folder_path(parent) {
+            let path = format!("{}/{}", folder, name);
+            self.insert(id, storage_id, path);
+        }
+    }
+
+    /// Forgets the object and everything below it.
+    pub(crate) fn remove(&mut self, id: u32) {
+        let cached = match self.paths.remove(&id) {
+            Some(cached) => cached,
+            None => return,
+        };
+        self.ids.remove(&(cached.storage_id, cached.path.clone()));
+
+        let prefix = format!("{}/", cached.path);
+        let below: Vec<u32> = self
+            .paths
+            .iter()
+            .filter(|(_, other)| {
+                other.storage_id == cached.storage_id && other.path.starts_with(&prefix)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in below {
+            if let Some(other) = self.paths.remove(&id) {
+                self.ids.remove(&(other.storage_id, other.path));
+            }
+        }
+    }
+
+    /// Moves the object (and everything below it) to the `parent` folder of the storage, it's
+    /// forgotten if the path of the folder isn't known.
+    pub(crate) fn relocate(&mut self, id: u32, storage_id: u32, parent: Parent) {
+        let name = match self.path(id) {
+            Some(path) => path.rsplit('/').next().unwrap_or(path).to_string(),
+            None => return,
+        };
+
+        match self.folder_path(parent) {
+            Some(folder) if storage_id != 0 => {
+                let path = format!("{}/{}", folder, name);
+                self.move_subtree(id, storage_id, path);
+            }
+            _ => self.remove(id),
+        }
+    }
+
+    /// Renames the object, keeping it in the same folder.
+    pub(crate) fn rename(&mut self, id: u32, name: &str) {
+        if let Some(cached) = self.paths.get(&id) {
+            let folder = match cached.path.rfind('/') {
+                Some(index) => &cached.path[..index],
+                None => "",
+            };
+
+            let path = format!("{}/{}", folder, name);
+            self.move_subtree(id, cached.storage_id, path);
+        }
+    }
+
+    fn move_subtree(&mut self, id: u32, storage_id: u32, path: String) {
+        let old = match self.paths.get(&id) {
+            Some(cached) => cached.clone(),
+            None => return,
+        };
+
+        let prefix = format!("{}/", old.path);
+        let below: Vec<(u32, String)> = self
+            .paths
+            .iter()
+            .filter(|(_, other)| {
+                other.storage_id == old.storage_id && other.path.starts_with(&prefix)
+            })
+            .map(|(id, other)| (*id, format!("{}/{}", path, &other.path[prefix.len()..])))
+            .collect();
+
+        self.insert(id, storage_id, path);
+        for (id, path) in below {
+            self.insert(id, storage_id, path);
+        }
+    }
+
+    /// Forgets every object of the storage.
+    pub(crate) fn remove_storage(&mut self, storage_id: u32) {
+        self.paths
+            .retain(|_, cached| cached.storage_id != storage_id);
+        self.ids.retain(|(storage, _), _| *storage != storage_id);
+    }
+}
+
+/// Objects created by uploads, remembered by the path cache.
+pub(crate) trait Placed<'a>: Sized {
+    /// Returns the id, storage, parent folder and name of the object.
+    fn placement(&self) -> (u32, u32, Parent, Option<Cow<'_, str>>);
+
+    /// Fetches an existing object, returned by uploads skipped because of a conflict.
+    fn fetch(mtpdev: &'a MtpDevice, id: u32) -> Result<Self>;
+
+    /// Gives its final name to an object uploaded under a temporary name.
+    fn rename_to(&mut self, name: &str) -> Result<()>;
+}
+
+impl<'a> Placed<'a> for File<'a> {
+    fn placement(&self) -> (u32, u32, Parent, Option<Cow<'_, str>>) {
+        (
+            self.id(),
+            self.storage_id(),
+            self.parent_id(),
+            Some(self.name()),
+        )
+    }
+
+    fn fetch(mtpdev: &'a MtpDevice, id: u32) -> Result<Self> {
+        mtpdev.search_file(id)
+    }
+
+    fn rename_to(&mut self, name: &str) -> Result<()> {
+        self.rename(name)
+    }
+}
+
+impl<'a> Placed<'a> for Track<'a> {
+    fn placement(&self) -> (u32, u32, Parent, Option<Cow<'_, str>>) {
+        (
+            self.id(),
+            self.storage_id(),
+            self.parent_id(),
+            self.file_name(),
+        )
+    }
+
+    fn fetch(mtpdev: &'a MtpDevice, id: u32) -> Result<Self> {
+        mtpdev.search_track(id)
+    }
+
+    fn rename_to(&mut self, name: &str) -> Result<()> {
+        // Tracks have no rename of their own, it's fetched again to see the new name
+        self.set_string(Property::ObjectFileName, name)?;
+        *self = Self::fetch(self.owner, self.id())?;
+        Ok(())
+    }
+}
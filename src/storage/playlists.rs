@@ -0,0 +1,268 @@
+//! Contains relevant items to handle playlist objects in the device.
+
+use libmtp_sys as ffi;
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::fmt::{self, Debug};
+
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::Error;
+use crate::object::{AsObjectId, Object};
+use crate::storage::Parent;
+use crate::trace;
+use crate::util::{optional_str, replace_id_array};
+use crate::Result;
+
+/// Abstraction of a playlist object, it implements `Object`, a playlist is an ordered list of
+/// references to track objects.
+pub struct Playlist<'a> {
+    pub(crate) inner: *mut ffi::LIBMTP_playlist_t,
+    pub(crate) owner: &'a MtpDevice,
+}
+
+impl Drop for Playlist<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::LIBMTP_destroy_playlist_t(self.inner);
+        }
+    }
+}
+
+impl Object for Playlist<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).playlist_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Object for &Playlist<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).playlist_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Debug for Playlist<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Playlist")
+            .field("id", &self.id())
+            .field("parent_id", &self.parent_id())
+            .field("storage_id", &self.storage_id())
+            .field("name", &self.name())
+            .field("tracks", &self.tracks())
+            .finish()
+    }
+}
+
+impl Playlist<'_> {
+    /// Returns the id of the storage it belongs to.
+    pub fn storage_id(&self) -> u32 {
+        unsafe { (*self.inner).storage_id }
+    }
+
+    /// Returns the id of its parent.
+    pub fn parent_id(&self) -> Parent {
+        let id = unsafe { (*self.inner).parent_id };
+
+        if id == ffi::LIBMTP_FILES_AND_FOLDERS_ROOT {
+            Parent::Root
+        } else {
+            Parent::Folder(id)
+        }
+    }
+
+    /// Returns the name of this playlist, replacing invalid UTF-8 sequences with `U+FFFD`.
+    pub fn name(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).name) }
+    }
+
+    /// Returns the ids of the tracks in this playlist, in order.
+    pub fn tracks(&self) -> Vec<u32> {
+        unsafe {
+            let len = (*self.inner).no_tracks;
+
+            if (*self.inner).tracks.is_null() || len == 0 {
+                Vec::new()
+            } else {
+                prim_array_ptr_to_vec!((*self.inner).tracks, u32, len)
+            }
+        }
+    }
+
+    /// Returns the number of tracks in this playlist.
+    pub fn len(&self) -> usize {
+        unsafe { (*self.inner).no_tracks as usize }
+    }
+
+    /// Check whether this playlist has no tracks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces the tracks of this playlist and updates it on the device, the rest of the
+    /// metadata is preserved. Note that the id of the playlist may change on some devices.
+    pub fn set_tracks(&mut self, tracks: &[u32]) -> Result<()> {
+        let session = self.owner.session()?;
+        let old_tracks = self.tracks();
+
+        let res = unsafe {
+            replace_id_array(
+                &mut (*self.inner).tracks,
+                &mut (*self.inner).no_tracks,
+                tracks,
+            );
+            ffi::LIBMTP_Update_Playlist(session, self.inner)
+        };
+
+        if res != 0 {
+            unsafe {
+                replace_id_array(
+                    &mut (*self.inner).tracks,
+                    &mut (*self.inner).no_tracks,
+                    &old_tracks,
+                )
+            };
+            Err(self
+                .owner
+                .error_in("LIBMTP_Update_Playlist", Some(self.id())))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves the track at the index `from` to the index `to`, shifting the tracks in between,
+    /// and updates the playlist on the device.
+    pub fn move_track(&mut self, from: usize, to: usize) -> Result<()> {
+        let mut tracks = self.tracks();
+
+        if from >= tracks.len() || to >= tracks.len() {
+            return Err(Error::InvalidArgument {
+                reason: format!(
+                    "Can't move track from {} to {}, playlist has {} tracks",
+                    from,
+                    to,
+                    tracks.len()
+                ),
+            });
+        }
+
+        let track = tracks.remove(from);
+        tracks.insert(to, track);
+
+        self.set_tracks(&tracks)
+    }
+
+    /// Reorders the tracks of this playlist and updates it on the device, `order` must contain
+    /// exactly the same tracks this playlist has, use `set_tracks` to add or remove tracks.
+    pub fn set_order(&mut self, order: &[impl AsObjectId]) -> Result<()> {
+        let order: Vec<_> = order.iter().map(|track| track.as_id()).collect();
+
+        let mut current = self.tracks();
+        let mut sorted = order.clone();
+        current.sort_unstable();
+        sorted.sort_unstable();
+
+        if current != sorted {
+            return Err(Error::InvalidArgument {
+                reason: "The new order must contain exactly the tracks of the playlist".to_string(),
+            });
+        }
+
+        self.set_tracks(&order)
+    }
+
+    /// Rename this playlist in-place.
+    pub fn rename(&mut self, new_name: &str) -> Result<()> {
+        let session = self.owner.session()?;
+        let new_name = CString::new(new_name)?;
+
+        let res = unsafe { ffi::LIBMTP_Set_Playlist_Name(session, self.inner, new_name.as_ptr()) };
+
+        if res != 0 {
+            Err(self
+                .owner
+                .error_in("LIBMTP_Set_Playlist_Name", Some(self.id())))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn get_playlist_list(mtpdev: &MtpDevice) -> Result<Vec<Playlist<'_>>> {
+    trace::list(mtpdev, "playlists", 0, || {
+        let session = mtpdev.session()?;
+        mtpdev.require_cache_mode(CacheMode::Cached)?;
+
+        let mut head = unsafe { ffi::LIBMTP_Get_Playlist_List(session) };
+
+        // No playlists and a failed listing both give a null list
+        if head.is_null() {
+            if let Some(err) = mtpdev.latest_error_in("LIBMTP_Get_Playlist_List", None) {
+                return Err(err);
+            }
+        }
+
+        let mut playlists = Vec::new();
+        while !head.is_null() {
+            playlists.push(Playlist {
+                inner: head,
+                owner: mtpdev,
+            });
+
+            head = unsafe { (*head).next };
+        }
+
+        Ok(playlists)
+    })
+}
+
+pub(crate) fn get_playlist(mtpdev: &MtpDevice, playlist: impl AsObjectId) -> Result<Playlist<'_>> {
+    let session = mtpdev.session()?;
+    let playlist = unsafe { ffi::LIBMTP_Get_Playlist(session, playlist.as_id()) };
+
+    if playlist.is_null() {
+        Err(mtpdev.error_in("LIBMTP_Get_Playlist", None))
+    } else {
+        Ok(Playlist {
+            inner: playlist,
+            owner: mtpdev,
+        })
+    }
+}
+
+pub(crate) fn create_playlist<'a>(
+    mtpdev: &'a MtpDevice,
+    name: &str,
+    tracks: &[u32],
+    parent: Parent,
+    storage_id: u32,
+) -> Result<Playlist<'a>> {
+    let session = mtpdev.session()?;
+    let name = CString::new(name)?;
+
+    unsafe {
+        let playlist = ffi::LIBMTP_new_playlist_t();
+        (*playlist).name = libc::strdup(name.as_ptr());
+        (*playlist).parent_id = parent.to_id();
+        (*playlist).storage_id = storage_id;
+        replace_id_array(&mut (*playlist).tracks, &mut (*playlist).no_tracks, tracks);
+
+        let res = ffi::LIBMTP_Create_New_Playlist(session, playlist);
+
+        if res != 0 {
+            ffi::LIBMTP_destroy_playlist_t(playlist);
+            Err(mtpdev.error_in("LIBMTP_Create_New_Playlist", None))
+        } else {
+            Ok(Playlist {
+                inner: playlist,
+                owner: mtpdev,
+            })
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Contains `StoragePolicy`, used by the uploads of `StoragePool`, `send_files` and
+//! `DirectorySync` to choose the storage of new objects, e.g. to spill onto the SD card once the
+//! internal storage is tight.
+//!
+//! The free space is the one reported by the device the last time the storages were fetched
+//! (when the device was opened or with `update_storage`), batches subtract what they already
+//! sent so they don't have to fetch it again after every file.
+
+use crate::device::MtpDevice;
+use crate::storage::{AccessCapability, Storage, StorageId, StorageType};
+
+use num_traits::FromPrimitive;
+
+/// How the storage of an upload is chosen, a storage id (or `&Storage`) converts into
+/// `StoragePolicy::Specific`.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::policy::StoragePolicy;
+///
+/// let pool = mtp_device.storage_pool();
+/// pool.send_file_from_path(StoragePolicy::MostFree, "video.mp4", Parent::Root, metadata)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoragePolicy {
+    /// The writable storage with the most free space.
+    MostFree,
+    /// The given storage, `0` lets the device choose.
+    Specific(StorageId),
+    /// The first removable storage (like an SD card) with room for the upload, otherwise the
+    /// writable storage with the most free space.
+    PreferRemovable,
+}
+
+impl From<StorageId> for StoragePolicy {
+    fn from(id: StorageId) -> Self {
+        StoragePolicy::Specific(id)
+    }
+}
+
+impl From<u32> for StoragePolicy {
+    fn from(id: u32) -> Self {
+        StoragePolicy::Specific(StorageId(id))
+    }
+}
+
+impl From<&Storage<'_>> for StoragePolicy {
+    fn from(storage: &Storage<'_>) -> Self {
+        StoragePolicy::Specific(StorageId(storage.id()))
+    }
+}
+
+#[derive(Debug)]
+struct Candidate {
+    id: u32,
+    removable: bool,
+    free: u64,
+}
+
+/// Free space of the writable storages of a device, taken once and reduced by every upload
+/// planned on it.
+#[derive(Debug)]
+pub(crate) struct StorageSpace {
+    storages: Vec<Candidate>,
+}
+
+impl StorageSpace {
+    pub(crate) fn new(mtpdev: &MtpDevice) -> Self {
+        let mut storages = Vec::new();
+
+        unsafe {
+            let mut storage = (*mtpdev.inner).storage;
+            while !storage.is_null() {
+                let writable = matches!(
+                    AccessCapability::from_u16((*storage).AccessCapability),
+                    Some(AccessCapability::ReadWrite)
+                );
+
+                if writable {
+                    let removable = matches!(
+                        StorageType::from_u16((*storage).StorageType),
+                        Some(StorageType::RemovableRam | StorageType::RemovableRom)
+                    );
+
+                    storages.push(Candidate {
+                        id: (*storage).id,
+                        removable,
+                        free: (*storage).FreeSpaceInBytes,
+                    });
+                }
+
+                storage = (*storage).next;
+            }
+        }
+
+        StorageSpace { storages }
+    }
+
+    /// Returns the storage chosen by `policy` for an upload of `size` bytes and takes them from
+    /// its free space, `0` (the device chooses) if there's no writable storage.
+    pub(crate) fn choose(&mut self, policy: StoragePolicy, size: u64) -> u32 {
+        let chosen = match policy {
+            StoragePolicy::Specific(StorageId(id)) => {
+                self.storages.iter_mut().find(|storage| storage.id == id)
+            }
+            StoragePolicy::MostFree => self.most_free(),
+            StoragePolicy::PreferRemovable => {
+                let removable = self
+                    .storages
+                    .iter()
+                    .position(|storage| storage.removable && storage.free >= size);
+
+                match removable {
+                    Some(index) => Some(&mut self.storages[index]),
+                    None => self.most_free(),
+                }
+            }
+        };
+
+        match chosen {
+            Some(storage) => {
+                storage.free = storage.free.saturating_sub(size);
+                storage.id
+            }
+            None => match policy {
+                StoragePolicy::Specific(StorageId(id)) => id,
+                _ => 0,
+            },
+        }
+    }
+
+    fn most_free(&mut self) -> Option<&mut Candidate> {
+        // The first storage wins ties, it's usually the primary one
+        self.storages
+            .iter_mut()
+            .rev()
+            .max_by_key(|storage| storage.free)
+    }
+}
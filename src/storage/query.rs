@@ -0,0 +1,251 @@
+//! Contains a small query builder to search files with composable filters, the filters are
+//! evaluated while the folders are walked and matching files are returned lazily, see
+//! [`Storage::search`](../struct.Storage.html#method.search).
+
+use chrono::{DateTime, Utc};
+use derivative::Derivative;
+use std::rc::Rc;
+
+use crate::device::{CacheMode, MtpDevice};
+use crate::object::filetypes::Filetype;
+use crate::object::Object;
+use crate::storage::files::{list_files, File};
+use crate::storage::ignore::glob;
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Size of a megabyte, handy for size filters like `size_gt(10 * MB)`.
+pub const MB: u64 = 1024 * 1024;
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Clone)]
+enum Condition {
+    SizeGt(u64),
+    SizeLt(u64),
+    ModifiedAfter(DateTime<Utc>),
+    ModifiedBefore(DateTime<Utc>),
+    Name(String, #[derivative(Debug = "ignore")] Vec<char>),
+    Filetype(Filetype),
+    Or(Box<Query>, Box<Query>),
+    Custom(#[derivative(Debug = "ignore")] Rc<dyn Fn(&File<'_>) -> bool>),
+}
+
+impl Condition {
+    fn matches(&self, file: &File<'_>) -> bool {
+        match self {
+            Condition::SizeGt(size) => file.size() > *size,
+            Condition::SizeLt(size) => file.size() < *size,
+            Condition::ModifiedAfter(date) => file.modification_date() > *date,
+            Condition::ModifiedBefore(date) => file.modification_date() < *date,
+            Condition::Name(_, pattern) => {
+                let name: Vec<char> = file.name().chars().collect();
+                glob(pattern, &name)
+            }
+            Condition::Filetype(ftype) => file.ftype() == *ftype,
+            Condition::Or(left, right) => left.matches(file) || right.matches(file),
+            Condition::Custom(filter) => filter(file),
+        }
+    }
+}
+
+/// A set of conditions files must pass (all of them), by default every file below the folder
+/// being searched matches, recursively.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::query::{Query, MB};
+///
+/// let query = Query::new()
+///     .size_gt(10 * MB)
+///     .modified_after(last_backup)
+///     .name_matches("*.mp4");
+///
+/// for file in storage.search(query, Parent::Root) {
+///     let file = file?;
+///     println!("{} ({} bytes)", file.name(), file.size());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    conditions: Vec<Condition>,
+    recursive: bool,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query {
+            conditions: Vec::new(),
+            recursive: true,
+        }
+    }
+}
+
+impl Query {
+    /// Query matching every file.
+    pub fn new() -> Self {
+        Query::default()
+    }
+
+    /// Only files bigger than `size` bytes.
+    pub fn size_gt(mut self, size: u64) -> Self {
+        self.conditions.push(Condition::SizeGt(size));
+        self
+    }
+
+    /// Only files smaller than `size` bytes.
+    pub fn size_lt(mut self, size: u64) -> Self {
+        self.conditions.push(Condition::SizeLt(size));
+        self
+    }
+
+    /// Only files modified after `date`.
+    pub fn modified_after(mut self, date: DateTime<Utc>) -> Self {
+        self.conditions.push(Condition::ModifiedAfter(date));
+        self
+    }
+
+    /// Only files modified before `date`.
+    pub fn modified_before(mut self, date: DateTime<Utc>) -> Self {
+        self.conditions.push(Condition::ModifiedBefore(date));
+        self
+    }
+
+    /// Only files whose name matches the glob `pattern` (`*`, `?` and `[a-z]` like in
+    /// [`IgnorePatterns`](../ignore/struct.IgnorePatterns.html)), use `filter` to match names
+    /// with a regex.
+    pub fn name_matches(mut self, pattern: &str) -> Self {
+        let glob = pattern.chars().collect();
+        self.conditions
+            .push(Condition::Name(pattern.to_string(), glob));
+        self
+    }
+
+    /// Only files with the given filetype.
+    pub fn filetype(mut self, ftype: Filetype) -> Self {
+        self.conditions.push(Condition::Filetype(ftype));
+        self
+    }
+
+    /// Only files passing the custom `filter`, which has the signature `(file: &File) -> bool`.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&File<'_>) -> bool + 'static,
+    {
+        self.conditions.push(Condition::Custom(Rc::new(filter)));
+        self
+    }
+
+    /// Files passing either this query or `other`.
+    pub fn or(self, other: Query) -> Self {
+        let recursive = self.recursive;
+
+        Query {
+            conditions: vec![Condition::Or(Box::new(self), Box::new(other))],
+            recursive,
+        }
+    }
+
+    /// Whether to search the subfolders too, by default `true`.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Check whether the given file passes every condition of this query.
+    pub fn matches(&self, file: &File<'_>) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(file))
+    }
+}
+
+/// Iterator over the files matching a `Query`, folders are fetched from the device only when
+/// the files of the previous ones are exhausted, so stopping early avoids walking the rest of
+/// the tree.
+#[derive(Debug)]
+pub struct Search<'a> {
+    owner: &'a MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+    query: Query,
+    pending: Vec<Parent>,
+    current: std::vec::IntoIter<File<'a>>,
+    started: bool,
+}
+
+impl<'a> Search<'a> {
+    pub(crate) fn new(owner: &'a MtpDevice, storage_id: u32, parent: Parent, query: Query) -> Self {
+        Search {
+            owner,
+            storage_id,
+            parent,
+            query,
+            pending: Vec::new(),
+            current: Vec::new().into_iter(),
+            started: false,
+        }
+    }
+
+    /// Returns the query being evaluated.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Fills `current` with the first files to check, cached devices already have the whole
+    /// listing in memory so it's filtered at once.
+    fn start(&mut self) -> Result<()> {
+        self.started = true;
+
+        if let CacheMode::Uncached = self.owner.cache_mode() {
+            self.pending.push(self.parent);
+            return Ok(());
+        }
+
+        let mut files = list_files(self.owner, self.storage_id, self.parent)?;
+        if !self.query.recursive {
+            let parent = self.parent;
+            files.retain(|file| match parent {
+                Parent::Root => matches!(file.parent_id(), Parent::Root | Parent::Folder(0)),
+                Parent::Folder(id) => file.parent_id().to_id() == id,
+            });
+        }
+
+        self.current = files.into_iter();
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Search<'a> {
+    type Item = Result<File<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            if let Err(err) = self.start() {
+                return Some(Err(err));
+            }
+        }
+
+        loop {
+            for file in &mut self.current {
+                if matches!(file.ftype(), Filetype::Folder) {
+                    if self.query.recursive {
+                        self.pending.push(Parent::Folder(file.id()));
+                    }
+
+                    continue;
+                }
+
+                if self.query.matches(&file) {
+                    return Some(Ok(file));
+                }
+            }
+
+            let folder = self.pending.pop()?;
+            match files_and_folders(self.owner, self.storage_id, folder) {
+                Ok(files) => self.current = files.into_iter(),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Scratch directories for the tests working with local files.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory in the temporary directory, deleted with its contents once dropped, so
+/// it doesn't leak when an assertion fails.
+pub(crate) struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// Creates the directory, `name` only helps finding it while the test runs.
+    pub(crate) fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("libmtp-rs-{}-{}-{}", name, std::process::id(), id));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    /// Writes a file at `path` inside the directory, creating its parents, returns its full
+    /// path.
+    pub(crate) fn write(&self, path: &str, contents: &str) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,163 @@
+//! Contains a utility to build playlists from queries over the tracks of a device, like "every
+//! Jazz track" or "the 50 most recently added tracks", the query is evaluated against the track
+//! list of the device and the on-device playlist is created or updated accordingly.
+
+use chrono::{DateTime, Utc};
+
+use crate::object::Object;
+use crate::storage::playlists::Playlist;
+use crate::storage::tracks::Track;
+use crate::storage::{Parent, StoragePool};
+use crate::Result;
+
+/// Predicate over the metadata of a track.
+type TrackFilter<'f> = Box<dyn Fn(&Track<'_>) -> bool + 'f>;
+
+/// Builder of a playlist defined by filters over track metadata, all the filters must match
+/// for a track to be included in the playlist.
+///
+/// ## Example
+/// ```no_run
+/// let playlist = SmartPlaylist::new("Best of Jazz")
+///     .genre("Jazz")
+///     .min_stars(4)
+///     .filter(|track| track.duration() > 60_000)
+///     .sync(&storage_pool, Parent::Root)?;
+/// ```
+pub struct SmartPlaylist<'f> {
+    name: String,
+    filters: Vec<TrackFilter<'f>>,
+    newest_first: bool,
+    limit: Option<usize>,
+}
+
+impl<'f> SmartPlaylist<'f> {
+    /// Starts a new smart playlist with the given name, without filters it matches every track.
+    pub fn new(name: &str) -> Self {
+        SmartPlaylist {
+            name: name.to_string(),
+            filters: Vec::new(),
+            newest_first: false,
+            limit: None,
+        }
+    }
+
+    /// Returns the name of the playlist.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Only include tracks that satisfy a custom predicate.
+    pub fn filter(mut self, predicate: impl Fn(&Track<'_>) -> bool + 'f) -> Self {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Only include tracks of the given genre (case insensitive).
+    pub fn genre(self, genre: &str) -> Self {
+        let genre = genre.to_lowercase();
+        self.filter(move |track| {
+            track
+                .genre()
+                .map(|g| g.to_lowercase() == genre)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Only include tracks of the given artist (case insensitive).
+    pub fn artist(self, artist: &str) -> Self {
+        let artist = artist.to_lowercase();
+        self.filter(move |track| {
+            track
+                .artist()
+                .map(|a| a.to_lowercase() == artist)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Only include tracks of the given album (case insensitive).
+    pub fn album(self, album: &str) -> Self {
+        let album = album.to_lowercase();
+        self.filter(move |track| {
+            track
+                .album()
+                .map(|a| a.to_lowercase() == album)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Only include tracks with a rating of at least `stars`, see `Track::stars`.
+    pub fn min_stars(self, stars: u8) -> Self {
+        self.filter(move |track| track.stars() >= stars)
+    }
+
+    /// Only include tracks used (played) at least `count` times.
+    pub fn min_use_count(self, count: u32) -> Self {
+        self.filter(move |track| track.use_count() >= count)
+    }
+
+    /// Only include tracks added (or modified) after the given date.
+    pub fn added_after(self, date: DateTime<Utc>) -> Self {
+        self.filter(move |track| track.modification_date() >= date)
+    }
+
+    /// Order the matching tracks from the most recently added (or modified) to the oldest one,
+    /// by default tracks keep the order of the device track list.
+    pub fn newest_first(mut self) -> Self {
+        self.newest_first = true;
+        self
+    }
+
+    /// Include at most `limit` tracks, useful together with `newest_first`.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Check whether a track satisfies all the filters.
+    pub fn matches(&self, track: &Track<'_>) -> bool {
+        self.filters.iter().all(|filter| filter(track))
+    }
+
+    /// Evaluates the query against some tracks, returns the ids of the matching tracks in the
+    /// order they'd have in the playlist.
+    pub fn matching_tracks(&self, tracks: &[Track<'_>]) -> Vec<u32> {
+        let mut matching: Vec<_> = tracks.iter().filter(|track| self.matches(track)).collect();
+
+        if self.newest_first {
+            matching.sort_by_key(|track| std::cmp::Reverse(track.modification_date()));
+        }
+
+        let limit = self.limit.unwrap_or(matching.len());
+        matching
+            .iter()
+            .take(limit)
+            .map(|track| track.id())
+            .collect()
+    }
+
+    /// Evaluates the query against the track list of the device and updates the playlist with
+    /// the same name, if there isn't one it's created in the primary storage under `parent`.
+    /// This is only available on cached devices.
+    pub fn sync<'a>(&self, pool: &StoragePool<'a>, parent: Parent) -> Result<Playlist<'a>> {
+        let tracks = pool.track_list()?;
+        let matching = self.matching_tracks(&tracks);
+
+        let existing = pool
+            .playlist_list()?
+            .into_iter()
+            .find(|playlist| playlist.name().as_deref() == Some(self.name.as_str()));
+
+        match existing {
+            Some(mut playlist) => {
+                if playlist.tracks() != matching {
+                    playlist.set_tracks(&matching)?;
+                }
+
+                Ok(playlist)
+            }
+
+            None => pool.create_playlist(&self.name, &matching, parent),
+        }
+    }
+}
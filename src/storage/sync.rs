@@ -0,0 +1,1166 @@
+//! Contains relevant items to mirror a local directory into a folder of the device, a
+//! [`DirectorySync`](struct.DirectorySync.html) first plans the needed operations as a
+//! [`SyncPlan`](struct.SyncPlan.html), which can be inspected or printed before it's applied,
+//! so destructive operations can be previewed (a dry run is just not applying the plan).
+//!
+//! A local file is uploaded if it's missing on the device, or if it differs in size or is newer
+//! than the one on the device, in this case the file on the device is replaced (it's deleted
+//! once the new one is uploaded, so a failed upload doesn't lose it). If the file on
+//! the device is newer, or a file and a folder have the same name, it's a conflict and nothing
+//! is done with it.
+//!
+//! Files matching the [ignore patterns](../ignore/index.html) of the `.mtpignore` file in the
+//! root of the local directory, or the ones given with `DirectorySync::ignore`, are neither
+//! uploaded nor deleted.
+//!
+//! A byte quota may be given with `DirectorySync::quota` (e.g. to use at most 20 GB of a
+//! shared device), uploads that don't fit are left out of the plan and reported as skipped.
+//!
+//! The type of the uploaded files is guessed with
+//! [`Filetype::guess`](../../object/filetypes/enum.Filetype.html#method.guess) (from their
+//! contents with the `magic` feature), unless one is given with `DirectorySync::file_type`.
+//!
+//! The storage may also be chosen by a [`StoragePolicy`](../policy/enum.StoragePolicy.html)
+//! with `DirectorySync::storage_policy`, the whole directory goes to the same storage.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::filetypes::Filetype;
+use crate::object::{DummyObject, Object};
+use crate::storage::files::{self, FileMetadata};
+use crate::storage::folders::create_folder;
+use crate::storage::ignore::{IgnorePatterns, IGNORE_FILE_NAME};
+use crate::storage::policy::{StoragePolicy, StorageSpace};
+use crate::storage::{files_and_folders, temp_upload_name, Parent};
+use crate::Result;
+
+/// An object of the device as seen when planning a synchronization.
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteEntry {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    /// Modification date in seconds since the epoch.
+    pub(crate) modified: i64,
+    pub(crate) is_folder: bool,
+}
+
+/// The device side of a synchronization, implemented by `MtpDevice`, the tests use an
+/// in-memory tree instead.
+pub(crate) trait SyncBackend {
+    /// Lists the objects of the `parent` folder.
+    fn list(&self, storage_id: u32, parent: Parent) -> Result<Vec<RemoteEntry>>;
+
+    /// Chooses a storage with room for `size` bytes, see `StorageSpace::choose`.
+    fn choose_storage(&self, policy: StoragePolicy, size: u64) -> u32;
+
+    /// Creates a folder, returns its id.
+    fn create_folder(&self, storage_id: u32, parent: Parent, name: &str) -> Result<u32>;
+
+    /// Uploads the `local` file, returns the id of the new object.
+    fn upload(
+        &self,
+        storage_id: u32,
+        parent: Parent,
+        local: &Path,
+        metadata: FileMetadata<'_>,
+    ) -> Result<u32>;
+
+    /// Gives a new name to the object `id`.
+    fn rename(&self, id: u32, name: &str) -> Result<()>;
+
+    /// Deletes the object `id`.
+    fn delete(&self, id: u32) -> Result<()>;
+}
+
+impl SyncBackend for MtpDevice {
+    fn list(&self, storage_id: u32, parent: Parent) -> Result<Vec<RemoteEntry>> {
+        let files = files_and_folders(self, storage_id, parent)?;
+
+        Ok(files
+            .iter()
+            .map(|file| RemoteEntry {
+                id: file.id(),
+                name: file.name().to_string(),
+                size: file.size(),
+                modified: file.modification_date().timestamp(),
+                is_folder: matches!(file.ftype(), Filetype::Folder),
+            })
+            .collect())
+    }
+
+    fn choose_storage(&self, policy: StoragePolicy, size: u64) -> u32 {
+        StorageSpace::new(self).choose(policy, size)
+    }
+
+    fn create_folder(&self, storage_id: u32, parent: Parent, name: &str) -> Result<u32> {
+        create_folder(self, name, parent, storage_id).map(|(id, _)| id)
+    }
+
+    fn upload(
+        &self,
+        storage_id: u32,
+        parent: Parent,
+        local: &Path,
+        metadata: FileMetadata<'_>,
+    ) -> Result<u32> {
+        files::send_file_from_path(self, storage_id, local, parent, metadata).map(|file| file.id())
+    }
+
+    fn rename(&self, id: u32, name: &str) -> Result<()> {
+        self.search_file(id)?.rename(name)
+    }
+
+    fn delete(&self, id: u32) -> Result<()> {
+        DummyObject { id, mtpdev: self }.delete()
+    }
+}
+
+/// Single operation of a [`SyncPlan`](struct.SyncPlan.html), every `path` is relative to the
+/// synchronized folder of the device.
+#[derive(Debug, Clone)]
+pub enum SyncOperation {
+    /// Create the folder at `path`.
+    CreateFolder { path: PathBuf },
+    /// Upload the `local` file to `path`, replacing the object `replaces` if any. A replaced
+    /// object is only deleted once the file is uploaded (under a temporary name).
+    Upload {
+        local: PathBuf,
+        path: PathBuf,
+        size: u64,
+        replaces: Option<u32>,
+    },
+    /// Delete the object `id` at `path`, folders are deleted after their contents.
+    Delete { path: PathBuf, id: u32 },
+    /// Nothing will be done with `path` because of `reason`.
+    Conflict { path: PathBuf, reason: String },
+}
+
+impl SyncOperation {
+    /// Returns the path (relative to the synchronized folder) this operation acts on.
+    pub fn path(&self) -> &Path {
+        match self {
+            SyncOperation::CreateFolder { path }
+            | SyncOperation::Upload { path, .. }
+            | SyncOperation::Delete { path, .. }
+            | SyncOperation::Conflict { path, .. } => path,
+        }
+    }
+
+    /// Check whether this operation removes data from the device, i.e. deletes or replaces
+    /// an object.
+    pub fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            SyncOperation::Delete { .. }
+                | SyncOperation::Upload {
+                    replaces: Some(_),
+                    ..
+                }
+        )
+    }
+}
+
+impl Display for SyncOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncOperation::CreateFolder { path } => write!(f, "create   {}", path.display()),
+            SyncOperation::Upload {
+                path,
+                size,
+                replaces,
+                ..
+            } => {
+                let action = if replaces.is_some() {
+                    "replace"
+                } else {
+                    "upload"
+                };
+                write!(f, "{:<8} {} ({} bytes)", action, path.display(), size)
+            }
+            SyncOperation::Delete { path, .. } => write!(f, "delete   {}", path.display()),
+            SyncOperation::Conflict { path, reason } => {
+                write!(f, "conflict {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+/// Summary of an applied [`SyncPlan`](struct.SyncPlan.html).
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    /// Number of created folders.
+    pub created: usize,
+    /// Number of uploaded files, including replaced ones.
+    pub uploaded: usize,
+    /// Number of bytes uploaded.
+    pub bytes_uploaded: u64,
+    /// Number of deleted objects, not counting replaced ones.
+    pub deleted: usize,
+    /// Number of conflicts left untouched.
+    pub conflicts: usize,
+    /// Number of uploads skipped because they didn't fit in the quota.
+    pub skipped: usize,
+}
+
+/// How a `DirectorySync` with a quota chooses the uploads that fit in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Uploads in the order of the plan until one doesn't fit, the rest are skipped.
+    Stop,
+    /// Uploads the most recently modified files first, skipping the ones that don't fit.
+    NewestFirst,
+    /// Uploads the smallest files first, so as many files as possible fit.
+    SmallestFirst,
+}
+
+/// An upload left out of a [`SyncPlan`](struct.SyncPlan.html) because it didn't fit in the
+/// quota.
+#[derive(Debug, Clone)]
+pub struct SkippedUpload {
+    pub local: PathBuf,
+    /// Path relative to the synchronized folder of the device.
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Operations needed to synchronize a local directory into a folder of the device, nothing is
+/// done until it's applied. Displaying a plan prints one operation per line.
+///
+/// ## Example
+/// ```no_run
+/// let plan = storage.sync_directory("Music", Parent::Folder(music_id)).plan()?;
+///
+/// if plan.operations().iter().any(|op| op.is_destructive()) {
+///     println!("{}", plan);
+///     // Ask before going on...
+/// }
+///
+/// let report = plan.apply()?;
+/// ```
+pub struct SyncPlan<'a> {
+    owner: &'a dyn SyncBackend,
+    storage_id: u32,
+    parent: Parent,
+    folders: HashMap<PathBuf, u32>,
+    operations: Vec<SyncOperation>,
+    kept_bytes: u64,
+    replaced_sizes: HashMap<u32, u64>,
+    skipped: Vec<SkippedUpload>,
+    file_type: Option<Filetype>,
+}
+
+impl Debug for SyncPlan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncPlan")
+            .field("storage_id", &self.storage_id)
+            .field("parent", &self.parent)
+            .field("operations", &self.operations)
+            .field("skipped", &self.skipped)
+            .finish()
+    }
+}
+
+impl Display for SyncPlan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for operation in &self.operations {
+            writeln!(f, "{}", operation)?;
+        }
+
+        for skipped in &self.skipped {
+            let path = skipped.path.display();
+            writeln!(f, "skip     {} ({} bytes, over quota)", path, skipped.size)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> SyncPlan<'a> {
+    /// Returns the planned operations, in the order they'll be applied.
+    pub fn operations(&self) -> &[SyncOperation] {
+        &self.operations
+    }
+
+    /// Check whether applying this plan wouldn't change anything on the device, i.e. there
+    /// are no operations besides conflicts.
+    pub fn is_empty(&self) -> bool {
+        self.operations
+            .iter()
+            .all(|operation| matches!(operation, SyncOperation::Conflict { .. }))
+    }
+
+    /// Returns the number of bytes that will be uploaded.
+    pub fn upload_bytes(&self) -> u64 {
+        self.operations
+            .iter()
+            .map(|operation| match operation {
+                SyncOperation::Upload { size, .. } => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the uploads left out of this plan because they didn't fit in the quota.
+    pub fn skipped(&self) -> &[SkippedUpload] {
+        &self.skipped
+    }
+
+    /// Returns the conflicts of this plan, which are left untouched when applied.
+    pub fn conflicts(&self) -> impl Iterator<Item = &SyncOperation> {
+        self.operations
+            .iter()
+            .filter(|operation| matches!(operation, SyncOperation::Conflict { .. }))
+    }
+
+    fn parent_of(&self, folders: &HashMap<PathBuf, u32>, path: &Path) -> Result<Parent> {
+        match path.parent() {
+            Some(parent) if parent != Path::new("") => match folders.get(parent) {
+                Some(&id) => Ok(Parent::Folder(id)),
+                None => Err(Error::InvalidArgument {
+                    reason: format!("folder {} doesn't exist", parent.display()),
+                }),
+            },
+            _ => Ok(self.parent),
+        }
+    }
+
+    /// Applies the operations of this plan in order, stopping at the first error (the
+    /// operations applied until then aren't undone).
+    pub fn apply(&self) -> Result<SyncReport> {
+        let mut folders = self.folders.clone();
+        let mut report = SyncReport::default();
+
+        for operation in &self.operations {
+            match operation {
+                SyncOperation::CreateFolder { path } => {
+                    let parent = self.parent_of(&folders, path)?;
+                    let id = self
+                        .owner
+                        .create_folder(self.storage_id, parent, file_name(path))?;
+
+                    folders.insert(path.clone(), id);
+                    report.created += 1;
+                }
+
+                SyncOperation::Upload {
+                    local,
+                    path,
+                    replaces,
+                    ..
+                } => {
+                    let parent = self.parent_of(&folders, path)?;
+                    let metadata = fs::metadata(local)?;
+                    let file_metadata = FileMetadata {
+                        file_size: metadata.len(),
+                        file_name: file_name(path),
+                        file_type: match &self.file_type {
+                            Some(file_type) => file_type.clone(),
+                            None => Filetype::guess(local),
+                        },
+                        modification_date: metadata.modified()?.into(),
+                    };
+
+                    match *replaces {
+                        Some(old) => self.replace(old, parent, local, file_metadata)?,
+                        None => {
+                            self.owner
+                                .upload(self.storage_id, parent, local, file_metadata)?;
+                        }
+                    }
+
+                    report.uploaded += 1;
+                    report.bytes_uploaded += metadata.len();
+                }
+
+                SyncOperation::Delete { id, .. } => {
+                    self.owner.delete(*id)?;
+
+                    report.deleted += 1;
+                }
+
+                SyncOperation::Conflict { .. } => report.conflicts += 1,
+            }
+        }
+
+        report.skipped = self.skipped.len();
+        Ok(report)
+    }
+
+    /// Uploads a file replacing the object `old`, which is kept if the upload fails.
+    fn replace(
+        &self,
+        old: u32,
+        parent: Parent,
+        local: &Path,
+        metadata: FileMetadata<'_>,
+    ) -> Result<()> {
+        let name = metadata.file_name;
+        let temp_name = temp_upload_name(name);
+        let temp_metadata = FileMetadata {
+            file_name: &temp_name,
+            ..metadata
+        };
+
+        let id = self
+            .owner
+            .upload(self.storage_id, parent, local, temp_metadata)?;
+
+        if let Err(err) = self.owner.delete(old) {
+            let _ = self.owner.delete(id);
+            return Err(err);
+        }
+
+        self.owner.rename(id, name)
+    }
+
+    /// Leaves out the uploads that don't fit in `quota` bytes, which counts the files of the
+    /// synchronized folder that stay on the device and the uploads.
+    fn apply_quota(&mut self, quota: u64, policy: QuotaPolicy) -> Result<()> {
+        // Replaced files stay on the device if their upload is skipped
+        let mut used = self.kept_bytes + self.replaced_sizes.values().sum::<u64>();
+
+        let mut uploads = Vec::new();
+        for (index, operation) in self.operations.iter().enumerate() {
+            if let SyncOperation::Upload {
+                local,
+                size,
+                replaces,
+                ..
+            } = operation
+            {
+                let replaced = replaces.and_then(|id| self.replaced_sizes.get(&id).copied());
+                let cost = size.saturating_sub(replaced.unwrap_or(0));
+
+                let modified = match policy {
+                    QuotaPolicy::NewestFirst => Some(fs::metadata(local)?.modified()?),
+                    _ => None,
+                };
+
+                uploads.push((index, *size, cost, modified));
+            }
+        }
+
+        match policy {
+            QuotaPolicy::Stop => {}
+            QuotaPolicy::NewestFirst => {
+                uploads.sort_by_key(|&(_, _, _, modified)| std::cmp::Reverse(modified))
+            }
+            QuotaPolicy::SmallestFirst => uploads.sort_by_key(|&(_, size, _, _)| size),
+        }
+
+        let mut skip = vec![false; self.operations.len()];
+        let mut stopped = false;
+        for (index, _, cost, _) in uploads {
+            if stopped || used + cost > quota {
+                skip[index] = true;
+                stopped = policy == QuotaPolicy::Stop;
+            } else {
+                used += cost;
+            }
+        }
+
+        let operations = std::mem::take(&mut self.operations);
+        for (operation, skip) in operations.into_iter().zip(skip) {
+            match operation {
+                SyncOperation::Upload {
+                    local, path, size, ..
+                } if skip => self.skipped.push(SkippedUpload { local, path, size }),
+                operation => self.operations.push(operation),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Names are checked to be valid UTF-8 while planning.
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .expect("Planned paths have valid names")
+}
+
+/// Mirrors a local directory into a folder of the device, build it with
+/// `Storage::sync_directory` or `StoragePool::sync_directory`, note that listing the contents
+/// of the device requires an uncached device.
+///
+/// ## Example
+/// ```no_run
+/// let report = storage
+///     .sync_directory("Music", Parent::Folder(music_id))
+///     .delete_extraneous(true)
+///     .run()?;
+/// println!("Uploaded {} files", report.uploaded);
+/// ```
+pub struct DirectorySync<'a> {
+    owner: &'a dyn SyncBackend,
+    storage_id: u32,
+    local: PathBuf,
+    parent: Parent,
+    delete_extraneous: bool,
+    ignore: IgnorePatterns,
+    quota: Option<(u64, QuotaPolicy)>,
+    storage_policy: Option<StoragePolicy>,
+    file_type: Option<Filetype>,
+}
+
+/// Total size of the files below `dir`.
+fn local_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        size += if metadata.is_dir() {
+            local_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
+
+impl<'a> DirectorySync<'a> {
+    pub(crate) fn new(
+        owner: &'a dyn SyncBackend,
+        storage_id: u32,
+        local: impl AsRef<Path>,
+        parent: Parent,
+    ) -> Self {
+        DirectorySync {
+            owner,
+            storage_id,
+            local: local.as_ref().to_path_buf(),
+            parent,
+            delete_extraneous: false,
+            ignore: IgnorePatterns::new(),
+            quota: None,
+            storage_policy: None,
+            file_type: None,
+        }
+    }
+
+    /// Whether objects on the device that don't exist locally are deleted, `false` by default.
+    pub fn delete_extraneous(mut self, delete: bool) -> Self {
+        self.delete_extraneous = delete;
+        self
+    }
+
+    /// Skips the paths matching these patterns (relative to the synchronized directory), they
+    /// are checked after the ones of the `.mtpignore` file, if any.
+    pub fn ignore(mut self, patterns: IgnorePatterns) -> Self {
+        self.ignore = patterns;
+        self
+    }
+
+    /// Limits the space used by the synchronized folder of the device to `bytes`, counting the
+    /// files mirrored from the local directory (the ones already up to date and the uploads),
+    /// objects of the device that aren't mirrored don't count. The uploads that don't fit are
+    /// chosen by `policy`, left out of the plan and reported as skipped.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::storage::sync::QuotaPolicy;
+    ///
+    /// let plan = storage
+    ///     .sync_directory("Photos", Parent::Folder(photos_id))
+    ///     .quota(20 * 1024 * 1024 * 1024, QuotaPolicy::NewestFirst)
+    ///     .plan()?;
+    ///
+    /// for skipped in plan.skipped() {
+    ///     println!("No room for {}", skipped.path.display());
+    /// }
+    /// ```
+    pub fn quota(mut self, bytes: u64, policy: QuotaPolicy) -> Self {
+        self.quota = Some((bytes, policy));
+        self
+    }
+
+    /// Chooses the storage with `policy` when planning, with room for the whole local
+    /// directory if possible, instead of the storage given when the builder was created. Meant
+    /// for synchronizations into `Parent::Root`, folders belong to a single storage.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use libmtp_rs::storage::policy::StoragePolicy;
+    ///
+    /// let report = mtp_device
+    ///     .storage_pool()
+    ///     .sync_directory("Podcasts", Parent::Root)
+    ///     .storage_policy(StoragePolicy::PreferRemovable)
+    ///     .run()?;
+    /// ```
+    pub fn storage_policy(mut self, policy: StoragePolicy) -> Self {
+        self.storage_policy = Some(policy);
+        self
+    }
+
+    /// Uploads every file with this type instead of guessing it from the local file.
+    pub fn file_type(mut self, file_type: Filetype) -> Self {
+        self.file_type = Some(file_type);
+        self
+    }
+
+    /// Compares the local directory with the folder of the device and returns the operations
+    /// needed to synchronize them, nothing is changed on the device.
+    pub fn plan(&self) -> Result<SyncPlan<'a>> {
+        let storage_id = match self.storage_policy {
+            Some(policy) => self.owner.choose_storage(policy, local_size(&self.local)?),
+            None => self.storage_id,
+        };
+
+        let mut plan = SyncPlan {
+            owner: self.owner,
+            storage_id,
+            parent: self.parent,
+            folders: HashMap::new(),
+            operations: Vec::new(),
+            kept_bytes: 0,
+            replaced_sizes: HashMap::new(),
+            skipped: Vec::new(),
+            file_type: self.file_type.clone(),
+        };
+
+        let ignore_file = self.local.join(IGNORE_FILE_NAME);
+        let mut ignore = if ignore_file.is_file() {
+            IgnorePatterns::load(ignore_file)?
+        } else {
+            IgnorePatterns::new()
+        };
+        ignore.extend(&self.ignore);
+
+        let root = Some(self.parent);
+        self.plan_folder(&self.local, Path::new(""), root, &ignore, &mut plan)?;
+
+        if let Some((quota, policy)) = self.quota {
+            plan.apply_quota(quota, policy)?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Plans and applies the synchronization right away.
+    pub fn run(&self) -> Result<SyncReport> {
+        self.plan()?.apply()
+    }
+
+    fn plan_folder(
+        &self,
+        local: &Path,
+        relative: &Path,
+        remote: Option<Parent>,
+        ignore: &IgnorePatterns,
+        plan: &mut SyncPlan<'a>,
+    ) -> Result<()> {
+        let mut remote_files: HashMap<String, RemoteEntry> = match remote {
+            Some(parent) => self
+                .owner
+                .list(plan.storage_id, parent)?
+                .into_iter()
+                .map(|entry| (entry.name.clone(), entry))
+                .collect(),
+            None => HashMap::new(),
+        };
+
+        let mut entries = fs::read_dir(local)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = relative.join(entry.file_name());
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => {
+                    plan.operations.push(SyncOperation::Conflict {
+                        path,
+                        reason: "the name isn't valid UTF-8".to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let metadata = fs::metadata(entry.path())?;
+            let is_ignore_file = relative == Path::new("") && name == IGNORE_FILE_NAME;
+            if is_ignore_file || ignore.is_ignored(&path, metadata.is_dir()) {
+                continue;
+            }
+
+            let existing = remote_files.remove(&name);
+            let existing_is_folder = existing.as_ref().map(|entry| entry.is_folder);
+
+            if metadata.is_dir() {
+                match (existing, existing_is_folder) {
+                    (None, _) => {
+                        plan.operations
+                            .push(SyncOperation::CreateFolder { path: path.clone() });
+                        self.plan_folder(&entry.path(), &path, None, ignore, plan)?;
+                    }
+                    (Some(folder), Some(true)) => {
+                        plan.folders.insert(path.clone(), folder.id);
+                        self.plan_folder(
+                            &entry.path(),
+                            &path,
+                            Some(Parent::Folder(folder.id)),
+                            ignore,
+                            plan,
+                        )?;
+                    }
+                    (Some(_), _) => plan.operations.push(SyncOperation::Conflict {
+                        path,
+                        reason: "a file with this name exists on the device".to_string(),
+                    }),
+                }
+            } else {
+                let size = metadata.len();
+                let modified = DateTime::<Utc>::from(metadata.modified()?).timestamp();
+
+                match (existing, existing_is_folder) {
+                    (None, _) => plan.operations.push(SyncOperation::Upload {
+                        local: entry.path(),
+                        path,
+                        size,
+                        replaces: None,
+                    }),
+                    (Some(_), Some(true)) => plan.operations.push(SyncOperation::Conflict {
+                        path,
+                        reason: "a folder with this name exists on the device".to_string(),
+                    }),
+                    (Some(file), _) => {
+                        // Devices usually keep dates with a resolution of seconds
+                        let remote_modified = file.modified;
+
+                        if file.size == size && modified <= remote_modified {
+                            plan.kept_bytes += size;
+                            continue;
+                        }
+
+                        if modified >= remote_modified {
+                            plan.replaced_sizes.insert(file.id, file.size);
+                            plan.operations.push(SyncOperation::Upload {
+                                local: entry.path(),
+                                path,
+                                size,
+                                replaces: Some(file.id),
+                            });
+                        } else {
+                            plan.kept_bytes += file.size;
+                            plan.operations.push(SyncOperation::Conflict {
+                                path,
+                                reason: "the file on the device is newer".to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.delete_extraneous {
+            let mut extraneous: Vec<_> = remote_files.into_iter().collect();
+            extraneous.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (name, file) in extraneous {
+                self.plan_delete(file, relative.join(name), ignore, plan)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Plans the deletion of `file` and its contents, ignored objects are kept (and so their
+    /// folders), returns whether `file` will be deleted.
+    fn plan_delete(
+        &self,
+        file: RemoteEntry,
+        path: PathBuf,
+        ignore: &IgnorePatterns,
+        plan: &mut SyncPlan<'a>,
+    ) -> Result<bool> {
+        if ignore.is_ignored(&path, file.is_folder) {
+            return Ok(false);
+        }
+
+        let mut empty = true;
+        if file.is_folder {
+            let children = self.owner.list(plan.storage_id, Parent::Folder(file.id))?;
+
+            for child in children {
+                let child_path = path.join(&child.name);
+                empty &= self.plan_delete(child, child_path, ignore, plan)?;
+            }
+        }
+
+        if empty {
+            plan.operations
+                .push(SyncOperation::Delete { path, id: file.id });
+        }
+
+        Ok(empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::scratch::ScratchDir;
+    use std::cell::{Cell, RefCell};
+
+    /// In-memory tree of the device, objects are kept with the id of their parent.
+    #[derive(Default)]
+    struct FakeDevice {
+        objects: RefCell<Vec<(u32, RemoteEntry)>>,
+        next_id: Cell<u32>,
+        fail_uploads: Cell<bool>,
+    }
+
+    impl FakeDevice {
+        fn add(&self, parent: Parent, name: &str, size: u64, modified: i64) -> u32 {
+            self.insert(parent, name, size, modified, false)
+        }
+
+        fn add_folder(&self, parent: Parent, name: &str) -> u32 {
+            self.insert(parent, name, 0, 0, true)
+        }
+
+        fn insert(
+            &self,
+            parent: Parent,
+            name: &str,
+            size: u64,
+            modified: i64,
+            is_folder: bool,
+        ) -> u32 {
+            let id = self.next_id.get() + 1;
+            self.next_id.set(id);
+
+            let entry = RemoteEntry {
+                id,
+                name: name.to_string(),
+                size,
+                modified,
+                is_folder,
+            };
+
+            self.objects.borrow_mut().push((parent.to_id(), entry));
+            id
+        }
+
+        fn names(&self, parent: Parent) -> Vec<String> {
+            let mut names: Vec<_> = self
+                .list(0, parent)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect();
+
+            names.sort();
+            names
+        }
+
+        fn find(&self, parent: Parent, name: &str) -> Option<RemoteEntry> {
+            self.list(0, parent)
+                .unwrap()
+                .into_iter()
+                .find(|entry| entry.name == name)
+        }
+    }
+
+    impl SyncBackend for FakeDevice {
+        fn list(&self, _storage_id: u32, parent: Parent) -> Result<Vec<RemoteEntry>> {
+            Ok(self
+                .objects
+                .borrow()
+                .iter()
+                .filter(|(parent_id, _)| *parent_id == parent.to_id())
+                .map(|(_, entry)| entry.clone())
+                .collect())
+        }
+
+        fn choose_storage(&self, _policy: StoragePolicy, _size: u64) -> u32 {
+            1
+        }
+
+        fn create_folder(&self, _storage_id: u32, parent: Parent, name: &str) -> Result<u32> {
+            Ok(self.add_folder(parent, name))
+        }
+
+        fn upload(
+            &self,
+            _storage_id: u32,
+            parent: Parent,
+            _local: &Path,
+            metadata: FileMetadata<'_>,
+        ) -> Result<u32> {
+            if self.fail_uploads.get() {
+                return Err(Error::Disconnected);
+            }
+
+            let modified = metadata.modification_date.timestamp();
+            Ok(self.add(parent, metadata.file_name, metadata.file_size, modified))
+        }
+
+        fn rename(&self, id: u32, name: &str) -> Result<()> {
+            for (_, entry) in self.objects.borrow_mut().iter_mut() {
+                if entry.id == id {
+                    entry.name = name.to_string();
+                }
+            }
+
+            Ok(())
+        }
+
+        fn delete(&self, id: u32) -> Result<()> {
+            self.objects
+                .borrow_mut()
+                .retain(|(parent_id, entry)| entry.id != id && *parent_id != id);
+            Ok(())
+        }
+    }
+
+    /// A scratch directory with the given local files and an empty device.
+    fn setup(name: &str, files: &[(&str, &str)]) -> (ScratchDir, FakeDevice) {
+        let dir = ScratchDir::new(&format!("sync-{}", name));
+        for (path, contents) in files {
+            dir.write(path, contents);
+        }
+
+        (dir, FakeDevice::default())
+    }
+
+    /// Returns the modification date of a local file in seconds.
+    fn mtime(dir: &Path, path: &str) -> i64 {
+        let modified = fs::metadata(dir.join(path)).unwrap().modified().unwrap();
+        DateTime::<Utc>::from(modified).timestamp()
+    }
+
+    fn summary(plan: &SyncPlan<'_>) -> Vec<String> {
+        plan.operations()
+            .iter()
+            .map(|operation| operation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn plans_missing_files_and_folders() {
+        let (dir, device) = setup("missing", &[("a.txt", "a"), ("sub/b.txt", "bb")]);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+
+        assert_eq!(
+            summary(&plan),
+            [
+                "upload   a.txt (1 bytes)",
+                "create   sub",
+                "upload   sub/b.txt (2 bytes)",
+            ]
+        );
+        assert!(plan.operations().iter().all(|op| !op.is_destructive()));
+        assert_eq!(plan.upload_bytes(), 3);
+    }
+
+    #[test]
+    fn replaces_only_older_files() {
+        let (dir, device) = setup(
+            "replace",
+            &[
+                ("same.txt", "same"),
+                ("changed.txt", "changed"),
+                ("edited.txt", "edited"),
+            ],
+        );
+        let modified = mtime(&dir, "same.txt");
+
+        device.add(Parent::Root, "same.txt", 4, modified);
+        let changed = device.add(Parent::Root, "changed.txt", 1, modified - 100);
+        device.add(Parent::Root, "edited.txt", 1, modified + 100);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+
+        assert_eq!(
+            summary(&plan),
+            [
+                "replace  changed.txt (7 bytes)",
+                "conflict edited.txt: the file on the device is newer",
+            ]
+        );
+        assert!(matches!(
+            plan.operations()[0],
+            SyncOperation::Upload { replaces: Some(id), .. } if id == changed
+        ));
+        assert!(plan.operations()[0].is_destructive());
+        assert!(!plan.operations()[1].is_destructive());
+        assert_eq!(plan.conflicts().count(), 1);
+    }
+
+    #[test]
+    fn up_to_date_plan_is_empty() {
+        let (dir, device) = setup("empty", &[("a.txt", "a")]);
+        let modified = mtime(&dir, "a.txt");
+
+        device.add(Parent::Root, "a.txt", 1, modified);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+        assert!(plan.is_empty());
+        assert!(plan.operations().is_empty());
+    }
+
+    #[test]
+    fn files_and_folders_with_the_same_name_conflict() {
+        let (dir, device) = setup("clash", &[("x", "file"), ("y/inner.txt", "inner")]);
+
+        device.add_folder(Parent::Root, "x");
+        device.add(Parent::Root, "y", 1, 0);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+
+        assert_eq!(
+            summary(&plan),
+            [
+                "conflict x: a folder with this name exists on the device",
+                "conflict y: a file with this name exists on the device",
+            ]
+        );
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn deletes_extraneous_objects_after_their_contents() {
+        let (dir, device) = setup("delete", &[("kept.txt", "kept")]);
+
+        device.add(Parent::Root, "kept.txt", 4, i64::MAX);
+        device.add(Parent::Root, "old.txt", 1, 0);
+        let gone = device.add_folder(Parent::Root, "gone");
+        device.add(Parent::Folder(gone), "z.txt", 1, 0);
+        let thumbs = device.add_folder(Parent::Root, "thumbs");
+        device.add(Parent::Folder(thumbs), "a.txt", 1, 0);
+        device.add(Parent::Folder(thumbs), "b.thm", 1, 0);
+
+        let sync = DirectorySync::new(&device, 1, &dir, Parent::Root);
+        assert!(sync.plan().unwrap().is_empty());
+
+        let plan = sync
+            .delete_extraneous(true)
+            .ignore(IgnorePatterns::parse("*.thm"))
+            .plan()
+            .unwrap();
+
+        // `thumbs` keeps an ignored file, so it isn't deleted
+        assert_eq!(
+            summary(&plan),
+            [
+                "delete   gone/z.txt",
+                "delete   gone",
+                "delete   old.txt",
+                "delete   thumbs/a.txt",
+            ]
+        );
+        assert!(plan.operations().iter().all(|op| op.is_destructive()));
+    }
+
+    #[test]
+    fn ignored_local_files_are_not_uploaded() {
+        let (dir, device) = setup(
+            "ignore",
+            &[
+                (IGNORE_FILE_NAME, "*.tmp\ncache/"),
+                ("a.tmp", "a"),
+                ("cache/b.txt", "b"),
+                ("c.txt", "c"),
+            ],
+        );
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+
+        assert_eq!(summary(&plan), ["upload   c.txt (1 bytes)"]);
+    }
+
+    #[test]
+    fn quota_skips_uploads_that_do_not_fit() {
+        let (dir, device) = setup(
+            "quota",
+            &[("big.bin", &"x".repeat(100)), ("small.bin", "x")],
+        );
+
+        let sync = DirectorySync::new(&device, 1, &dir, Parent::Root);
+
+        let plan = sync.quota(50, QuotaPolicy::SmallestFirst).plan().unwrap();
+        assert_eq!(summary(&plan), ["upload   small.bin (1 bytes)"]);
+        assert_eq!(plan.skipped().len(), 1);
+        assert_eq!(plan.skipped()[0].path, Path::new("big.bin"));
+
+        // `big.bin` comes first and doesn't fit, so nothing after it is uploaded
+        let sync = DirectorySync::new(&device, 1, &dir, Parent::Root);
+        let plan = sync.quota(50, QuotaPolicy::Stop).plan().unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.skipped().len(), 2);
+    }
+
+    #[test]
+    fn applied_plan_mirrors_the_directory() {
+        let (dir, device) = setup("apply", &[("a.txt", "a"), ("sub/b.txt", "bb")]);
+
+        device.add(Parent::Root, "extra.txt", 1, 0);
+
+        let report = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .delete_extraneous(true)
+            .run()
+            .unwrap();
+
+        assert_eq!(report.created, 1);
+        assert_eq!(report.uploaded, 2);
+        assert_eq!(report.bytes_uploaded, 3);
+        assert_eq!(report.deleted, 1);
+        assert_eq!(device.names(Parent::Root), ["a.txt", "sub"]);
+
+        let sub = device.find(Parent::Root, "sub").unwrap();
+        assert_eq!(device.names(Parent::Folder(sub.id)), ["b.txt"]);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn failed_replacement_keeps_the_old_file() {
+        let (dir, device) = setup("failed", &[("changed.txt", "changed")]);
+        let modified = mtime(&dir, "changed.txt");
+
+        let old = device.add(Parent::Root, "changed.txt", 1, modified - 100);
+
+        let plan = DirectorySync::new(&device, 1, &dir, Parent::Root)
+            .plan()
+            .unwrap();
+        device.fail_uploads.set(true);
+        assert!(matches!(plan.apply(), Err(Error::Disconnected)));
+
+        assert_eq!(device.names(Parent::Root), ["changed.txt"]);
+        assert_eq!(device.find(Parent::Root, "changed.txt").unwrap().id, old);
+
+        device.fail_uploads.set(false);
+        let report = plan.apply().unwrap();
+        assert_eq!(report.uploaded, 1);
+
+        let new = device.find(Parent::Root, "changed.txt").unwrap();
+        assert_ne!(new.id, old);
+        assert_eq!(new.size, 7);
+        assert_eq!(device.names(Parent::Root), ["changed.txt"]);
+    }
+}
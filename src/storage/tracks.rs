@@ -0,0 +1,608 @@
+//! Contains relevant items to handle track objects in the device.
+
+use chrono::{DateTime, TimeZone, Utc};
+use libmtp_sys as ffi;
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::fmt::{self, Debug};
+use std::path::Path;
+
+use crate::device::{CacheMode, MtpDevice};
+use crate::error::Error;
+use crate::object::filetypes::Filetype;
+use crate::object::properties::Property;
+use crate::object::{AsObjectId, Object};
+use crate::storage::files::{read_object_to_vec, DEFAULT_READ_LIMIT};
+use crate::storage::verify::UploadSource;
+use crate::storage::{self, Parent};
+use crate::trace;
+use crate::util::{guard_callbacks, DataHandler, ProgressCallback};
+use crate::util::{optional_str, throttled, CallbackReturn, HandlerReturn, RateLimiter};
+use crate::Result;
+
+/// Maximum rating of a track, MTP ratings go from `0` to `100`.
+pub const MAX_RATING: u16 = 100;
+
+/// Maximum rating of a track in stars, see [`Track::stars`](struct.Track.html#method.stars).
+pub const MAX_STARS: u8 = 5;
+
+/// Abstraction of a track object, it implements `Object`, tracks are files with music
+/// metadata (title, artist, album, etc) attached.
+pub struct Track<'a> {
+    pub(crate) inner: *mut ffi::LIBMTP_track_t,
+    pub(crate) owner: &'a MtpDevice,
+}
+
+impl Drop for Track<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::LIBMTP_destroy_track_t(self.inner);
+        }
+    }
+}
+
+impl Object for Track<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).item_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Object for &Track<'_> {
+    fn id(&self) -> u32 {
+        unsafe { (*self.inner).item_id }
+    }
+
+    fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+}
+
+impl Debug for Track<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Track")
+            .field("id", &self.id())
+            .field("parent_id", &self.parent_id())
+            .field("storage_id", &self.storage_id())
+            .field("title", &self.title())
+            .field("artist", &self.artist())
+            .field("album", &self.album())
+            .field("genre", &self.genre())
+            .field("track_number", &self.track_number())
+            .field("duration", &self.duration())
+            .field("rating", &self.rating())
+            .field("use_count", &self.use_count())
+            .field("size", &self.size())
+            .field("ftype", &self.ftype())
+            .finish()
+    }
+}
+
+impl Track<'_> {
+    /// Returns the id of the storage it belongs to.
+    pub fn storage_id(&self) -> u32 {
+        unsafe { (*self.inner).storage_id }
+    }
+
+    /// Returns the id of its parent.
+    pub fn parent_id(&self) -> Parent {
+        let id = unsafe { (*self.inner).parent_id };
+
+        if id == ffi::LIBMTP_FILES_AND_FOLDERS_ROOT {
+            Parent::Root
+        } else {
+            Parent::Folder(id)
+        }
+    }
+
+    /// Returns the title of this track.
+    pub fn title(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).title) }
+    }
+
+    /// Returns the name of the recording artist.
+    pub fn artist(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).artist) }
+    }
+
+    /// Returns the name of the recording composer.
+    pub fn composer(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).composer) }
+    }
+
+    /// Returns the genre name of this track.
+    pub fn genre(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).genre) }
+    }
+
+    /// Returns the album name of this track.
+    pub fn album(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).album) }
+    }
+
+    /// Returns the date of the original recording as a string.
+    pub fn date(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).date) }
+    }
+
+    /// Returns the original file name of this track.
+    pub fn file_name(&self) -> Option<Cow<'_, str>> {
+        unsafe { optional_str((*self.inner).filename) }
+    }
+
+    /// Returns the track number (in sequence on recording).
+    pub fn track_number(&self) -> u16 {
+        unsafe { (*self.inner).tracknumber }
+    }
+
+    /// Returns the duration of this track in milliseconds.
+    pub fn duration(&self) -> u32 {
+        unsafe { (*self.inner).duration }
+    }
+
+    /// Returns the sample rate of the original file.
+    pub fn sample_rate(&self) -> u32 {
+        unsafe { (*self.inner).samplerate }
+    }
+
+    /// Returns the number of channels in this recording, `0` means unknown.
+    pub fn channels(&self) -> u16 {
+        unsafe { (*self.inner).nochannels }
+    }
+
+    /// Returns the FourCC wave codec name.
+    pub fn wave_codec(&self) -> u32 {
+        unsafe { (*self.inner).wavecodec }
+    }
+
+    /// Returns the (average) bitrate of this track.
+    pub fn bitrate(&self) -> u32 {
+        unsafe { (*self.inner).bitrate }
+    }
+
+    /// Returns the bitrate type, `0` means unused, `1` constant, `2` VBR and `3` free.
+    pub fn bitrate_type(&self) -> u16 {
+        unsafe { (*self.inner).bitratetype }
+    }
+
+    /// Returns the user rating of this track, from `0` to `MAX_RATING` (`100`).
+    pub fn rating(&self) -> u16 {
+        unsafe { (*self.inner).rating }
+    }
+
+    /// Sets the user rating of this track (from `0` to `MAX_RATING`) on the device.
+    pub fn set_rating(&mut self, rating: u16) -> Result<()> {
+        if rating > MAX_RATING {
+            return Err(Error::InvalidArgument {
+                reason: format!("Rating {} is greater than {}", rating, MAX_RATING),
+            });
+        }
+
+        self.set_u16(Property::Rating, rating)?;
+        unsafe { (*self.inner).rating = rating };
+        Ok(())
+    }
+
+    /// Returns the user rating of this track in stars, from `0` to `MAX_STARS` (`5`), each star
+    /// is worth `20` rating points and the value is rounded to the nearest star.
+    pub fn stars(&self) -> u8 {
+        let rating = self.rating().min(MAX_RATING);
+        ((rating + 10) / 20) as u8
+    }
+
+    /// Sets the user rating of this track in stars (from `0` to `MAX_STARS`) on the device.
+    pub fn set_stars(&mut self, stars: u8) -> Result<()> {
+        if stars > MAX_STARS {
+            return Err(Error::InvalidArgument {
+                reason: format!("{} stars is greater than {}", stars, MAX_STARS),
+            });
+        }
+
+        self.set_rating(stars as u16 * 20)
+    }
+
+    /// Returns the number of times this track was used (played).
+    pub fn use_count(&self) -> u32 {
+        unsafe { (*self.inner).usecount }
+    }
+
+    /// Sets the number of times this track was used (played) on the device.
+    pub fn set_use_count(&mut self, count: u32) -> Result<()> {
+        self.set_u32(Property::UseCount, count)?;
+        unsafe { (*self.inner).usecount = count };
+        Ok(())
+    }
+
+    /// Increments by one the number of times this track was used (played) on the device.
+    pub fn increment_use_count(&mut self) -> Result<()> {
+        self.set_use_count(self.use_count().saturating_add(1))
+    }
+
+    /// Returns the size of this track.
+    pub fn size(&self) -> u64 {
+        unsafe { (*self.inner).filesize }
+    }
+
+    /// Returns the type of this track.
+    pub fn ftype(&self) -> Filetype {
+        let ftype = unsafe { (*self.inner).filetype };
+        Filetype::from_u32(ftype).expect("Unexpected raw variant of Filetype")
+    }
+
+    /// Returns the latest modification date in UTC, the Unix epoch if the device reports a
+    /// date out of range.
+    pub fn modification_date(&self) -> DateTime<Utc> {
+        let epoch = unsafe { (*self.inner).modificationdate };
+        Utc.timestamp_opt(epoch, 0).single().unwrap_or_default()
+    }
+
+    /// Retrieves the cover art of this track from the device, the representative sample of
+    /// the track is used if there's one, otherwise its thumbnail. Only data that looks like an
+    /// image is returned, you can check its format with `Filetype::detect_image`.
+    ///
+    /// Note that many devices store the cover art on the album instead of the track.
+    pub fn cover_art(&self) -> Option<Vec<u8>> {
+        let is_image = |data: &Vec<u8>| Filetype::detect_image(data).is_some();
+
+        self.representative_sample()
+            .ok()
+            .map(|sample| sample.data)
+            .filter(is_image)
+            .or_else(|| self.thumbnail().ok().filter(is_image))
+    }
+
+    /// Downloads this track into memory, fails with `Error::InvalidArgument` if the track is
+    /// larger than [`DEFAULT_READ_LIMIT`](../files/constant.DEFAULT_READ_LIMIT.html), use
+    /// `read_to_vec_with_limit` for bigger tracks (like lossless albums).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// let data = track.read_to_vec()?;
+    /// let transcoded = transcode_to_opus(&data)?;
+    /// ```
+    pub fn read_to_vec(&self) -> Result<Vec<u8>> {
+        self.read_to_vec_with_limit(DEFAULT_READ_LIMIT)
+    }
+
+    /// Like `read_to_vec` but fails if the track is larger than `limit` bytes.
+    pub fn read_to_vec_with_limit(&self, limit: u64) -> Result<Vec<u8>> {
+        let name = self.file_name();
+        let name = name.as_deref().unwrap_or("The track");
+        read_object_to_vec(self.owner, self.id(), name, self.size(), limit)
+    }
+}
+
+/// Convenience struct used as a parameter to send local tracks to an MTP device, unlike
+/// `FileMetadata` it owns its strings so it can be filled from the tags of a local file with
+/// `from_local_file` (requires the `lofty` feature).
+#[derive(Debug, Clone)]
+pub struct TrackMetadata {
+    pub file_size: u64,
+    pub file_name: String,
+    pub file_type: Filetype,
+    pub modification_date: DateTime<Utc>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub composer: Option<String>,
+    pub genre: Option<String>,
+    pub album: Option<String>,
+    /// Date of the original recording, usually just the year.
+    pub date: Option<String>,
+    pub track_number: u16,
+    /// Duration in milliseconds.
+    pub duration: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Average bitrate in bits per second.
+    pub bitrate: u32,
+    /// User rating, from `0` to `MAX_RATING`.
+    pub rating: u16,
+}
+
+#[cfg(feature = "lofty")]
+impl TrackMetadata {
+    /// Reads the audio tags (ID3, Vorbis comments, MP4 atoms, etc) and properties (duration,
+    /// bitrate, etc) of a local file to fill the metadata needed to send it as a track.
+    pub fn from_local_file(path: impl AsRef<Path>) -> Result<TrackMetadata> {
+        use lofty::file::FileType;
+        use lofty::prelude::*;
+        use lofty::tag::ItemKey;
+
+        let path = path.as_ref();
+        let fs_metadata = std::fs::metadata(path)?;
+
+        let tagged = lofty::read_from_path(path).map_err(|err| Error::MetadataError {
+            text: err.to_string(),
+        })?;
+
+        let file_type = match tagged.file_type() {
+            FileType::Mpeg => Filetype::Mp3,
+            FileType::Flac => Filetype::Flac,
+            FileType::Vorbis | FileType::Opus | FileType::Speex => Filetype::Ogg,
+            FileType::Mp4 => Filetype::M4a,
+            FileType::Wav => Filetype::Wav,
+            FileType::Aac => Filetype::Aac,
+            _ => Filetype::UndefAudio,
+        };
+
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| Error::InvalidArgument {
+                reason: "The path doesn't have a valid UTF-8 file name".to_string(),
+            })?
+            .to_string();
+
+        let properties = tagged.properties();
+        let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+        let text = |value: Option<std::borrow::Cow<'_, str>>| value.map(|v| v.into_owned());
+
+        Ok(TrackMetadata {
+            file_size: fs_metadata.len(),
+            file_name,
+            file_type,
+            modification_date: fs_metadata.modified()?.into(),
+            title: tag.and_then(|t| text(t.title())),
+            artist: tag.and_then(|t| text(t.artist())),
+            composer: tag
+                .and_then(|t| t.get_string(&ItemKey::Composer))
+                .map(str::to_string),
+            genre: tag.and_then(|t| text(t.genre())),
+            album: tag.and_then(|t| text(t.album())),
+            date: tag.and_then(|t| t.year()).map(|year| year.to_string()),
+            track_number: tag.and_then(|t| t.track()).unwrap_or(0) as u16,
+            duration: properties.duration().as_millis() as u32,
+            sample_rate: properties.sample_rate().unwrap_or(0),
+            channels: properties.channels().unwrap_or(0) as u16,
+            bitrate: properties.audio_bitrate().unwrap_or(0) * 1000,
+            rating: 0,
+        })
+    }
+}
+
+/// Fills a `libmtp` track struct with the given metadata, strings are duplicated so `libmtp`
+/// can free them later. Fails if some string contains a nul byte, in this case the struct may
+/// be partially filled.
+unsafe fn fill_track_t(
+    metadata: &TrackMetadata,
+    parent: u32,
+    storage: u32,
+    track_t: *mut ffi::LIBMTP_track_t,
+) -> Result<()> {
+    let strdup = |string: &str| -> Result<*mut libc::c_char> {
+        let string = CString::new(string)?;
+        Ok(libc::strdup(string.as_ptr()))
+    };
+
+    let optional_strdup = |string: &Option<String>| match string {
+        Some(string) => strdup(string),
+        None => Ok(std::ptr::null_mut()),
+    };
+
+    (*track_t).parent_id = parent;
+    (*track_t).storage_id = storage;
+    (*track_t).filesize = metadata.file_size;
+    (*track_t).filename = strdup(&metadata.file_name)?;
+    (*track_t).filetype = metadata
+        .file_type
+        .to_u32()
+        .expect("Unexpected variant in Filetype");
+    (*track_t).modificationdate = metadata.modification_date.timestamp() as libc::time_t;
+    (*track_t).title = optional_strdup(&metadata.title)?;
+    (*track_t).artist = optional_strdup(&metadata.artist)?;
+    (*track_t).composer = optional_strdup(&metadata.composer)?;
+    (*track_t).genre = optional_strdup(&metadata.genre)?;
+    (*track_t).album = optional_strdup(&metadata.album)?;
+    (*track_t).date = optional_strdup(&metadata.date)?;
+    (*track_t).tracknumber = metadata.track_number;
+    (*track_t).duration = metadata.duration;
+    (*track_t).samplerate = metadata.sample_rate;
+    (*track_t).nochannels = metadata.channels;
+    (*track_t).bitrate = metadata.bitrate;
+    (*track_t).rating = metadata.rating;
+
+    Ok(())
+}
+
+pub(crate) fn send_track_from_path<'a>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    path: impl AsRef<Path>,
+    parent: Parent,
+    metadata: &TrackMetadata,
+) -> Result<Track<'a>> {
+    // Always with a callback, so the transfer can be aborted
+    send_track_from_path_with_callback(mtpdev, storage_id, path, parent, metadata, |_, _| {
+        CallbackReturn::Continue
+    })
+}
+
+pub(crate) fn send_track_from_path_with_callback<'a, C>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    path: impl AsRef<Path>,
+    parent: Parent,
+    metadata: &TrackMetadata,
+    mut callback: C,
+) -> Result<Track<'a>>
+where
+    C: FnMut(u64, u64) -> CallbackReturn,
+{
+    let name = &metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(
+        mtpdev,
+        storage_id,
+        parent,
+        name,
+        size,
+        Some(UploadSource::Path(path.as_ref())),
+        |name| {
+            let renamed = TrackMetadata {
+                file_name: name.to_string(),
+                ..metadata.clone()
+            };
+            let metadata = &renamed;
+
+            guard_callbacks(mtpdev, || {
+                let session = mtpdev.session()?;
+                let path = path.as_ref();
+                let path = path_to_cvec!(path);
+
+                let track_t = unsafe { ffi::LIBMTP_new_track_t() };
+                if let Err(err) =
+                    unsafe { fill_track_t(metadata, parent.to_id(), storage_id, track_t) }
+                {
+                    unsafe { ffi::LIBMTP_destroy_track_t(track_t) };
+                    return Err(err);
+                }
+
+                let callback = ProgressCallback::new(&mut callback);
+
+                let res = unsafe {
+                    ffi::LIBMTP_Send_Track_From_File(
+                        session,
+                        path.as_ptr() as *const _,
+                        track_t,
+                        callback.func(),
+                        callback.data(),
+                    )
+                };
+
+                if res != 0 {
+                    unsafe { ffi::LIBMTP_destroy_track_t(track_t) };
+                    Err(mtpdev.error_in("LIBMTP_Send_Track_From_File", None))
+                } else {
+                    Ok(Track {
+                        inner: track_t,
+                        owner: mtpdev,
+                    })
+                }
+            })
+        },
+    )
+}
+
+/// Internal function to upload a track with the contents of `data`, the `file_size` of the
+/// metadata is replaced by the length of `data`.
+pub(crate) fn send_track_from_bytes<'a>(
+    mtpdev: &'a MtpDevice,
+    storage_id: u32,
+    data: &[u8],
+    parent: Parent,
+    metadata: &TrackMetadata,
+) -> Result<Track<'a>> {
+    let name = &metadata.file_name;
+    let size = metadata.file_size;
+    storage::upload_to(
+        mtpdev,
+        storage_id,
+        parent,
+        name,
+        size,
+        Some(UploadSource::Bytes(data)),
+        |name| {
+            let renamed = TrackMetadata {
+                file_name: name.to_string(),
+                ..metadata.clone()
+            };
+            let metadata = &renamed;
+
+            guard_callbacks(mtpdev, || {
+                let session = mtpdev.session()?;
+
+                let track_t = unsafe { ffi::LIBMTP_new_track_t() };
+                if let Err(err) =
+                    unsafe { fill_track_t(metadata, parent.to_id(), storage_id, track_t) }
+                {
+                    unsafe { ffi::LIBMTP_destroy_track_t(track_t) };
+                    return Err(err);
+                }
+                unsafe { (*track_t).filesize = data.len() as u64 };
+
+                let mut remaining = data;
+                let mut limiter = mtpdev.max_throughput().map(RateLimiter::new);
+                let mut handler = |buf: &mut [u8]| {
+                    let len = buf.len().min(remaining.len());
+                    buf[..len].copy_from_slice(&remaining[..len]);
+                    remaining = &remaining[len..];
+                    throttled(&mut limiter, HandlerReturn::Ok(len as u32))
+                };
+                let mut handler = DataHandler::new(&mut handler);
+
+                let res = unsafe {
+                    ffi::LIBMTP_Send_Track_From_Handler(
+                        session,
+                        handler.get_func(),
+                        handler.data(),
+                        track_t,
+                        None,
+                        std::ptr::null(),
+                    )
+                };
+
+                if res != 0 {
+                    unsafe { ffi::LIBMTP_destroy_track_t(track_t) };
+                    Err(mtpdev.error_in("LIBMTP_Send_Track_From_Handler", None))
+                } else {
+                    Ok(Track {
+                        inner: track_t,
+                        owner: mtpdev,
+                    })
+                }
+            })
+        },
+    )
+}
+
+pub(crate) fn get_track_list(mtpdev: &MtpDevice, storage_id: u32) -> Result<Vec<Track<'_>>> {
+    trace::list(mtpdev, "tracks", storage_id, || {
+        let session = mtpdev.session()?;
+        mtpdev.require_cache_mode(CacheMode::Cached)?;
+
+        let mut head = unsafe {
+            ffi::LIBMTP_Get_Tracklisting_With_Callback_For_Storage(
+                session,
+                storage_id,
+                None,
+                std::ptr::null(),
+            )
+        };
+
+        let mut tracks = Vec::new();
+        while !head.is_null() {
+            tracks.push(Track {
+                inner: head,
+                owner: mtpdev,
+            });
+
+            head = unsafe { (*head).next };
+        }
+
+        if let Some(err) =
+            mtpdev.latest_error_in("LIBMTP_Get_Tracklisting_With_Callback_For_Storage", None)
+        {
+            Err(err)
+        } else {
+            Ok(tracks)
+        }
+    })
+}
+
+pub(crate) fn get_track(mtpdev: &MtpDevice, track: impl AsObjectId) -> Result<Track<'_>> {
+    let session = mtpdev.session()?;
+    let track = unsafe { ffi::LIBMTP_Get_Trackmetadata(session, track.as_id()) };
+
+    if track.is_null() {
+        Err(mtpdev.error_in("LIBMTP_Get_Trackmetadata", None))
+    } else {
+        Ok(Track {
+            inner: track,
+            owner: mtpdev,
+        })
+    }
+}
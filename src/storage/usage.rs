@@ -0,0 +1,144 @@
+//! Contains a disk usage analyzer (like `du`) that adds up the sizes of the files below every
+//! folder, see [`Storage::usage_report`](../struct.Storage.html#method.usage_report).
+
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::object::Object;
+use crate::storage::{files_and_folders, Parent};
+use crate::Result;
+
+/// Cumulative usage of a folder, i.e. of every file below it.
+#[derive(Debug, Clone)]
+pub struct FolderUsage {
+    /// The folder, `Parent::Root` for the root of the storage.
+    pub folder: Parent,
+    /// Path of the folder relative to the root of the storage, empty for the root.
+    pub path: PathBuf,
+    /// Total size in bytes of the files below this folder.
+    pub size: u64,
+    /// Number of files below this folder.
+    pub files: u64,
+}
+
+/// Sizes of every folder of a storage, folders are listed after their subfolders (like `du`).
+///
+/// ## Example
+/// ```no_run
+/// let report = storage.usage_report()?;
+/// println!("Total: {} bytes", report.total_size());
+///
+/// for usage in report.largest(10) {
+///     println!("{:>12}  {}", usage.size, usage.path.display());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UsageReport {
+    folders: Vec<FolderUsage>,
+}
+
+impl UsageReport {
+    /// Returns the usage of every folder, subfolders come before their parents and the root
+    /// is the last one.
+    pub fn folders(&self) -> &[FolderUsage] {
+        &self.folders
+    }
+
+    /// Returns the usage of the root, i.e. of the whole walk.
+    pub fn root(&self) -> &FolderUsage {
+        self.folders.last().expect("The root is always reported")
+    }
+
+    /// Total size in bytes of every file.
+    pub fn total_size(&self) -> u64 {
+        self.root().size
+    }
+
+    /// Total number of files.
+    pub fn total_files(&self) -> u64 {
+        self.root().files
+    }
+
+    /// Returns the usage of the folder at `path` (relative to the root), if any.
+    pub fn by_path(&self, path: impl AsRef<Path>) -> Option<&FolderUsage> {
+        let path = path.as_ref();
+        self.folders.iter().find(|usage| usage.path == path)
+    }
+
+    /// Returns the `count` folders using the most space, largest first, the root isn't
+    /// included.
+    pub fn largest(&self, count: usize) -> Vec<&FolderUsage> {
+        let mut folders: Vec<_> = self.folders[..self.folders.len() - 1].iter().collect();
+        folders.sort_by_key(|usage| std::cmp::Reverse(usage.size));
+        folders.truncate(count);
+        folders
+    }
+}
+
+impl Display for UsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for usage in &self.folders {
+            let path = if usage.path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                &usage.path
+            };
+
+            writeln!(f, "{}\t{}", usage.size, path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Walks the tree below `parent` adding up the sizes of the files.
+pub(crate) fn usage_report(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    parent: Parent,
+) -> Result<UsageReport> {
+    let mut folders = Vec::new();
+    walk(mtpdev, storage_id, parent, PathBuf::new(), &mut folders)?;
+
+    Ok(UsageReport { folders })
+}
+
+fn walk(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    folder: Parent,
+    path: PathBuf,
+    folders: &mut Vec<FolderUsage>,
+) -> Result<(u64, u64)> {
+    let mut size = 0;
+    let mut files = 0;
+
+    for file in files_and_folders(mtpdev, storage_id, folder)? {
+        if matches!(file.ftype(), Filetype::Folder) {
+            let (folder_size, folder_files) = walk(
+                mtpdev,
+                storage_id,
+                Parent::Folder(file.id()),
+                path.join(&*file.name()),
+                folders,
+            )?;
+
+            size += folder_size;
+            files += folder_files;
+        } else {
+            size += file.size();
+            files += 1;
+        }
+    }
+
+    folders.push(FolderUsage {
+        folder,
+        path,
+        size,
+        files,
+    });
+
+    Ok((size, files))
+}
@@ -0,0 +1,198 @@
+//! Contains `Verification`, how uploads are read back from the device and compared with their
+//! source, for files where silent corruption is unacceptable (like firmware). Set it with
+//! [`MtpDevice::set_verify_uploads`](../../device/struct.MtpDevice.html#method.set_verify_uploads).
+//!
+//! Only uploads from a local path or bytes are verified, the other sources (descriptors,
+//! handlers and async readers) can't be read a second time.
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::device::capabilities::DeviceCapability;
+use crate::device::MtpDevice;
+use crate::error::Error;
+use crate::object::properties::Property;
+use crate::object::Object;
+use crate::storage::files::get_file_to_handler;
+use crate::util::HandlerReturn;
+use crate::Result;
+
+/// Number of parts compared by `Verification::Sampled`.
+const SAMPLES: u64 = 16;
+
+/// Size of the parts compared by `Verification::Sampled`.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// How much of an upload is read back from the device.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::storage::verify::Verification;
+///
+/// mtp_device.set_verify_uploads(Some(Verification::Full));
+/// match storage.send_file_from_path("firmware.bin", Parent::Root, metadata) {
+///     Err(Error::VerificationFailed { id, mismatch }) => eprintln!("{} is corrupted: {}", id, mismatch),
+///     result => { result?; }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// Only the size reported by the device is compared, nothing is downloaded.
+    Size,
+    /// The size and a few parts spread over the file (the start and the end included) are
+    /// compared, needs
+    /// [`DeviceCapability::GetPartialObject`](../../device/capabilities/enum.DeviceCapability.html#variant.GetPartialObject),
+    /// without it (or for small files) the whole file is compared.
+    Sampled,
+    /// The size and every byte are compared, downloading the whole file again.
+    Full,
+}
+
+/// How an uploaded object differs from its source, contained in `Error::VerificationFailed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The device reports a different size.
+    Size { expected: u64, actual: u64 },
+    /// The contents first differ at this offset.
+    Content { offset: u64 },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Size { expected, actual } => {
+                write!(f, "expected {} bytes, found {}", expected, actual)
+            }
+            Mismatch::Content { offset } => write!(f, "contents differ at byte {}", offset),
+        }
+    }
+}
+
+/// Source of an upload that can be read again to verify it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum UploadSource<'s> {
+    Path(&'s Path),
+    Bytes(&'s [u8]),
+}
+
+/// Returns the offset of the first byte that differs, or where the shorter one ends.
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))
+}
+
+fn sampled<R: Read + Seek>(
+    mtpdev: &MtpDevice,
+    id: u32,
+    source: &mut R,
+    size: u64,
+) -> Result<Option<Mismatch>> {
+    let object = mtpdev.dummy_object(id);
+    let mut expected = vec![0; SAMPLE_SIZE as usize];
+
+    for sample in 0..SAMPLES {
+        let offset = (size - SAMPLE_SIZE) * sample / (SAMPLES - 1);
+        source.seek(SeekFrom::Start(offset))?;
+        source.read_exact(&mut expected)?;
+
+        let actual = object.get_partial_object(offset, SAMPLE_SIZE as u32)?;
+        if let Some(index) = first_difference(&expected, &actual) {
+            return Ok(Some(Mismatch::Content {
+                offset: offset + index as u64,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+fn full<R: Read + Seek>(mtpdev: &MtpDevice, id: u32, source: &mut R) -> Result<Option<Mismatch>> {
+    source.seek(SeekFrom::Start(0))?;
+
+    let mut expected = Vec::new();
+    let mut offset = 0;
+    let mut mismatch = None;
+    let mut read_error = None;
+
+    let result = get_file_to_handler(mtpdev, id, |actual: &[u8]| {
+        expected.resize(actual.len(), 0);
+        if let Err(err) = source.read_exact(&mut expected) {
+            read_error = Some(err);
+            return HandlerReturn::Error;
+        }
+
+        match first_difference(&expected, actual) {
+            Some(index) => {
+                mismatch = Some(Mismatch::Content {
+                    offset: offset + index as u64,
+                });
+                HandlerReturn::Cancel
+            }
+            None => {
+                offset += actual.len() as u64;
+                HandlerReturn::Ok(actual.len() as u32)
+            }
+        }
+    });
+
+    if mismatch.is_some() {
+        return Ok(mismatch);
+    }
+
+    match read_error {
+        // The source is shorter than the object
+        Some(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            Ok(Some(Mismatch::Content { offset }))
+        }
+        Some(err) => Err(err.into()),
+        None => result.map(|_| None),
+    }
+}
+
+fn compare<R: Read + Seek>(
+    mtpdev: &MtpDevice,
+    id: u32,
+    mut source: R,
+    verification: Verification,
+) -> Result<Option<Mismatch>> {
+    let expected = source.seek(SeekFrom::End(0))?;
+    let actual = mtpdev.dummy_object(id).get_u64(Property::ObjectSize)?;
+    if expected != actual {
+        return Ok(Some(Mismatch::Size { expected, actual }));
+    }
+
+    match verification {
+        Verification::Size => Ok(None),
+        Verification::Sampled
+            if expected > SAMPLES * SAMPLE_SIZE
+                && mtpdev.check_capability(DeviceCapability::GetPartialObject) =>
+        {
+            sampled(mtpdev, id, &mut source, expected)
+        }
+        Verification::Sampled | Verification::Full => full(mtpdev, id, &mut source),
+    }
+}
+
+/// Reads the object `id` back as the `verification` asks and compares it with `source`, fails
+/// with `Error::VerificationFailed` if they differ.
+pub(crate) fn verify_upload(
+    mtpdev: &MtpDevice,
+    id: u32,
+    source: UploadSource<'_>,
+    verification: Verification,
+) -> Result<()> {
+    let mismatch = match source {
+        UploadSource::Path(path) => compare(mtpdev, id, fs::File::open(path)?, verification)?,
+        UploadSource::Bytes(data) => compare(mtpdev, id, Cursor::new(data), verification)?,
+    };
+
+    match mismatch {
+        Some(mismatch) => Err(Error::VerificationFailed { id, mismatch }),
+        None => Ok(()),
+    }
+}
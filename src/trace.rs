@@ -0,0 +1,218 @@
+//! Instrumentation of device operations, the latency and bytes transferred are recorded in the
+//! session stats and reported to the [`Metrics`](../metrics/trait.Metrics.html) of the device
+//! and, with the `tracing` feature, to [`tracing`](https://docs.rs/tracing).
+//!
+//! Opens, listings and transfers are wrapped in the `mtp_open`, `mtp_list` and `mtp_transfer`
+//! spans, once the operation finishes an event with its duration (`elapsed_ms`) is emitted,
+//! which also carries the number of objects for listings and the number of bytes for transfers.
+
+use chrono::Utc;
+use std::time::{Duration, Instant};
+
+use crate::device::MtpDevice;
+use crate::journal::JournalEntry;
+use crate::Result;
+
+/// Instruments the opening of the raw device at the given location.
+#[cfg(feature = "tracing")]
+pub(crate) fn open(
+    bus_number: u32,
+    dev_number: u8,
+    cached: bool,
+    open: impl FnOnce() -> Option<MtpDevice>,
+) -> Option<MtpDevice> {
+    let span = tracing::info_span!("mtp_open", bus_number, dev_number, cached);
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let device = open();
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    match &device {
+        Some(device) => tracing::info!(device = %device.identity, elapsed_ms, "opened device"),
+        None => tracing::warn!(elapsed_ms, "failed to open device"),
+    }
+
+    device
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn open(
+    _bus_number: u32,
+    _dev_number: u8,
+    _cached: bool,
+    open: impl FnOnce() -> Option<MtpDevice>,
+) -> Option<MtpDevice> {
+    open()
+}
+
+/// Records a finished operation in the journal of the device.
+fn log<T>(
+    mtpdev: &MtpDevice,
+    operation: &'static str,
+    object_id: Option<u32>,
+    storage_id: Option<u32>,
+    elapsed: Duration,
+    result: &Result<T>,
+) {
+    let elapsed_chrono =
+        chrono::Duration::from_std(elapsed).unwrap_or_else(|_| chrono::Duration::zero());
+    let started = Utc::now() - elapsed_chrono;
+
+    mtpdev.log(JournalEntry {
+        operation,
+        object_id,
+        path: object_id.and_then(|id| mtpdev.cached_path(id)),
+        storage_id,
+        started,
+        duration: elapsed,
+        error: result.as_ref().err().map(ToString::to_string),
+    });
+}
+
+/// Instruments a listing of objects (files, tracks, albums, etc) from the given storage.
+pub(crate) fn list<T>(
+    mtpdev: &MtpDevice,
+    operation: &'static str,
+    storage_id: u32,
+    list: impl FnOnce() -> Result<Vec<T>>,
+) -> Result<Vec<T>> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!("mtp_list", device = %mtpdev.identity, operation, storage_id);
+    #[cfg(feature = "tracing")]
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let result = list();
+    let elapsed = start.elapsed();
+
+    mtpdev.record(|stats| stats.listings += 1);
+    log(mtpdev, operation, None, Some(storage_id), elapsed, &result);
+    if let Some(metrics) = mtpdev.metrics() {
+        metrics.operation(operation, elapsed);
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        match &result {
+            Ok(objects) => {
+                tracing::debug!(objects = objects.len() as u64, elapsed_ms, "listed objects")
+            }
+            Err(err) => tracing::warn!(error = %err, elapsed_ms, "listing failed"),
+        }
+    }
+
+    result
+}
+
+/// Instruments the download of the given object, `download` returns the number of bytes
+/// received.
+pub(crate) fn download(
+    mtpdev: &MtpDevice,
+    object_id: u32,
+    download: impl FnOnce() -> Result<u64>,
+) -> Result<()> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "mtp_transfer",
+        device = %mtpdev.identity,
+        direction = "download",
+        object_id,
+    );
+    #[cfg(feature = "tracing")]
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let result = download();
+    let elapsed = start.elapsed();
+
+    mtpdev.record(|stats| stats.downloads += 1);
+    log(mtpdev, "download", Some(object_id), None, elapsed, &result);
+    let metrics = mtpdev.metrics();
+    if let Some(metrics) = &metrics {
+        metrics.operation("download", elapsed);
+    }
+
+    if let Ok(bytes) = result {
+        mtpdev.record(|stats| stats.bytes_received += bytes);
+
+        if let Some(metrics) = &metrics {
+            metrics.bytes_received(bytes);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            bytes,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "downloaded object"
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        if let Err(err) = &result {
+            let elapsed_ms = elapsed.as_millis() as u64;
+            tracing::warn!(error = %err, elapsed_ms, "download failed");
+        }
+    }
+
+    result.map(|_| ())
+}
+
+/// Instruments an upload to the given storage, `size` returns the number of bytes sent given
+/// the uploaded object (e.g. `File::size`).
+pub(crate) fn upload<T>(
+    mtpdev: &MtpDevice,
+    storage_id: u32,
+    size: impl FnOnce(&T) -> u64,
+    upload: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "mtp_transfer",
+        device = %mtpdev.identity,
+        direction = "upload",
+        storage_id,
+    );
+    #[cfg(feature = "tracing")]
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let result = upload();
+    let elapsed = start.elapsed();
+
+    mtpdev.record(|stats| stats.uploads += 1);
+    log(mtpdev, "upload", None, Some(storage_id), elapsed, &result);
+    let metrics = mtpdev.metrics();
+    if let Some(metrics) = &metrics {
+        metrics.operation("upload", elapsed);
+    }
+
+    match &result {
+        Ok(object) => {
+            let bytes = size(object);
+            mtpdev.record(|stats| stats.bytes_sent += bytes);
+
+            if let Some(metrics) = &metrics {
+                metrics.bytes_sent(bytes);
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                bytes,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "uploaded object"
+            );
+        }
+        #[cfg(feature = "tracing")]
+        Err(err) => {
+            let elapsed_ms = elapsed.as_millis() as u64;
+            tracing::warn!(error = %err, elapsed_ms, "upload failed");
+        }
+        #[cfg(not(feature = "tracing"))]
+        Err(_) => {}
+    }
+
+    result
+}
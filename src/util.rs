@@ -2,6 +2,480 @@
 //! (which are not public) and other useful public items.
 
 use libmtp_sys as ffi;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::device::MtpDevice;
+use crate::error::{Error, MtpErrorKind};
+use crate::Result;
+
+/// Converts a nullable C string owned by some `libmtp` struct into a string, replacing invalid
+/// UTF-8 sequences with `U+FFFD`.
+pub(crate) unsafe fn optional_str<'a>(ptr: *const libc::c_char) -> Option<Cow<'a, str>> {
+    if ptr.is_null() {
+        None
+    } else {
+        let cstr = CStr::from_ptr(ptr);
+        Some(cstr.to_string_lossy())
+    }
+}
+
+/// Formats a number of bytes with binary units (powers of 1024) and one decimal, dropped if
+/// it's zero, this is how the sizes of storage and files are meant to be shown.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::format_bytes;
+///
+/// assert_eq!(format_bytes(512), "512 B");
+/// assert_eq!(format_bytes(62_599_495_270), "58.3 GiB");
+/// assert_eq!(format_bytes(128 * 1024 * 1024 * 1024), "128 GiB");
+/// ```
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    let value = format!("{:.1}", value);
+    let value = value.strip_suffix(".0").unwrap_or(&value);
+    format!("{} {}", value, UNITS[unit])
+}
+
+/// Replaces an array of object ids allocated by `libmtp` (like the tracks of playlists and
+/// albums), the old array is freed and the new one is allocated with `malloc` so `libmtp` can
+/// free it later. Empty arrays are represented with a null pointer.
+pub(crate) unsafe fn replace_id_array(array: &mut *mut u32, len: &mut u32, ids: &[u32]) {
+    if !array.is_null() {
+        libc::free(*array as *mut _);
+    }
+
+    if ids.is_empty() {
+        *array = std::ptr::null_mut();
+    } else {
+        let new = libc::malloc(std::mem::size_of_val(ids)) as *mut u32;
+        std::ptr::copy_nonoverlapping(ids.as_ptr(), new, ids.len());
+        *array = new;
+    }
+
+    *len = ids.len() as u32;
+}
+
+/// Policy to retry operations that failed with transient errors (like PTP timeouts or a busy
+/// device), check [`Error::is_transient`](../error/enum.Error.html#method.is_transient). The
+/// backoff between attempts doubles after every failed attempt up to `max_backoff`.
+///
+/// The policy of a device is set with
+/// [`MtpDevice::set_retry_policy`](../device/struct.MtpDevice.html#method.set_retry_policy) and
+/// it's honored by file transfers from and to paths and by object property getters and
+/// setters, by default operations aren't retried.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::RetryPolicy;
+/// use std::time::Duration;
+///
+/// mtp_device.set_retry_policy(RetryPolicy::new(3, Duration::from_millis(500)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Time to wait before the second attempt.
+    pub initial_backoff: Duration,
+    /// Maximum time to wait between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(0),
+            max_backoff: Duration::from_secs(0),
+        }
+    }
+
+    /// Policy with the given maximum number of attempts and initial backoff, the maximum
+    /// backoff is 30 seconds.
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff,
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+
+    /// Changes the maximum time to wait between attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the time to wait after the given failed attempt (starting from 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that isn't transient or the
+    /// attempts are exhausted, in the last two cases the latest error is returned.
+    pub fn run<T>(&self, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 1;
+
+        loop {
+            match operation() {
+                Err(err) if err.is_transient() && attempt < self.max_attempts => {
+                    std::thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Limits the throughput of a transfer by sleeping once it goes faster than allowed, it's used
+/// by the handler-based transfers when the device has a
+/// [`max_throughput`](../device/struct.MtpDevice.html#method.set_max_throughput), but you may
+/// also use it on your own handler to limit a single transfer.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::{HandlerReturn, RateLimiter};
+///
+/// // At most 1 MiB/s
+/// let mut limiter = RateLimiter::new(1024 * 1024);
+/// storage.get_file_to_handler(file, |data| {
+///     buffer.extend_from_slice(data);
+///     limiter.throttle(data.len() as u64);
+///     HandlerReturn::Ok(data.len() as u32)
+/// })?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    start: Instant,
+    transferred: u64,
+}
+
+impl RateLimiter {
+    /// Limiter that allows at most `bytes_per_second`, the time starts to count now.
+    ///
+    /// ## Panics
+    /// If `bytes_per_second` is zero.
+    pub fn new(bytes_per_second: u64) -> Self {
+        assert!(
+            bytes_per_second > 0,
+            "the throughput must be greater than zero"
+        );
+
+        RateLimiter {
+            bytes_per_second,
+            start: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    /// Returns the maximum throughput of this limiter.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Accounts `bytes` more transferred, blocking the current thread until the average
+    /// throughput since the start is below the limit.
+    pub fn throttle(&mut self, bytes: u64) {
+        self.transferred = self.transferred.saturating_add(bytes);
+
+        let expected =
+            Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_second as f64);
+        let elapsed = self.start.elapsed();
+
+        if expected > elapsed {
+            std::thread::sleep(expected - elapsed);
+        }
+    }
+}
+
+/// Computes the throughput and ETA of a transfer from the progress reported to a callback, the
+/// throughput is smoothed with an exponential moving average so it doesn't jump around with
+/// every chunk.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::{CallbackReturn, TransferStats};
+///
+/// let mut stats = TransferStats::new();
+/// storage.get_file_to_path_with_callback(file, "song.mp3", |sent, total| {
+///     stats.update(sent, total);
+///     if let Some(eta) = stats.eta() {
+///         print!("\r{:.0} B/s, {}s left", stats.throughput(), eta.as_secs());
+///     }
+///     CallbackReturn::Continue
+/// })?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TransferStats {
+    start: Instant,
+    last: Option<(Instant, u64)>,
+    sent: u64,
+    total: u64,
+    throughput: Option<f64>,
+    smoothing: f64,
+}
+
+impl Default for TransferStats {
+    fn default() -> Self {
+        TransferStats::new()
+    }
+}
+
+impl TransferStats {
+    /// Stats of a transfer that starts now, with a smoothing factor of `0.3`.
+    pub fn new() -> Self {
+        TransferStats {
+            start: Instant::now(),
+            last: None,
+            sent: 0,
+            total: 0,
+            throughput: None,
+            smoothing: 0.3,
+        }
+    }
+
+    /// Changes the smoothing factor (clamped to `0.0..=1.0`), the weight given to the latest
+    /// throughput sample, `1.0` means no smoothing at all.
+    pub fn smoothing(mut self, factor: f64) -> Self {
+        self.smoothing = factor.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Feeds the progress of the transfer, i.e. the arguments of the callback.
+    pub fn update(&mut self, sent: u64, total: u64) {
+        let now = Instant::now();
+        let (last_time, last_sent) = self.last.unwrap_or((self.start, 0));
+        let seconds = now.duration_since(last_time).as_secs_f64();
+
+        if seconds > 0.0 && sent >= last_sent {
+            let sample = (sent - last_sent) as f64 / seconds;
+            self.throughput = Some(match self.throughput {
+                Some(throughput) => self.smoothing * sample + (1.0 - self.smoothing) * throughput,
+                None => sample,
+            });
+        }
+
+        self.last = Some((now, sent));
+        self.sent = sent;
+        self.total = total;
+    }
+
+    /// Returns the bytes transferred so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Returns the total bytes of the transfer.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the progress of the transfer from `0.0` to `1.0`.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.sent as f64 / self.total as f64).min(1.0)
+        }
+    }
+
+    /// Returns the time since the transfer started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Returns the smoothed throughput in bytes per second, zero until there are samples.
+    pub fn throughput(&self) -> f64 {
+        self.throughput.unwrap_or(0.0)
+    }
+
+    /// Returns the average throughput in bytes per second since the transfer started.
+    pub fn average_throughput(&self) -> f64 {
+        let seconds = self.elapsed().as_secs_f64();
+        if seconds > 0.0 {
+            self.sent as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the estimated time left to finish the transfer using the smoothed throughput,
+    /// `None` if it can't be estimated yet.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total.saturating_sub(self.sent);
+        if remaining == 0 && self.total > 0 {
+            return Some(Duration::from_secs(0));
+        }
+
+        match self.throughput {
+            Some(throughput) if throughput > 0.0 => {
+                Some(Duration::from_secs_f64(remaining as f64 / throughput))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Throttles the chunk handled by a handler (if it went ok) with the given limiter.
+pub(crate) fn throttled(limiter: &mut Option<RateLimiter>, ret: HandlerReturn) -> HandlerReturn {
+    if let (Some(limiter), HandlerReturn::Ok(len)) = (limiter.as_mut(), ret) {
+        limiter.throttle(len as u64);
+    }
+
+    ret
+}
+
+/// Handle to abort the transfers of a device from anywhere, even from another thread or a
+/// signal handler (aborting only stores an atomic flag), get it with
+/// [`MtpDevice::abort_handle`](../device/struct.MtpDevice.html#method.abort_handle).
+///
+/// Once aborted, the transfers in flight are cancelled the next time `libmtp` reports their
+/// progress, and new transfers fail right away, all of them with an `MtpErrorKind::Cancelled`
+/// error. The cancellation goes through `libmtp` like a callback returning `Cancel`, so the
+/// session stays usable (e.g. to delete a partial upload), call `reset` to allow transfers
+/// again.
+///
+/// ## Example
+/// ```no_run
+/// let abort = mtp_device.abort_handle();
+/// ctrlc::set_handler(move || abort.abort())?;
+///
+/// if let Err(err) = storage.get_file_to_path(file, "video.mp4") {
+///     std::fs::remove_file("video.mp4")?;
+///     return Err(err.into());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    aborted: Arc<AtomicBool>,
+}
+
+impl AbortHandle {
+    /// Aborts the transfers in flight and the ones started later, until `reset`.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+    }
+
+    /// Allows transfers again after an abort.
+    pub fn reset(&self) {
+        self.aborted.store(false, Ordering::SeqCst);
+    }
+
+    /// Check whether transfers are aborted.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+}
+
+pub(crate) fn aborted() -> Error {
+    Error::MtpError {
+        kind: MtpErrorKind::Cancelled,
+        text: "Transfer aborted".to_string(),
+        details: Vec::new(),
+    }
+}
+
+thread_local! {
+    /// Abort handle of the device whose operation is running on this thread, `libmtp` calls
+    /// callbacks synchronously so they belong to that operation.
+    static ACTIVE_ABORT: RefCell<Option<AbortHandle>> = const { RefCell::new(None) };
+
+    /// Why the latest callback called by `libmtp` on this thread cancelled the operation
+    /// instead of running, a caught panic or an abort.
+    static INTERRUPTION: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// Runs a closure given by the user from a C trampoline, returning `cancel` instead if the
+/// device was aborted. Unwinding into `libmtp` is undefined behavior so a panic is caught too,
+/// in both cases the reason is kept for `guard_callbacks`.
+fn call_user_closure<T>(cancel: T, closure: impl FnOnce() -> T) -> T {
+    let interrupt = |error| {
+        INTERRUPTION.with(|slot| *slot.borrow_mut() = Some(error));
+        cancel
+    };
+
+    let aborted_now =
+        ACTIVE_ABORT.with(|abort| abort.borrow().as_ref().is_some_and(AbortHandle::is_aborted));
+    if aborted_now {
+        return interrupt(aborted());
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(closure)) {
+        Ok(value) => value,
+        Err(payload) => interrupt(Error::CallbackPanicked {
+            message: panic_message(&*payload),
+        }),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Runs an operation that gives callbacks or handlers to `libmtp`, fails right away if the
+/// device was aborted. If a callback panicked or was aborted the error stack of the cancelled
+/// operation is cleared and `Error::CallbackPanicked` (or the abort error) is returned.
+pub(crate) fn guard_callbacks<T>(
+    mtpdev: &MtpDevice,
+    operation: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    if mtpdev.abort.is_aborted() {
+        return Err(aborted());
+    }
+
+    INTERRUPTION.with(|slot| slot.borrow_mut().take());
+    let previous = ACTIVE_ABORT.with(|abort| abort.replace(Some(mtpdev.abort.clone())));
+    let result = operation();
+    ACTIVE_ABORT.with(|abort| abort.replace(previous));
+
+    match INTERRUPTION.with(|slot| slot.borrow_mut().take()) {
+        Some(error) => {
+            let _ = mtpdev.latest_error();
+            Err(error)
+        }
+        None => result,
+    }
+}
 
 /// Must return type on callbacks (send and get files)
 #[derive(Debug, Copy, Clone)]
@@ -12,14 +486,72 @@ pub enum CallbackReturn {
     Cancel,
 }
 
-#[allow(clippy::transmute_ptr_to_ref)]
-pub(crate) unsafe extern "C" fn progress_func_handler(
+/// A progress callback ready to be given to `libmtp`, i.e. a C trampoline and the `void *data`
+/// pointer it expects. The trampoline is generic over the type of the closure, so the pointer
+/// is cast back to exactly that type, and panics are caught before they reach `libmtp` (the
+/// transfer is cancelled instead). The closure stays borrowed for `'c`, so it can capture
+/// `&mut` state.
+///
+/// This is what you need to call `libmtp` functions that aren't wrapped by this crate, without
+/// writing your own unsafe trampolines.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::{CallbackReturn, ProgressCallback};
+///
+/// let mut last = 0;
+/// let mut closure = |sent, _total| {
+///     last = sent;
+///     CallbackReturn::Continue
+/// };
+///
+/// let callback = ProgressCallback::new(&mut closure);
+/// let res = unsafe {
+///     ffi::LIBMTP_Get_File_To_File(session, id, path, callback.func(), callback.data())
+/// };
+/// ```
+#[derive(Debug)]
+pub struct ProgressCallback<'c> {
+    func: ffi::LIBMTP_progressfunc_t,
+    data: *const libc::c_void,
+    _closure: PhantomData<&'c mut ()>,
+}
+
+impl<'c> ProgressCallback<'c> {
+    /// Wraps `callback`, which has the signature `(sent: u64, total: u64) -> CallbackReturn`.
+    pub fn new<C>(callback: &'c mut C) -> Self
+    where
+        C: FnMut(u64, u64) -> CallbackReturn,
+    {
+        ProgressCallback {
+            func: Some(progress_func_handler::<C>),
+            data: callback as *mut C as *const libc::c_void,
+            _closure: PhantomData,
+        }
+    }
+
+    /// Returns the C trampoline, to be given as the `LIBMTP_progressfunc_t` argument.
+    pub fn func(&self) -> ffi::LIBMTP_progressfunc_t {
+        self.func
+    }
+
+    /// Returns the pointer to the closure, to be given as the `data` argument.
+    pub fn data(&self) -> *const libc::c_void {
+        self.data
+    }
+}
+
+unsafe extern "C" fn progress_func_handler<C>(
     sent: u64,
     total: u64,
     data: *const libc::c_void,
-) -> libc::c_int {
-    let closure: &mut &mut dyn FnMut(u64, u64) -> CallbackReturn = std::mem::transmute(data);
-    match closure(sent, total) {
+) -> libc::c_int
+where
+    C: FnMut(u64, u64) -> CallbackReturn,
+{
+    // The pointer comes from a `&mut C` in `ProgressCallback::new`
+    let callback = &mut *(data as *mut C);
+    match call_user_closure(CallbackReturn::Cancel, || callback(sent, total)) {
         CallbackReturn::Continue => 0,
         CallbackReturn::Cancel => 1,
     }
@@ -39,36 +571,103 @@ pub enum HandlerReturn {
     Cancel,
 }
 
-impl HandlerReturn {
-    pub(crate) fn is_error(&self) -> bool {
-        matches!(&self, HandlerReturn::Error)
+/// A data handler ready to be given to `libmtp` (`MTPDataPutFunc` to receive data and
+/// `MTPDataGetFunc` to send data) together with the `void *priv` pointer it expects, check
+/// [`ProgressCallback`](struct.ProgressCallback.html). It also keeps what the handler returned
+/// the last time, since `libmtp` reports cancellations and errors the same way.
+///
+/// The pointer given by `data` points to this struct, so it must not be moved while `libmtp`
+/// uses it.
+///
+/// ## Example
+/// ```no_run
+/// use libmtp_rs::util::{DataHandler, HandlerReturn};
+///
+/// let mut buffer = Vec::new();
+/// let mut closure = |data: &[u8]| {
+///     buffer.extend_from_slice(data);
+///     HandlerReturn::Ok(data.len() as u32)
+/// };
+///
+/// let mut handler = DataHandler::new(&mut closure);
+/// let res = unsafe {
+///     ffi::LIBMTP_Get_File_To_Handler(
+///         session,
+///         id,
+///         handler.put_func(),
+///         handler.data(),
+///         None,
+///         std::ptr::null(),
+///     )
+/// };
+/// ```
+#[derive(Debug)]
+pub struct DataHandler<'h, H> {
+    handler: &'h mut H,
+    last_return: HandlerReturn,
+}
+
+impl<'h, H> DataHandler<'h, H> {
+    /// Wraps `handler`, which has the signature `(data: &[u8]) -> HandlerReturn` to receive data
+    /// or `(data: &mut [u8]) -> HandlerReturn` to send data.
+    pub fn new(handler: &'h mut H) -> Self {
+        DataHandler {
+            handler,
+            last_return: HandlerReturn::Ok(0),
+        }
+    }
+
+    /// Returns what the handler returned the last time it was called.
+    pub fn last_return(&self) -> HandlerReturn {
+        self.last_return
+    }
+
+    /// Returns the pointer to this handler, to be given as the `priv` argument.
+    pub fn data(&mut self) -> *mut libc::c_void {
+        self as *mut Self as *mut libc::c_void
+    }
+}
+
+impl<H> DataHandler<'_, H>
+where
+    H: FnMut(&[u8]) -> HandlerReturn,
+{
+    /// Returns the C trampoline to receive data, to be given as the `MTPDataPutFunc` argument.
+    pub fn put_func(&self) -> ffi::MTPDataPutFunc {
+        Some(data_put_func_handler::<H>)
     }
+}
 
-    pub(crate) fn is_cancel(&self) -> bool {
-        matches!(&self, HandlerReturn::Cancel)
+impl<H> DataHandler<'_, H>
+where
+    H: FnMut(&mut [u8]) -> HandlerReturn,
+{
+    /// Returns the C trampoline to send data, to be given as the `MTPDataGetFunc` argument.
+    pub fn get_func(&self) -> ffi::MTPDataGetFunc {
+        Some(data_get_func_handler::<H>)
     }
 }
 
-#[allow(clippy::transmute_ptr_to_ref)]
-pub(crate) unsafe extern "C" fn data_put_func_handler(
+unsafe extern "C" fn data_put_func_handler<H>(
     _params: *mut libc::c_void,
     private: *mut libc::c_void,
     sendlen: u32,
     data: *mut libc::c_uchar,
     putlen: *mut u32,
-) -> u16 {
-    let (handler_return, closure): &mut (
-        &mut HandlerReturn,
-        &mut dyn FnMut(&[u8]) -> HandlerReturn,
-    ) = std::mem::transmute(private);
-
-    let data = prim_array_ptr_to_vec!(data, u8, sendlen);
+) -> u16
+where
+    H: FnMut(&[u8]) -> HandlerReturn,
+{
+    // The pointer comes from `DataHandler::data`
+    let state = &mut *(private as *mut DataHandler<'_, H>);
+    let data = std::slice::from_raw_parts(data, sendlen as usize);
 
-    **handler_return = closure(&data);
-    let ret = match **handler_return {
+    let handler = &mut state.handler;
+    state.last_return = call_user_closure(HandlerReturn::Cancel, || handler(data));
+    let ret = match state.last_return {
         HandlerReturn::Ok(len) => {
             // Shouldn't be null
-            *putlen = len;
+            *putlen = len.min(sendlen);
 
             ffi::LIBMTP_HANDLER_RETURN_OK
         }
@@ -80,32 +679,29 @@ pub(crate) unsafe extern "C" fn data_put_func_handler(
     ret as u16
 }
 
-#[allow(clippy::transmute_ptr_to_ref)]
-pub(crate) unsafe extern "C" fn data_get_func_handler(
+unsafe extern "C" fn data_get_func_handler<H>(
     _params: *mut libc::c_void,
     private: *mut libc::c_void,
     wantlen: u32,
     data: *mut libc::c_uchar,
     gotlen: *mut u32,
-) -> u16 {
-    let (handler_return, closure): &mut (
-        &mut HandlerReturn,
-        &mut dyn FnMut(&mut [u8]) -> HandlerReturn,
-    ) = std::mem::transmute(private);
+) -> u16
+where
+    H: FnMut(&mut [u8]) -> HandlerReturn,
+{
+    // The pointer comes from `DataHandler::data`
+    let state = &mut *(private as *mut DataHandler<'_, H>);
 
-    let mut rsdata = vec![0u8; wantlen as usize];
+    // The buffer of `libmtp` may be uninitialized
+    std::ptr::write_bytes(data, 0, wantlen as usize);
+    let data = std::slice::from_raw_parts_mut(data, wantlen as usize);
 
-    **handler_return = closure(&mut rsdata);
-    let ret = match **handler_return {
+    let handler = &mut state.handler;
+    state.last_return = call_user_closure(HandlerReturn::Cancel, || handler(data));
+    let ret = match state.last_return {
         HandlerReturn::Ok(len) => {
             // Shouldn't be null
-            *gotlen = len;
-
-            libc::memcpy(
-                data as *mut _,
-                rsdata.as_ptr() as *const _,
-                wantlen as usize,
-            );
+            *gotlen = len.min(wantlen);
 
             ffi::LIBMTP_HANDLER_RETURN_OK
         }
@@ -116,3 +712,159 @@ pub(crate) unsafe extern "C" fn data_get_func_handler(
 
     ret as u16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_interruption() -> Option<Error> {
+        INTERRUPTION.with(|slot| slot.borrow_mut().take())
+    }
+
+    fn progress(callback: &ProgressCallback<'_>, sent: u64, total: u64) -> libc::c_int {
+        unsafe { callback.func().unwrap()(sent, total, callback.data()) }
+    }
+
+    fn put(
+        handler: &mut DataHandler<'_, impl FnMut(&[u8]) -> HandlerReturn>,
+        data: &[u8],
+    ) -> (u16, u32) {
+        let mut data = data.to_vec();
+        let mut putlen = 0;
+        let ret = unsafe {
+            handler.put_func().unwrap()(
+                std::ptr::null_mut(),
+                handler.data(),
+                data.len() as u32,
+                data.as_mut_ptr(),
+                &mut putlen,
+            )
+        };
+
+        (ret, putlen)
+    }
+
+    #[test]
+    fn progress_callback_continues_and_cancels() {
+        let mut reported = Vec::new();
+        let mut closure = |sent, total| {
+            reported.push((sent, total));
+            if sent < total {
+                CallbackReturn::Continue
+            } else {
+                CallbackReturn::Cancel
+            }
+        };
+
+        let callback = ProgressCallback::new(&mut closure);
+        assert_eq!(progress(&callback, 10, 100), 0);
+        assert_eq!(progress(&callback, 100, 100), 1);
+        assert_eq!(reported, [(10, 100), (100, 100)]);
+    }
+
+    #[test]
+    fn progress_callback_cancels_on_panics() {
+        take_interruption();
+        let mut closure = |_, _| -> CallbackReturn { panic!("Progress went wrong") };
+
+        let callback = ProgressCallback::new(&mut closure);
+        assert_eq!(progress(&callback, 10, 100), 1);
+        assert!(matches!(
+            take_interruption(),
+            Some(Error::CallbackPanicked { message }) if message == "Progress went wrong"
+        ));
+    }
+
+    #[test]
+    fn data_handler_accounts_bytes() {
+        let mut received = Vec::new();
+        let mut closure = |data: &[u8]| {
+            // Takes at most 3 bytes, and claims more than it was given once
+            let len = data.len().min(3);
+            received.extend_from_slice(&data[..len]);
+            HandlerReturn::Ok(if data.len() == 1 { 10 } else { len as u32 })
+        };
+
+        let mut handler = DataHandler::new(&mut closure);
+        assert_eq!(put(&mut handler, &[1, 2, 3, 4, 5]), (0, 3));
+        assert_eq!(put(&mut handler, &[6]), (0, 1));
+        assert!(matches!(handler.last_return(), HandlerReturn::Ok(10)));
+        assert_eq!(received, [1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn data_handler_propagates_errors_and_cancellations() {
+        let mut calls = 0;
+        let mut closure = |_: &[u8]| {
+            calls += 1;
+            match calls {
+                1 => HandlerReturn::Error,
+                _ => HandlerReturn::Cancel,
+            }
+        };
+
+        let mut handler = DataHandler::new(&mut closure);
+        assert_eq!(
+            put(&mut handler, &[1]).0,
+            ffi::LIBMTP_HANDLER_RETURN_ERROR as u16
+        );
+        assert!(matches!(handler.last_return(), HandlerReturn::Error));
+        assert_eq!(
+            put(&mut handler, &[1]).0,
+            ffi::LIBMTP_HANDLER_RETURN_CANCEL as u16
+        );
+        assert!(matches!(handler.last_return(), HandlerReturn::Cancel));
+    }
+
+    #[test]
+    fn data_handler_fills_the_buffer_to_send() {
+        let mut closure = |buf: &mut [u8]| {
+            buf[..2].copy_from_slice(&[7, 8]);
+            HandlerReturn::Ok(2)
+        };
+
+        let mut handler = DataHandler::new(&mut closure);
+        let mut buf = [0xFF; 4];
+        let mut gotlen = 0;
+        let ret = unsafe {
+            handler.get_func().unwrap()(
+                std::ptr::null_mut(),
+                handler.data(),
+                buf.len() as u32,
+                buf.as_mut_ptr(),
+                &mut gotlen,
+            )
+        };
+
+        assert_eq!(ret, ffi::LIBMTP_HANDLER_RETURN_OK as u16);
+        assert_eq!(gotlen, 2);
+        assert_eq!(buf, [7, 8, 0, 0]);
+    }
+
+    #[test]
+    fn aborted_devices_cancel_callbacks() {
+        take_interruption();
+        let abort = AbortHandle::default();
+        abort.abort();
+        let previous = ACTIVE_ABORT.with(|active| active.replace(Some(abort)));
+
+        let mut called = false;
+        let mut closure = |_, _| {
+            called = true;
+            CallbackReturn::Continue
+        };
+        let callback = ProgressCallback::new(&mut closure);
+        let ret = progress(&callback, 10, 100);
+
+        ACTIVE_ABORT.with(|active| active.replace(previous));
+        assert_eq!(ret, 1);
+        assert!(!called);
+        assert!(matches!(
+            take_interruption(),
+            Some(Error::MtpError {
+                kind: MtpErrorKind::Cancelled,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,532 @@
+//! Contains a watcher that listens to the events of a device and automatically downloads the
+//! new files that match some filters, useful for screenshot or recording pipelines, and a
+//! [`FreeSpaceWatcher`](struct.FreeSpaceWatcher.html) that tells when the storage is filling up,
+//! and a [`FolderWatch`](struct.FolderWatch.html) that reports the changes below a folder.
+//!
+//! ## Example
+//! ```no_run
+//! let watcher = Watcher::new(&mtp_device, "screenshots")
+//!     .folder(Parent::Folder(screenshots_id))
+//!     .filetype(Filetype::Png);
+//!
+//! watcher.run(|file, path| {
+//!     println!("{} downloaded to {}", file.name(), path.display());
+//!     CallbackReturn::Continue
+//! })?;
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::device::events::Event;
+use crate::device::MtpDevice;
+use crate::object::filetypes::Filetype;
+use crate::object::{Object, ObjectId};
+use crate::storage::conflict::{local_file_name, persist_download};
+use crate::storage::files::{self, File};
+use crate::storage::folders::{get_folder_list, FolderIter};
+use crate::storage::Parent;
+use crate::util::CallbackReturn;
+use crate::Result;
+
+/// Listens for `ObjectAdded` events and downloads the new files into a local directory, by
+/// default every new file is downloaded, use `folder` and `filetype` to restrict them.
+///
+/// Note that some devices report new objects before they're completely written (e.g. screen
+/// recordings), files are downloaded as soon as the event arrives. Local files are never
+/// replaced, a new file named like an existing one gets a suffix (like `screen (1).png`), and
+/// files whose name can't be used locally (like `../x`) fail with `Error::InvalidArgument`.
+#[derive(Debug)]
+pub struct Watcher<'a> {
+    owner: &'a MtpDevice,
+    destination: PathBuf,
+    folders: Vec<Parent>,
+    filetypes: Vec<Filetype>,
+}
+
+impl<'a> Watcher<'a> {
+    /// Creates a watcher for the given device that downloads new files into `destination`.
+    pub fn new(device: &'a MtpDevice, destination: impl Into<PathBuf>) -> Self {
+        Watcher {
+            owner: device,
+            destination: destination.into(),
+            folders: Vec::new(),
+            filetypes: Vec::new(),
+        }
+    }
+
+    /// Only download files created directly inside `parent`, may be called several times to
+    /// watch multiple folders.
+    pub fn folder(mut self, parent: Parent) -> Self {
+        self.folders.push(parent);
+        self
+    }
+
+    /// Only download files with the given filetype, may be called several times to accept
+    /// multiple filetypes.
+    pub fn filetype(mut self, ftype: Filetype) -> Self {
+        self.filetypes.push(ftype);
+        self
+    }
+
+    /// Returns the `MtpDevice` being watched.
+    pub fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+
+    /// Returns the directory where new files are downloaded.
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Check whether the given file passes the folder and filetype filters.
+    pub fn matches(&self, file: &File<'_>) -> bool {
+        let parent = file.parent_id().to_id();
+
+        let folder_matches =
+            self.folders.is_empty() || self.folders.iter().any(|f| f.to_id() == parent);
+        let filetype_matches = self.filetypes.is_empty() || self.filetypes.contains(&file.ftype());
+
+        folder_matches && filetype_matches
+    }
+
+    /// Blocks until a new matching file is added to the device and downloads it, returns the
+    /// file and the local path where it was downloaded.
+    pub fn next_download(&self) -> Result<(File<'a>, PathBuf)> {
+        loop {
+            if let Some(download) = self.handle_event(self.owner.read_event()?)? {
+                return Ok(download);
+            }
+        }
+    }
+
+    /// Downloads new matching files until the `callback` returns `CallbackReturn::Cancel`, the
+    /// `callback` has the signature `(file: &File, path: &Path) -> CallbackReturn` and is called
+    /// after every download.
+    pub fn run<C>(&self, mut callback: C) -> Result<()>
+    where
+        C: FnMut(&File<'a>, &Path) -> CallbackReturn,
+    {
+        loop {
+            let (file, path) = self.next_download()?;
+
+            if let CallbackReturn::Cancel = callback(&file, &path) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn handle_event(&self, event: Event) -> Result<Option<(File<'a>, PathBuf)>> {
+        let id = match event {
+            Event::ObjectAdded(ObjectId(id)) => id,
+            _ => return Ok(None),
+        };
+
+        let file = self.owner.search_file(id)?;
+        if matches!(file.ftype(), Filetype::Folder) || !self.matches(&file) {
+            return Ok(None);
+        }
+
+        let file_name = file.name();
+        let name = local_file_name(&file_name)?;
+        std::fs::create_dir_all(&self.destination)?;
+
+        let partial = self.destination.join(format!(".{}.part", name));
+        let path = files::get_file_to_path(self.owner, &file, &partial)
+            .and_then(|_| persist_download(&partial, &self.destination, name));
+
+        match path {
+            Ok(path) => Ok(Some((file, path))),
+            Err(err) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// A change of the free space of a storage relative to one of the thresholds of a
+/// `FreeSpaceWatcher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeSpaceEvent {
+    /// The free space dropped below the threshold (or was already below it at the first check).
+    Below {
+        storage_id: u32,
+        threshold: u64,
+        free_space: u64,
+    },
+    /// The free space went back above the threshold.
+    Above {
+        storage_id: u32,
+        threshold: u64,
+        free_space: u64,
+    },
+}
+
+impl FreeSpaceEvent {
+    /// Returns the id of the storage.
+    pub fn storage_id(&self) -> u32 {
+        match *self {
+            FreeSpaceEvent::Below { storage_id, .. } | FreeSpaceEvent::Above { storage_id, .. } => {
+                storage_id
+            }
+        }
+    }
+
+    /// Returns the threshold that was crossed, in bytes.
+    pub fn threshold(&self) -> u64 {
+        match *self {
+            FreeSpaceEvent::Below { threshold, .. } | FreeSpaceEvent::Above { threshold, .. } => {
+                threshold
+            }
+        }
+    }
+
+    /// Returns the free space of the storage when the threshold was crossed, in bytes.
+    pub fn free_space(&self) -> u64 {
+        match *self {
+            FreeSpaceEvent::Below { free_space, .. } | FreeSpaceEvent::Above { free_space, .. } => {
+                free_space
+            }
+        }
+    }
+}
+
+/// Periodically refreshes the storage of a device and reports when its free space crosses
+/// some thresholds (in bytes), so old files can be rotated before the storage fills up, by
+/// default every storage is watched every 30 seconds.
+///
+/// ## Example
+/// ```no_run
+/// let mut watcher = FreeSpaceWatcher::new(&mut mtp_device)
+///     .threshold(1024 * 1024 * 1024)
+///     .interval(Duration::from_secs(60));
+///
+/// watcher.run(|device, event| {
+///     if let FreeSpaceEvent::Below { storage_id, .. } = event {
+///         rotate_old_recordings(device, *storage_id);
+///     }
+///
+///     CallbackReturn::Continue
+/// })?;
+/// ```
+#[derive(Debug)]
+pub struct FreeSpaceWatcher<'a> {
+    owner: &'a mut MtpDevice,
+    thresholds: Vec<u64>,
+    storage_ids: Vec<u32>,
+    interval: Duration,
+    below: HashMap<(u32, u64), bool>,
+}
+
+impl<'a> FreeSpaceWatcher<'a> {
+    /// Creates a watcher for the given device, without thresholds nothing is reported.
+    pub fn new(device: &'a mut MtpDevice) -> Self {
+        FreeSpaceWatcher {
+            owner: device,
+            thresholds: Vec::new(),
+            storage_ids: Vec::new(),
+            interval: Duration::from_secs(30),
+            below: HashMap::new(),
+        }
+    }
+
+    /// Adds a threshold in bytes of free space, may be called several times to get warned
+    /// at different levels.
+    pub fn threshold(mut self, bytes: u64) -> Self {
+        self.thresholds.push(bytes);
+        self
+    }
+
+    /// Only watch the storage with the given id, may be called several times to watch
+    /// multiple storage.
+    pub fn storage(mut self, storage_id: u32) -> Self {
+        self.storage_ids.push(storage_id);
+        self
+    }
+
+    /// Changes how often the storage is refreshed.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the `MtpDevice` being watched.
+    pub fn device(&self) -> &MtpDevice {
+        self.owner
+    }
+
+    /// Refreshes the storage once and returns the thresholds crossed since the previous check,
+    /// useful to drive the watcher from your own loop.
+    pub fn check(&mut self) -> Result<Vec<FreeSpaceEvent>> {
+        self.owner.update_storage(self.owner.storage_sort)?;
+
+        let storage_pool = self.owner.storage_pool();
+        let mut events = Vec::new();
+
+        for (storage_id, storage) in storage_pool.iter() {
+            if !self.storage_ids.is_empty() && !self.storage_ids.contains(&storage_id) {
+                continue;
+            }
+
+            let free_space = storage.free_space_in_bytes();
+            for &threshold in &self.thresholds {
+                let below = free_space < threshold;
+                let was_below = self.below.insert((storage_id, threshold), below);
+
+                let event = match (was_below, below) {
+                    (None, true) | (Some(false), true) => FreeSpaceEvent::Below {
+                        storage_id,
+                        threshold,
+                        free_space,
+                    },
+                    (Some(true), false) => FreeSpaceEvent::Above {
+                        storage_id,
+                        threshold,
+                        free_space,
+                    },
+                    _ => continue,
+                };
+
+                events.push(event);
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Checks the storage every `interval` until the `callback` returns
+    /// `CallbackReturn::Cancel`, the `callback` has the signature
+    /// `(device: &MtpDevice, event: &FreeSpaceEvent) -> CallbackReturn` and is called for every
+    /// crossed threshold, the device may be used to delete files.
+    pub fn run<C>(&mut self, mut callback: C) -> Result<()>
+    where
+        C: FnMut(&MtpDevice, &FreeSpaceEvent) -> CallbackReturn,
+    {
+        loop {
+            for event in self.check()? {
+                if let CallbackReturn::Cancel = callback(&*self.owner, &event) {
+                    return Ok(());
+                }
+            }
+
+            std::thread::sleep(self.interval);
+        }
+    }
+}
+
+/// Kind of a change below a watched folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The object was created.
+    Created,
+    /// The object was removed.
+    Removed,
+}
+
+/// A change below the folder watched by a `FolderWatch`, like the events of the `notify`
+/// crate but for MTP objects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Id of the object that changed.
+    pub object_id: u32,
+    /// Path of the object relative to the watched folder.
+    pub path: PathBuf,
+    /// Whether the object is a folder.
+    pub is_folder: bool,
+}
+
+#[derive(Debug, Clone)]
+struct WatchedEntry {
+    path: PathBuf,
+    is_folder: bool,
+}
+
+/// Iterator over the changes below a folder (recursively), made from the `ObjectAdded` and
+/// `ObjectRemoved` events of the device, every call to `next` blocks until a change below the
+/// folder happens. Created with [`MtpDevice::watch`](../device/struct.MtpDevice.html#method.watch).
+///
+/// The contents of the folder are listed when the watch starts, so removed objects can still be
+/// resolved to their paths, objects removed before the watch started are unknown and ignored.
+/// Objects moved into or out of the folder aren't reported, MTP has no event for them. Objects
+/// whose name can't be part of a local path (like `..`) are ignored, with their contents.
+///
+/// ## Example
+/// ```no_run
+/// for change in mtp_device.watch(Parent::Folder(camera_id))? {
+///     let change = change?;
+///     println!("{:?} {}", change.kind, change.path.display());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FolderWatch<'a> {
+    owner: &'a MtpDevice,
+    folder: Parent,
+    entries: HashMap<u32, WatchedEntry>,
+}
+
+impl<'a> FolderWatch<'a> {
+    pub(crate) fn new(owner: &'a MtpDevice, folder: Parent) -> Result<Self> {
+        let mut watch = FolderWatch {
+            owner,
+            folder,
+            entries: HashMap::new(),
+        };
+
+        watch.snapshot()?;
+        Ok(watch)
+    }
+
+    /// Returns the folder being watched.
+    pub fn folder(&self) -> Parent {
+        self.folder
+    }
+
+    /// Returns the path (relative to the watched folder) of an object known to be below it.
+    pub fn path_of(&self, object_id: u32) -> Option<&Path> {
+        self.entries
+            .get(&object_id)
+            .map(|entry| entry.path.as_path())
+    }
+
+    /// Lists every folder and file below the watched folder to know their paths.
+    fn snapshot(&mut self) -> Result<()> {
+        let mut objects: Vec<(u32, u32, String, bool)> =
+            FolderIter::new(get_folder_list(self.owner))
+                .map(|folder| {
+                    (
+                        folder.id(),
+                        folder.parent_id(),
+                        folder.name().to_string(),
+                        true,
+                    )
+                })
+                .collect();
+
+        for file in files::list_files(self.owner, 0, self.folder)? {
+            objects.push((
+                file.id(),
+                file.parent_id().to_id(),
+                file.name().to_string(),
+                false,
+            ));
+        }
+
+        let mut children: HashMap<u32, Vec<(u32, String, bool)>> = HashMap::new();
+        for (id, parent_id, name, is_folder) in objects {
+            children
+                .entry(parent_id)
+                .or_default()
+                .push((id, name, is_folder));
+        }
+
+        let mut pending = vec![self.folder.to_id()];
+        while let Some(parent_id) = pending.pop() {
+            let parent_path = self.parent_path(parent_id).unwrap_or_default();
+
+            for (id, name, is_folder) in children.remove(&parent_id).unwrap_or_default() {
+                if local_file_name(&name).is_err() {
+                    continue;
+                }
+
+                if is_folder {
+                    pending.push(id);
+                }
+
+                self.entries.insert(
+                    id,
+                    WatchedEntry {
+                        path: parent_path.join(name),
+                        is_folder,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of a folder, empty for the watched folder and `None` if it isn't below.
+    fn parent_path(&self, parent_id: u32) -> Option<PathBuf> {
+        if parent_id == self.folder.to_id() {
+            return Some(PathBuf::new());
+        }
+
+        self.entries
+            .get(&parent_id)
+            .filter(|entry| entry.is_folder)
+            .map(|entry| entry.path.clone())
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<Option<Change>> {
+        match event {
+            Event::ObjectAdded(ObjectId(id)) => {
+                let file = self.owner.search_file(id)?;
+                let parent_id = file.parent_id().to_id();
+                let path = match (self.parent_path(parent_id), local_file_name(&file.name())) {
+                    (Some(parent_path), Ok(name)) => parent_path.join(name),
+                    _ => return Ok(None),
+                };
+
+                let is_folder = matches!(file.ftype(), Filetype::Folder);
+                self.entries.insert(
+                    id,
+                    WatchedEntry {
+                        path: path.clone(),
+                        is_folder,
+                    },
+                );
+
+                Ok(Some(Change {
+                    kind: ChangeKind::Created,
+                    object_id: id,
+                    path,
+                    is_folder,
+                }))
+            }
+
+            Event::ObjectRemoved(ObjectId(id)) => {
+                let entry = match self.entries.remove(&id) {
+                    Some(entry) => entry,
+                    None => return Ok(None),
+                };
+
+                // The contents of a removed folder are gone too
+                if entry.is_folder {
+                    self.entries
+                        .retain(|_, other| !other.path.starts_with(&entry.path));
+                }
+
+                Ok(Some(Change {
+                    kind: ChangeKind::Removed,
+                    object_id: id,
+                    path: entry.path,
+                    is_folder: entry.is_folder,
+                }))
+            }
+
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Iterator for FolderWatch<'_> {
+    type Item = Result<Change>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let change = self
+                .owner
+                .read_event()
+                .and_then(|event| self.handle_event(event));
+
+            match change {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}